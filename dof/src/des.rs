@@ -14,10 +14,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::borrow::Cow;
 use std::convert::{TryFrom, TryInto};
 use std::mem::size_of;
 use std::path::Path;
 
+use goblin::elf::reloc::{R_AARCH64_ABS64, R_X86_64_64};
+use goblin::elf::section_header::SHT_NOBITS;
+use goblin::elf::Elf;
 use goblin::Object;
 use zerocopy::Ref;
 
@@ -65,6 +69,7 @@ fn parse_probe_section(
         Probe {
             name: extract_strings(&strtab[probe.dofpr_name as _..], Some(1))[0].clone(),
             function: extract_strings(&strtab[probe.dofpr_func as _..], Some(1))[0].clone(),
+            module: String::new(),
             address: probe.dofpr_addr,
             offsets: offs,
             enabled_offsets: enabled_offs,
@@ -122,20 +127,30 @@ fn parse_providers(sections: &[dof_sec], buf: &[u8]) -> Vec<Provider> {
             .map(|probe| (probe.name.clone(), probe))
             .collect();
 
-        providers.push(Provider { name, probes });
+        providers.push(Provider {
+            name,
+            probes,
+            version_tag: None,
+        });
     }
     providers
 }
 
 fn deserialize_raw_headers(buf: &[u8]) -> Result<(dof_hdr, Vec<dof_sec>), Error> {
-    let file_header =
-        *Ref::<_, dof_hdr>::new(&buf[..size_of::<dof_hdr>()]).ok_or(Error::ParseError)?;
+    let file_header = *buf
+        .get(..size_of::<dof_hdr>())
+        .and_then(Ref::<_, dof_hdr>::new)
+        .ok_or(Error::ParseError)?;
     let n_sections: usize = file_header.dofh_secnum as _;
     let mut section_headers = Vec::with_capacity(n_sections);
     for i in 0..n_sections {
         let start = file_header.dofh_secoff as usize + file_header.dofh_secsize as usize * i;
         let end = start + file_header.dofh_secsize as usize;
-        section_headers.push(*Ref::<_, dof_sec>::new(&buf[start..end]).ok_or(Error::ParseError)?);
+        section_headers.push(
+            *buf.get(start..end)
+                .and_then(Ref::<_, dof_sec>::new)
+                .ok_or(Error::ParseError)?,
+        );
     }
     Ok((file_header, section_headers))
 }
@@ -184,18 +199,26 @@ pub fn is_dof_section(buf: &[u8]) -> bool {
     buf.len() >= DOF_MAGIC.len() && buf.starts_with(&DOF_MAGIC)
 }
 
-/// Return the raw byte blobs for each DOF section in the given object file
-pub fn collect_dof_sections<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<u8>>, Error> {
-    let data = std::fs::read(path)?;
-    match Object::parse(&data)? {
+// Return the data of each DOF section in the given object file data.
+//
+// The data is borrowed from the file, unless relocations must be applied to it.
+fn dof_section_slices(data: &[u8]) -> Result<Vec<Cow<'_, [u8]>>, Error> {
+    match Object::parse(data)? {
         Object::Elf(elf) => Ok(elf
             .section_headers
             .iter()
-            .filter_map(|section| {
+            .enumerate()
+            .filter_map(|(index, section)| {
+                // Sections such as `.bss` occupy no space in the file, though their offset may be
+                // that of the following section.
+                if section.sh_type == SHT_NOBITS {
+                    return None;
+                }
                 let start = section.sh_offset as usize;
                 let end = start + section.sh_size as usize;
-                if is_dof_section(&data[start..end]) {
-                    Some(data[start..end].to_vec())
+                let section_data = data.get(start..end)?;
+                if is_dof_section(section_data) {
+                    Some(relocate_elf_section(&elf, index, section_data))
                 } else {
                     None
                 }
@@ -208,7 +231,7 @@ pub fn collect_dof_sections<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<u8>>, Err
             .filter_map(|item| {
                 if let Ok((_, section_data)) = item {
                     if is_dof_section(section_data) {
-                        Some(section_data.to_vec())
+                        Some(Cow::Borrowed(section_data))
                     } else {
                         None
                     }
@@ -221,6 +244,65 @@ pub fn collect_dof_sections<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<u8>>, Err
     }
 }
 
+// Apply any relocations against the ELF section with the given index to its data.
+//
+// Relocatable objects generated by the system `dtrace -G` contain DOF in which the address of
+// each probe is zero, along with a relocation against the symbol of the function containing the
+// probe. Applying these gives each probe the address of its function, as the linker would.
+fn relocate_elf_section<'a>(elf: &Elf<'_>, index: usize, data: &'a [u8]) -> Cow<'a, [u8]> {
+    let relocs = elf
+        .shdr_relocs
+        .iter()
+        .filter(|(reloc_index, _)| {
+            elf.section_headers
+                .get(*reloc_index)
+                .map_or(false, |header| header.sh_info as usize == index)
+        })
+        .flat_map(|(_, relocs)| relocs.iter())
+        .filter(|reloc| matches!(reloc.r_type, R_X86_64_64 | R_AARCH64_ABS64))
+        .collect::<Vec<_>>();
+    if relocs.is_empty() {
+        return Cow::Borrowed(data);
+    }
+
+    let mut data = data.to_vec();
+    for reloc in relocs {
+        let offset = reloc.r_offset as usize;
+        let (symbol, field) = match (
+            elf.syms.get(reloc.r_sym),
+            data.get_mut(offset..offset + size_of::<u64>()),
+        ) {
+            (Some(symbol), Some(field)) => (symbol, field),
+            _ => continue,
+        };
+        let field: &mut [u8; 8] = field.try_into().unwrap();
+        // REL relocations store the addend in place.
+        let addend = reloc.r_addend.unwrap_or_else(|| {
+            if elf.little_endian {
+                i64::from_le_bytes(*field)
+            } else {
+                i64::from_be_bytes(*field)
+            }
+        });
+        let value = symbol.st_value.wrapping_add(addend as u64);
+        *field = if elf.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        };
+    }
+    Cow::Owned(data)
+}
+
+/// Return the raw byte blobs for each DOF section in the given object file
+pub fn collect_dof_sections<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<u8>>, Error> {
+    let data = std::fs::read(path)?;
+    Ok(dof_section_slices(&data)?
+        .into_iter()
+        .map(|sect| sect.to_vec())
+        .collect())
+}
+
 /// Extract DOF sections from the given object file (ELF or Mach-O)
 pub fn extract_dof_sections<P: AsRef<Path>>(path: P) -> Result<Vec<Section>, Error> {
     collect_dof_sections(path)?
@@ -228,3 +310,24 @@ pub fn extract_dof_sections<P: AsRef<Path>>(path: P) -> Result<Vec<Section>, Err
         .map(|sect| Section::from_bytes(&sect))
         .collect()
 }
+
+/// Extract DOF sections from the contents of an object file (ELF or Mach-O), without copying them
+pub fn extract_dof_sections_from_bytes(data: &[u8]) -> Result<Vec<Section>, Error> {
+    dof_section_slices(data)?
+        .into_iter()
+        .map(|sect| Section::from_bytes(&sect))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deserialize_raw_sections, deserialize_section};
+    use crate::dof::DOF_MAGIC;
+
+    #[test]
+    fn test_deserialize_truncated() {
+        // Data starting with the DOF magic but too short for its headers is an error.
+        assert!(deserialize_section(&DOF_MAGIC).is_err());
+        assert!(deserialize_raw_sections(&DOF_MAGIC).is_err());
+    }
+}
@@ -199,6 +199,77 @@ impl Section {
     pub fn to_json(&self) -> String {
         serde_json::to_string_pretty(self).unwrap()
     }
+
+    /// Return true if this section contains no probes.
+    ///
+    /// This is the case if it has no providers, or if none of its providers have any probes, for
+    /// example after filtering out their probes.
+    pub fn is_empty(&self) -> bool {
+        self.providers
+            .values()
+            .all(|provider| provider.probes.is_empty())
+    }
+
+    /// Merge the providers and probes of another section into this one.
+    ///
+    /// This is useful to build a single view of the probes in a program split across several
+    /// object files, such as an executable and its shared libraries. Providers and probes in
+    /// either section are included in the result. The offsets of a probe in both sections are
+    /// combined, relative to the lower of the two addresses, and duplicates are removed.
+    ///
+    /// Probes are only combined if they're in the same module. A probe of `other` in a different
+    /// module than that of the same name in this section is kept as a distinct probe, under the
+    /// key `module:name`. If a probe appears in both sections with different arguments, or its
+    /// offsets can't be combined, the probe from this section is kept, and that from `other` is
+    /// ignored. The identifier of this section is always kept, as is the version tag of a
+    /// provider in both, unless it has none.
+    pub fn merge(mut self, other: Section) -> Section {
+        for (name, other_provider) in other.providers {
+            let provider = match self.providers.get_mut(&name) {
+                Some(provider) => provider,
+                None => {
+                    self.providers.insert(name, other_provider);
+                    continue;
+                }
+            };
+            if provider.version_tag.is_none() {
+                provider.version_tag = other_provider.version_tag;
+            }
+            for (name, other_probe) in other_provider.probes {
+                let name = match provider.probes.get(&name) {
+                    Some(probe) if probe.module != other_probe.module => {
+                        format!("{}:{}", other_probe.module, name)
+                    }
+                    _ => name,
+                };
+                match provider.probes.get_mut(&name) {
+                    Some(probe) => probe.merge(other_probe),
+                    None => {
+                        provider.probes.insert(name, other_probe);
+                    }
+                }
+            }
+        }
+        self
+    }
+
+    /// Group the probes of all providers in this section by the function containing them.
+    ///
+    /// The probes of each function are ordered by provider, and then by name.
+    pub fn by_function(&self) -> BTreeMap<String, Vec<&Probe>> {
+        let mut functions = BTreeMap::<_, Vec<_>>::new();
+        for probe in self
+            .providers
+            .values()
+            .flat_map(|provider| provider.probes.values())
+        {
+            functions
+                .entry(probe.function.clone())
+                .or_default()
+                .push(probe);
+        }
+        functions
+    }
 }
 
 impl Default for Section {
@@ -225,6 +296,9 @@ pub struct Probe {
     pub name: String,
     /// Name of the function containing this probe
     pub function: String,
+    /// Name of the module (object file) containing this probe, without any directory
+    /// components. This is not stored in the DOF itself, and is empty if it's not known.
+    pub module: String,
     /// Address or offset in the resulting object code
     pub address: u64,
     /// Offsets in containing function at which this probe occurs.
@@ -235,6 +309,89 @@ pub struct Probe {
     pub arguments: Vec<String>,
 }
 
+impl Probe {
+    /// Return the fully-qualified name of this probe, as listed by `dtrace -l`.
+    ///
+    /// This has the form `provider:module:function:name`.
+    pub fn full_name(&self, provider: &Provider) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            provider.name, self.module, self.function, self.name
+        )
+    }
+
+    /// Return the Rust type of each of this probe's arguments, e.g., `u8` for `uint8_t`.
+    ///
+    /// The arguments are stored as the names of their C types. Those which aren't one of the
+    /// types supported in D provider definitions, such as the `void*` of struct pointers, are
+    /// returned as they are.
+    pub fn argument_rust_types(&self) -> Vec<String> {
+        self.arguments
+            .iter()
+            .map(|arg| {
+                dtrace_parser::DataType::from_c_type(arg)
+                    .map(|typ| typ.to_rust_type())
+                    .unwrap_or_else(|| arg.clone())
+            })
+            .collect()
+    }
+
+    /// Return an identifier for this probe which is stable across builds of a binary.
+    ///
+    /// This is the 64-bit FNV-1a hash of the probe's signature, `provider:name:arg0,arg1,...`.
+    /// Supported argument types are first normalized, e.g., `char *` to `char*`. The identifier
+    /// depends only on the signature, not on the address or offsets of the probe, so it's
+    /// unchanged if the probe moves or other probes are added or reordered.
+    pub fn stable_id(&self, provider: &Provider) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+        let arguments = self
+            .arguments
+            .iter()
+            .map(|arg| {
+                dtrace_parser::DataType::from_c_type(arg)
+                    .map(|typ| typ.to_c_type())
+                    .unwrap_or_else(|| arg.clone())
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let signature = format!("{}:{}:{}", provider.name, self.name, arguments);
+        signature.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    // Merge the offsets of another instance of this probe, if it has the same arguments, and the
+    // offsets can all be represented relative to the lower of the two addresses.
+    fn merge(&mut self, other: Probe) {
+        if self.arguments != other.arguments {
+            return;
+        }
+        let address = self.address.min(other.address);
+        let rebase = |offsets: &[u32], base: u64| -> Option<Vec<u32>> {
+            offsets
+                .iter()
+                .map(|offset| u32::try_from(base - address + u64::from(*offset)).ok())
+                .collect()
+        };
+        let combine = |ours: &[u32], theirs: &[u32]| -> Option<Vec<u32>> {
+            let mut offsets = rebase(ours, self.address)?;
+            offsets.extend(rebase(theirs, other.address)?);
+            offsets.sort_unstable();
+            offsets.dedup();
+            Some(offsets)
+        };
+        if let (Some(offsets), Some(enabled_offsets)) = (
+            combine(&self.offsets, &other.offsets),
+            combine(&self.enabled_offsets, &other.enabled_offsets),
+        ) {
+            self.address = address;
+            self.offsets = offsets;
+            self.enabled_offsets = enabled_offsets;
+        }
+    }
+}
+
 /// Information about a single provider
 #[derive(Debug, Clone, Serialize)]
 pub struct Provider {
@@ -242,4 +399,202 @@ pub struct Provider {
     pub name: String,
     /// List of probes this provider exports
     pub probes: BTreeMap<String, Probe>,
+    /// The version string or build hash of the provider, if its probe records include one. This
+    /// is not stored in the DOF itself.
+    pub version_tag: Option<String>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Probe, Provider, Section};
+    use std::collections::BTreeMap;
+
+    fn section(providers: &[(&str, &[Probe])]) -> Section {
+        let providers = providers
+            .iter()
+            .map(|(name, probes)| {
+                let probes = probes
+                    .iter()
+                    .map(|probe| (probe.name.clone(), probe.clone()))
+                    .collect();
+                let provider = Provider {
+                    name: name.to_string(),
+                    probes,
+                    version_tag: None,
+                };
+                (name.to_string(), provider)
+            })
+            .collect::<BTreeMap<_, _>>();
+        Section {
+            providers,
+            ..Default::default()
+        }
+    }
+
+    fn probe(name: &str, address: u64, offsets: &[u32], arguments: &[&str]) -> Probe {
+        Probe {
+            name: name.to_string(),
+            function: String::from("func"),
+            module: String::new(),
+            address,
+            offsets: offsets.to_vec(),
+            enabled_offsets: vec![],
+            arguments: arguments.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_section_merge() {
+        let first = section(&[
+            (
+                "foo",
+                &[
+                    probe("shared", 0x1010, &[0, 0x10], &["uint8_t"]),
+                    probe("conflict", 0x3000, &[0], &["uint8_t"]),
+                ],
+            ),
+            ("bar", &[probe("only-first", 0x4000, &[0], &[])]),
+        ]);
+        let second = section(&[
+            (
+                "foo",
+                &[
+                    probe("shared", 0x1000, &[0, 0x20], &["uint8_t"]),
+                    probe("conflict", 0x5000, &[0], &["char *"]),
+                    probe("only-second", 0x6000, &[0], &[]),
+                ],
+            ),
+            ("baz", &[probe("only-second", 0x7000, &[0], &[])]),
+        ]);
+        let merged = first.merge(second);
+        assert_eq!(
+            merged.providers.keys().collect::<Vec<_>>(),
+            vec!["bar", "baz", "foo"]
+        );
+
+        let foo = &merged.providers["foo"].probes;
+        assert_eq!(
+            foo.keys().collect::<Vec<_>>(),
+            vec!["conflict", "only-second", "shared"]
+        );
+
+        // The offsets of the shared probe are combined, relative to the lower address, with the
+        // duplicate removed.
+        let shared = &foo["shared"];
+        assert_eq!(shared.address, 0x1000);
+        assert_eq!(shared.offsets, vec![0, 0x10, 0x20]);
+
+        // The probe with conflicting arguments is kept from the first section.
+        let conflict = &foo["conflict"];
+        assert_eq!(conflict.address, 0x3000);
+        assert_eq!(conflict.arguments, vec!["uint8_t"]);
+
+        assert_eq!(merged.providers["bar"].probes["only-first"].address, 0x4000);
+        assert_eq!(
+            merged.providers["baz"].probes["only-second"].address,
+            0x7000
+        );
+    }
+
+    #[test]
+    fn test_section_merge_modules() {
+        let in_module = |module: &str, address: u64| Probe {
+            module: module.to_string(),
+            ..probe("start", address, &[0], &["uint8_t"])
+        };
+        let first = section(&[("foo", &[in_module("a.out", 0x1000)])]);
+        let second = section(&[("foo", &[in_module("libfoo.so", 0x2000)])]);
+
+        // The probes of the same name in different modules are both kept, and merging the second
+        // section again combines its probe with that of the same module.
+        let merged = first.merge(second.clone()).merge(second);
+        let foo = &merged.providers["foo"].probes;
+        assert_eq!(
+            foo.keys().collect::<Vec<_>>(),
+            vec!["libfoo.so:start", "start"]
+        );
+        assert_eq!(foo["start"].module, "a.out");
+        assert_eq!(foo["start"].address, 0x1000);
+        assert_eq!(foo["start"].offsets, vec![0]);
+        assert_eq!(foo["libfoo.so:start"].name, "start");
+        assert_eq!(foo["libfoo.so:start"].module, "libfoo.so");
+        assert_eq!(foo["libfoo.so:start"].address, 0x2000);
+        assert_eq!(foo["libfoo.so:start"].offsets, vec![0]);
+    }
+
+    #[test]
+    fn test_section_is_empty() {
+        assert!(Section::default().is_empty());
+        assert!(section(&[("foo", &[])]).is_empty());
+        assert!(!section(&[("foo", &[]), ("bar", &[probe("baz", 0x1000, &[0], &[])])]).is_empty());
+    }
+
+    #[test]
+    fn test_section_by_function() {
+        let in_function = |name: &str, function: &str| Probe {
+            function: function.to_string(),
+            ..probe(name, 0x1000, &[0], &[])
+        };
+        let section = section(&[
+            (
+                "foo",
+                &[in_function("start", "handle"), in_function("idle", "wait")],
+            ),
+            ("bar", &[in_function("done", "handle")]),
+        ]);
+        let functions = section.by_function();
+        assert_eq!(functions.keys().collect::<Vec<_>>(), vec!["handle", "wait"]);
+        let names = |function: &str| {
+            functions[function]
+                .iter()
+                .map(|probe| probe.name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("handle"), vec!["done", "start"]);
+        assert_eq!(names("wait"), vec!["idle"]);
+    }
+
+    #[test]
+    fn test_probe_stable_id() {
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: BTreeMap::new(),
+            version_tag: None,
+        };
+        let original = probe("work", 0x1000, &[0, 0x10], &["uint8_t", "char*"]);
+        let id = original.stable_id(&provider);
+
+        // The hash of the signature "foo:work:uint8_t,char*".
+        assert_eq!(id, 0x00ae_47a0_6bac_6c35);
+
+        let moved = probe("work", 0x8000, &[0x40], &["uint8_t", "char *"]);
+        assert_eq!(moved.stable_id(&provider), id);
+
+        let retyped = probe("work", 0x1000, &[0, 0x10], &["uint16_t", "char*"]);
+        assert_ne!(retyped.stable_id(&provider), id);
+        let renamed = probe("rest", 0x1000, &[0, 0x10], &["uint8_t", "char*"]);
+        assert_ne!(renamed.stable_id(&provider), id);
+        let other = Provider {
+            name: String::from("bar"),
+            probes: BTreeMap::new(),
+            version_tag: None,
+        };
+        assert_ne!(original.stable_id(&other), id);
+    }
+
+    #[test]
+    fn test_probe_argument_rust_types() {
+        let probe = probe(
+            "mixed",
+            0x1000,
+            &[0],
+            &[
+                "uint8_t", "int64_t*", "char*", "double", "hrtime_t", "void*",
+            ],
+        );
+        assert_eq!(
+            probe.argument_rust_types(),
+            &["u8", "*const i64", "&str", "f64", "i64", "void*"]
+        );
+    }
 }
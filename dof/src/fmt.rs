@@ -92,6 +92,67 @@ fn fmt_dof_sec_type<T: Debug + FromBytes + Copy>(data: &[u8]) -> String {
         .join("\n")
 }
 
+// The column of argument types included in a probe listing.
+#[derive(Clone, Copy, PartialEq)]
+enum ArgumentColumn {
+    Omitted,
+    CTypes,
+    RustTypes,
+}
+
+// Format all probes in a list of sections as a table, with columns for each component of the
+// probe's full name. This mimics the output of `dtrace -l`.
+//
+// `arguments` selects whether a column listing the types of each probe's arguments is included,
+// and whether those are the stored C types or their Rust equivalents.
+fn fmt_probe_list(sections: &[Section], arguments: ArgumentColumn) -> String {
+    let rows = sections
+        .iter()
+        .flat_map(|section| section.providers.values())
+        .flat_map(|provider| {
+            provider.probes.values().map(move |probe| {
+                let mut row = vec![
+                    provider.name.clone(),
+                    probe.module.clone(),
+                    probe.function.clone(),
+                    probe.name.clone(),
+                ];
+                match arguments {
+                    ArgumentColumn::Omitted => {}
+                    ArgumentColumn::CTypes => row.push(probe.arguments.join(", ")),
+                    ArgumentColumn::RustTypes => row.push(probe.argument_rust_types().join(", ")),
+                }
+                row
+            })
+        })
+        .collect::<Vec<_>>();
+    if rows.is_empty() {
+        return String::new();
+    }
+    let mut header = ["PROVIDER", "MODULE", "FUNCTION", "NAME"]
+        .map(String::from)
+        .to_vec();
+    if arguments != ArgumentColumn::Omitted {
+        header.push(String::from("ARGUMENTS"));
+    }
+    let mut widths = header.iter().map(String::len).collect::<Vec<_>>();
+    for row in rows.iter() {
+        for (width, column) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(column.len());
+        }
+    }
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let (last, columns) = row.split_last().expect("Rows are never empty");
+        for (column, width) in columns.iter().zip(widths.iter()) {
+            out.push_str(&format!("{:<width$} ", column, width = width));
+        }
+        out.push_str(last);
+        out.push('\n');
+    }
+    out
+}
+
 /// Controls how DOF data is formatted
 #[derive(Clone, Copy)]
 pub enum FormatMode {
@@ -99,6 +160,14 @@ pub enum FormatMode {
     Pretty,
     // Emit Rust types as json for parsing
     Json,
+    /// Emit one line per probe, in the style of `dtrace -l`
+    List,
+    /// Emit one line per probe, in the style of `dtrace -l`, followed by its argument types
+    ListArguments {
+        /// If true, the Rust equivalent of each argument's type is printed, e.g., `u8` for
+        /// `uint8_t`. If false, the C types stored with the probes are printed.
+        rust_types: bool,
+    },
     /// Emit underlying DOF C types
     Raw {
         /// If true, the DOF section data is included, along with the section headers.
@@ -116,7 +185,7 @@ pub fn fmt_dof(sections: Vec<Section>, format: FormatMode) -> Result<Option<Stri
         FormatMode::Raw { include_sections } => {
             for section in sections.iter() {
                 let RawSections { header, sections } =
-                    crate::des::deserialize_raw_sections(&section.as_bytes().as_slice())?;
+                    crate::des::deserialize_raw_sections(section.as_bytes().as_slice())?;
                 out.push_str(&format!("{:#?}\n", header));
                 for (index, (section_header, data)) in sections.into_iter().enumerate() {
                     out.push_str(&format!("{}\n", fmt_dof_sec(&section_header, index)));
@@ -131,6 +200,15 @@ pub fn fmt_dof(sections: Vec<Section>, format: FormatMode) -> Result<Option<Stri
                 out.push_str(section.to_json().as_str());
             }
         }
+        FormatMode::List => out.push_str(&fmt_probe_list(&sections, ArgumentColumn::Omitted)),
+        FormatMode::ListArguments { rust_types } => {
+            let arguments = if rust_types {
+                ArgumentColumn::RustTypes
+            } else {
+                ArgumentColumn::CTypes
+            };
+            out.push_str(&fmt_probe_list(&sections, arguments))
+        }
         FormatMode::Pretty => {
             for section in sections.iter() {
                 out.push_str(&format!("{:#?}\n", section));
@@ -144,3 +222,50 @@ pub fn fmt_dof(sections: Vec<Section>, format: FormatMode) -> Result<Option<Stri
         Ok(Some(out))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{fmt_dof, FormatMode};
+    use crate::{Probe, Provider, Section};
+
+    fn section() -> Section {
+        let probe = Probe {
+            name: String::from("start-work"),
+            function: String::from("main"),
+            module: String::from("app"),
+            address: 0x1000,
+            offsets: vec![0],
+            enabled_offsets: vec![],
+            arguments: vec![String::from("uint8_t"), String::from("char*")],
+        };
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: [(probe.name.clone(), probe)].into_iter().collect(),
+            version_tag: None,
+        };
+        Section {
+            providers: [(provider.name.clone(), provider)].into_iter().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fmt_probe_list_arguments() {
+        let format = |mode| fmt_dof(vec![section()], mode).unwrap().unwrap();
+        assert_eq!(
+            format(FormatMode::List),
+            "PROVIDER MODULE FUNCTION NAME\n\
+             foo      app    main     start-work\n"
+        );
+        assert_eq!(
+            format(FormatMode::ListArguments { rust_types: false }),
+            "PROVIDER MODULE FUNCTION NAME       ARGUMENTS\n\
+             foo      app    main     start-work uint8_t, char*\n"
+        );
+        assert_eq!(
+            format(FormatMode::ListArguments { rust_types: true }),
+            "PROVIDER MODULE FUNCTION NAME       ARGUMENTS\n\
+             foo      app    main     start-work u8, &str\n"
+        );
+    }
+}
@@ -51,7 +51,8 @@ pub mod ser;
 
 #[cfg(feature = "des")]
 pub use crate::des::{
-    collect_dof_sections, deserialize_section, extract_dof_sections, is_dof_section,
+    collect_dof_sections, deserialize_section, extract_dof_sections,
+    extract_dof_sections_from_bytes, is_dof_section,
 };
 pub use crate::dof::*;
 pub use crate::ser::serialize_section;
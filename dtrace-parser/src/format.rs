@@ -0,0 +1,395 @@
+//! Formatting of D provider files in a canonical style.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{parse_file_tokens, DTraceError, File, Rule};
+use pest::iterators::Pair;
+use std::convert::TryFrom;
+
+/// Format the source of a D provider file in a canonical style.
+///
+/// Each provider is written with its probes indented by a tab, one per line, and each attribute
+/// on its own line. Any attributes following the closing brace of a provider are moved before it,
+/// after those preceding it. Stray semicolons, groups of probes, and any name following the
+/// closing brace are removed. Typedefs and pragmas are kept in place.
+///
+/// Types are spelled consistently whatever the whitespace in the source. Strings and other
+/// pointers are written with a space before the stars, e.g., `char *` and `struct conn **`, while
+/// pointers to integers are written without one, e.g., `uint8_t*`, as only that spelling is read
+/// as an integer pointer.
+///
+/// Comments between the items of the file, or between the probes of a provider, are kept on their
+/// own lines before the following item or probe. A comment anywhere else, such as within the
+/// arguments of a probe, is an error, as is an invalid provider file. Formatting the result again
+/// leaves it unchanged.
+pub fn format_source(source: &str) -> Result<String, DTraceError> {
+    File::try_from(source)?;
+    let file = parse_file_tokens(source)?
+        .next()
+        .ok_or(DTraceError::EmptyPairsIterator)?;
+    let mut out = String::new();
+    let mut previous = None;
+    let mut position = file.as_span().start();
+    for item in file.into_inner() {
+        let rule = item.as_rule();
+        let comments = comments_between(&source[position..item.as_span().start()]);
+        position = item.as_span().end();
+        let formatted = match rule {
+            Rule::PRAGMA => item.as_str().trim().to_string(),
+            Rule::TYPEDEF => {
+                check_no_comments(source, &item)?;
+                format_typedef(&item)
+            }
+            Rule::PROVIDER => format_provider(source, &item)?,
+            // Comments at the end of the file follow the last item.
+            Rule::EOI if !comments.is_empty() => String::new(),
+            _ => continue,
+        };
+        // Consecutive pragmas or typedefs are kept together, and any other items are separated
+        // by a blank line.
+        match previous {
+            Some(previous) if previous == rule && rule != Rule::PROVIDER => {}
+            Some(_) => out.push('\n'),
+            None => {}
+        }
+        for comment in comments {
+            out.push_str(comment);
+            out.push('\n');
+        }
+        if !formatted.is_empty() {
+            out.push_str(&formatted);
+            out.push('\n');
+        }
+        previous = Some(rule);
+    }
+    Ok(out)
+}
+
+// Return the comments in the source between two tokens, which otherwise contains only whitespace
+// and punctuation.
+fn comments_between(text: &str) -> Vec<&str> {
+    let mut comments = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("/*") {
+        let end = rest[start + 2..]
+            .find("*/")
+            .map_or(rest.len(), |end| start + end + 4);
+        comments.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    comments
+}
+
+// Return an error if there's a comment between any of the tokens of a declaration, where it
+// can't be kept.
+fn check_no_comments(source: &str, pair: &Pair<'_, Rule>) -> Result<(), DTraceError> {
+    let mut position = None;
+    for inner in pair.clone().into_inner() {
+        let start = position.unwrap_or(pair.as_span().start());
+        check_no_comments_between(&source[start..inner.as_span().start()])?;
+        check_no_comments(source, &inner)?;
+        position = Some(inner.as_span().end());
+    }
+    // Tokens without inner tokens, such as strings, are taken as they are.
+    match position {
+        Some(position) => check_no_comments_between(&source[position..pair.as_span().end()]),
+        None => Ok(()),
+    }
+}
+
+fn check_no_comments_between(text: &str) -> Result<(), DTraceError> {
+    match comments_between(text).first() {
+        Some(comment) => Err(DTraceError::CommentInDeclaration(comment.to_string())),
+        None => Ok(()),
+    }
+}
+
+// Format a `TYPEDEF` token.
+fn format_typedef(pair: &Pair<'_, Rule>) -> String {
+    let mut inner = pair.clone().into_inner().skip(1);
+    let typ = inner.next().expect("Expected a data type");
+    let name = inner.next().expect("Expected a typedef name");
+    format!("typedef {} {};", format_data_type(&typ), name.as_str())
+}
+
+// Format a `PROVIDER` token, including the attributes preceding it, and any comments between its
+// probes.
+fn format_provider(source: &str, pair: &Pair<'_, Rule>) -> Result<String, DTraceError> {
+    let mut out = String::new();
+    let mut name = "";
+    let mut body = String::new();
+    let mut position = pair.as_span().start();
+    for item in pair.clone().into_inner() {
+        // Only the comments before a probe or the closing brace are kept.
+        let gap = &source[position..item.as_span().start()];
+        position = item.as_span().end();
+        match item.as_rule() {
+            Rule::PROBE | Rule::RIGHT_BRACE => {
+                for comment in comments_between(gap) {
+                    body.push('\t');
+                    body.push_str(comment);
+                    body.push('\n');
+                }
+            }
+            _ => check_no_comments_between(gap)?,
+        }
+        match item.as_rule() {
+            Rule::ATTRIBUTE => {
+                check_no_comments(source, &item)?;
+                out.push_str(&format_attribute(&item));
+                out.push('\n');
+            }
+            // Attributes following the provider are written before it, after any others.
+            Rule::ATTRIBUTE_LIST => {
+                check_no_comments(source, &item)?;
+                for attribute in item.into_inner() {
+                    out.push_str(&format!("#[{}]\n", format_attribute_item(&attribute)));
+                }
+            }
+            Rule::IDENTIFIER => name = item.as_str(),
+            Rule::PROBE => {
+                check_no_comments(source, &item)?;
+                body.push_str(&format_probe(&item));
+            }
+            _ => {}
+        }
+    }
+    out.push_str(&format!("provider {} {{\n", name));
+    out.push_str(&body);
+    out.push_str("};");
+    Ok(out)
+}
+
+// Format a `PROBE` token, including the attributes preceding it, as indented lines.
+fn format_probe(pair: &Pair<'_, Rule>) -> String {
+    let mut out = String::new();
+    let mut name = "";
+    let mut arguments = Vec::new();
+    for item in pair.clone().into_inner() {
+        match item.as_rule() {
+            Rule::ATTRIBUTE => out.push_str(&format!("\t{}\n", format_attribute(&item))),
+            Rule::PROBE_IDENTIFIER => name = item.as_str(),
+            Rule::ARGUMENT_LIST => {
+                arguments = item
+                    .into_inner()
+                    .map(|argument| match argument.as_rule() {
+                        Rule::ELLIPSIS => String::from("..."),
+                        _ => format_data_type(&argument),
+                    })
+                    .collect();
+            }
+            _ => {}
+        }
+    }
+    out.push_str(&format!("\tprobe {}({});\n", name, arguments.join(", ")));
+    out
+}
+
+// Format an `ATTRIBUTE` token.
+fn format_attribute(pair: &Pair<'_, Rule>) -> String {
+    let item = pair
+        .clone()
+        .into_inner()
+        .next()
+        .expect("Expected an attribute item");
+    format!("#[{}]", format_attribute_item(&item))
+}
+
+// Format an `ATTRIBUTE_ITEM` token.
+fn format_attribute_item(pair: &Pair<'_, Rule>) -> String {
+    let mut inner = pair.clone().into_inner();
+    let name = inner.next().expect("Expected an attribute name").as_str();
+    match inner.next() {
+        None => name.to_string(),
+        Some(value) if value.as_rule() == Rule::ATTRIBUTE_LIST => {
+            let items = value
+                .into_inner()
+                .map(|item| format_attribute_item(&item))
+                .collect::<Vec<_>>();
+            format!("{}({})", name, items.join(", "))
+        }
+        Some(value) => format!("{} = {}", name, value.as_str()),
+    }
+}
+
+// Format a `DATA_TYPE` or `FORMAT_STRING` token, as the type is written rather than resolved, so
+// that typedef names and array lengths are kept.
+fn format_data_type(pair: &Pair<'_, Rule>) -> String {
+    let mut out = String::new();
+    for part in pair.clone().into_inner() {
+        let formatted = match part.as_rule() {
+            Rule::CONST => String::from("const"),
+            // The grammar doesn't allow whitespace within a pointer to an integer.
+            Rule::INTEGER_POINTER => format!(
+                "{}*",
+                part.into_inner()
+                    .next()
+                    .expect("Expected an integral type")
+                    .as_str()
+            ),
+            Rule::STRING => String::from("char *"),
+            Rule::WIDE_STRING => String::from("wchar_t *"),
+            Rule::INTEGER_ARRAY => {
+                let mut inner = part.into_inner();
+                let integer = inner.next().expect("Expected an integral type");
+                let length = inner.next().expect("Expected an array length");
+                format!("{}[{}]", integer.as_str(), length.as_str())
+            }
+            Rule::OPAQUE_POINTER => {
+                let text = part.as_str();
+                let (base, stars) = text.split_at(text.find('*').unwrap_or(text.len()));
+                let base = base.split_whitespace().collect::<Vec<_>>().join(" ");
+                let stars = stars.chars().filter(|ch| *ch == '*').collect::<String>();
+                format!("{} {}", base, stars)
+            }
+            _ => part
+                .as_str()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+        if !out.is_empty() {
+            out.push(' ');
+        }
+        out.push_str(&formatted);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_source;
+
+    #[test]
+    fn test_format_source() {
+        let source = r#"
+#pragma D option quiet
+typedef   uint16_t port_t ;
+typedef port_t listen_port_t;
+/* The provider */
+#[ stability( evolving ,unstable) ]
+provider foo {
+    ;
+    probe begin (  ) ;
+    #[deprecated(note="use begin")] probe gc-begin(uint8_t*,char*, const   char *,);
+    {
+        probe gc__end(uint8_t[16], struct  conn  * *, wchar_t*, port_t);
+    }
+    probe log(char *, ...);
+} foo (private, since = "1.2");
+provider bar { probe baz(float, double, hrtime_t); };
+"#;
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            r#"#pragma D option quiet
+
+typedef uint16_t port_t;
+typedef port_t listen_port_t;
+
+/* The provider */
+#[stability(evolving, unstable)]
+#[private]
+#[since = "1.2"]
+provider foo {
+	probe begin();
+	#[deprecated(note = "use begin")]
+	probe gc-begin(uint8_t*, char *, const char *);
+	probe gc__end(uint8_t[16], struct conn **, wchar_t *, port_t);
+	probe log(char *, ...);
+};
+
+provider bar {
+	probe baz(float, double, hrtime_t);
+};
+"#
+        );
+
+        // Formatting is idempotent, and doesn't change the definition.
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+        assert_eq!(
+            crate::File::try_from(formatted.as_str()).unwrap(),
+            crate::File::try_from(source).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_format_source_comments() {
+        let source = r#"/* The license */
+#pragma D option quiet
+/* Ports */ typedef uint16_t port_t;
+provider foo { /* Before the first probe */
+    probe begin();
+    /*
+     * Spanning lines
+     */
+    probe end(port_t); /* After the last probe */
+};
+/* The end */
+"#;
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            r#"/* The license */
+#pragma D option quiet
+
+/* Ports */
+typedef uint16_t port_t;
+
+provider foo {
+	/* Before the first probe */
+	probe begin();
+	/*
+     * Spanning lines
+     */
+	probe end(port_t);
+	/* After the last probe */
+};
+
+/* The end */
+"#
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+
+        // Comments within a declaration can't be kept.
+        for source in [
+            "provider foo { probe bar(uint8_t /* count */); };",
+            "provider foo { #[deprecated] /* probe */ probe bar(); };",
+            "provider foo { probe bar(); } (private /* trailing */);",
+            "provider /* name */ foo { probe bar(); };",
+            "provider foo { probe bar(); } /* trailing */ foo;",
+            "typedef uint16_t /* port */ port_t;",
+        ] {
+            assert!(
+                matches!(
+                    format_source(source),
+                    Err(crate::DTraceError::CommentInDeclaration(_))
+                ),
+                "{}",
+                source
+            );
+        }
+
+        // Comment delimiters within strings are kept as they are.
+        let source = "provider foo {\n\t#[doc = \"/* not a comment */\"]\n\tprobe bar();\n};\n";
+        assert_eq!(format_source(source).unwrap(), source);
+    }
+
+    #[test]
+    fn test_format_source_invalid() {
+        assert!(format_source("provider foo { probe bar(uint8_t) };").is_err());
+        assert!(format_source("provider foo { probe bar(); probe bar(); };").is_err());
+    }
+}
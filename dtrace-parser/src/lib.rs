@@ -16,12 +16,15 @@
 
 use pest::iterators::{Pair, Pairs};
 use pest_derive::Parser;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fs;
 use std::path::Path;
 use thiserror::Error;
 
+mod format;
+pub use format::format_source;
+
 type PestError = pest::error::Error<Rule>;
 
 /// Type representing errors that occur when parsing a D file.
@@ -33,16 +36,54 @@ pub enum DTraceError {
     EmptyPairsIterator,
     #[error("Provider and probe name pairs must be unique: duplicated \"{0:?}\"")]
     DuplicateProbeName((String, String)),
+    #[error(
+        "Probe names \"{0}\" and \"{1}\" map to the same Rust identifier or DTrace probe name"
+    )]
+    CollidingProbeName(String, String),
     #[error("The provider name \"{0}\" is invalid")]
     InvalidProviderName(String),
+    #[error("The name \"{1}\" following the provider \"{0}\" doesn't match it")]
+    MismatchedProviderName(String, String),
     #[error("The probe name \"{0}\" is invalid")]
     InvalidProbeName(String),
+    #[error("The array length {0} is invalid, arrays must have between 1 and {max} elements", max = MAX_ARRAY_LEN)]
+    InvalidArrayLength(String),
+    #[error("The integer type \"{0}\" is invalid, integers must be 8, 16, 32 or 64 bits wide")]
+    InvalidIntegerType(String),
+    #[error("The type \"{0}\" is not supported")]
+    UnsupportedType(String),
+    #[error("Conditional compilation with the attribute \"{0}\" is not supported")]
+    UnsupportedAnnotation(String),
+    #[error("The typedef name \"{0}\" is invalid, typedef names must end in \"_t\"")]
+    InvalidTypedefName(String),
+    #[error("The type \"{typ}\" of an argument of the probe \"{probe}\" is not supported")]
+    UnsupportedArgumentType { probe: String, typ: String },
+    #[error("The comment \"{0}\" is within a declaration, where it can't be formatted")]
+    CommentInDeclaration(String),
     #[error(transparent)]
     IO(#[from] std::io::Error),
     #[error("Input is not a valid DTrace provider definition:\n{0}")]
     ParseError(#[from] Box<PestError>),
 }
 
+impl DTraceError {
+    /// Return the 1-based line and column of a syntax error, and a short description of it.
+    ///
+    /// Other errors aren't tied to a location in the source, and return `None`.
+    pub fn syntax_error(&self) -> Option<(usize, usize, String)> {
+        match self {
+            DTraceError::ParseError(error) => {
+                let (line, column) = match error.line_col {
+                    pest::error::LineColLocation::Pos(pos) => pos,
+                    pest::error::LineColLocation::Span(start, _) => start,
+                };
+                Some((line, column, error.variant.message().to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
 #[derive(Parser, Debug)]
 #[grammar = "dtrace.pest"]
 struct DTraceParser;
@@ -86,6 +127,92 @@ pub struct Integer {
 
 const RUST_TYPE_PREFIX: &str = "::std::os::raw::c_";
 
+// The integer type by which a `DataType::Opaque` argument is passed.
+const OPAQUE_INTEGER: Integer = Integer {
+    sign: Sign::Unsigned,
+    width: BitWidth::Pointer,
+};
+
+/// The maximum number of elements in an array argument.
+///
+/// The elements of arrays are copied each time a probe fires, and must be copied in again by
+/// consumers, so their length is bounded to keep probe arguments small.
+pub const MAX_ARRAY_LEN: usize = 256;
+
+/// The number of integer arguments following the format string of a printf-style probe.
+///
+/// A probe declared as `probe log(char *, ...);` takes a format string and this many `uintptr_t`
+/// arguments, which fills the arguments passed to DTrace in registers.
+pub const MAX_PRINTF_ARGS: usize = 5;
+
+/// Expand to the list of types which may be used as probe arguments, as a Markdown list.
+///
+/// This is the single source of the list given in the error for an unsupported type, and in the
+/// docs of the `dtrace_provider!` macro, e.g., `#[doc = dtrace_parser::supported_types!()]`.
+#[macro_export]
+macro_rules! supported_types {
+    () => {
+        concat!(
+            "- Integers of specific bit-width, e.g., `uint16_t` or `int64_t`, and `uintptr_t` and ",
+            "`intptr_t`\n",
+            "- Pointers to the above integer types, e.g., `uint8_t *`\n",
+            "- `char *`, passed from Rust as a `&str`\n",
+            "- `wchar_t *`, which is treated exactly as `char *`\n",
+            "- `float` and `double`\n",
+            "- `hrtime_t`, a timestamp in nanoseconds\n",
+            "- Fixed-length arrays of the above integer types, e.g., `uint8_t[16]`, with at most ",
+            "256 elements\n",
+            "- Standard POSIX typedefs, e.g., `pid_t`, and typedefs declared in the D file or ",
+            "given with `types`, whose names must end in `_t`\n",
+            "- Other pointers and typedefs, e.g., `void *`, which are passed as a `uintptr_t`\n",
+        )
+    };
+}
+
+const fn int(sign: Sign, width: BitWidth) -> DataType {
+    DataType::Integer(Integer { sign, width })
+}
+
+/// The standard POSIX and illumos typedefs which may be used as argument types without being
+/// declared, and the types they resolve to.
+///
+/// A `typedef` of the same name in a D file takes precedence over these.
+pub const BUILTIN_TYPEDEFS: &[(&str, DataType)] = &[
+    ("pid_t", int(Sign::Signed, BitWidth::Bit32)),
+    ("uid_t", int(Sign::Unsigned, BitWidth::Bit32)),
+    ("gid_t", int(Sign::Unsigned, BitWidth::Bit32)),
+    ("id_t", int(Sign::Signed, BitWidth::Bit32)),
+    ("zoneid_t", int(Sign::Signed, BitWidth::Bit32)),
+    ("projid_t", int(Sign::Signed, BitWidth::Bit32)),
+    ("taskid_t", int(Sign::Signed, BitWidth::Bit32)),
+    ("processorid_t", int(Sign::Signed, BitWidth::Bit32)),
+    ("clockid_t", int(Sign::Signed, BitWidth::Bit32)),
+    ("mode_t", int(Sign::Unsigned, BitWidth::Bit32)),
+    ("off_t", int(Sign::Signed, BitWidth::Bit64)),
+    ("dev_t", int(Sign::Unsigned, BitWidth::Bit64)),
+    ("ino_t", int(Sign::Unsigned, BitWidth::Bit64)),
+    ("time_t", int(Sign::Signed, BitWidth::Bit64)),
+    ("size_t", int(Sign::Unsigned, BitWidth::Pointer)),
+    ("ssize_t", int(Sign::Signed, BitWidth::Pointer)),
+];
+
+// Typedefs declared in a D file, which are looked up before `BUILTIN_TYPEDEFS`.
+type Typedefs = BTreeMap<String, DataType>;
+
+// Return the type a typedef resolves to, or `DataType::Opaque` if it's unknown.
+fn resolve_typedef(name: &str, typedefs: &Typedefs) -> DataType {
+    typedefs
+        .get(name)
+        .copied()
+        .or_else(|| {
+            BUILTIN_TYPEDEFS
+                .iter()
+                .find(|(builtin, _)| *builtin == name)
+                .map(|(_, typ)| *typ)
+        })
+        .unwrap_or(DataType::Opaque)
+}
+
 impl Integer {
     fn width_to_c_str(&self) -> &'static str {
         match self.width {
@@ -157,6 +284,18 @@ pub enum DataType {
     Integer(Integer),
     Pointer(Integer),
     String,
+    /// A 32-bit floating point number, `float`.
+    Float,
+    /// A 64-bit floating point number, `double`.
+    Double,
+    /// A fixed-length array of integers, e.g., `uint8_t[16]`, whose elements are copied when the
+    /// probe fires.
+    Array(Integer, usize),
+    /// A high-resolution timestamp, `hrtime_t`, which is a signed 64-bit count of nanoseconds.
+    HrTime,
+    /// A pointer, or typedef, of a type which isn't otherwise supported, e.g., `void *` or a
+    /// function pointer typedef such as `callback_t`. This is passed as a pointer-sized integer.
+    Opaque,
 }
 
 impl From<Pair<'_, Rule>> for Integer {
@@ -182,20 +321,23 @@ impl TryFrom<&Pair<'_, Rule>> for DataType {
     type Error = DTraceError;
 
     fn try_from(pair: &Pair<'_, Rule>) -> Result<DataType, Self::Error> {
+        DataType::from_pair(pair, &Typedefs::new())
+    }
+}
+
+impl DataType {
+    // Convert a `DATA_TYPE` token, resolving any typedef with the given declared typedefs.
+    fn from_pair(pair: &Pair<'_, Rule>, typedefs: &Typedefs) -> Result<DataType, DTraceError> {
         expect_token(pair, Rule::DATA_TYPE)?;
+        // Skip any leading `const` qualifier.
         let inner = pair
             .clone()
             .into_inner()
-            .next()
+            .find(|pair| pair.as_rule() != Rule::CONST)
             .expect("Data type token is expected to contain a concrete type");
         let typ = match inner.as_rule() {
             Rule::INTEGER => {
-                let integer = pair
-                    .clone()
-                    .into_inner()
-                    .next()
-                    .expect("Expected a signed or unsigned integral type");
-                assert!(matches!(integer.as_rule(), Rule::INTEGER));
+                let integer = inner.clone();
                 DataType::Integer(Integer::from(
                     integer
                         .clone()
@@ -205,12 +347,7 @@ impl TryFrom<&Pair<'_, Rule>> for DataType {
                 ))
             }
             Rule::INTEGER_POINTER => {
-                let pointer = pair
-                    .clone()
-                    .into_inner()
-                    .next()
-                    .expect("Expected a pointer to a signed or unsigned integral type");
-                assert!(matches!(pointer.as_rule(), Rule::INTEGER_POINTER));
+                let pointer = inner.clone();
                 let mut parts = pointer.clone().into_inner();
                 let integer = parts
                     .next()
@@ -225,8 +362,37 @@ impl TryFrom<&Pair<'_, Rule>> for DataType {
                         .expect("Expected an integral type"),
                 ))
             }
-            Rule::STRING => DataType::String,
-            _ => unreachable!("Parsed an unexpected DATA_TYPE token"),
+            Rule::INTEGER_ARRAY => {
+                let mut parts = inner.clone().into_inner();
+                let integer = parts
+                    .next()
+                    .expect("Expected a signed or unsigned integral type");
+                let length = parts.next().expect("Expected an array length").as_str();
+                let len = length
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|len| (1..=MAX_ARRAY_LEN).contains(len))
+                    .ok_or_else(|| DTraceError::InvalidArrayLength(length.to_string()))?;
+                DataType::Array(
+                    Integer::from(
+                        integer
+                            .into_inner()
+                            .next()
+                            .expect("Expected an integral type"),
+                    ),
+                    len,
+                )
+            }
+            Rule::STRING | Rule::WIDE_STRING => DataType::String,
+            Rule::FLOAT => DataType::Float,
+            Rule::DOUBLE => DataType::Double,
+            Rule::HRTIME => DataType::HrTime,
+            Rule::INVALID_INTEGER => {
+                return Err(DTraceError::InvalidIntegerType(inner.as_str().to_string()))
+            }
+            Rule::OPAQUE_POINTER => DataType::Opaque,
+            Rule::TYPEDEF_NAME => resolve_typedef(inner.as_str(), typedefs),
+            _ => return Err(DTraceError::UnsupportedType(inner.as_str().to_string())),
         };
         Ok(typ)
     }
@@ -241,12 +407,57 @@ impl TryFrom<&Pairs<'_, Rule>> for DataType {
 }
 
 impl DataType {
+    /// Parse a type from its C type representation, such as `uint8_t` or `char*`.
+    ///
+    /// This accepts any type which may appear in the argument list of a probe, e.g., the argument
+    /// types stored in the probe records of an object file. `None` is returned for any other type,
+    /// including those which are only accepted as [`DataType::Opaque`].
+    pub fn from_c_type(typ: &str) -> Option<DataType> {
+        use pest::Parser;
+        let typ = typ.trim();
+        let pair = DTraceParser::parse(Rule::DATA_TYPE, typ).ok()?.next()?;
+        if pair.as_str() != typ {
+            return None;
+        }
+        DataType::try_from(&pair)
+            .ok()
+            .filter(|typ| *typ != DataType::Opaque)
+    }
+
+    /// Parse a type from the name of the equivalent Rust type, such as `u8` or `&str`.
+    ///
+    /// This accepts the primitive integer and floating point types, and `&str` for strings.
+    pub fn from_rust_type(typ: &str) -> Option<DataType> {
+        let integer = |sign, width| Some(DataType::Integer(Integer { sign, width }));
+        match typ.trim() {
+            "u8" => integer(Sign::Unsigned, BitWidth::Bit8),
+            "u16" => integer(Sign::Unsigned, BitWidth::Bit16),
+            "u32" => integer(Sign::Unsigned, BitWidth::Bit32),
+            "u64" => integer(Sign::Unsigned, BitWidth::Bit64),
+            "usize" => integer(Sign::Unsigned, BitWidth::Pointer),
+            "i8" => integer(Sign::Signed, BitWidth::Bit8),
+            "i16" => integer(Sign::Signed, BitWidth::Bit16),
+            "i32" => integer(Sign::Signed, BitWidth::Bit32),
+            "i64" => integer(Sign::Signed, BitWidth::Bit64),
+            "isize" => integer(Sign::Signed, BitWidth::Pointer),
+            "f32" => Some(DataType::Float),
+            "f64" => Some(DataType::Double),
+            "&str" => Some(DataType::String),
+            _ => None,
+        }
+    }
+
     /// Convert a type into its C type represenation as a string
     pub fn to_c_type(&self) -> String {
         match self {
             DataType::Integer(int) => int.to_c_type(),
             DataType::Pointer(int) => format!("{}*", int.to_c_type()),
             DataType::String => String::from("char*"),
+            DataType::Float => String::from("float"),
+            DataType::Double => String::from("double"),
+            DataType::Array(int, _) => format!("{}*", int.to_c_type()),
+            DataType::HrTime => String::from("hrtime_t"),
+            DataType::Opaque => OPAQUE_INTEGER.to_c_type(),
         }
     }
 
@@ -254,8 +465,14 @@ impl DataType {
     pub fn to_rust_ffi_type(&self) -> String {
         match self {
             DataType::Integer(int) => int.to_rust_ffi_type(),
-            DataType::Pointer(int) => format!("*const {}", int.to_rust_ffi_type()),
+            DataType::Pointer(int) | DataType::Array(int, _) => {
+                format!("*const {}", int.to_rust_ffi_type())
+            }
             DataType::String => format!("*const {RUST_TYPE_PREFIX}char"),
+            DataType::Float => format!("{RUST_TYPE_PREFIX}float"),
+            DataType::Double => format!("{RUST_TYPE_PREFIX}double"),
+            DataType::HrTime => String::from("i64"),
+            DataType::Opaque => OPAQUE_INTEGER.to_rust_ffi_type(),
         }
     }
 
@@ -265,32 +482,140 @@ impl DataType {
             DataType::Integer(int) => int.to_rust_type(),
             DataType::Pointer(int) => format!("*const {}", int.to_rust_type()),
             DataType::String => String::from("&str"),
+            DataType::Float => String::from("f32"),
+            DataType::Double => String::from("f64"),
+            DataType::Array(int, len) => format!("[{}; {}]", int.to_rust_type(), len),
+            DataType::HrTime => String::from("i64"),
+            DataType::Opaque => OPAQUE_INTEGER.to_rust_type(),
+        }
+    }
+}
+
+/// An attribute of a provider or probe, written like a Rust attribute, e.g.,
+/// `#[stability(evolving)]` or `#[cfg(target_os = "illumos")]`.
+///
+/// Attributes are parsed into this generic form, so that annotations such as stability levels
+/// share a single syntax. They don't affect the probes. Conditional compilation can't be
+/// expressed in a provider file, so the `cfg` and `cfg_attr` attributes are rejected, rather than
+/// being parsed and then ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Annotation {
+    /// A bare word, e.g., `evolving`.
+    Word(String),
+    /// A name and a value, e.g., `target_os = "illumos"`, with any quotes removed from the value.
+    NameValue(String, String),
+    /// A name and a list of nested items, e.g., `any(unix, windows)`.
+    List(String, Vec<Annotation>),
+}
+
+impl Annotation {
+    /// Return the name of the annotation, e.g., `cfg` for `cfg(unix)`.
+    pub fn name(&self) -> &str {
+        match self {
+            Annotation::Word(name) | Annotation::NameValue(name, _) | Annotation::List(name, _) => {
+                name
+            }
+        }
+    }
+
+    // Convert an `ATTRIBUTE_ITEM` token.
+    fn from_pair(pair: &Pair<'_, Rule>) -> Result<Self, DTraceError> {
+        expect_token(pair, Rule::ATTRIBUTE_ITEM)?;
+        let mut inner = pair.clone().into_inner();
+        let name = inner
+            .next()
+            .expect("Expected an attribute name")
+            .as_str()
+            .to_string();
+        let value = match inner.next() {
+            Some(value) => value,
+            None => return Ok(Annotation::Word(name)),
+        };
+        Ok(match value.as_rule() {
+            Rule::ATTRIBUTE_LIST => Annotation::List(name, Annotation::from_list(&value)?),
+            Rule::ATTRIBUTE_STRING => {
+                let quoted = value.as_str();
+                Annotation::NameValue(name, quoted[1..quoted.len() - 1].to_string())
+            }
+            _ => Annotation::NameValue(name, value.as_str().to_string()),
+        })
+    }
+
+    // Convert the items of an `ATTRIBUTE_LIST` token.
+    fn from_list(pair: &Pair<'_, Rule>) -> Result<Vec<Self>, DTraceError> {
+        expect_token(pair, Rule::ATTRIBUTE_LIST)?;
+        pair.clone()
+            .into_inner()
+            .map(|item| Annotation::from_pair(&item))
+            .collect()
+    }
+
+    // Check that the annotation is supported on a provider or probe.
+    fn check_supported(&self) -> Result<(), DTraceError> {
+        match self.name() {
+            "cfg" | "cfg_attr" => Err(DTraceError::UnsupportedAnnotation(self.name().to_string())),
+            _ => Ok(()),
         }
     }
 }
 
+// Parse the `ATTRIBUTE` tokens at the start of a provider or probe definition.
+fn parse_annotations(
+    inner: &mut std::iter::Peekable<Pairs<'_, Rule>>,
+) -> Result<Vec<Annotation>, DTraceError> {
+    let mut annotations = Vec::new();
+    while let Some(attribute) = inner.next_if(|pair| pair.as_rule() == Rule::ATTRIBUTE) {
+        let item = attribute
+            .into_inner()
+            .next()
+            .expect("Expected an attribute item");
+        let annotation = Annotation::from_pair(&item)?;
+        annotation.check_supported()?;
+        annotations.push(annotation);
+    }
+    Ok(annotations)
+}
+
 /// Type representing a single D probe definition within a provider.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Probe {
     pub name: String,
     pub types: Vec<DataType>,
+    /// The attributes written before the probe, e.g., `#[cfg(unix)] probe bar();`.
+    pub annotations: Vec<Annotation>,
+}
+
+impl Probe {
+    /// Return the name of this probe as a valid Rust identifier.
+    ///
+    /// DTrace allows dashes in probe names, e.g., `gc-start`, which are mapped to underscores,
+    /// e.g., `gc_start`.
+    pub fn rust_name(&self) -> String {
+        self.name.replace('-', "_")
+    }
 }
 
 impl TryFrom<&Pair<'_, Rule>> for Probe {
     type Error = DTraceError;
 
     fn try_from(pair: &Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        Probe::from_pair(pair, &Typedefs::new())
+    }
+}
+
+impl Probe {
+    // Convert a `PROBE` token, resolving typedefs in its arguments with the declared typedefs.
+    fn from_pair(pair: &Pair<'_, Rule>, typedefs: &Typedefs) -> Result<Self, DTraceError> {
         expect_token(pair, Rule::PROBE)?;
-        let mut inner = pair.clone().into_inner();
+        let mut inner = pair.clone().into_inner().peekable();
+        let annotations = parse_annotations(&mut inner)?;
         expect_token(
             &inner.next().expect("Expected the literal 'probe'"),
             Rule::PROBE_KEY,
         )?;
         let token = inner.next().expect("Expected a probe name");
         let name = token.as_str().to_string();
-        if name == "probe" || name == "start" {
-            return Err(DTraceError::InvalidProbeName(name));
-        }
+        check_probe_name(&name)?;
         expect_token(
             &inner.next().expect("Expected the literal '('"),
             Rule::LEFT_PAREN,
@@ -302,8 +627,28 @@ impl TryFrom<&Pair<'_, Rule>> for Probe {
         if expect_token(&possibly_argument_list, Rule::ARGUMENT_LIST).is_ok() {
             let arguments = possibly_argument_list.clone().into_inner();
             for data_type in arguments {
-                expect_token(&data_type, Rule::DATA_TYPE)?;
-                types.push(DataType::try_from(&data_type)?);
+                match data_type.as_rule() {
+                    Rule::FORMAT_STRING => types.push(DataType::String),
+                    Rule::ELLIPSIS => types.extend(
+                        [DataType::Integer(Integer {
+                            sign: Sign::Unsigned,
+                            width: BitWidth::Pointer,
+                        }); MAX_PRINTF_ARGS],
+                    ),
+                    _ => {
+                        let typ =
+                            DataType::from_pair(&data_type, typedefs).map_err(|e| match e {
+                                DTraceError::UnsupportedType(typ) => {
+                                    DTraceError::UnsupportedArgumentType {
+                                        probe: name.clone(),
+                                        typ,
+                                    }
+                                }
+                                e => e,
+                            })?;
+                        types.push(typ);
+                    }
+                }
             }
         }
         expect_token(
@@ -314,7 +659,11 @@ impl TryFrom<&Pair<'_, Rule>> for Probe {
             &inner.next().expect("Expected a literal ';'"),
             Rule::SEMICOLON,
         )?;
-        Ok(Probe { name, types })
+        Ok(Probe {
+            name,
+            types,
+            annotations,
+        })
     }
 }
 
@@ -331,14 +680,93 @@ impl TryFrom<&Pairs<'_, Rule>> for Probe {
 pub struct Provider {
     pub name: String,
     pub probes: Vec<Probe>,
+    /// The attributes written before the provider, e.g., `#[stability(evolving)] provider foo`.
+    pub annotations: Vec<Annotation>,
 }
 
 impl TryFrom<&Pair<'_, Rule>> for Provider {
     type Error = DTraceError;
 
     fn try_from(pair: &Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        Provider::from_pair(pair, &Typedefs::new())
+    }
+}
+
+// Return true if the whole of the input matches a rule of the grammar.
+fn matches_rule(rule: Rule, input: &str) -> bool {
+    use pest::Parser;
+    DTraceParser::parse(rule, input)
+        .ok()
+        .and_then(|mut pairs| pairs.next())
+        .is_some_and(|pair| pair.as_str() == input)
+}
+
+// Check that a provider name is a valid identifier, other than the keyword `provider`.
+fn check_provider_name(name: &str) -> Result<(), DTraceError> {
+    if name == "provider" || !matches_rule(Rule::IDENTIFIER, name) {
+        return Err(DTraceError::InvalidProviderName(name.to_string()));
+    }
+    Ok(())
+}
+
+// Check that a probe name is a valid identifier, which may contain dashes, other than the keyword
+// `probe` or the reserved name `start`.
+fn check_probe_name(name: &str) -> Result<(), DTraceError> {
+    if name == "probe" || name == "start" || !matches_rule(Rule::PROBE_IDENTIFIER, name) {
+        return Err(DTraceError::InvalidProbeName(name.to_string()));
+    }
+    Ok(())
+}
+
+impl Provider {
+    /// Check that the provider follows the same rules as one parsed from D source.
+    ///
+    /// The names of the provider and its probes must be valid, the probes must have distinct
+    /// names, which also map to distinct Rust identifiers and DTrace probe names, and arrays must
+    /// have a valid length.
+    /// This is done for every provider parsed from a file, and may be used to check a provider
+    /// built by other means, such as from another description of its probes.
+    pub fn validate(&self) -> Result<(), DTraceError> {
+        check_provider_name(&self.name)?;
+        let mut rust_names = HashMap::new();
+        let mut dtrace_names = HashMap::new();
+        for probe in self.probes.iter() {
+            check_probe_name(&probe.name)?;
+            for typ in probe.types.iter() {
+                if let DataType::Array(_, len) = typ {
+                    if !(1..=MAX_ARRAY_LEN).contains(len) {
+                        return Err(DTraceError::InvalidArrayLength(len.to_string()));
+                    }
+                }
+            }
+
+            // Probes such as `gc-start` and `gc_start` are distinct to DTrace, but would generate
+            // the same Rust probe macro.
+            if let Some(other) = rust_names.insert(probe.rust_name(), &probe.name) {
+                return Err(if other == &probe.name {
+                    DTraceError::DuplicateProbeName((self.name.clone(), probe.name.clone()))
+                } else {
+                    DTraceError::CollidingProbeName(other.clone(), probe.name.clone())
+                });
+            }
+
+            // Double underscores in a probe name appear to DTrace as a dash, so `gc__start` and
+            // `gc-start` would be the same DTrace probe.
+            if let Some(other) = dtrace_names.insert(probe.name.replace("__", "-"), &probe.name) {
+                return Err(DTraceError::CollidingProbeName(
+                    other.clone(),
+                    probe.name.clone(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Convert a `PROVIDER` token, resolving typedefs in its probes with the declared typedefs.
+    fn from_pair(pair: &Pair<'_, Rule>, typedefs: &Typedefs) -> Result<Self, DTraceError> {
         expect_token(pair, Rule::PROVIDER)?;
-        let mut inner = pair.clone().into_inner();
+        let mut inner = pair.clone().into_inner().peekable();
+        let mut annotations = parse_annotations(&mut inner)?;
         expect_token(
             &inner.next().expect("Expected the literal 'provider'"),
             Rule::PROVIDER_KEY,
@@ -348,9 +776,7 @@ impl TryFrom<&Pair<'_, Rule>> for Provider {
             .expect("Expected a provider name")
             .as_str()
             .to_string();
-        if name == "provider" {
-            return Err(DTraceError::InvalidProviderName(name));
-        }
+        check_provider_name(&name)?;
         expect_token(
             &inner.next().expect("Expected the literal '{'"),
             Rule::LEFT_BRACE,
@@ -360,15 +786,34 @@ impl TryFrom<&Pair<'_, Rule>> for Provider {
             .next()
             .expect("Expected at least one probe in the provider");
         while expect_token(&possibly_probe, Rule::PROBE).is_ok() {
-            probes.push(Probe::try_from(&possibly_probe)?);
+            probes.push(Probe::from_pair(&possibly_probe, typedefs)?);
             possibly_probe = inner.next().expect("Expected a token");
         }
         expect_token(&possibly_probe, Rule::RIGHT_BRACE)?;
-        expect_token(
-            &inner.next().expect("Expected a literal ';'"),
-            Rule::SEMICOLON,
-        )?;
-        Ok(Provider { name, probes })
+        let mut possibly_suffix = inner.next().expect("Expected a literal ';'");
+        if possibly_suffix.as_rule() == Rule::TRAILING_NAME {
+            if possibly_suffix.as_str() != name {
+                return Err(DTraceError::MismatchedProviderName(
+                    name,
+                    possibly_suffix.as_str().to_string(),
+                ));
+            }
+            possibly_suffix = inner.next().expect("Expected a literal ';'");
+        }
+        // Attributes following the provider are added to those preceding it.
+        if possibly_suffix.as_rule() == Rule::ATTRIBUTE_LIST {
+            for annotation in Annotation::from_list(&possibly_suffix)? {
+                annotation.check_supported()?;
+                annotations.push(annotation);
+            }
+            possibly_suffix = inner.next().expect("Expected a literal ';'");
+        }
+        expect_token(&possibly_suffix, Rule::SEMICOLON)?;
+        Ok(Provider {
+            name,
+            probes,
+            annotations,
+        })
     }
 }
 
@@ -391,18 +836,40 @@ impl TryFrom<&Pair<'_, Rule>> for File {
     type Error = DTraceError;
 
     fn try_from(pair: &Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        File::from_pair(pair, Typedefs::new())
+    }
+}
+
+impl File {
+    // Convert a `FILE` token, starting from the given typedefs, which are overridden by those the
+    // file declares.
+    fn from_pair(pair: &Pair<'_, Rule>, mut typedefs: Typedefs) -> Result<Self, DTraceError> {
         expect_token(pair, Rule::FILE)?;
+
+        // Typedefs apply to the whole file, and each may refer to those declared before it.
+        for item in pair.clone().into_inner() {
+            if item.as_rule() == Rule::TYPEDEF {
+                let mut inner = item.into_inner().skip(1);
+                let typ =
+                    DataType::from_pair(&inner.next().expect("Expected a data type"), &typedefs)?;
+                let name = inner.next().expect("Expected a typedef name");
+                typedefs.insert(name.as_str().to_string(), typ);
+            }
+        }
+
         let mut providers = Vec::new();
         let mut names = HashSet::new();
         for item in pair.clone().into_inner() {
             if item.as_rule() == Rule::PROVIDER {
-                let provider = Provider::try_from(&item)?;
+                let provider = Provider::from_pair(&item, &typedefs)?;
+                provider.validate()?;
+                // A provider may be defined more than once, but not with the same probe.
                 for probe in provider.probes.iter() {
                     let name = (provider.name.clone(), probe.name.clone());
                     if names.contains(&name) {
                         return Err(DTraceError::DuplicateProbeName(name));
                     }
-                    names.insert(name.clone());
+                    names.insert(name);
                 }
                 providers.push(provider);
             }
@@ -451,35 +918,65 @@ impl TryFrom<&str> for File {
     type Error = DTraceError;
 
     fn try_from(s: &str) -> Result<Self, Self::Error> {
-        use pest::Parser;
-        File::try_from(&DTraceParser::parse(Rule::FILE, s).map_err(|e| {
-            Box::new(e.renamed_rules(|rule| match *rule {
-                Rule::DATA_TYPE | Rule::BIT_WIDTH => {
-                    format!(
-                        "{:?}.\n\n{}",
-                        *rule,
-                        concat!(
-                            "Unsupported type, the following are supported:\n",
-                            "  - uint8_t\n",
-                            "  - uint16_t\n",
-                            "  - uint32_t\n",
-                            "  - uint64_t\n",
-                            "  - int8_t\n",
-                            "  - int16_t\n",
-                            "  - int32_t\n",
-                            "  - int64_t\n",
-                            "  - &str\n",
-                        )
-                    )
-                }
-                _ => format!("{:?}", rule),
-            }))
-        })?)
+        File::parse(s, Typedefs::new())
+    }
+}
+
+impl File {
+    /// Parse the providers in a D source file, with additional typedefs.
+    ///
+    /// The given typedefs behave as if declared before the contents of the file. They take
+    /// precedence over [`BUILTIN_TYPEDEFS`], but not over the typedefs declared in the file. Each
+    /// name must end in `_t`, as with the names of typedefs in the file.
+    pub fn parse_with_typedefs(
+        s: &str,
+        typedefs: &[(&str, DataType)],
+    ) -> Result<Self, DTraceError> {
+        let mut declared = Typedefs::new();
+        for (name, typ) in typedefs.iter() {
+            if !matches_rule(Rule::TYPEDEF_NAME, name) {
+                return Err(DTraceError::InvalidTypedefName(name.to_string()));
+            }
+            declared.insert(name.to_string(), *typ);
+        }
+        File::parse(s, declared)
+    }
+
+    // Parse the providers in a D source file, starting from the given typedefs.
+    fn parse(s: &str, typedefs: Typedefs) -> Result<Self, DTraceError> {
+        let pairs = parse_file_tokens(s)?;
+        File::from_pair(
+            &pairs.peek().ok_or(DTraceError::EmptyPairsIterator)?,
+            typedefs,
+        )
     }
 }
 
+// Parse a D source file into its `FILE` token.
+fn parse_file_tokens(s: &str) -> Result<Pairs<'_, Rule>, DTraceError> {
+    use pest::Parser;
+    // Some editors begin files with a byte-order mark, which is not part of the definition.
+    let s = s.strip_prefix('\u{feff}').unwrap_or(s);
+    Ok(DTraceParser::parse(Rule::FILE, s).map_err(|e| {
+        Box::new(e.renamed_rules(|rule| match *rule {
+            Rule::DATA_TYPE | Rule::BIT_WIDTH => {
+                let supported: String = supported_types!()
+                    .lines()
+                    .flat_map(|line| ["  ", line, "\n"])
+                    .collect();
+                format!(
+                    "{:?}.\n\nUnsupported type, the following are supported:\n{}",
+                    *rule, supported
+                )
+            }
+            _ => format!("{:?}", rule),
+        }))
+    })?)
+}
+
 #[cfg(test)]
 mod tests {
+    use super::Annotation;
     use super::BitWidth;
     use super::DTraceParser;
     use super::DataType;
@@ -490,8 +987,10 @@ mod tests {
     use super::Rule;
     use super::Sign;
     use super::TryFrom;
+    use super::MAX_PRINTF_ARGS;
     use ::pest::Parser;
     use rstest::{fixture, rstest};
+    use std::fs;
 
     #[rstest(
         token,
@@ -525,11 +1024,156 @@ mod tests {
         assert!(DTraceParser::parse(Rule::IDENTIFIER, "9foo").is_err());
     }
 
+    #[test]
+    fn test_probe_identifier() {
+        assert!(DTraceParser::parse(Rule::PROBE_IDENTIFIER, "gc-start").is_ok());
+        assert!(DTraceParser::parse(Rule::PROBE_IDENTIFIER, "-start").is_err());
+    }
+
     #[test]
     fn test_data_types() {
         assert!(DTraceParser::parse(Rule::DATA_TYPE, "uint8_t").is_ok());
-        assert!(DTraceParser::parse(Rule::DATA_TYPE, "int").is_err());
-        assert!(DTraceParser::parse(Rule::DATA_TYPE, "flaot").is_err());
+        // Other types are parsed, but rejected with an error naming the type.
+        for typ in ["int", "flaot"] {
+            let pair = DTraceParser::parse(Rule::DATA_TYPE, typ).unwrap();
+            assert!(
+                matches!(DataType::try_from(&pair), Err(super::DTraceError::UnsupportedType(t)) if t == typ)
+            );
+        }
+        assert!(DTraceParser::parse(Rule::DATA_TYPE, "").is_err());
+    }
+
+    #[rstest]
+    #[case("complex")]
+    #[case("long double")]
+    #[case("_Complex")]
+    #[case("const complex")]
+    fn test_probe_unsupported_type(#[case] typ: &str) {
+        let defn = format!("probe bad(uint8_t, {});", typ);
+        let err = Probe::try_from(&DTraceParser::parse(Rule::PROBE, &defn).unwrap()).unwrap_err();
+        let typ = typ.trim_start_matches("const ");
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "The type \"{}\" of an argument of the probe \"bad\" is not supported",
+                typ
+            )
+        );
+    }
+
+    #[rstest]
+    #[case("wchar_t *")]
+    #[case("wchar_t*")]
+    #[case("const wchar_t *")]
+    fn test_wide_string(#[case] typ: &str) {
+        // Wide strings are passed as UTF-8, the same as `char *`.
+        let defn = format!("provider foo {{ probe bar({}); }};", typ);
+        let file = File::try_from(defn.as_str()).unwrap();
+        assert_eq!(file.providers()[0].probes[0].types, vec![DataType::String]);
+    }
+
+    #[test]
+    fn test_builtin_typedefs() {
+        let defn = "provider foo { probe bar(pid_t, uid_t, gid_t, off_t, dev_t, ino_t, size_t); };";
+        let file = File::try_from(defn).unwrap();
+        let types = file.providers()[0].probes[0]
+            .types
+            .iter()
+            .map(DataType::to_rust_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["i32", "u32", "u32", "i64", "u64", "u64", "usize"]);
+    }
+
+    #[test]
+    fn test_declared_typedefs() {
+        let defn = r#"
+            typedef int64_t pid_t;
+            typedef uint16_t port_t;
+            typedef port_t listen_port_t;
+            provider foo { probe bar(pid_t, port_t, listen_port_t, uid_t, callback_t); };"#;
+        let file = File::try_from(defn).unwrap();
+        let types = &file.providers()[0].probes[0].types;
+        let int = |sign, width| DataType::Integer(Integer { sign, width });
+
+        // Declared typedefs override the builtin ones, and may refer to earlier typedefs.
+        assert_eq!(
+            types,
+            &[
+                int(Sign::Signed, BitWidth::Bit64),
+                int(Sign::Unsigned, BitWidth::Bit16),
+                int(Sign::Unsigned, BitWidth::Bit16),
+                int(Sign::Unsigned, BitWidth::Bit32),
+                DataType::Opaque,
+            ]
+        );
+
+        // Typedefs only apply within the file.
+        let defn = "provider foo { probe bar(port_t); };";
+        let file = File::try_from(defn).unwrap();
+        assert_eq!(file.providers()[0].probes[0].types, &[DataType::Opaque]);
+        assert!(File::try_from("typedef uint8_t byte;").is_err());
+        assert!(File::try_from("typedefuint8_t byte_t;").is_err());
+    }
+
+    #[test]
+    fn test_parse_with_typedefs() {
+        let defn = r#"
+            typedef uint16_t flags_t;
+            provider foo { probe bar(conn_t, flags_t, pid_t); };"#;
+        let int = |sign, width| DataType::Integer(Integer { sign, width });
+        let typedefs = [
+            ("conn_t", DataType::from_rust_type("u64").unwrap()),
+            ("flags_t", DataType::from_rust_type("u32").unwrap()),
+            ("pid_t", DataType::from_c_type("int64_t").unwrap()),
+        ];
+        let file = File::parse_with_typedefs(defn, &typedefs).unwrap();
+
+        // The given typedefs override the builtin ones, but not those declared in the file.
+        assert_eq!(
+            file.providers()[0].probes[0].types,
+            &[
+                int(Sign::Unsigned, BitWidth::Bit64),
+                int(Sign::Unsigned, BitWidth::Bit16),
+                int(Sign::Signed, BitWidth::Bit64),
+            ]
+        );
+        assert!(matches!(
+            File::parse_with_typedefs(defn, &[("conn", DataType::String)]),
+            Err(crate::DTraceError::InvalidTypedefName(name)) if name == "conn"
+        ));
+    }
+
+    #[test]
+    fn test_from_rust_type() {
+        assert_eq!(
+            DataType::from_rust_type("usize").unwrap().to_rust_type(),
+            "usize"
+        );
+        assert_eq!(DataType::from_rust_type("&str"), Some(DataType::String));
+        assert_eq!(DataType::from_rust_type("f64"), Some(DataType::Double));
+        assert_eq!(DataType::from_rust_type("u128"), None);
+        assert_eq!(DataType::from_rust_type("uint8_t"), None);
+    }
+
+    #[rstest]
+    #[case("probe log(char*, ...);")]
+    #[case("probe log(const char *, ...,);")]
+    fn test_printf_probe(#[case] defn: &str) {
+        let probe = Probe::try_from(&DTraceParser::parse(Rule::PROBE, defn).unwrap()).unwrap();
+        assert_eq!(probe.types.len(), 1 + MAX_PRINTF_ARGS);
+        assert_eq!(probe.types[0], DataType::String);
+        for typ in probe.types[1..].iter() {
+            assert_eq!(typ.to_rust_type(), "usize");
+        }
+    }
+
+    #[rstest]
+    #[case("probe log(...);")]
+    #[case("probe log(uint8_t, ...);")]
+    #[case("probe log(char*, uint8_t, ...);")]
+    #[case("probe log(char*, ..., uint8_t);")]
+    fn test_printf_probe_invalid(#[case] defn: &str) {
+        assert!(DTraceParser::parse(Rule::PROBE, defn).is_err());
     }
 
     #[test]
@@ -557,6 +1201,60 @@ mod tests {
         assert!(DTraceParser::parse(Rule::FILE, defn).is_err());
     }
 
+    #[test]
+    fn test_provider_with_empty_statements() {
+        let defn = r#"
+            provider foo {
+                ;
+                probe bar(); ;
+                {}
+                { probe baz(uint8_t); { ; } };
+                probe qux();
+            };"#;
+        let file = File::try_from(defn).unwrap();
+        let names = file.providers()[0]
+            .probes
+            .iter()
+            .map(|probe| probe.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["bar", "baz", "qux"]);
+
+        // Groups and semicolons alone don't make up a provider.
+        let defn = "provider foo { ; {} { ; } };";
+        assert!(DTraceParser::parse(Rule::FILE, defn).is_err());
+        let defn = "provider foo { probe bar(); { };";
+        assert!(DTraceParser::parse(Rule::FILE, defn).is_err());
+    }
+
+    #[rstest]
+    #[case("provider foo { probe bar(); } foo;", 0)]
+    #[case("provider foo { probe bar(); } foo (stability(evolving));", 1)]
+    #[case("#[private] provider foo { probe bar(); } (stability(evolving),);", 2)]
+    fn test_provider_with_trailing_name(#[case] defn: &str, #[case] n_annotations: usize) {
+        let file = File::try_from(defn).unwrap();
+        let provider = &file.providers()[0];
+        assert_eq!(provider.name, "foo");
+        assert_eq!(provider.probes.len(), 1);
+        assert_eq!(provider.probes[0].name, "bar");
+        assert_eq!(provider.annotations.len(), n_annotations);
+        if n_annotations > 0 {
+            assert_eq!(
+                provider.annotations.last().unwrap(),
+                &Annotation::List(
+                    String::from("stability"),
+                    vec![Annotation::Word(String::from("evolving"))]
+                )
+            );
+        }
+        assert!(DTraceParser::parse(Rule::FILE, "provider foo { probe bar(); } foo bar;").is_err());
+        assert!(File::try_from("provider foo { probe bar(); } foo (Evolving/Common);").is_err());
+        assert!(matches!(
+            File::try_from("provider foo { probe bar(); } baz;"),
+            Err(super::DTraceError::MismatchedProviderName(provider, name))
+                if provider == "foo" && name == "baz"
+        ));
+    }
+
     #[test]
     fn test_comment_provider() {
         let defn = r#"
@@ -581,6 +1279,175 @@ mod tests {
         assert!(DTraceParser::parse(Rule::FILE, defn).is_ok());
     }
 
+    #[test]
+    fn test_provider_and_probe_attributes() {
+        let defn = r#"
+            #[stability(evolving)]
+            #[doc = "A provider with attributes"]
+            provider foo {
+                #[since(any(version = "1.2", unstable,))]
+                # [ deprecated ]
+                probe bar(uint8_t);
+                probe baz();
+            };
+            "#;
+        let file = File::try_from(defn).unwrap();
+        let provider = &file.providers()[0];
+        assert_eq!(
+            provider.annotations,
+            vec![
+                Annotation::List(
+                    String::from("stability"),
+                    vec![Annotation::Word(String::from("evolving"))]
+                ),
+                Annotation::NameValue(
+                    String::from("doc"),
+                    String::from("A provider with attributes")
+                ),
+            ]
+        );
+        assert_eq!(provider.probes[0].name, "bar");
+        assert_eq!(
+            provider.probes[0].annotations,
+            vec![
+                Annotation::List(
+                    String::from("since"),
+                    vec![Annotation::List(
+                        String::from("any"),
+                        vec![
+                            Annotation::NameValue(String::from("version"), String::from("1.2")),
+                            Annotation::Word(String::from("unstable")),
+                        ]
+                    )]
+                ),
+                Annotation::Word(String::from("deprecated")),
+            ]
+        );
+        assert_eq!(provider.probes[0].annotations[0].name(), "since");
+        assert!(provider.probes[1].annotations.is_empty());
+    }
+
+    #[rstest(
+        defn,
+        case("#[cfg(unix)] provider foo { probe bar(); };"),
+        case("provider foo { #[cfg(target_os = \"illumos\")] probe bar(); };"),
+        case("provider foo { #[cfg_attr(unix, deprecated)] probe bar(); };"),
+        case("provider foo { probe bar(); } foo (stability(evolving), cfg(unix));")
+    )]
+    fn test_conditional_attributes(defn: &str) {
+        assert!(matches!(
+            File::try_from(defn),
+            Err(super::DTraceError::UnsupportedAnnotation(_))
+        ));
+    }
+
+    #[rstest(
+        defn,
+        case("#[] provider foo { probe bar(); };"),
+        case("#[cfg(] provider foo { probe bar(); };"),
+        case("#[doc = \"unterminated] provider foo { probe bar(); };"),
+        case("provider foo { probe #[cfg(unix)] bar(); };"),
+        case("provider foo { probe bar(); } #[cfg(unix)];")
+    )]
+    fn test_invalid_attributes(defn: &str) {
+        assert!(File::try_from(defn).is_err());
+    }
+
+    #[test]
+    fn test_arguments_on_separate_lines() {
+        let defn = r#"
+            provider foo {
+                probe bar(
+                    /* The number of bytes. */
+                    uint64_t,
+                    /* The name of the file,
+                     * which may be long. */
+                    char *   ,
+                    uint8_t[4] /* A small identifier. */ ,
+                );
+                probe baz(
+                    uint8_t,
+                );
+            };
+            "#;
+        let file = File::try_from(defn).unwrap();
+        let probes = &file.providers()[0].probes;
+        assert_eq!(
+            probes[0].types,
+            &[
+                DataType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit64
+                }),
+                DataType::String,
+                DataType::Array(
+                    Integer {
+                        sign: Sign::Unsigned,
+                        width: BitWidth::Bit8
+                    },
+                    4
+                ),
+            ]
+        );
+        assert_eq!(probes[1].types.len(), 1);
+
+        // Only a single trailing comma is allowed.
+        assert!(File::try_from("provider foo { probe bar(uint8_t,,); };").is_err());
+        assert!(File::try_from("provider foo { probe bar(,); };").is_err());
+    }
+
+    #[test]
+    fn test_hrtime_argument() {
+        let file = File::try_from("provider clock { probe tick(hrtime_t); };").unwrap();
+        let probe = &file.providers()[0].probes[0];
+        assert_eq!(probe.types, &[DataType::HrTime]);
+        assert_eq!(probe.types[0].to_c_type(), "hrtime_t");
+        assert_eq!(probe.types[0].to_rust_type(), "i64");
+    }
+
+    #[test]
+    fn test_file_indented_with_tabs() {
+        let file = File::from_file(std::path::Path::new(&data_file("tabs.d"))).unwrap();
+        let provider = &file.providers()[0];
+        assert_eq!(provider.name, "tabs");
+        let names = provider
+            .probes
+            .iter()
+            .map(|probe| probe.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, &["first", "second", "third"]);
+        assert_eq!(
+            provider.probes[0].types,
+            &[
+                DataType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit8,
+                }),
+                DataType::String,
+            ]
+        );
+        assert_eq!(provider.probes[1].types.len(), 3);
+        assert_eq!(provider.probes[1].types[1], DataType::String);
+        assert!(provider.probes[2].types.is_empty());
+    }
+
+    #[test]
+    fn test_file_with_byte_order_mark() {
+        let defn = "\u{feff}provider foo { probe bar(uint8_t); };";
+        let file = File::try_from(defn).unwrap();
+        assert_eq!(file.providers()[0].name, "foo");
+
+        let path = std::env::temp_dir().join(format!("bom-{}.d", std::process::id()));
+        fs::write(&path, defn.as_bytes()).unwrap();
+        let file = File::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        let file = file.unwrap();
+        assert_eq!(file.providers()[0].probes[0].name, "bar");
+
+        // Only a leading mark is ignored.
+        assert!(File::try_from("provider foo { \u{feff}probe bar(); };").is_err());
+    }
+
     #[test]
     fn test_two_providers() {
         let defn = r#"
@@ -618,7 +1485,10 @@ mod tests {
         case("int16_t*", DataType::Pointer(Integer { sign: Sign::Signed, width: BitWidth::Bit16})),
         case("int32_t*", DataType::Pointer(Integer { sign: Sign::Signed, width: BitWidth::Bit32})),
         case("int64_t*", DataType::Pointer(Integer { sign: Sign::Signed, width: BitWidth::Bit64})),
-        case("char*", DataType::String)
+        case("char*", DataType::String),
+        case("float", DataType::Float),
+        case("double", DataType::Double),
+        case("hrtime_t", DataType::HrTime)
     )]
     fn test_data_type_enum(defn: &str, data_type: DataType) {
         let dtype =
@@ -626,11 +1496,156 @@ mod tests {
         assert_eq!(dtype, data_type);
     }
 
+    #[rstest(
+        defn,
+        data_type,
+        case("const char *", DataType::String),
+        case("const uint32_t", DataType::Integer(Integer { sign: Sign::Unsigned, width: BitWidth::Bit32 })),
+        case("const int8_t*", DataType::Pointer(Integer { sign: Sign::Signed, width: BitWidth::Bit8 }))
+    )]
+    fn test_data_type_const(defn: &str, data_type: DataType) {
+        let dtype =
+            DataType::try_from(&DTraceParser::parse(Rule::DATA_TYPE, defn).unwrap()).unwrap();
+        assert_eq!(dtype, data_type);
+    }
+
+    #[test]
+    fn test_const_probe() {
+        let probe = Probe::try_from(
+            &DTraceParser::parse(Rule::PROBE, "probe foo(const char *, const uint32_t);").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            probe.types,
+            &[
+                DataType::String,
+                DataType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit32,
+                }),
+            ]
+        );
+        // A missing space after `const` isn't mistaken for a typedef or pointer.
+        for typ in ["constuint32_t", "constuint8_t*"] {
+            let pair = DTraceParser::parse(Rule::DATA_TYPE, typ);
+            assert!(pair.map_or(true, |pair| DataType::try_from(&pair).is_err()));
+        }
+    }
+
+    #[test]
+    fn test_data_type_array() {
+        let uint8 = Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        };
+        for defn in ["uint8_t[16]", "uint8_t [16]", "const uint8_t[ 16 ]"] {
+            let dtype =
+                DataType::try_from(&DTraceParser::parse(Rule::DATA_TYPE, defn).unwrap()).unwrap();
+            assert_eq!(dtype, DataType::Array(uint8, 16));
+        }
+        let dtype = DataType::Array(uint8, 16);
+        assert_eq!(dtype.to_c_type(), "uint8_t*");
+        assert_eq!(dtype.to_rust_ffi_type(), "*const ::std::os::raw::c_uchar");
+        assert_eq!(dtype.to_rust_type(), "[u8; 16]");
+
+        let probe =
+            Probe::try_from(&DTraceParser::parse(Rule::PROBE, "probe id(uint8_t[16]);").unwrap())
+                .unwrap();
+        assert_eq!(probe.types, &[dtype]);
+
+        for defn in [
+            "uint8_t[0]",
+            "uint8_t[257]",
+            "uint8_t[99999999999999999999]",
+        ] {
+            assert!(matches!(
+                DataType::try_from(&DTraceParser::parse(Rule::DATA_TYPE, defn).unwrap()),
+                Err(super::DTraceError::InvalidArrayLength(_))
+            ));
+        }
+        assert!(DTraceParser::parse(Rule::PROBE, "probe id(uint8_t[]);").is_err());
+    }
+
+    #[test]
+    fn test_supported_types() {
+        let supported = crate::supported_types!();
+        assert!(supported.contains(&format!("at most {} elements", super::MAX_ARRAY_LEN)));
+
+        // Each example in the list is accepted.
+        for defn in [
+            "uint16_t",
+            "int64_t",
+            "uintptr_t",
+            "intptr_t",
+            "uint8_t *",
+            "char *",
+            "wchar_t *",
+            "float",
+            "double",
+            "hrtime_t",
+            "uint8_t[16]",
+            "pid_t",
+            "void *",
+        ] {
+            assert!(supported.contains(&format!("`{}`", defn)), "{}", defn);
+            assert!(
+                DataType::try_from(&DTraceParser::parse(Rule::DATA_TYPE, defn).unwrap()).is_ok(),
+                "{}",
+                defn
+            );
+        }
+
+        // The list is given in the error for a type which isn't recognized at all.
+        let err = File::try_from("provider foo { probe bar(uint8_t, 7); };").unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("Unsupported type, the following are supported:"));
+        assert!(message.contains("  - `float` and `double`\n"));
+    }
+
+    #[rstest]
+    #[case("uint256_t")]
+    #[case("int128_t")]
+    #[case("uint0_t")]
+    #[case("const int7_t")]
+    fn test_data_type_invalid_integer_width(#[case] defn: &str) {
+        let err =
+            DataType::try_from(&DTraceParser::parse(Rule::DATA_TYPE, defn).unwrap()).unwrap_err();
+        let typ = defn.trim_start_matches("const ");
+        assert!(matches!(&err, super::DTraceError::InvalidIntegerType(t) if t == typ));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "The integer type \"{}\" is invalid, integers must be 8, 16, 32 or 64 bits wide",
+                typ
+            )
+        );
+        assert_eq!(DataType::from_c_type(defn), None);
+    }
+
+    #[rstest]
+    #[case("callback_t")]
+    #[case("void *")]
+    #[case("void**")]
+    #[case("struct foo *")]
+    fn test_data_type_opaque(#[case] defn: &str) {
+        let dtype =
+            DataType::try_from(&DTraceParser::parse(Rule::DATA_TYPE, defn).unwrap()).unwrap();
+        assert_eq!(dtype, DataType::Opaque);
+        assert_eq!(dtype.to_rust_type(), "usize");
+        assert_eq!(DataType::from_c_type(defn), None);
+    }
+
     #[test]
     fn test_data_type_conversion() {
         let dtype =
             DataType::try_from(&DTraceParser::parse(Rule::DATA_TYPE, "uint8_t").unwrap()).unwrap();
         assert_eq!(dtype.to_rust_ffi_type(), "::std::os::raw::c_uchar");
+
+        let dtype =
+            DataType::try_from(&DTraceParser::parse(Rule::DATA_TYPE, "double").unwrap()).unwrap();
+        assert_eq!(dtype.to_c_type(), "double");
+        assert_eq!(dtype.to_rust_ffi_type(), "::std::os::raw::c_double");
+        assert_eq!(dtype.to_rust_type(), "f64");
     }
 
     #[fixture]
@@ -670,6 +1685,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_data_type_from_c_type() {
+        let u8_type = Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        };
+        assert_eq!(
+            DataType::from_c_type("uint8_t"),
+            Some(DataType::Integer(u8_type))
+        );
+        assert_eq!(
+            DataType::from_c_type(" uint8_t* "),
+            Some(DataType::Pointer(u8_type))
+        );
+        assert_eq!(DataType::from_c_type("char*"), Some(DataType::String));
+        assert_eq!(DataType::from_c_type("hrtime_t"), Some(DataType::HrTime));
+        assert_eq!(DataType::from_c_type("void*"), None);
+        assert_eq!(DataType::from_c_type("uint8_t trailing"), None);
+        assert_eq!(DataType::from_c_type(""), None);
+    }
+
     fn data_file(name: &str) -> String {
         format!("{}/test-data/{}", env!("CARGO_MANIFEST_DIR"), name)
     }
@@ -691,6 +1727,61 @@ mod tests {
         assert_eq!(provider.probes[0].name, "baz");
     }
 
+    #[test]
+    fn test_provider_validate() {
+        let probe = |name: &str, types: Vec<DataType>| Probe {
+            name: name.to_string(),
+            types,
+            annotations: vec![],
+        };
+        let provider = |name: &str, probes: Vec<Probe>| Provider {
+            name: name.to_string(),
+            probes,
+            annotations: vec![],
+        };
+        let byte = Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        };
+        assert!(provider(
+            "foo",
+            vec![probe("gc-start", vec![DataType::Array(byte, 16)])]
+        )
+        .validate()
+        .is_ok());
+        for (invalid, expected) in [
+            (provider("provider", vec![]), "provider"),
+            (provider("foo-bar", vec![]), "foo-bar"),
+            (provider("foo", vec![probe("start", vec![])]), "start"),
+            (provider("foo", vec![probe("-bar", vec![])]), "-bar"),
+            (provider("foo", vec![probe("bar baz", vec![])]), "bar baz"),
+        ] {
+            assert_eq!(
+                invalid.validate().unwrap_err().to_string(),
+                match invalid.probes.first() {
+                    Some(_) => format!("The probe name \"{}\" is invalid", expected),
+                    None => format!("The provider name \"{}\" is invalid", expected),
+                }
+            );
+        }
+        assert!(matches!(
+            provider("foo", vec![probe("bar", vec![DataType::Array(byte, 0)])]).validate(),
+            Err(super::DTraceError::InvalidArrayLength(_))
+        ));
+        assert!(matches!(
+            provider("foo", vec![probe("bar", vec![]), probe("bar", vec![])]).validate(),
+            Err(super::DTraceError::DuplicateProbeName(_))
+        ));
+        assert!(matches!(
+            provider(
+                "foo",
+                vec![probe("gc-start", vec![]), probe("gc_start", vec![])]
+            )
+            .validate(),
+            Err(super::DTraceError::CollidingProbeName(_, _))
+        ));
+    }
+
     #[test]
     fn test_file_struct() {
         let defn = r#"
@@ -715,4 +1806,25 @@ mod tests {
 
         assert!(File::try_from("this is not a D file").is_err());
     }
+
+    #[test]
+    fn test_hyphenated_probe() {
+        let defn = "provider foo { probe gc-start(uint8_t); };";
+        let file = File::try_from(defn).unwrap();
+        let probe = &file.providers[0].probes[0];
+        assert_eq!(probe.name, "gc-start");
+        assert_eq!(probe.rust_name(), "gc_start");
+    }
+
+    #[test]
+    fn test_colliding_probe_names() {
+        for second in ["gc_start", "gc__start"] {
+            let defn = format!("provider foo {{ probe gc-start(); probe {}(); }};", second);
+            assert!(matches!(
+                File::try_from(defn.as_str()),
+                Err(super::DTraceError::CollidingProbeName(first, other))
+                    if first == "gc-start" && other == second
+            ));
+        }
+    }
 }
@@ -16,7 +16,7 @@
 
 use clap::Parser;
 use std::path::PathBuf;
-use usdt::probe_records;
+use usdt::{probe_records, probe_records_summary};
 use usdt_impl::Error as UsdtError;
 
 /// Inspect data related to USDT probes in object files.
@@ -36,6 +36,18 @@ struct Cmd {
     /// Format output as JSON
     #[arg(short, long)]
     json: bool,
+
+    /// List each probe on one line, similar to `dtrace -l`
+    #[arg(short, long, conflicts_with_all = ["raw", "json"])]
+    list: bool,
+
+    /// With `--list`, also print the C types of each probe's arguments
+    #[arg(short, long, requires = "list")]
+    arguments: bool,
+
+    /// With `--arguments`, print the Rust equivalent of each argument's type instead
+    #[arg(long, requires = "arguments")]
+    rust_types: bool,
 }
 
 fn main() {
@@ -46,16 +58,31 @@ fn main() {
         }
     } else if cmd.json {
         dof::fmt::FormatMode::Json
+    } else if cmd.list && cmd.arguments {
+        dof::fmt::FormatMode::ListArguments {
+            rust_types: cmd.rust_types,
+        }
+    } else if cmd.list {
+        dof::fmt::FormatMode::List
     } else {
         dof::fmt::FormatMode::Pretty
     };
 
     match probe_records(&cmd.file) {
-        Ok(data) => match dof::fmt::fmt_dof(data, format_mode) {
-            Ok(Some(dof)) => println!("{}", dof),
-            Ok(None) => println!("No probe information found"),
-            Err(e) => println!("Failed to format probe information, {:?}", e),
-        },
+        Ok(data) => {
+            let summary = probe_records_summary(&data);
+            if cmd.verbose && summary.unresolved_functions > 0 {
+                eprintln!(
+                    "warning: symbolization failed for {}/{} probes; is this the running binary?",
+                    summary.unresolved_functions, summary.probes,
+                );
+            }
+            match dof::fmt::fmt_dof(data, format_mode) {
+                Ok(Some(dof)) => println!("{}", dof),
+                Ok(None) => println!("No probe information found"),
+                Err(e) => println!("Failed to format probe information, {:?}", e),
+            }
+        }
         Err(UsdtError::InvalidFile) => {
             println!("No probe information found");
         }
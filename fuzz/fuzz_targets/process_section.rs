@@ -0,0 +1,29 @@
+//! Fuzz the extraction of probe records from an arbitrary probe section.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use usdt_impl::record::process_section;
+
+fuzz_target!(|data: &[u8]| {
+    // Processing both with and without registration exercises the one-time update of record
+    // versions, and then the skipping of previously-registered records.
+    let mut data = data.to_vec();
+    let _ = process_section(&mut data, /* register = */ false);
+    let _ = process_section(&mut data, /* register = */ true);
+    let _ = process_section(&mut data, /* register = */ true);
+});
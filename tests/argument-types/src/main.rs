@@ -31,14 +31,28 @@ struct NotSupported<'a> {
     x: &'a [i32],
 }
 
+/// Structs may be passed by pointer, if they are `#[repr(C)]` and implement `ProbeStruct`.
+#[derive(usdt::ProbeStruct)]
+#[repr(C)]
+struct Context {
+    id: u64,
+    flags: u32,
+}
+
 #[usdt::provider]
 mod refs {
+    use crate::Context;
+
     /// Simple types such as integers may be taken by value ...
     fn u8_as_value(_: u8) {}
 
     /// ... or by reference
     fn u8_as_reference(_: &u8) {}
 
+    /// Floating point types are passed as their bit patterns
+    fn f64_as_value(_: f64) {}
+    fn f32_as_reference(_: &f32) {}
+
     /// Same with strings
     fn string_as_value(_: String) {}
     fn string_as_reference(_: &String) {}
@@ -59,8 +73,31 @@ mod refs {
     /// Serializable types may also be taken by value or reference.
     fn serializable_as_value(_: crate::Arg) {}
     fn serializable_as_reference(_: &crate::Arg) {}
+
+    /// Probes may take any number of arguments, of any of the above types.
+    fn multiple(_: u8, _: &str, _: *const Context) {}
+
+    /// Pointers to probe structs pass only the address of the struct. Scripts may read its fields
+    /// by including its D definition, from `STRUCT_DEFINITIONS`.
+    fn struct_pointer(_: *const Context) {}
 }
 
+// Probes defined in D may take fixed-length arrays of integers, whose elements are copied into the
+// probe's buffer.
+usdt::dtrace_provider!("provider arrays { probe id(uint8_t[16]); };");
+
+// Timestamps are given as `hrtime_t`, a signed count of nanoseconds.
+usdt::dtrace_provider!("provider clock { probe tick(hrtime_t); };");
+
+// The probe records of a provider may carry a version string or build hash.
+usdt::dtrace_provider!(
+    "provider tagged { probe build(); };",
+    version_tag = "1.2.3+abcdef"
+);
+
+// Wide strings are given as `wchar_t *`, and are passed to DTrace as UTF-8, the same as `char *`.
+usdt::dtrace_provider!("provider wide { probe name(wchar_t *); };");
+
 fn main() {
     usdt::register_probes().unwrap();
 
@@ -75,13 +112,20 @@ fn main() {
     refs::u8_as_reference!(|| 0);
     refs::u8_as_reference!(|| &0);
 
+    // Floating point values are fired the same way.
+    refs::f64_as_value!(|| 1.5);
+    refs::f64_as_value!(|| &f64::NAN);
+    refs::f32_as_reference!(|| 0.25f32);
+
     // This is true for string types as well. Probes accepting a string type may be called with
-    // anything that implements `AsRef<str>`, which includes `&str`, owned `String`s, and
-    // `&String` as well.
+    // anything that implements `AsRef<str>`, which includes `&str`, owned `String`s, `&String`,
+    // and `Cow<str>` as well.
     refs::string_as_value!(|| "&'static str");
     refs::string_as_value!(|| String::from("owned"));
+    refs::string_as_value!(|| std::borrow::Cow::from("cow"));
     refs::string_as_reference!(|| "&'static str");
     refs::string_as_reference!(|| String::from("owned"));
+    refs::string_as_reference!(|| std::borrow::Cow::from("cow"));
 
     // Vectors are supported as well. In this case, the probe argument behaves the way it might in
     // a "normal" function -- with a signature like `fn foo(_: Vec<T>)`, one can pass a `Vec<T>`.
@@ -126,4 +170,116 @@ fn main() {
 
     // This line will fail to compile, indicating that `arg` is borrowed after it's been moved.
     // println!("{:#?}", arg.x);
+
+    // Probes taking a struct pointer may be passed a reference or a raw pointer.
+    let context = Context { id: 1, flags: 0 };
+    refs::struct_pointer!(|| &context);
+    refs::struct_pointer!(|| &context as *const Context);
+
+    // Array arguments may be passed by value or reference.
+    let id = [0xab_u8; 16];
+    arrays::id!(|| id);
+    arrays::id!(|| &id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{arrays, clock, refs, tagged, wide, Context};
+
+    #[test]
+    fn test_array() {
+        let id: [u8; 16] = *b"0123456789abcdef";
+        arrays::id!(|| id);
+        arrays::id!(|| &id);
+        assert_eq!(arrays::PROBE_SPECS, &["arrays:::id"]);
+    }
+
+    #[test]
+    fn test_hrtime() {
+        let start = std::time::Instant::now();
+        clock::tick!(|| start.elapsed().as_nanos() as i64);
+        clock::tick!(|| 0);
+        assert_eq!(clock::PROBE_SPECS, &["clock:::tick"]);
+    }
+
+    #[test]
+    fn test_wide_string() {
+        let name = String::from("wide");
+        wide::name!(|| "wide");
+        wide::name!(|| &name);
+        assert_eq!(wide::PROBE_SPECS, &["wide:::name"]);
+    }
+
+    #[test]
+    fn test_version_tag() {
+        tagged::build!(|| ());
+        assert_eq!(tagged::PROBE_SPECS, &["tagged:::build"]);
+
+        // The tag is read back from the probe records of the binary.
+        #[cfg(target_os = "illumos")]
+        {
+            let exe = std::env::current_exe().unwrap();
+            let sections = usdt::probe_records(exe).unwrap();
+            let provider = sections
+                .iter()
+                .find_map(|section| section.providers.get("tagged"))
+                .expect("No records for the tagged provider");
+            assert_eq!(provider.version_tag.as_deref(), Some("1.2.3+abcdef"));
+        }
+    }
+
+    #[test]
+    fn test_probe_arg_types() {
+        let context = Context { id: 1, flags: 2 };
+        refs::multiple!(|| (0, "multiple", &context));
+        let (_, types) = refs::PROBE_ARG_TYPES
+            .iter()
+            .find(|(name, _)| *name == "multiple")
+            .unwrap();
+        assert_eq!(*types, &["uint8_t", "char*", "void*"]);
+        assert_eq!(clock::PROBE_ARG_TYPES, &[("tick", &["hrtime_t"][..])]);
+    }
+
+    #[test]
+    fn test_matches_spec() {
+        assert_eq!(
+            refs::matches_spec("refs:::u8_*"),
+            &["u8_as_value", "u8_as_reference"]
+        );
+        assert_eq!(
+            refs::matches_spec("*_as_reference"),
+            &[
+                "u8_as_reference",
+                "f32_as_reference",
+                "string_as_reference",
+                "serializable_as_reference"
+            ]
+        );
+        assert_eq!(
+            refs::matches_spec("r*::main:f??_as_value"),
+            &["f64_as_value"]
+        );
+        assert_eq!(refs::matches_spec("refs:::").len(), refs::PROBE_SPECS.len());
+        assert!(refs::matches_spec("clock:::*").is_empty());
+        assert_eq!(clock::matches_spec("clock:::*"), &["tick"]);
+    }
+
+    #[test]
+    fn test_probe_count() {
+        const COUNTS: [u64; refs::probe_count()] = [0; refs::probe_count()];
+        assert_eq!(COUNTS.len(), refs::PROBE_SPECS.len());
+        assert_eq!(clock::probe_count(), 1);
+    }
+
+    #[test]
+    fn test_struct_pointer() {
+        assert_eq!(
+            refs::STRUCT_DEFINITIONS,
+            &["struct Context { uint64_t id; uint32_t flags; };"]
+        );
+        let context = Context { id: 1, flags: 2 };
+        refs::struct_pointer!(|| &context);
+        refs::struct_pointer!(|| std::ptr::addr_of!(context));
+        assert_eq!((context.id, context.flags), (1, 2));
+    }
 }
@@ -0,0 +1,20 @@
+//! Test that a provider may only be defined once in a module
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+usdt::dtrace_provider!("../../../../tests/compile-errors/providers/type-mismatch.d");
+usdt::dtrace_provider!("../../../../tests/compile-errors/providers/type-mismatch.d");
+
+fn main() {}
@@ -23,10 +23,17 @@ mod tests {
         let t = trybuild::TestCases::new();
         t.compile_fail("src/type-mismatch.rs");
         t.compile_fail("src/unsupported-type.rs");
+        t.compile_fail("src/invalid-integer-width.rs");
         t.compile_fail("src/no-closure.rs");
         t.compile_fail("src/no-provider-file.rs");
         t.compile_fail("src/zero-arg-probe-type-check.rs");
         t.compile_fail("src/different-serializable-type.rs");
         t.compile_fail("src/relative-import.rs");
+        t.compile_fail("src/too-many-printf-args.rs");
+        t.compile_fail("src/double-invocation.rs");
+        t.compile_fail("src/double-invocation-modules.rs");
+        t.compile_fail("src/unsupported-argument-type.rs");
+        t.compile_fail("src/txt-extension.rs");
+        t.compile_fail("src/too-many-arguments.rs");
     }
 }
@@ -41,8 +41,9 @@ fn run_test(rx: std::sync::mpsc::Receiver<()>) {
 mod tests {
     use super::run_test;
     use std::process::Stdio;
-    use std::sync::mpsc::channel;
+    use std::sync::mpsc::{channel, TryRecvError};
     use std::thread;
+    use std::time::Duration;
     use usdt_tests_common::root_command;
 
     #[test]
@@ -54,7 +55,7 @@ mod tests {
             .arg("-l")
             .arg("-v")
             .arg("-n")
-            .arg("does__it*:::")
+            .arg("does__it*:::work")
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .spawn()
@@ -122,4 +123,40 @@ mod tests {
 
         thr.join().expect("Failed to join test runner thread");
     }
+
+    #[test]
+    fn test_array_argument() {
+        // Arrays are passed as a pointer to a copy of their elements, so DTrace must copy in the
+        // length given by the provider definition.
+        super::register_probes().unwrap();
+        let (send, recv) = channel();
+        let thr = thread::spawn(move || {
+            let samples: [u8; 16] = std::array::from_fn(|i| 2 * i as u8);
+            while let Err(TryRecvError::Empty) = recv.try_recv() {
+                super::does__it::samples!(|| samples);
+                thread::sleep(Duration::from_millis(10));
+            }
+        });
+        let output = std::process::Command::new(root_command())
+            .arg("dtrace")
+            .arg("-q")
+            .arg("-n")
+            .arg(
+                "does__it*:::samples { \
+                this->s = (uint8_t *)copyin(arg0, 16 * sizeof (uint8_t)); \
+                printf(\"%d %d\", this->s[1], this->s[15]); exit(0); }",
+            )
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .output()
+            .expect("Could not run DTrace");
+
+        // Stop the thread firing the probe
+        let _ = send.send(());
+        thr.join().expect("Failed to join probe thread");
+
+        let output = String::from_utf8_lossy(&output.stdout);
+        println!("{}", output);
+        assert_eq!(output.trim(), "2 30", "Array argument is incorrect");
+    }
 }
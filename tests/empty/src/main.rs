@@ -32,9 +32,38 @@ fn main() {
 
 #[cfg(test)]
 mod test {
+    use super::stuff;
+
     // We just want to make sure that main builds and runs.
     #[test]
     fn test_main() {
         super::main();
     }
+
+    // Probes which aren't enabled never evaluate their arguments, and with the no-op
+    // implementation, they can't be enabled.
+    #[test]
+    fn test_arguments_not_evaluated() {
+        let evaluated = std::cell::Cell::new(false);
+        stuff::start_work!(|| {
+            evaluated.set(true);
+            0
+        });
+        assert!(!evaluated.get());
+        assert_eq!(stuff::start_work_is_enabled_raw(), 0);
+    }
+
+    // The no-op implementation emits no probe records into the binary. The `asm` feature alone
+    // doesn't determine the backend, as it also depends on the target and compiler.
+    #[test]
+    fn test_no_probe_records() {
+        if !usdt::PROBES_ARE_NOOP {
+            return;
+        }
+        let exe = std::env::current_exe().unwrap();
+        assert!(matches!(
+            usdt::probe_records(exe),
+            Err(usdt::Error::InvalidFile)
+        ));
+    }
 }
@@ -0,0 +1,96 @@
+//! Integration test verifying that probe firings are counted with the `test-counters` feature.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg_attr(usdt_need_feat_asm, feature(asm))]
+#![cfg_attr(usdt_need_feat_asm_sym, feature(asm_sym))]
+
+#[usdt::provider]
+mod counted {
+    fn work(_: u8) {}
+    fn idle() {}
+}
+
+// A probe which fires whether or not a tracer has enabled it.
+#[usdt::provider(always_fire = true)]
+mod unconditional {
+    fn tick(_: u64) {}
+    fn message(_: &str) {}
+}
+
+// A printf-style probe, which takes a format string and `usdt::MAX_PRINTF_ARGS` integers.
+usdt::dtrace_provider!("logging.d");
+
+fn do_work(n: u8) {
+    for i in 0..n {
+        counted::work!(|| i);
+    }
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    do_work(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{counted, do_work, logging, unconditional};
+
+    #[test]
+    fn test_fire_counts() {
+        // Probes are counted whether or not DTrace is attached.
+        assert_eq!(counted::work_fire_count(), 0);
+        do_work(5);
+        assert_eq!(counted::work_fire_count(), 5);
+        assert_eq!(counted::idle_fire_count(), 0);
+        counted::idle!();
+        assert_eq!(counted::idle_fire_count(), 1);
+    }
+
+    #[test]
+    fn test_printf_probe_fire_counts() {
+        // Fewer than `MAX_PRINTF_ARGS` arguments are padded with zeros.
+        assert_eq!(logging::log_fire_count(), 0);
+        logging::log!(|| usdt::printf_args!("worked %d times", 3u8));
+        logging::log!(|| usdt::printf_args!("idle"));
+        assert_eq!(logging::log_fire_count(), 2);
+    }
+
+    #[test]
+    fn test_always_fire_probe_fire_counts() {
+        // The arguments of a probe which always fires are evaluated without a tracer, unless the
+        // probes are no-ops.
+        let evaluated = std::cell::Cell::new(false);
+        assert_eq!(unconditional::tick_fire_count(), 0);
+        unconditional::tick!(|| {
+            evaluated.set(true);
+            1
+        });
+        assert_eq!(unconditional::tick_fire_count(), 1);
+        assert_eq!(evaluated.get(), !usdt::PROBES_ARE_NOOP);
+    }
+
+    #[test]
+    fn test_string_probe_fire_counts() {
+        // String arguments may be anything implementing `AsRef<str>`. The probe always fires, so
+        // each argument is copied into the probe's buffer.
+        let owned = String::from("owned");
+        unconditional::message!(|| "borrowed");
+        unconditional::message!(|| owned.clone());
+        unconditional::message!(|| &owned);
+        unconditional::message!(|| std::borrow::Cow::from("cow"));
+        assert_eq!(unconditional::message_fire_count(), 4);
+    }
+}
@@ -17,6 +17,16 @@
 
 mod inner;
 
+/// A provider defined in this module's documentation.
+///
+/// ```dtrace
+/// provider documented {
+///     probe also_visible(uint8_t);
+/// };
+/// ```
+#[usdt::doc_provider]
+mod documented {}
+
 fn main() {
     usdt::register_probes().expect("Could not register probes");
     // Verify that we can call the probe from its full path.
@@ -26,4 +36,31 @@ fn main() {
     // probe macro, with a link-name for a symbol that the macOS linker will generate for us. This
     // checks that there is no issue defining these locally-scoped extern symbols multiple times.
     inner::probes::am_i_visible!(|| ());
+
+    // Providers can be enabled and disabled for compatibility with other USDT crates, which
+    // register the probes and do nothing, respectively.
+    inner::probes::enable().expect("Could not enable provider");
+    inner::probes::disable().expect("Could not disable provider");
+
+    // Probes defined in doc comments are generated in place of the annotated module.
+    documented::also_visible!(|| 1);
+
+    // Both providers are collected into the registry of all providers in the program.
+    let mut providers = usdt::all_providers();
+    providers.sort_by_key(|provider| provider.name);
+    assert_eq!(
+        providers,
+        &[
+            usdt::ProviderInfo {
+                name: "documented",
+                module_path: "modules::documented",
+                probes: &["also_visible"],
+            },
+            usdt::ProviderInfo {
+                name: "modules",
+                module_path: "modules::inner::probes",
+                probes: &["am_i_visible"],
+            },
+        ]
+    );
 }
@@ -0,0 +1,48 @@
+//! Integration test verifying that `probes!` re-exports the probe macros at the crate root.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg_attr(usdt_need_feat_asm, feature(asm))]
+#![cfg_attr(usdt_need_feat_asm_sym, feature(asm_sym))]
+
+usdt::probes!("provider reexported { probe fired(uint8_t); probe stopped(); };");
+
+mod inner {
+    pub fn fire() {
+        // The probe macros are available from the crate root, as well as the provider module.
+        crate::fired!(|| 1);
+        crate::stopped!();
+        crate::reexported::fired!(|| 2);
+    }
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    fired!(|| 0);
+    inner::fire();
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_reexported_from_crate_root() {
+        assert_eq!(
+            crate::PROBE_SPECS,
+            &["reexported:::fired", "reexported:::stopped"]
+        );
+        assert_eq!(crate::PROBE_SPECS, crate::reexported::PROBE_SPECS);
+        super::inner::fire();
+    }
+}
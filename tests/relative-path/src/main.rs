@@ -0,0 +1,41 @@
+//! Integration test verifying that provider files may be found relative to the invoking file.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg_attr(usdt_need_feat_asm, feature(asm))]
+#![cfg_attr(usdt_need_feat_asm_sym, feature(asm_sym))]
+
+mod nested;
+
+// Found next to this file, rather than at the root of the package.
+usdt::dtrace_provider!(relative = "provider.d");
+
+fn main() {
+    usdt::register_probes().unwrap();
+    nested::start(1);
+    top::stop!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nested, top};
+
+    #[test]
+    fn test_relative_providers() {
+        assert_eq!(top::PROBE_SPECS, &["top:::stop"]);
+        assert_eq!(nested::nested::PROBE_SPECS, &["nested:::start"]);
+        super::main();
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use usdt::Builder;
+use usdt::{BitWidth, Builder, Integer, NativeType, ProviderSpec, Sign};
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
@@ -29,4 +29,19 @@ fn main() {
 
     println!("cargo:rerun-if-changed=test.d");
     Builder::new("test.d").module("still_test").build().unwrap();
+
+    // A provider defined in Rust, rather than in a D file.
+    let spec = ProviderSpec::new("generated")
+        .probe(
+            "got-request",
+            &[
+                NativeType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit64,
+                }),
+                NativeType::String,
+            ],
+        )
+        .probe("sent-response", &[]);
+    Builder::from_spec(spec).module("spec").build().unwrap();
 }
@@ -18,6 +18,7 @@
 #![cfg_attr(usdt_need_feat_asm, feature(asm))]
 #![cfg_attr(usdt_need_feat_asm_sym, feature(asm_sym))]
 include!(concat!(env!("OUT_DIR"), "/test.rs"));
+include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 
 fn main() {
     usdt::register_probes().unwrap();
@@ -25,4 +26,12 @@ fn main() {
     // Renamed the module that the probes are generated to `still_test`. So naming them as
     // `test::start_work` will fail.
     still_test::start_work!(|| 0);
+
+    // Probes generated from a `ProviderSpec` are the same as those from D source.
+    spec::got_request!(|| (1, "foo"));
+    spec::sent_response!(|| ());
+    assert_eq!(
+        spec::PROBE_SPECS,
+        &["generated:::got-request", "generated:::sent-response"]
+    );
 }
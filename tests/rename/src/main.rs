@@ -22,7 +22,28 @@ mod probes {
     fn something() {}
 }
 
+// The Rust module name differs from the provider name seen by DTrace.
+usdt::dtrace_provider!(
+    "provider renamed { probe fired(); };",
+    rust_name = "RenamedProbes"
+);
+
+// Providers and probes named with Rust keywords are generated as raw identifiers, while DTrace sees
+// the original names.
+usdt::dtrace_provider!("provider type { probe match(uint8_t); };");
+
+#[usdt::provider]
+mod r#async {
+    fn r#match(_: u8) {}
+}
+
 fn main() {
     usdt::register_probes().unwrap();
     probes::probe_something!(|| ());
+    RenamedProbes::fired!(|| ());
+    assert_eq!(RenamedProbes::PROBE_SPECS, &["renamed:::fired"]);
+    r#type::r#match!(|| 1);
+    assert_eq!(r#type::PROBE_SPECS, &["type:::match"]);
+    r#async::r#match!(|| 1);
+    assert_eq!(r#async::PROBE_SPECS, &["async:::match"]);
 }
@@ -0,0 +1,308 @@
+//! Integration test verifying the SystemTap SDT notes emitted with the `stapsdt` feature.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg_attr(usdt_need_feat_asm, feature(asm))]
+#![cfg_attr(usdt_need_feat_asm_sym, feature(asm_sym))]
+
+#[usdt::provider]
+mod traced {
+    fn request(_: i32, _: &str, _: u64) {}
+    fn done() {}
+    fn lookup(_: &str, _: &str) {}
+    fn idle() {}
+}
+
+// A probe which fires whether or not a tracer has enabled it.
+#[usdt::provider(always_fire = true)]
+mod unconditional {
+    fn nested(_: u64) {}
+}
+
+// Large slices of integers are passed by reference, rather than copied into the JSON.
+#[usdt::provider(always_fire = true)]
+mod large {
+    fn samples(_: &[u32]) {}
+}
+
+// A provider whose name, as seen by tracers, is namespaced by a prefix.
+#[usdt::provider(provider_prefix = "mylib_")]
+mod prefixed {
+    fn begin() {}
+}
+
+fn handle(id: i32, path: &str) {
+    traced::request!(|| (id, path, path.len() as u64));
+    traced::done!();
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    handle(1, "/index.html");
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::{large, prefixed, traced, unconditional};
+    use goblin::elf::Elf;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+
+    // An allocator counting the allocations made by each thread, since tests run concurrently.
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    // A single SDT note, as read back from the test binary.
+    #[derive(Debug)]
+    struct StapsdtNote {
+        pc: u64,
+        base: u64,
+        semaphore: u64,
+        provider: String,
+        probe: String,
+        arguments: String,
+    }
+
+    fn read_notes(data: &[u8], elf: &Elf<'_>) -> Vec<StapsdtNote> {
+        elf.iter_note_sections(data, Some(".note.stapsdt"))
+            .into_iter()
+            .flatten()
+            .map(|note| note.unwrap())
+            .filter(|note| note.name == "stapsdt" && note.n_type == 3)
+            .map(|note| {
+                let word = |i: usize| {
+                    u64::from_ne_bytes(note.desc[i * 8..(i + 1) * 8].try_into().unwrap())
+                };
+                let mut strings = note.desc[24..]
+                    .split(|byte| *byte == 0)
+                    .map(|s| String::from_utf8(s.to_vec()).unwrap());
+                StapsdtNote {
+                    pc: word(0),
+                    base: word(1),
+                    semaphore: word(2),
+                    provider: strings.next().unwrap(),
+                    probe: strings.next().unwrap(),
+                    arguments: strings.next().unwrap(),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stapsdt_notes() {
+        let data = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let elf = Elf::parse(&data).unwrap();
+        let notes = read_notes(&data, &elf);
+        let section_addr = |name: &str| {
+            elf.section_headers
+                .iter()
+                .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(name))
+                .map(|header| (header.sh_addr, header.sh_addr + header.sh_size))
+                .unwrap()
+        };
+        let (base, _) = section_addr(".stapsdt.base");
+        let (probes_start, probes_end) = section_addr(".probes");
+
+        let request = notes
+            .iter()
+            .find(|note| note.provider == "traced" && note.probe == "request")
+            .unwrap();
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(request.arguments, "-8@%rdi 8@%rsi 8@%rdx");
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(request.arguments, "-8@x0 8@x1 8@x2");
+        assert_eq!(request.base, base);
+        assert_ne!(request.pc, 0);
+        assert!((probes_start..probes_end).contains(&request.semaphore));
+
+        let done = notes
+            .iter()
+            .find(|note| note.provider == "traced" && note.probe == "done")
+            .unwrap();
+        assert_eq!(done.arguments, "");
+        assert_ne!(done.semaphore, request.semaphore);
+    }
+
+    #[test]
+    fn test_stapsdt_semaphores() {
+        let data = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let elf = Elf::parse(&data).unwrap();
+        let notes = read_notes(&data, &elf);
+        let symbol = |name: &str| {
+            elf.syms
+                .iter()
+                .find(|sym| elf.strtab.get_at(sym.st_name) == Some(name))
+                .unwrap()
+        };
+
+        // Each probe has its own semaphore, referenced by the notes of all of its sites.
+        for (probe, name) in [
+            ("request", "__usdt_sema_traced_request"),
+            ("done", "__usdt_sema_traced_done"),
+        ] {
+            let semaphore = symbol(name);
+            assert_eq!(semaphore.st_size, 2);
+            let mut sites = notes.iter().filter(|note| note.probe == probe).peekable();
+            assert!(sites.peek().is_some());
+            assert!(sites.all(|note| note.semaphore == semaphore.st_value));
+        }
+    }
+
+    #[test]
+    fn test_stapsdt_disabled() {
+        // No tracer has incremented the semaphore, so the arguments aren't evaluated.
+        let evaluated = Cell::new(false);
+        traced::request!(|| {
+            evaluated.set(true);
+            (0, "", 0)
+        });
+        assert!(!evaluated.get());
+        super::handle(2, "/");
+    }
+
+    #[test]
+    fn test_stapsdt_enabled() {
+        extern "C" {
+            #[link_name = "__usdt_sema_traced_done"]
+            static mut SEMAPHORE: u16;
+        }
+
+        // Act as a tracer attaching to the probe, which enables it.
+        let evaluated = Cell::new(false);
+        unsafe { std::ptr::write_volatile(std::ptr::addr_of_mut!(SEMAPHORE), 1) };
+        traced::done!(|| evaluated.set(true));
+        unsafe { std::ptr::write_volatile(std::ptr::addr_of_mut!(SEMAPHORE), 0) };
+        assert!(evaluated.get());
+    }
+
+    #[test]
+    fn test_stapsdt_is_enabled_raw() {
+        extern "C" {
+            #[link_name = "__usdt_sema_traced_idle"]
+            static mut SEMAPHORE: u16;
+        }
+
+        // The probe has no sites, so its semaphore is defined by the reader alone.
+        assert_eq!(traced::idle_is_enabled_raw(), 0);
+        unsafe { std::ptr::write_volatile(std::ptr::addr_of_mut!(SEMAPHORE), 2) };
+        let raw = traced::idle_is_enabled_raw();
+        unsafe { std::ptr::write_volatile(std::ptr::addr_of_mut!(SEMAPHORE), 0) };
+        assert_eq!(raw, 2);
+    }
+
+    #[test]
+    fn test_stapsdt_buffer() {
+        extern "C" {
+            #[link_name = "__usdt_sema_traced_lookup"]
+            static mut SEMAPHORE: u16;
+        }
+
+        unsafe { std::ptr::write_volatile(std::ptr::addr_of_mut!(SEMAPHORE), 1) };
+        let mut buffer = Vec::new();
+        let key = String::from("user");
+        let value = String::from("alice");
+        // The first firing may allocate, e.g., to count firings with the `test-counters` feature.
+        traced::lookup!(buffer = &mut buffer, || (&key, &value));
+        let before = ALLOCATIONS.with(Cell::get);
+        traced::lookup!(buffer = &mut buffer, || (&key, &value));
+        traced::lookup!(buffer = &mut buffer, || (&key, "bob"));
+        let after = ALLOCATIONS.with(Cell::get);
+        unsafe { std::ptr::write_volatile(std::ptr::addr_of_mut!(SEMAPHORE), 0) };
+
+        // The strings of the last firing are left in the buffer, each NUL-terminated.
+        assert_eq!(buffer, b"user\0bob\0");
+        assert_eq!(after, before);
+    }
+
+    // Fire the nested probe from within its own argument closure, to the given depth.
+    fn fire_nested(depth: u64, calls: &Cell<u64>) {
+        unconditional::nested!(|| {
+            calls.set(calls.get() + 1);
+            if depth > 0 {
+                fire_nested(depth - 1, calls);
+            }
+            depth
+        });
+    }
+
+    #[test]
+    fn test_stapsdt_reentrant_fire_dropped() {
+        // Only the outermost probe evaluates its arguments, the nested fire is dropped.
+        let calls = Cell::new(0);
+        fire_nested(3, &calls);
+        assert_eq!(calls.get(), 1);
+
+        // The guard is released once the probe has fired.
+        fire_nested(0, &calls);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_stapsdt_provider_prefix() {
+        // The module keeps its name, while the note carries the prefixed provider name.
+        prefixed::begin!();
+        let data = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let elf = Elf::parse(&data).unwrap();
+        let notes = read_notes(&data, &elf);
+        assert!(notes
+            .iter()
+            .any(|note| note.provider == "mylib_prefixed" && note.probe == "begin"));
+        assert!(!notes.iter().any(|note| note.provider == "prefixed"));
+    }
+
+    #[test]
+    fn test_stapsdt_large_slice_argument() {
+        // The probe always fires, so a large array is accepted where it takes a slice, and its
+        // argument is evaluated.
+        let samples = [7u32; 128];
+        let fired = Cell::new(false);
+        large::samples!(|| {
+            fired.set(true);
+            &samples
+        });
+        assert!(fired.get());
+
+        // The argument is passed in a single register, as a pointer to the NUL-terminated JSON
+        // referring to the slice, whose contents are checked against the generated code in
+        // `usdt-impl`.
+        let data = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let elf = Elf::parse(&data).unwrap();
+        let note = read_notes(&data, &elf)
+            .into_iter()
+            .find(|note| note.provider == "large" && note.probe == "samples")
+            .unwrap();
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(note.arguments, "8@%rdi");
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(note.arguments, "8@x0");
+    }
+}
@@ -141,7 +141,7 @@ mod tests {
         // Wait for the process to finish, up to a pretty generous limit.
         let output = tokio::time::timeout_at(now + MAX_WAIT, dtrace.wait_with_output())
             .await
-            .expect(&format!("DTrace did not complete within {:?}", MAX_WAIT))
+            .unwrap_or_else(|_| panic!("DTrace did not complete within {:?}", MAX_WAIT))
             .expect("Failed to wait for DTrace subprocess");
         assert!(
             output.status.success(),
@@ -150,7 +150,7 @@ mod tests {
         );
         let stdout = std::str::from_utf8(&output.stdout).expect("Non-UTF8 stdout");
         println!("DTrace output\n{}\n", stdout);
-        let json: Value = serde_json::from_str(&stdout).unwrap();
+        let json: Value = serde_json::from_str(stdout).unwrap();
         json
     }
 
@@ -168,16 +168,13 @@ mod tests {
                 let mut bytes = vec![0; 128];
                 stdout.read(&mut bytes).await.map(|_| bytes)
             });
-            match read_task.await {
-                Ok(read_result) => {
-                    let chunk = read_result.expect("Failed to read DTrace stdout");
-                    output.push_str(std::str::from_utf8(&chunk).expect("Non-UTF8 stdout"));
-                    if output.contains(BEGIN_SENTINEL) {
-                        println!("DTrace started up successfully");
-                        return;
-                    }
+            if let Ok(read_result) = read_task.await {
+                let chunk = read_result.expect("Failed to read DTrace stdout");
+                output.push_str(std::str::from_utf8(&chunk).expect("Non-UTF8 stdout"));
+                if output.contains(BEGIN_SENTINEL) {
+                    println!("DTrace started up successfully");
+                    return;
                 }
-                _ => {}
             }
             println!("DTrace not yet ready");
             continue;
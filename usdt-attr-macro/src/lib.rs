@@ -37,6 +37,10 @@ pub fn provider(
                 )
                 .to_compile_error()
                 .into()
+            } else if let Err(e) = config.validate() {
+                syn::Error::new(attr.span(), e.to_string())
+                    .to_compile_error()
+                    .into()
             } else {
                 generate_provider_item(TokenStream::from(item), config)
                     .unwrap_or_else(|e| e.to_compile_error())
@@ -47,6 +51,100 @@ pub fn provider(
     }
 }
 
+/// Derive `usdt::ProbeStruct` for a `#[repr(C)]` struct, so that it may be passed to a probe by
+/// pointer.
+///
+/// The fields of the struct must be named, and each must be an integer, a floating point number,
+/// or a const pointer to an integer.
+#[proc_macro_derive(ProbeStruct)]
+pub fn derive_probe_struct(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    generate_probe_struct_impl(TokenStream::from(item))
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+// Generate the implementation of `ProbeStruct`, with the D definition of the struct.
+fn generate_probe_struct_impl(item: TokenStream) -> Result<TokenStream, syn::Error> {
+    let item = syn::parse2::<syn::ItemStruct>(item)?;
+    if !is_repr_c(&item.attrs) {
+        return Err(syn::Error::new(
+            item.ident.span(),
+            "Probe structs must be #[repr(C)]",
+        ));
+    }
+    if !item.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            item.generics.span(),
+            "Probe structs may not be generic",
+        ));
+    }
+    let fields = match &item.fields {
+        syn::Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let name = syn::ext::IdentExt::unraw(field.ident.as_ref().unwrap());
+                probe_struct_field_type(&field.ty).map(|ty| format!("{} {};", ty, name))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => {
+            return Err(syn::Error::new(
+                item.fields.span(),
+                "Probe structs must have named fields",
+            ))
+        }
+    };
+    let ident = &item.ident;
+    let definition = format!("struct {} {{ {} }};", ident, fields.join(" "));
+    Ok(quote! {
+        impl ::usdt::ProbeStruct for #ident {
+            const D_DEFINITION: &'static str = #definition;
+        }
+    })
+}
+
+// Return `true` if the attributes include `#[repr(C)]`.
+fn is_repr_c(attrs: &[syn::Attribute]) -> bool {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("repr"))
+        .any(|attr| {
+            let mut repr_c = false;
+            let _ = attr.parse_nested_meta(|meta| {
+                repr_c |= meta.path.is_ident("C");
+                Ok(())
+            });
+            repr_c
+        })
+}
+
+// Return the C type of a field of a probe struct.
+fn probe_struct_field_type(ty: &syn::Type) -> syn::Result<String> {
+    let to_err = || {
+        syn::Error::new(
+            ty.span(),
+            "Fields of probe structs must be integers, floats, or const pointers to integers",
+        )
+    };
+    match ty {
+        syn::Type::Path(path) => {
+            let ident = path.path.get_ident().ok_or_else(to_err)?;
+            if is_simple_type(ident) && ident != "String" && ident != "str" {
+                Ok(data_type_from_path(&path.path, false).to_c_type())
+            } else {
+                Err(to_err())
+            }
+        }
+        syn::Type::Ptr(pointer) if pointer.mutability.is_none() => match &*pointer.elem {
+            syn::Type::Path(path) if path.path.get_ident().map_or(false, is_integer_type) => {
+                Ok(data_type_from_path(&path.path, true).to_c_type())
+            }
+            _ => Err(to_err()),
+        },
+        _ => Err(to_err()),
+    }
+}
+
 // Generate the actual provider implementation, include the type-checks and probe macros.
 fn generate_provider_item(
     item: TokenStream,
@@ -97,7 +195,7 @@ fn generate_provider_item(
                 }
                 check_fns.extend(item_check_fns);
                 probes.push(Probe {
-                    name: signature.ident.to_string(),
+                    name: syn::ext::IdentExt::unraw(&signature.ident).to_string(),
                     types: item_types,
                 });
             }
@@ -121,11 +219,11 @@ fn generate_provider_item(
     let name = match &config.provider {
         Some(name) => {
             let name = name.to_string();
-            config.module = Some(mod_.ident.to_string());
+            config.module = Some(syn::ext::IdentExt::unraw(&mod_.ident).to_string());
             name
         }
         None => {
-            let name = mod_.ident.to_string();
+            let name = syn::ext::IdentExt::unraw(&mod_.ident).to_string();
             config.provider = Some(name.clone());
             config.module = Some(name.clone());
             name
@@ -144,7 +242,10 @@ fn generate_provider_item(
         quote! {
             const _: fn() = || {
                 #(#use_statements)*
+                #[allow(dead_code)]
                 fn usdt_types_must_be_clone_and_serialize<T: ?Sized + Clone + ::serde::Serialize>() {}
+                #[allow(dead_code)]
+                fn usdt_types_must_be_probe_structs<T: ::usdt::ProbeStruct>() {}
                 #(#check_fns)*
             };
         }
@@ -207,13 +308,13 @@ fn parse_probe_argument(
                         syn::Error::new(path.span(), "Probe arguments should resolve to path types")
                     })?
                     .ident;
-                if !is_integer_type(last_ident) {
-                    return Err(syn::Error::new(
-                        item.span(),
-                        "Only pointers to integer types are supported",
-                    ));
+                if is_integer_type(last_ident) {
+                    Ok((None, data_type_from_path(&path.path, true)))
+                } else {
+                    // Any other pointee must be a struct whose layout is described to DTrace.
+                    let check_fn = build_probe_struct_check_function(ty, fn_index, arg_index);
+                    Ok((Some(check_fn), DataType::StructPointer(ty.clone())))
                 }
-                Ok((None, data_type_from_path(&path.path, true)))
             } else {
                 Err(syn::Error::new(
                     item.span(),
@@ -239,7 +340,7 @@ fn parse_probe_argument(
             item.span(),
             concat!(
                 "Probe arguments must be path types, slices, arrays, tuples, ",
-                "references, or const pointers to integers",
+                "references, or const pointers to integers or structs",
             ),
         )),
     }
@@ -283,6 +384,24 @@ where
     }
 }
 
+// Create a function that statically asserts the given type implements `ProbeStruct`.
+fn build_probe_struct_check_function(
+    ty: &syn::Type,
+    fn_index: usize,
+    arg_index: usize,
+) -> TokenStream {
+    let fn_name = quote::format_ident!(
+        "usdt_types_must_be_probe_structs_{}_{}",
+        fn_index,
+        arg_index
+    );
+    quote! {
+        fn #fn_name() {
+            usdt_types_must_be_probe_structs::<#ty>()
+        }
+    }
+}
+
 // Return `true` if the type is an integer
 fn is_integer_type(ident: &syn::Ident) -> bool {
     let ident = format!("{}", ident);
@@ -309,6 +428,8 @@ fn is_simple_type(ident: &syn::Ident) -> bool {
             | "str"
             | "usize"
             | "isize"
+            | "f32"
+            | "f64"
     )
 }
 
@@ -365,6 +486,10 @@ fn data_type_from_path(path: &syn::Path, pointer: bool) -> DataType {
             sign: Sign::Signed,
             width: BitWidth::Bit64,
         }))
+    } else if path.is_ident("f32") {
+        DataType::Native(DType::Float)
+    } else if path.is_ident("f64") {
+        DataType::Native(DType::Double)
     } else if path.is_ident("String") || path.is_ident("str") {
         DataType::Native(DType::String)
     } else if path.is_ident("isize") {
@@ -466,6 +591,8 @@ mod tests {
     #[case("String", DType::String)]
     #[case("&&str", DType::String)]
     #[case("&String", DType::String)]
+    #[case("f32", DType::Float)]
+    #[case("&f64", DType::Double)]
     fn test_parse_probe_argument_native(#[case] name: &str, #[case] ty: dtrace_parser::DataType) {
         let arg = syn::parse_str(name).unwrap();
         let out = parse_probe_argument(&arg, 0, 0).unwrap();
@@ -499,6 +626,55 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("*const Context")]
+    #[case("*const crate::Context")]
+    fn test_parse_probe_argument_struct_pointer(#[case] name: &str) {
+        let ty: syn::Type = syn::parse_str(name).unwrap();
+        let out = parse_probe_argument(&ty, 0, 0).unwrap();
+        assert!(out.0.is_some());
+        if let syn::Type::Ptr(pointer) = ty {
+            assert_eq!(out.1, DataType::StructPointer(*pointer.elem));
+        } else {
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn test_generate_probe_struct_impl() {
+        let item = quote! {
+            #[derive(Clone)]
+            #[repr(C)]
+            struct Context {
+                id: u64,
+                r#type: u8,
+                ratio: f64,
+                len: u32,
+                data: *const i32,
+            }
+        };
+        let expected = quote! {
+            impl ::usdt::ProbeStruct for Context {
+                const D_DEFINITION: &'static str =
+                    "struct Context { uint64_t id; uint8_t type; double ratio; uint32_t len; int32_t* data; };";
+            }
+        };
+        assert_eq!(
+            generate_probe_struct_impl(item).unwrap().to_string(),
+            expected.to_string()
+        );
+    }
+
+    #[rstest]
+    #[case(quote! { struct Context { id: u64 } })]
+    #[case(quote! { #[repr(C)] struct Context(u64); })]
+    #[case(quote! { #[repr(C)] struct Context<T> { id: T } })]
+    #[case(quote! { #[repr(C)] struct Context { name: String } })]
+    #[case(quote! { #[repr(C)] struct Context { data: *mut u8 } })]
+    fn test_generate_probe_struct_impl_invalid(#[case] item: TokenStream) {
+        assert!(generate_probe_struct_impl(item).is_err());
+    }
+
     #[test]
     fn test_check_probe_function_signature() {
         let signature = syn::parse_str::<syn::Signature>("fn foo(_: u8)").unwrap();
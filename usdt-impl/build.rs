@@ -14,13 +14,17 @@
 //
 
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug)]
 enum Backend {
     // Standard (read: illumos) probe registration
     Standard,
     // MacOS linker-aware probe registration
     Linker,
+    // SystemTap SDT notes, read by Linux tracers
+    Stapsdt,
     // Provide probe macros, but probes are no-ops (dtrace-less OSes)
     NoOp,
 }
@@ -38,6 +42,7 @@ fn main() {
 
     let feat_asm = env::var_os("CARGO_FEATURE_ASM").is_some();
     let feat_strict_asm = env::var_os("CARGO_FEATURE_STRICT_ASM").is_some();
+    let feat_stapsdt = env::var_os("CARGO_FEATURE_STAPSDT").is_some();
 
     let backend = match env::var("CARGO_CFG_TARGET_OS").ok().as_deref() {
         Some("macos") if feat_asm => {
@@ -65,6 +70,16 @@ fn main() {
                 Backend::NoOp
             }
         }
+        Some("linux") if feat_asm && feat_stapsdt => {
+            if have_stable_asm {
+                Backend::Stapsdt
+            } else if feat_strict_asm || is_nightly {
+                println!("cargo:rustc-cfg=usdt_need_feat_asm");
+                Backend::Stapsdt
+            } else {
+                Backend::NoOp
+            }
+        }
         _ => {
             if !have_stable_asm {
                 println!("cargo:rustc-cfg=usdt_need_feat_asm");
@@ -79,6 +94,8 @@ fn main() {
         println!("cargo:rustc-cfg=usdt_stable_asm");
     }
 
+    emit_generator_hash(backend);
+
     match backend {
         Backend::NoOp => {
             println!("cargo:rustc-cfg=usdt_backend_noop");
@@ -89,5 +106,59 @@ fn main() {
         Backend::Standard => {
             println!("cargo:rustc-cfg=usdt_backend_standard");
         }
+        Backend::Stapsdt => {
+            println!("cargo:rustc-cfg=usdt_backend_stapsdt");
+        }
+    }
+}
+
+// Emit a hash identifying the code generator, as `USDT_IMPL_GENERATOR_HASH`.
+//
+// This covers the sources of this crate and, when built in the workspace, of `dtrace-parser`, as
+// well as the backend and features. Published versions of the crates are immutable, but path and
+// git dependencies may change without a change in version.
+fn emit_generator_hash(backend: Backend) {
+    let manifest_dir = PathBuf::from(env::var_os("CARGO_MANIFEST_DIR").unwrap());
+    let mut files = Vec::new();
+    for dir in [
+        manifest_dir.join("src"),
+        manifest_dir.join("../dtrace-parser/src"),
+    ] {
+        if dir.is_dir() {
+            println!("cargo:rerun-if-changed={}", dir.display());
+            collect_files(&dir, &mut files);
+        }
+    }
+    files.sort();
+
+    // 64-bit FNV-1a, as used by `usdt::Builder` for the provider source.
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    let mut update = |bytes: &[u8]| {
+        for byte in bytes {
+            hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    };
+    for file in files.iter() {
+        update(&fs::read(file).unwrap_or_default());
+        update(b"\0");
+    }
+    let mut features = env::vars()
+        .map(|(key, _)| key)
+        .filter(|key| key.starts_with("CARGO_FEATURE_"))
+        .collect::<Vec<_>>();
+    features.sort();
+    update(format!("{:?}\0{:?}", backend, features).as_bytes());
+    println!("cargo:rustc-env=USDT_IMPL_GENERATOR_HASH={:016x}", hash);
+}
+
+// Collect the paths of all files under a directory.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
     }
 }
@@ -0,0 +1,260 @@
+//! Validate provider definitions without generating any code.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use dtrace_parser::DTraceError;
+use std::convert::TryFrom;
+
+/// A problem found in a provider definition, at a location in its source.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The line of the problem, starting from 1.
+    pub line: usize,
+    /// The column of the problem, in characters, starting from 1.
+    pub column: usize,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Check that D source defines valid providers, from which probes can be generated.
+///
+/// This applies the same checks as generating the probes, without generating any code: the source
+/// must parse, the names of providers and probes must be valid and unique, and must not be
+/// reserved Rust keywords. Names longer than DTrace supports, and probes with more arguments than
+/// are passed to DTrace, are also reported, although generating their probes succeeds.
+///
+/// Parsing stops at the first syntax error, so at most one diagnostic is returned in that case.
+pub fn check_provider_source(source: &str) -> Result<(), Vec<Diagnostic>> {
+    let source = source.strip_prefix('\u{feff}').unwrap_or(source);
+    let file = dtrace_parser::File::try_from(source)
+        .map_err(|error| vec![parse_error_diagnostic(source, &error)])?;
+
+    let mut diagnostics = Vec::new();
+    for provider in file.providers() {
+        let at_provider = |message: String| {
+            let (line, column) = locate(source, "provider", &provider.name, 0);
+            Diagnostic {
+                line,
+                column,
+                message,
+            }
+        };
+        if crate::RESERVED_NAMES.contains(&provider.name.as_str()) {
+            diagnostics.push(at_provider(
+                crate::Error::ReservedName(provider.name.clone()).to_string(),
+            ));
+        }
+        if provider.name.len() >= crate::MAX_PROVIDER_NAME_LEN {
+            diagnostics.push(at_provider(format!(
+                "The provider name \"{}\" is longer than the {} characters supported by DTrace",
+                provider.name,
+                crate::MAX_PROVIDER_NAME_LEN - 1,
+            )));
+        }
+
+        for probe in provider.probes.iter() {
+            let at_probe = |message: String| {
+                let (line, column) = locate(source, "probe", &probe.name, 0);
+                Diagnostic {
+                    line,
+                    column,
+                    message,
+                }
+            };
+            let rust_name = probe.rust_name();
+            if crate::RESERVED_NAMES.contains(&rust_name.as_str()) {
+                diagnostics.push(at_probe(crate::Error::ReservedName(rust_name).to_string()));
+            }
+            if probe.name.len() >= crate::MAX_PROBE_NAME_LEN {
+                diagnostics.push(at_probe(format!(
+                    "The probe name \"{}\" is longer than the {} characters supported by DTrace",
+                    probe.name,
+                    crate::MAX_PROBE_NAME_LEN - 1,
+                )));
+            }
+            if probe.types.len() > crate::MAX_PROBE_ARGUMENTS {
+                diagnostics.push(at_probe(format!(
+                    "The probe \"{}\" has {} arguments, but only the first {} are visible to DTrace",
+                    probe.name,
+                    probe.types.len(),
+                    crate::MAX_PROBE_ARGUMENTS,
+                )));
+            }
+        }
+    }
+
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics)
+    }
+}
+
+// Build the diagnostic for an error parsing the source.
+//
+// Syntax errors carry their own location. Other errors only name the offending provider or probe,
+// so they're located by finding its definition in the source.
+fn parse_error_diagnostic(source: &str, error: &DTraceError) -> Diagnostic {
+    if let Some((line, column, message)) = error.syntax_error() {
+        return Diagnostic {
+            line,
+            column,
+            message,
+        };
+    }
+    let (line, column) = match error {
+        DTraceError::InvalidProviderName(name) => locate(source, "provider", name, 0),
+        DTraceError::InvalidProbeName(name) => locate(source, "probe", name, 0),
+        // The second definition of the probe is the duplicate.
+        DTraceError::DuplicateProbeName((_, name)) => locate(source, "probe", name, 1),
+        DTraceError::CollidingProbeName(_, name) => locate(source, "probe", name, 0),
+        DTraceError::UnsupportedArgumentType { probe, .. } => locate(source, "probe", probe, 0),
+        _ => (1, 1),
+    };
+    Diagnostic {
+        line,
+        column,
+        message: error.to_string(),
+    }
+}
+
+// Return the line and column of the `nth` definition of the given name, following the keyword
+// `provider` or `probe`, or the start of the source if it can't be found.
+fn locate(source: &str, keyword: &str, name: &str, nth: usize) -> (usize, usize) {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+    source
+        .match_indices(name)
+        .filter(|(start, _)| {
+            let before = &source[..*start];
+            let after = &source[start + name.len()..];
+            !after.starts_with(is_name_char)
+                && before.ends_with(char::is_whitespace)
+                && before.trim_end().ends_with(keyword)
+        })
+        .nth(nth)
+        .map(|(start, _)| {
+            let before = &source[..start];
+            let line = before.matches('\n').count() + 1;
+            let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+            (line, before[line_start..].chars().count() + 1)
+        })
+        .unwrap_or((1, 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_provider_source, Diagnostic};
+
+    fn diagnostic(line: usize, column: usize, message: &str) -> Diagnostic {
+        Diagnostic {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_check_valid_provider() {
+        let source = "provider foo {\n    probe bar(uint8_t);\n    probe baz-qux(char*);\n};";
+        assert_eq!(check_provider_source(source), Ok(()));
+    }
+
+    #[test]
+    fn test_check_syntax_error() {
+        let diagnostics =
+            check_provider_source("provider foo {\n    probe bar(uint8_t;\n};").unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 22));
+    }
+
+    #[test]
+    fn test_check_unsupported_type() {
+        let source = "provider foo {\n    probe bar(u8);\n};";
+        assert_eq!(
+            check_provider_source(source),
+            Err(vec![diagnostic(
+                2,
+                11,
+                "The type \"u8\" of an argument of the probe \"bar\" is not supported"
+            )])
+        );
+    }
+
+    #[test]
+    fn test_check_duplicate_probe() {
+        let source = "provider foo {\n    probe bar();\n    probe bar();\n};";
+        assert_eq!(
+            check_provider_source(source),
+            Err(vec![diagnostic(
+                3,
+                11,
+                "Provider and probe name pairs must be unique: duplicated \"(\"foo\", \"bar\")\""
+            )])
+        );
+    }
+
+    #[test]
+    fn test_check_colliding_probes() {
+        let source = "provider foo {\n    probe gc-begin();\n    probe gc_begin();\n};";
+        let diagnostics = check_provider_source(source).unwrap_err();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (3, 11));
+    }
+
+    #[test]
+    fn test_check_reserved_and_limits() {
+        let name = "p".repeat(64);
+        let source = format!(
+            "provider crate {{\n\tprobe {}();\n\tprobe many({});\n}};",
+            name,
+            ["uint8_t"; 7].join(", ")
+        );
+        let diagnostics = check_provider_source(&source).unwrap_err();
+        assert_eq!(
+            diagnostics,
+            vec![
+                diagnostic(
+                    1,
+                    10,
+                    "The name \"crate\" is a reserved Rust keyword, and can't be used as a \
+                    provider or probe name"
+                ),
+                diagnostic(
+                    2,
+                    8,
+                    &format!(
+                        "The probe name \"{}\" is longer than the 63 characters supported by DTrace",
+                        name
+                    )
+                ),
+                diagnostic(
+                    3,
+                    8,
+                    "The probe \"many\" has 7 arguments, but only the first 6 are visible to DTrace"
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnostics[2].to_string(),
+            "3:8: The probe \"many\" has 7 arguments, but only the first 6 are visible to DTrace"
+        );
+    }
+}
@@ -77,6 +77,8 @@ pub fn generate_type_check(
                 }
             }
             DataType::Native(dtrace_parser::DataType::String) => quote! { _: impl AsRef<str> },
+            // Taking the pointer itself allows passing either a reference or a raw pointer.
+            DataType::StructPointer(ty) => quote! { _: *const #ty },
             _ => {
                 let arg = typ.to_rust_type();
                 quote! { _: impl ::std::borrow::Borrow<#arg> }
@@ -94,8 +96,11 @@ pub fn generate_type_check(
 
     let preamble = unpack_argument_lambda(types, /* clone = */ true);
 
-    let type_check_function =
-        format_ident!("__usdt_private_{}_{}_type_check", provider_name, probe_name);
+    let type_check_function = format_ident!(
+        "__usdt_private_{}_{}_type_check",
+        provider_name,
+        probe_name.replace('-', "_")
+    );
     quote! {
         let __usdt_private_args_lambda = $args_lambda;
         #[allow(unused_imports)]
@@ -119,21 +124,19 @@ fn shared_slice_elem_type(reference: &syn::TypeReference) -> Option<&syn::Type>
 
 // Return code to destructure a probe arguments into identifiers, and to pass those to ASM
 // registers.
+//
+// Only the first `MAX_PROBE_ARGUMENTS` arguments are passed, see `build_argument_warnings`.
 pub fn construct_probe_args(types: &[DataType]) -> (TokenStream, TokenStream) {
     // x86_64 passes the first 6 arguments in registers, with the rest on the stack.
     // We limit this to 6 arguments in all cases for now, as handling those stack
     // arguments would be challenging with the current `asm!` macro implementation.
     #[cfg(target_arch = "x86_64")]
-    let abi_regs = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
+    let abi_regs: [&str; crate::MAX_PROBE_ARGUMENTS] = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
     #[cfg(target_arch = "aarch64")]
-    let abi_regs = ["x0", "x1", "x2", "x3", "x4", "x5"];
+    let abi_regs: [&str; crate::MAX_PROBE_ARGUMENTS] = ["x0", "x1", "x2", "x3", "x4", "x5"];
     #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
     compile_error!("USDT only supports x86_64 and ARM64 architectures");
 
-    assert!(
-        types.len() <= abi_regs.len(),
-        "Up to 6 probe arguments are currently supported"
-    );
     let (unpacked_args, in_regs): (Vec<_>, Vec<_>) = types
         .iter()
         .zip(&abi_regs)
@@ -157,8 +160,21 @@ pub fn construct_probe_args(types: &[DataType]) -> (TokenStream, TokenStream) {
         })
         .unzip();
     let preamble = unpack_argument_lambda(types, /* clone = */ false);
+    // String and array arguments are copied into the buffer bound by the probe macro, which is
+    // reused across firings if the caller provides it.
+    let clear_buffer = if types.iter().take(crate::MAX_PROBE_ARGUMENTS).any(|typ| {
+        matches!(
+            typ,
+            DataType::Native(dtrace_parser::DataType::String | dtrace_parser::DataType::Array(..))
+        )
+    }) {
+        quote! { __usdt_private_buffer.clear(); }
+    } else {
+        quote! {}
+    };
     let unpacked_args = quote! {
         #preamble
+        #clear_buffer
         #(#unpacked_args)*
     };
     let in_regs = quote! { #(#in_regs,)* };
@@ -186,27 +202,63 @@ fn unpack_argument_lambda(types: &[DataType], clone: bool) -> TokenStream {
 // register.
 fn asm_type_convert(typ: &DataType, input: TokenStream) -> (TokenStream, TokenStream) {
     match typ {
-        DataType::Serializable(_) => (
+        DataType::Serializable(ty) => {
             // Convert the input to JSON. This is a fallible operation, however, so we wrap the
             // data in a result-like JSON blob, mapping the `Result`'s variants to the keys "ok"
-            // and "err".
-            quote! {
-                [
-                    match ::usdt::to_json(&#input) {
-                        Ok(json) => format!("{{\"ok\":{}}}", json),
-                        Err(e) => format!("{{\"err\":\"{}\"}}", e.to_string()),
-                    }.as_bytes(),
-                    &[0_u8]
-                ].concat()
-            },
-            quote! { .as_ptr() as i64 },
-        ),
+            // and "err". Large arrays and slices of integers are instead referred to by address
+            // and size, see `usdt::serialize_probe_integers`, so they're borrowed as a slice.
+            let value = match integer_array_elem_type(ty) {
+                Some(elem) => quote! {
+                    ::usdt::serialize_probe_integers(<_ as AsRef<[#elem]>>::as_ref(&#input))
+                },
+                None => quote! { ::usdt::serialize_probe_argument(&#input) },
+            };
+            (
+                quote! { [#value.as_bytes(), &[0_u8]].concat() },
+                quote! { .as_ptr() as i64 },
+            )
+        }
+        // Strings are copied into the probe's buffer, NUL-terminated, and stored as their offset
+        // into it. The buffer is only read once all arguments are copied, since it may be
+        // reallocated while they are.
         DataType::Native(dtrace_parser::DataType::String) => (
             quote! {
-                [(#input.as_ref() as &str).as_bytes(), &[0_u8]].concat()
+                {
+                    let offset = __usdt_private_buffer.len();
+                    __usdt_private_buffer.extend_from_slice((#input.as_ref() as &str).as_bytes());
+                    __usdt_private_buffer.push(0);
+                    offset
+                }
             },
-            quote! { .as_ptr() as i64 },
+            quote! { .wrapping_add(__usdt_private_buffer.as_ptr() as usize) as i64 },
         ),
+        // DTrace reads all probe arguments from the integer registers, so floating point values
+        // are passed as their bit patterns, for consumers to reinterpret.
+        DataType::Native(dtrace_parser::DataType::Float) => (
+            quote! { (<_ as ::std::borrow::Borrow<f32>>::borrow(&#input).to_bits() as i64) },
+            quote! {},
+        ),
+        DataType::Native(dtrace_parser::DataType::Double) => (
+            quote! { (<_ as ::std::borrow::Borrow<f64>>::borrow(&#input).to_bits() as i64) },
+            quote! {},
+        ),
+        // Arrays are copied into the probe's buffer, as their elements' bytes in native order,
+        // and stored as their offset into it, in the same way as strings.
+        DataType::Native(dtrace_parser::DataType::Array(..)) => {
+            let ty = typ.to_rust_type();
+            (
+                quote! {
+                    {
+                        let offset = __usdt_private_buffer.len();
+                        for element in <_ as ::std::borrow::Borrow<#ty>>::borrow(&#input).iter() {
+                            __usdt_private_buffer.extend_from_slice(&element.to_ne_bytes());
+                        }
+                        offset
+                    }
+                },
+                quote! { .wrapping_add(__usdt_private_buffer.as_ptr() as usize) as i64 },
+            )
+        }
         DataType::Native(_) => {
             let ty = typ.to_rust_type();
             (
@@ -215,9 +267,47 @@ fn asm_type_convert(typ: &DataType, input: TokenStream) -> (TokenStream, TokenSt
             )
         }
         DataType::UniqueId => (quote! { #input.as_u64() as i64 }, quote! {}),
+        // Only the address of the struct is passed; consumers read the fields from the process.
+        DataType::StructPointer(_) => (quote! { (#input as *const _ as usize as i64) }, quote! {}),
+    }
+}
+
+// Return the element type of a serializable argument declared as an array or slice of
+// integers, or a reference to one, which may be passed by reference rather than copied.
+fn integer_array_elem_type(ty: &syn::Type) -> Option<&syn::Type> {
+    let elem = match ty {
+        syn::Type::Reference(reference) => return integer_array_elem_type(&reference.elem),
+        syn::Type::Slice(syn::TypeSlice { elem, .. })
+        | syn::Type::Array(syn::TypeArray { elem, .. }) => &**elem,
+        _ => return None,
+    };
+    const INTEGERS: &[&str] = &[
+        "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+    ];
+    match elem {
+        syn::Type::Path(path)
+            if path.qself.is_none()
+                && path
+                    .path
+                    .get_ident()
+                    .map_or(false, |ident| INTEGERS.iter().any(|int| ident == int)) =>
+        {
+            Some(elem)
+        }
+        _ => None,
     }
 }
 
+// Return the options of the `asm!` block firing a probe.
+//
+// The block is never marked `pure`, which means the compiler must assume it has side effects,
+// and may not remove it even if the probe's arguments are otherwise unused. DTrace reads the
+// memory that pointer arguments refer to, such as string data, so the block is `readonly` rather
+// than `nomem`. This ensures that memory has been written before the probe fires.
+pub(crate) fn probe_asm_options() -> TokenStream {
+    quote! { options(readonly, nostack, preserves_flags) }
+}
+
 pub(crate) fn build_probe_macro(
     config: &crate::CompileProvidersConfig,
     provider: &Provider,
@@ -234,16 +324,43 @@ pub(crate) fn build_probe_macro(
     } else {
         quote! {}
     };
+    let (counter, count_firing) = if cfg!(feature = "test-counters") {
+        build_fire_counter(config, provider, probe_name)
+    } else {
+        (quote! {}, quote! {})
+    };
+    // Arrays are passed to DTrace as a pointer to a copy of their elements, so the length is only
+    // known from the provider definition. Spell out how to read them in the macro's docs.
+    let array_docs = types.iter().enumerate().filter_map(|(i, typ)| match typ {
+        DataType::Native(dtrace_parser::DataType::Array(int, len)) => Some(format!(
+            " Argument `arg{i}` points to a copy of the {len} elements of a `{ty}[{len}]`, \
+            which may be read in DTrace with `copyin(arg{i}, {len} * sizeof ({ty}))`.",
+            ty = int.to_c_type(),
+        )),
+        _ => None,
+    });
     quote! {
+        #counter
+        #(#[doc = #array_docs])*
         #[allow(unused_macros)]
         macro_rules! #macro_name {
             #no_args_match
+            (buffer = $buffer:expr, $args_lambda:expr) => {
+                {
+                    let __usdt_private_buffer: &mut ::std::vec::Vec<u8> = $buffer;
+                    #type_check_block
+                    #count_firing
+                    #impl_block
+                }
+            };
             ($tree:tt) => {
                 compile_error!("USDT probe macros should be invoked with a closure returning the arguments");
             };
             ($args_lambda:expr) => {
                 {
+                    let __usdt_private_buffer = &mut ::std::vec::Vec::<u8>::new();
                     #type_check_block
+                    #count_firing
                     #impl_block
                 }
             };
@@ -253,6 +370,366 @@ pub(crate) fn build_probe_macro(
     }
 }
 
+// Wrap the code firing a probe, once it's known to be enabled, so that it's dropped if another
+// probe is already firing on the same thread, e.g., from within that probe's argument closure.
+//
+// See `crate::enter_probe_firing`.
+pub(crate) fn guard_probe_firing(fire: TokenStream) -> TokenStream {
+    quote! {
+        if let Some(_guard) = ::usdt::enter_probe_firing() {
+            #fire
+        }
+    }
+}
+
+// Build a counter of the number of times a probe has fired, for the `test-counters` feature.
+//
+// Returns the definition of the accessor function, `{probe}_fire_count()`, and the statement
+// incrementing the counter each time the probe macro is invoked. The counter is incremented
+// whether or not the probe is enabled. Counters are kept by `usdt` itself, keyed by the provider
+// and probe name, so that the macro need not know the path of the provider module.
+pub(crate) fn build_fire_counter(
+    config: &crate::CompileProvidersConfig,
+    provider: &Provider,
+    probe_name: &str,
+) -> (TokenStream, TokenStream) {
+    let provider_name = &provider.name;
+    let count_fn = format_ident!("{}_fire_count", config.format_probe(probe_name));
+    let definition = quote! {
+        /// Return the number of times this probe has fired.
+        #[allow(dead_code)]
+        pub(crate) fn #count_fn() -> u64 {
+            ::usdt::probe_fire_count(#provider_name, #probe_name)
+        }
+    };
+    let increment = quote! {
+        ::usdt::count_probe_firing(#provider_name, #probe_name);
+    };
+    (definition, increment)
+}
+
+// Build the function returning the raw enabled state of a probe, `{probe}_is_enabled_raw()`.
+//
+// `read_flag` is the backend's expression for the underlying flag, such as the SystemTap
+// semaphore or the result of the is-enabled probe site, converted to a `u16`. This is intended for
+// diagnosing why a probe does or doesn't fire, not for deciding whether to fire it.
+pub(crate) fn build_is_enabled_raw(
+    config: &crate::CompileProvidersConfig,
+    probe_name: &str,
+    read_flag: TokenStream,
+) -> TokenStream {
+    let raw_fn = format_ident!("{}_is_enabled_raw", config.format_probe(probe_name));
+    quote! {
+        /// Return the raw value of the flag indicating whether this probe is enabled.
+        ///
+        /// This is zero while no tracer has enabled the probe.
+        #[allow(dead_code)]
+        pub(crate) fn #raw_fn() -> u16 {
+            #read_flag
+        }
+    }
+}
+
+// The fixed-size portion of a probe record: the length, version, argument count, flags, and
+// probe address.
+const PROBE_RECORD_HEADER_LEN: usize = 4 + 1 + 1 + 2 + 8;
+
+// Each probe record is aligned to, and padded out to, this many bytes.
+const PROBE_RECORD_ALIGN: usize = 8;
+
+// Return the size in bytes of the record emitted for a probe, including its trailing padding. If
+// `types` is `None`, this is the record for the is-enabled probe. The record ends with the version
+// tag of the provider, if it has one.
+//
+// This must be kept in sync with `crate::record::emit_probe_record`.
+pub(crate) fn probe_record_len(
+    provider: &str,
+    probe: &str,
+    types: Option<&[DataType]>,
+    version_tag: Option<&str>,
+) -> usize {
+    let strings = provider.len()
+        + 1
+        + probe.replace("__", "-").len()
+        + 1
+        + types.map_or(0, |types| {
+            types.iter().map(|typ| typ.to_c_type().len() + 1).sum()
+        })
+        + version_tag.map_or(0, |tag| tag.len() + 1);
+    let len = PROBE_RECORD_HEADER_LEN + strings;
+    (len + PROBE_RECORD_ALIGN - 1) / PROBE_RECORD_ALIGN * PROBE_RECORD_ALIGN
+}
+
+// Return the total size of the probe records emitted when each probe macro of a provider is
+// invoked once, and each `<probe>_is_enabled_raw` function is called once. Each macro invocation
+// emits a probe record, preceded by an is-enabled record unless the probes always fire, and each
+// call of the raw is-enabled function emits another is-enabled record.
+pub(crate) fn probe_record_section_size(
+    provider: &Provider,
+    config: &crate::CompileProvidersConfig,
+) -> usize {
+    let version_tag = config.version_tag.as_deref();
+    let is_enabled_sites = if config.always_fire { 1 } else { 2 };
+    provider
+        .probes
+        .iter()
+        .map(|probe| {
+            is_enabled_sites * probe_record_len(&provider.name, &probe.name, None, version_tag)
+                + probe_record_len(
+                    &provider.name,
+                    &probe.name,
+                    Some(probe.dtrace_types()),
+                    version_tag,
+                )
+        })
+        .sum()
+}
+
+// Build the constant giving the size of the probe records for a provider.
+pub(crate) fn build_record_section_size(
+    provider: &Provider,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    let size = probe_record_section_size(provider, config);
+    quote! {
+        /// The number of bytes of probe records emitted for this provider, when each probe
+        /// macro is invoked exactly once and each `*_is_enabled_raw` function is called exactly
+        /// once.
+        ///
+        /// Every invocation of a probe macro emits its own records, as does every copy of a raw
+        /// is-enabled function inlined into its callers, so a program with more call sites will
+        /// use correspondingly more space. Each record starts on, and is padded out
+        /// to, an 8-byte boundary, so this assumes the start of the section is 8-byte aligned.
+        #[allow(dead_code)]
+        pub const PROBE_RECORD_SECTION_SIZE: usize = #size;
+    }
+}
+
+// Build a warning for each probe with more arguments than are passed to DTrace, and for each
+// argument of an unsupported type which is passed as a `usize`.
+//
+// There's no stable way to emit warnings from a procedural macro, so this refers to a deprecated
+// constant, whose note is then reported by the compiler.
+pub(crate) fn build_argument_warnings(provider: &Provider) -> TokenStream {
+    let count_warnings = provider
+        .probes
+        .iter()
+        .filter(|probe| probe.types.len() > crate::MAX_PROBE_ARGUMENTS)
+        .map(|probe| {
+            let note = format!(
+                "The probe \"{}\" has {} arguments, but only the first {} are visible to DTrace",
+                probe.name,
+                probe.types.len(),
+                crate::MAX_PROBE_ARGUMENTS,
+            );
+            let ident = format_ident!(
+                "__usdt_private_{}_has_too_many_arguments",
+                probe.name.replace('-', "_")
+            );
+            quote! {
+                #[deprecated(note = #note)]
+                #[allow(non_upper_case_globals)]
+                const #ident: () = ();
+                const _: () = #ident;
+            }
+        });
+    let opaque_warnings = provider.probes.iter().flat_map(|probe| {
+        probe
+            .types
+            .iter()
+            .enumerate()
+            .filter(|(_, typ)| matches!(typ, DataType::Native(dtrace_parser::DataType::Opaque)))
+            .map(move |(i, _)| {
+                let note = format!(
+                    "Argument {} of the probe \"{}\" has an unsupported type, and is passed as a `usize`",
+                    i,
+                    probe.name,
+                );
+                let ident = format_ident!(
+                    "__usdt_private_{}_arg_{}_is_opaque",
+                    probe.name.replace('-', "_"),
+                    i
+                );
+                quote! {
+                    #[deprecated(note = #note)]
+                    #[allow(non_upper_case_globals)]
+                    const #ident: () = ();
+                    const _: () = #ident;
+                }
+            })
+    });
+    quote! {
+        #(#count_warnings)*
+        #(#opaque_warnings)*
+    }
+}
+
+// Build a marker which makes defining the same provider twice in a crate a compile error.
+//
+// Each invocation of the provider macros emits a module, and the generated probe macros refer to
+// it by its absolute path, so a provider may only be defined once per crate. Exported macros always
+// live at the crate root, wherever they're defined, so a second definition of this one is rejected
+// even from another module. The compiler's error names the marker, which spells out the problem.
+pub(crate) fn build_provider_marker(provider: &Provider) -> TokenStream {
+    let ident = format_ident!(
+        "__usdt_private_provider_{}_is_defined_by_more_than_one_macro_invocation",
+        provider.name
+    );
+    quote! {
+        #[doc(hidden)]
+        #[macro_export]
+        macro_rules! #ident {
+            () => {};
+        }
+    }
+}
+
+// Build the constant listing the DTrace probe specifications of a provider's probes, the function
+// matching them against a probe description, and that returning the number of probes.
+pub(crate) fn build_probe_specs(provider: &Provider) -> TokenStream {
+    let specs = provider
+        .probes
+        .iter()
+        .map(|probe| format!("{}:::{}", provider.name, probe.name.replace("__", "-")))
+        .collect::<Vec<_>>();
+    quote! {
+        /// The DTrace probe specification of each probe in this provider, e.g.,
+        /// `"provider:::probe"`, suitable for passing to `dtrace -n`.
+        #[allow(dead_code)]
+        pub const PROBE_SPECS: &[&str] = &[#(#specs),*];
+
+        /// Return the names of the probes in this provider matching a DTrace-style probe
+        /// description, e.g., `"provider:::probe*"`, in the order of `PROBE_SPECS`.
+        ///
+        /// See `usdt::probe_spec_matches` for the supported patterns.
+        #[allow(dead_code)]
+        pub fn matches_spec(description: &str) -> Vec<&'static str> {
+            PROBE_SPECS
+                .iter()
+                .filter(|spec| ::usdt::probe_spec_matches(spec, description))
+                .filter_map(|spec| spec.split_once(":::").map(|(_, name)| name))
+                .collect()
+        }
+
+        /// Return the number of probes in this provider.
+        ///
+        /// This is a `const fn`, so it may be used in constant contexts, such as array lengths.
+        #[allow(dead_code)]
+        pub const fn probe_count() -> usize {
+            PROBE_SPECS.len()
+        }
+    }
+}
+
+// Build the constant listing the D types of each probe's arguments, for checking at runtime.
+pub(crate) fn build_probe_arg_types(provider: &Provider) -> TokenStream {
+    let entries = provider.probes.iter().map(|probe| {
+        let name = probe.name.replace("__", "-");
+        let types = probe.types.iter().map(|typ| typ.to_c_type());
+        quote! { (#name, &[#(#types),*]) }
+    });
+    quote! {
+        /// The declared argument types of each probe in this provider, as the names of their D
+        /// types, e.g., `("probe", &["uint8_t", "char*"])`.
+        #[allow(dead_code)]
+        pub const PROBE_ARG_TYPES: &[(&str, &[&str])] = &[#(#entries),*];
+    }
+}
+
+// Build the static describing a provider, which is collected into the registry of all providers
+// in the program by the linker.
+pub(crate) fn build_provider_info(provider: &Provider) -> TokenStream {
+    let name = &provider.name;
+    let probes = provider
+        .probes
+        .iter()
+        .map(|probe| probe.name.replace("__", "-"))
+        .collect::<Vec<_>>();
+    quote! {
+        #[used]
+        #[cfg_attr(
+            any(
+                target_os = "linux",
+                target_os = "illumos",
+                target_os = "solaris",
+                target_os = "freebsd"
+            ),
+            link_section = "set_usdt_providers"
+        )]
+        #[cfg_attr(target_os = "macos", link_section = "__DATA,__usdt_provs")]
+        static __USDT_PROVIDER_INFO: ::usdt::ProviderInfo = ::usdt::ProviderInfo {
+            name: #name,
+            module_path: module_path!(),
+            probes: &[#(#probes),*],
+        };
+    }
+}
+
+// Build the `enable()` and `disable()` functions of a provider, for compatibility with crates
+// which require explicitly enabling a provider's probes.
+pub(crate) fn build_enable_functions() -> TokenStream {
+    let disable = build_disable_function();
+    quote! {
+        /// Register the probes of this provider with DTrace.
+        ///
+        /// This is the same as calling [`usdt::register_probes`], which registers the probes of
+        /// all providers in the program at most once, and so may be called for any number of
+        /// providers.
+        #[allow(dead_code)]
+        pub fn enable() -> ::core::result::Result<(), ::usdt::Error> {
+            ::usdt::register_probes()
+        }
+
+        #disable
+    }
+}
+
+// Build the `disable()` function of a provider, which does nothing.
+pub(crate) fn build_disable_function() -> TokenStream {
+    quote! {
+        /// Do nothing, as probes can't be unregistered from DTrace.
+        ///
+        /// This exists so that code calling `disable()` on providers from other USDT crates
+        /// compiles unchanged. Registered probes have no cost unless they're enabled by a DTrace
+        /// consumer.
+        #[allow(dead_code)]
+        pub fn disable() -> ::core::result::Result<(), ::usdt::Error> {
+            Ok(())
+        }
+    }
+}
+
+// Build the constant listing the D definitions of the structs passed by pointer to probes.
+pub(crate) fn build_struct_definitions(provider: &Provider) -> TokenStream {
+    let mut types = Vec::new();
+    for typ in provider.probes.iter().flat_map(|probe| probe.types.iter()) {
+        if let DataType::StructPointer(ty) = typ {
+            if !types.contains(&ty) {
+                types.push(ty);
+            }
+        }
+    }
+    // The struct types are named as in the provider, so its imports must be in scope.
+    let definitions = if types.is_empty() {
+        quote! { &[] }
+    } else {
+        let use_statements = &provider.use_statements;
+        quote! {
+            {
+                #[allow(unused_imports)]
+                #(#use_statements)*
+                &[#(<#types as ::usdt::ProbeStruct>::D_DEFINITION),*]
+            }
+        }
+    };
+    quote! {
+        /// The D definition of each struct passed by pointer to a probe in this provider, which
+        /// may be included in D scripts to read the struct's fields.
+        #[allow(dead_code)]
+        pub const STRUCT_DEFINITIONS: &[&str] = #definitions;
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -346,6 +823,27 @@ mod tests {
         assert_eq!(block.to_string(), expected.to_string());
     }
 
+    #[test]
+    fn test_construct_probe_args_with_shared_slice() {
+        // The probe serializes a slice of integers with `serialize_probe_integers`, which refers
+        // to a large slice by its address and size, and passes the NUL-terminated JSON.
+        let types = &[DataType::Serializable(syn::parse_str("&[u32]").unwrap())];
+        let (args, regs) = construct_probe_args(types);
+        let expected = quote! {
+            let args = (__usdt_private_args_lambda(),);
+            let arg_0 = [
+                ::usdt::serialize_probe_integers(<_ as AsRef<[u32]>>::as_ref(&args.0)).as_bytes(),
+                &[0_u8]
+            ]
+            .concat();
+        };
+        assert_eq!(args.to_string(), expected.to_string());
+        assert!(regs
+            .to_string()
+            .replace(' ', "")
+            .ends_with("(arg_0.as_ptr()asi64),"));
+    }
+
     #[test]
     fn test_generate_type_check_with_custom_type() {
         let provider = "provider";
@@ -380,8 +878,14 @@ mod tests {
         let (args, regs) = construct_probe_args(types);
         let expected = quote! {
             let args = __usdt_private_args_lambda();
+            __usdt_private_buffer.clear();
             let arg_0 = (*<_ as ::std::borrow::Borrow<*const u8>>::borrow(&args.0) as i64);
-            let arg_1 = [(args.1.as_ref() as &str).as_bytes(), &[0_u8]].concat();
+            let arg_1 = {
+                let offset = __usdt_private_buffer.len();
+                __usdt_private_buffer.extend_from_slice((args.1.as_ref() as &str).as_bytes());
+                __usdt_private_buffer.push(0);
+                offset
+            };
         };
         assert_eq!(args.to_string(), expected.to_string());
 
@@ -396,6 +900,35 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_construct_probe_args_array() {
+        // Arrays are copied into the buffer, so it's cleared even without any string arguments.
+        let types = &[DataType::Native(DType::Array(
+            Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit16,
+            },
+            4,
+        ))];
+        let (args, regs) = construct_probe_args(types);
+        let expected = quote! {
+            let args = (__usdt_private_args_lambda(),);
+            __usdt_private_buffer.clear();
+            let arg_0 = {
+                let offset = __usdt_private_buffer.len();
+                for element in <_ as ::std::borrow::Borrow<[u16; 4]>>::borrow(&args.0).iter() {
+                    __usdt_private_buffer.extend_from_slice(&element.to_ne_bytes());
+                }
+                offset
+            };
+        };
+        assert_eq!(args.to_string(), expected.to_string());
+        assert!(regs
+            .to_string()
+            .replace(' ', "")
+            .contains("(arg_0.wrapping_add(__usdt_private_buffer.as_ptr()asusize)asi64)"));
+    }
+
     #[test]
     fn test_asm_type_convert() {
         use std::str::FromStr;
@@ -418,8 +951,446 @@ mod tests {
         );
         assert_eq!(
             out.to_string(),
-            quote! { [(foo.as_ref() as &str).as_bytes(), &[0_u8]].concat() }.to_string()
+            quote! {
+                {
+                    let offset = __usdt_private_buffer.len();
+                    __usdt_private_buffer.extend_from_slice((foo.as_ref() as &str).as_bytes());
+                    __usdt_private_buffer.push(0);
+                    offset
+                }
+            }
+            .to_string()
+        );
+        assert_eq!(
+            post.to_string(),
+            quote! { .wrapping_add(__usdt_private_buffer.as_ptr() as usize) as i64 }.to_string()
+        );
+
+        let (out, post) = asm_type_convert(
+            &DataType::Native(dtrace_parser::DataType::Double),
+            TokenStream::from_str("foo").unwrap(),
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! {(<_ as ::std::borrow::Borrow<f64>>::borrow(&foo).to_bits() as i64)}.to_string()
+        );
+        assert_eq!(post.to_string(), quote! {}.to_string());
+
+        let (out, post) = asm_type_convert(
+            &DataType::Native(DType::Array(
+                Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit8,
+                },
+                16,
+            )),
+            TokenStream::from_str("foo").unwrap(),
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! {
+                {
+                    let offset = __usdt_private_buffer.len();
+                    for element in <_ as ::std::borrow::Borrow<[u8; 16]>>::borrow(&foo).iter() {
+                        __usdt_private_buffer.extend_from_slice(&element.to_ne_bytes());
+                    }
+                    offset
+                }
+            }
+            .to_string()
+        );
+        assert_eq!(
+            post.to_string(),
+            quote! { .wrapping_add(__usdt_private_buffer.as_ptr() as usize) as i64 }.to_string()
+        );
+
+        let (out, post) = asm_type_convert(
+            &DataType::StructPointer(syn::parse_str("Context").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! { (foo as *const _ as usize as i64) }.to_string()
+        );
+        assert_eq!(post.to_string(), quote! {}.to_string());
+
+        // Arrays and slices of integers are borrowed as slices, so that they may be passed by
+        // reference if they're large, and other serializable arguments are always copied.
+        for (ty, value) in [
+            (
+                "[u64; 32]",
+                quote! { ::usdt::serialize_probe_integers(<_ as AsRef<[u64]>>::as_ref(&foo)) },
+            ),
+            (
+                "&[u32]",
+                quote! { ::usdt::serialize_probe_integers(<_ as AsRef<[u32]>>::as_ref(&foo)) },
+            ),
+            ("&Arg", quote! { ::usdt::serialize_probe_argument(&foo) }),
+            (
+                "[Arg; 4]",
+                quote! { ::usdt::serialize_probe_argument(&foo) },
+            ),
+            (
+                "(u8, u8)",
+                quote! { ::usdt::serialize_probe_argument(&foo) },
+            ),
+        ] {
+            let (out, post) = asm_type_convert(
+                &DataType::Serializable(syn::parse_str(ty).unwrap()),
+                TokenStream::from_str("foo").unwrap(),
+            );
+            assert_eq!(
+                out.to_string(),
+                quote! { [#value.as_bytes(), &[0_u8]].concat() }.to_string()
+            );
+            assert_eq!(post.to_string(), quote! { .as_ptr() as i64 }.to_string());
+        }
+    }
+
+    #[test]
+    fn test_build_struct_definitions() {
+        let context = DataType::StructPointer(syn::parse_str("Context").unwrap());
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: vec![
+                crate::Probe {
+                    name: String::from("bar"),
+                    types: vec![context.clone()],
+                },
+                crate::Probe {
+                    name: String::from("baz"),
+                    types: vec![DataType::UniqueId, context],
+                },
+            ],
+            use_statements: vec![syn::parse_str("use crate::Context;").unwrap()],
+        };
+        let expected = quote! {
+            pub const STRUCT_DEFINITIONS: &[&str] = {
+                #[allow(unused_imports)]
+                use crate::Context;
+                &[<Context as ::usdt::ProbeStruct>::D_DEFINITION]
+            };
+        };
+        assert!(build_struct_definitions(&provider)
+            .to_string()
+            .ends_with(&expected.to_string()));
+    }
+
+    #[test]
+    fn test_build_probe_specs() {
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: vec![
+                crate::Probe {
+                    name: String::from("start"),
+                    types: vec![],
+                },
+                crate::Probe {
+                    name: String::from("gc__begin"),
+                    types: vec![],
+                },
+                crate::Probe {
+                    name: String::from("gc-end"),
+                    types: vec![],
+                },
+            ],
+            use_statements: vec![],
+        };
+        let expected = quote! {
+            /// The DTrace probe specification of each probe in this provider, e.g.,
+            /// `"provider:::probe"`, suitable for passing to `dtrace -n`.
+            #[allow(dead_code)]
+            pub const PROBE_SPECS: &[&str] = &["foo:::start", "foo:::gc-begin", "foo:::gc-end"];
+
+            /// Return the names of the probes in this provider matching a DTrace-style probe
+            /// description, e.g., `"provider:::probe*"`, in the order of `PROBE_SPECS`.
+            ///
+            /// See `usdt::probe_spec_matches` for the supported patterns.
+            #[allow(dead_code)]
+            pub fn matches_spec(description: &str) -> Vec<&'static str> {
+                PROBE_SPECS
+                    .iter()
+                    .filter(|spec| ::usdt::probe_spec_matches(spec, description))
+                    .filter_map(|spec| spec.split_once(":::").map(|(_, name)| name))
+                    .collect()
+            }
+
+            /// Return the number of probes in this provider.
+            ///
+            /// This is a `const fn`, so it may be used in constant contexts, such as array lengths.
+            #[allow(dead_code)]
+            pub const fn probe_count() -> usize {
+                PROBE_SPECS.len()
+            }
+        };
+        assert_eq!(
+            build_probe_specs(&provider).to_string(),
+            expected.to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_probe_arg_types() {
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: vec![
+                crate::Probe {
+                    name: String::from("gc__begin"),
+                    types: vec![
+                        DataType::Native(DType::Integer(Integer {
+                            sign: Sign::Unsigned,
+                            width: BitWidth::Bit8,
+                        })),
+                        DataType::Native(DType::String),
+                        DataType::UniqueId,
+                    ],
+                },
+                crate::Probe {
+                    name: String::from("gc-end"),
+                    types: vec![],
+                },
+            ],
+            use_statements: vec![],
+        };
+        let expected = quote! {
+            /// The declared argument types of each probe in this provider, as the names of their D
+            /// types, e.g., `("probe", &["uint8_t", "char*"])`.
+            #[allow(dead_code)]
+            pub const PROBE_ARG_TYPES: &[(&str, &[&str])] = &[
+                ("gc-begin", &["uint8_t", "char*", "uint64_t"]),
+                ("gc-end", &[])
+            ];
+        };
+        assert_eq!(
+            build_probe_arg_types(&provider).to_string(),
+            expected.to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_provider_marker() {
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: vec![],
+            use_statements: vec![],
+        };
+        let expected = quote! {
+            #[doc(hidden)]
+            #[macro_export]
+            macro_rules! __usdt_private_provider_foo_is_defined_by_more_than_one_macro_invocation {
+                () => {};
+            }
+        };
+        assert_eq!(
+            build_provider_marker(&provider).to_string(),
+            expected.to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_probe_macro_array_docs() {
+        let types = vec![
+            DataType::Native(DType::String),
+            DataType::Native(DType::Array(
+                Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit32,
+                },
+                16,
+            )),
+        ];
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: vec![crate::Probe {
+                name: String::from("samples"),
+                types: types.clone(),
+            }],
+            use_statements: vec![],
+        };
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("foo")),
+            ..Default::default()
+        };
+        let out = build_probe_macro(&config, &provider, "samples", &types, quote! {});
+        let file: syn::File = syn::parse2(out).unwrap();
+        let syn::Item::Macro(item) = &file.items[0] else {
+            panic!("Expected the probe macro to be the first item");
+        };
+        let docs = item
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("doc"))
+            .map(|attr| match &attr.meta {
+                syn::Meta::NameValue(syn::MetaNameValue {
+                    value:
+                        syn::Expr::Lit(syn::ExprLit {
+                            lit: syn::Lit::Str(doc),
+                            ..
+                        }),
+                    ..
+                }) => doc.value(),
+                _ => panic!("Expected a doc string"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            docs,
+            vec![
+                " Argument `arg1` points to a copy of the 16 elements of a `uint32_t[16]`, \
+                which may be read in DTrace with `copyin(arg1, 16 * sizeof (uint32_t))`."
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_argument_warnings() {
+        let arg = DataType::Native(DType::Integer(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        }));
+        let mut provider = Provider {
+            name: String::from("foo"),
+            probes: vec![crate::Probe {
+                name: String::from("many"),
+                types: vec![arg.clone(); crate::MAX_PROBE_ARGUMENTS],
+            }],
+            use_statements: vec![],
+        };
+        assert!(build_argument_warnings(&provider).is_empty());
+
+        provider.probes[0].types.push(arg);
+        let expected = quote! {
+            #[deprecated(
+                note = "The probe \"many\" has 7 arguments, but only the first 6 are visible to DTrace"
+            )]
+            #[allow(non_upper_case_globals)]
+            const __usdt_private_many_has_too_many_arguments: () = ();
+            const _: () = __usdt_private_many_has_too_many_arguments;
+        };
+        assert_eq!(
+            build_argument_warnings(&provider).to_string(),
+            expected.to_string()
+        );
+
+        // Arguments of unsupported types are passed as a `usize`.
+        let opaque = Provider {
+            name: String::from("foo"),
+            probes: vec![crate::Probe {
+                name: String::from("callback"),
+                types: vec![DataType::Native(DType::Opaque)],
+            }],
+            use_statements: vec![],
+        };
+        let expected = quote! {
+            #[deprecated(
+                note = "Argument 0 of the probe \"callback\" has an unsupported type, and is passed as a `usize`"
+            )]
+            #[allow(non_upper_case_globals)]
+            const __usdt_private_callback_arg_0_is_opaque: () = ();
+            const _: () = __usdt_private_callback_arg_0_is_opaque;
+        };
+        assert_eq!(
+            build_argument_warnings(&opaque).to_string(),
+            expected.to_string()
+        );
+        let rust_type = opaque.probes[0].types[0].to_rust_type();
+        assert_eq!(quote! { #rust_type }.to_string(), "usize");
+
+        // Only the arguments visible to DTrace are passed in registers.
+        let (_, in_regs) = construct_probe_args(&provider.probes[0].types);
+        assert_eq!(
+            in_regs.to_string().matches("in (").count(),
+            crate::MAX_PROBE_ARGUMENTS
+        );
+        assert_eq!(
+            provider.probes[0].dtrace_types().len(),
+            crate::MAX_PROBE_ARGUMENTS
+        );
+    }
+
+    #[test]
+    fn test_probe_asm_options() {
+        let options = probe_asm_options().to_string();
+        assert!(!options.contains("pure"));
+        assert!(!options.contains("nomem"));
+        assert!(options.contains("readonly"));
+    }
+
+    #[test]
+    fn test_guard_probe_firing() {
+        let fire = guard_probe_firing(quote! { fire(); });
+        assert_eq!(
+            fire.to_string(),
+            quote! {
+                if let Some(_guard) = ::usdt::enter_probe_firing() {
+                    fire();
+                }
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_fire_counter() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("foo")),
+            probe_format: Some(String::from("probe_{probe}")),
+            module: Some(String::from("foo")),
+            section: None,
+            always_fire: false,
+            version_tag: None,
+            provider_prefix: None,
+            types: Default::default(),
+            probe_names: Default::default(),
+        };
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: vec![],
+            use_statements: vec![],
+        };
+        let (definition, increment) = build_fire_counter(&config, &provider, "gc-start");
+        assert_eq!(
+            definition.to_string(),
+            quote! {
+                /// Return the number of times this probe has fired.
+                #[allow(dead_code)]
+                pub(crate) fn probe_gc_start_fire_count() -> u64 {
+                    ::usdt::probe_fire_count("foo", "gc-start")
+                }
+            }
+            .to_string()
+        );
+        assert_eq!(
+            increment.to_string(),
+            quote! { ::usdt::count_probe_firing("foo", "gc-start"); }.to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_is_enabled_raw() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("foo")),
+            probe_format: Some(String::from("probe_{probe}")),
+            module: Some(String::from("foo")),
+            section: None,
+            always_fire: false,
+            version_tag: None,
+            provider_prefix: None,
+            types: Default::default(),
+            probe_names: Default::default(),
+        };
+        let raw = build_is_enabled_raw(&config, "gc-start", quote! { 0 });
+        assert_eq!(
+            raw.to_string(),
+            quote! {
+                /// Return the raw value of the flag indicating whether this probe is enabled.
+                ///
+                /// This is zero while no tracer has enabled the probe.
+                #[allow(dead_code)]
+                pub(crate) fn probe_gc_start_is_enabled_raw() -> u16 {
+                    0
+                }
+            }
+            .to_string()
         );
-        assert_eq!(post.to_string(), quote! { .as_ptr() as i64 }.to_string());
     }
 }
@@ -20,16 +20,15 @@ use crate::common;
 use crate::{Probe, Provider};
 use proc_macro2::TokenStream;
 use quote::quote;
-use std::convert::TryFrom;
 
 pub fn compile_provider_source(
     source: &str,
     config: &crate::CompileProvidersConfig,
 ) -> Result<TokenStream, crate::Error> {
-    let dfile = dtrace_parser::File::try_from(source)?;
+    let dfile = config.parse_source(source)?;
     let providers = dfile
         .providers()
-        .into_iter()
+        .iter()
         .map(|provider| {
             let provider = Provider::from(provider);
             // Ensure that the name of the module in the config is set, either by the caller or
@@ -41,7 +40,14 @@ pub fn compile_provider_source(
                     None => Some(provider.name.clone()),
                     other => other.clone(),
                 },
+                section: config.section.clone(),
+                always_fire: config.always_fire,
+                version_tag: config.version_tag.clone(),
+                provider_prefix: config.provider_prefix.clone(),
+                types: config.types.clone(),
+                probe_names: config.probe_names.clone(),
             };
+            let provider = config.prefix_provider(provider);
             compile_provider(&provider, &config)
         })
         .collect::<Vec<_>>();
@@ -64,8 +70,25 @@ fn compile_provider(provider: &Provider, config: &crate::CompileProvidersConfig)
         .map(|probe| compile_probe(provider, probe, config))
         .collect::<Vec<_>>();
     let module = config.module_ident();
+    let record_size = common::build_record_section_size(provider, config);
+    let probe_specs = common::build_probe_specs(provider);
+    let probe_arg_types = common::build_probe_arg_types(provider);
+    let enable_functions = common::build_enable_functions();
+    let provider_info = common::build_provider_info(provider);
+    let struct_definitions = common::build_struct_definitions(provider);
+    let warnings = common::build_argument_warnings(provider);
+    let marker = common::build_provider_marker(provider);
     quote! {
+        #[allow(non_snake_case)]
         pub(crate) mod #module {
+            #marker
+            #warnings
+            #record_size
+            #probe_specs
+            #probe_arg_types
+            #enable_functions
+            #provider_info
+            #struct_definitions
             #(#probe_impls)*
         }
     }
@@ -77,7 +100,13 @@ fn compile_probe(
     config: &crate::CompileProvidersConfig,
 ) -> TokenStream {
     let impl_block = quote! { let _ = || (__usdt_private_args_lambda()) ; };
-    common::build_probe_macro(config, provider, &probe.name, &probe.types, impl_block)
+    let probe_macro =
+        common::build_probe_macro(config, provider, &probe.name, &probe.types, impl_block);
+    let is_enabled_raw = common::build_is_enabled_raw(config, &probe.name, quote! { 0 });
+    quote! {
+        #probe_macro
+        #is_enabled_raw
+    }
 }
 
 pub fn register_probes() -> Result<(), crate::Error> {
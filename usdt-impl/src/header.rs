@@ -0,0 +1,134 @@
+//! Generate C header files declaring the probes of a provider.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{DataType, Probe, Provider};
+
+// The declaration of `hrtime_t`, for headers with probes taking one.
+//
+// It's only defined by the system headers on illumos, and is otherwise declared here, guarded so
+// that several generated headers may be included in the same file.
+const HRTIME_DECLARATION: &str = "#if defined(__sun)\n\
+    #include <sys/time.h>\n\
+    #elif !defined(_USDT_HRTIME_T)\n\
+    #define\t_USDT_HRTIME_T\n\
+    typedef long long hrtime_t;\n\
+    #endif\n\n";
+
+// Return the name of a probe as it appears in DTrace-generated symbols and macros. Dashes are
+// encoded as double-underscores, the same as in D source.
+fn symbol_probe_name(probe: &Probe) -> String {
+    probe.name.replace('-', "__")
+}
+
+// Return the name of the C macro used to fire a probe, e.g., `FOO_BAR`.
+//
+// This matches the output of `dtrace -h`, where double-underscores in the probe name collapse
+// into a single underscore.
+fn probe_macro_name(provider: &Provider, probe: &Probe) -> String {
+    format!(
+        "{}_{}",
+        provider.name.to_uppercase(),
+        symbol_probe_name(probe).replace("__", "_").to_uppercase()
+    )
+}
+
+// Emit the `#define`s and `extern` declarations for a single probe.
+//
+// Returns the enabled definitions, the extern declarations, and the disabled definitions.
+fn emit_probe(provider: &Provider, probe: &Probe) -> (String, String, String) {
+    let macro_name = probe_macro_name(provider, probe);
+    let symbol = format!("{}___{}", provider.name, symbol_probe_name(probe));
+    let args = (0..probe.dtrace_types().len())
+        .map(|i| format!("arg{}", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let types = if probe.dtrace_types().is_empty() {
+        String::from("void")
+    } else {
+        probe
+            .dtrace_types()
+            .iter()
+            .map(|typ| typ.to_c_type())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let enabled = format!(
+        "#define\t{macro_name}({args}) \\\n\t__dtrace_{symbol}({args})\n\
+         #define\t{macro_name}_ENABLED() \\\n\t__dtraceenabled_{symbol}()\n"
+    );
+    let externs = format!(
+        "extern void __dtrace_{symbol}({types});\n\
+         extern int __dtraceenabled_{symbol}(void);\n"
+    );
+    let disabled = format!(
+        "#define\t{macro_name}({args})\n\
+         #define\t{macro_name}_ENABLED() (0)\n"
+    );
+    (enabled, externs, disabled)
+}
+
+/// Emit a C header declaring the probes of a provider.
+///
+/// The header follows the layout of those generated by `dtrace -h`. Each probe is declared as a
+/// macro named `{PROVIDER}_{PROBE}`, along with a macro `{PROVIDER}_{PROBE}_ENABLED` for the
+/// is-enabled check. Defining `DTRACE_PROBES_DISABLED` expands all probes to nothing.
+///
+/// As with `dtrace -h`, the macros call external `__dtrace_*` functions, which the header only
+/// declares. They're defined by the object which `dtrace -G` generates from the provider's D file
+/// and the compiled C objects, which must be linked into the program.
+///
+/// If any probe takes an `hrtime_t`, which is a system type only on illumos, the header declares
+/// it as a `long long` elsewhere.
+pub(crate) fn emit_c_header(provider: &Provider) -> String {
+    let guard = format!("_USDT_{}_H", provider.name.to_uppercase());
+    let uses_hrtime = provider.probes.iter().any(|probe| {
+        probe
+            .dtrace_types()
+            .iter()
+            .any(|typ| matches!(typ, DataType::Native(dtrace_parser::DataType::HrTime)))
+    });
+    let hrtime = if uses_hrtime { HRTIME_DECLARATION } else { "" };
+    let mut enabled = Vec::new();
+    let mut externs = Vec::new();
+    let mut disabled = Vec::new();
+    for probe in provider.probes.iter() {
+        let (e, x, d) = emit_probe(provider, probe);
+        enabled.push(e);
+        externs.push(x);
+        disabled.push(d);
+    }
+    format!(
+        "/*\n * Generated by usdt from the provider \"{provider}\". Do not edit.\n *\n \
+         * The probes are defined by linking the object generated by `dtrace -G`.\n */\n\n\
+         #ifndef\t{guard}\n#define\t{guard}\n\n\
+         #include <stdint.h>\n\n\
+         {hrtime}\
+         #ifdef\t__cplusplus\nextern \"C\" {{\n#endif\n\n\
+         #if !defined(DTRACE_PROBES_DISABLED) || !DTRACE_PROBES_DISABLED\n\n\
+         {enabled}\n{externs}\n\
+         #else\n\n\
+         {disabled}\n\
+         #endif\n\n\
+         #ifdef\t__cplusplus\n}}\n#endif\n\n\
+         #endif\t/* {guard} */\n",
+        provider = provider.name,
+        guard = guard,
+        hrtime = hrtime,
+        enabled = enabled.join("\n"),
+        externs = externs.join("\n"),
+        disabled = disabled.join("\n"),
+    )
+}
@@ -18,18 +18,32 @@
 #![cfg_attr(usdt_need_feat_asm_sym, feature(asm_sym))]
 
 use serde::Deserialize;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use thiserror::Error;
 
+pub use dtrace_parser::DataType as NativeType;
+pub use dtrace_parser::{BitWidth, Integer, Sign, MAX_PRINTF_ARGS};
+
 // Probe record parsing required for standard backend (and `des` feature used by `dusty util)
 #[cfg(any(usdt_backend_standard, feature = "des"))]
 pub mod record;
 
+mod registry;
+pub use registry::{all_providers, ProviderInfo};
+
+mod check;
+pub use check::{check_provider_source, Diagnostic};
+
 #[cfg_attr(usdt_backend_noop, path = "empty.rs")]
 #[cfg_attr(usdt_backend_linker, path = "linker.rs")]
 #[cfg_attr(usdt_backend_standard, path = "no-linker.rs")]
+#[cfg_attr(usdt_backend_stapsdt, path = "stapsdt.rs")]
 mod internal;
 
+mod header;
+
 // Since the `empty` is mostly a no-op, parts of the common code will go unused when it is
 // selected for use.
 #[cfg_attr(usdt_backend_noop, allow(dead_code))]
@@ -51,6 +65,18 @@ pub fn register_probes() -> Result<(), Error> {
     crate::internal::register_probes()
 }
 
+/// The bounds of a provider's own section of probe records, collected by the linker so that
+/// [`register_probes`] also registers the records of the section.
+///
+/// NOTE: This is emitted by the generated code, and is not intended to be used directly.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct RecordSection {
+    /// Return the addresses of the start and end of the section.
+    pub bounds: fn() -> (*mut u8, *mut u8),
+}
+
 /// Errors related to building DTrace probes into Rust code
 #[derive(Error, Debug)]
 pub enum Error {
@@ -66,6 +92,37 @@ pub enum Error {
     /// An error occurred extracting probe information from the encoded object file sections
     #[error("The file is not a valid object file")]
     InvalidFile,
+    /// A probe record in the object file's probe section is malformed
+    #[error("Malformed probe record: {0}")]
+    MalformedRecord(String),
+    /// The Rust name given for a provider module or probe macro is not a valid identifier
+    #[error("Invalid Rust name for provider module or probe macro: \"{0}\"")]
+    InvalidRustName(String),
+    /// The name given for a provider's probe record section is not a valid C identifier
+    #[error("Invalid name for probe record section: \"{0}\"")]
+    InvalidSectionName(String),
+    /// The prefix given for provider names would not make them valid C identifiers
+    #[error("Invalid prefix for provider names: \"{0}\"")]
+    InvalidProviderPrefix(String),
+    /// The version tag given for a provider contains characters which can't be emitted
+    #[error("Invalid version tag for provider: \"{0}\"")]
+    InvalidVersionTag(String),
+    /// The type given for a name in a provider's type mapping is neither a Rust nor a D type
+    #[error("Invalid type \"{1}\" in the mapping of type \"{0}\"")]
+    InvalidTypeMapping(String, String),
+    /// The name of a provider or probe is a Rust keyword which can't be used as an identifier
+    #[error(
+        "The name \"{0}\" is a reserved Rust keyword, and can't be used as a provider or probe name"
+    )]
+    ReservedName(String),
+    /// A probe given a macro name in `probe_names` isn't defined by any provider
+    #[error("Unknown probe \"{0}\" in the names of probe macros")]
+    UnknownProbeName(String),
+    /// Two probes of a provider would generate probe macros of the same name
+    #[error(
+        "Probes \"{1}\" and \"{2}\" of provider \"{0}\" would both generate the macro \"{3}\""
+    )]
+    DuplicateMacroName(String, String, String, String),
     /// Error related to calling out to DTrace itself
     #[error("Failed to call DTrace subprocess")]
     DTraceError,
@@ -78,12 +135,68 @@ pub enum Error {
 pub struct CompileProvidersConfig {
     pub provider: Option<String>,
     pub probe_format: Option<String>,
+    /// The name of the generated Rust module, which defaults to the provider name.
+    ///
+    /// This may also be given as `rust_name`, and allows the Rust name to differ from the
+    /// provider name seen by DTrace.
+    #[serde(alias = "rust_name")]
     pub module: Option<String>,
+    /// The name of the linker section containing the provider's probe records, which defaults to
+    /// `set_dtrace_probes`.
+    ///
+    /// This only affects platforms on which the probe records are emitted by this crate, such as
+    /// illumos, and must be a valid C identifier.
+    pub section: Option<String>,
+    /// Whether the probes fire unconditionally, rather than only while enabled by a tracer.
+    ///
+    /// The arguments of such probes are evaluated on every invocation, even while no tracer is
+    /// attached, so this trades performance for compatibility with tracers which don't manage
+    /// semaphores or is-enabled sites.
+    #[serde(default)]
+    pub always_fire: bool,
+    /// A version string or build hash of the provider, which is included in each of its probe
+    /// records.
+    ///
+    /// This only affects platforms on which the probe records are emitted by this crate, and
+    /// allows tools extracting the records to tell which build a probe came from. The tag may only
+    /// contain ASCII letters and digits, `.`, `_`, `+` and `-`.
+    pub version_tag: Option<String>,
+    /// A prefix added to the name of each provider, as seen by tracers and in the probe records.
+    ///
+    /// This lets a library namespace its providers, so that they don't collide with those of the
+    /// application using it. The generated Rust module keeps the unprefixed name, unless renamed
+    /// with `module`. The prefix must be a valid C identifier.
+    pub provider_prefix: Option<String>,
+    /// Additional typedefs for the provider, mapping each name to a Rust or D type, such as `u64`
+    /// or `uint64_t`.
+    ///
+    /// These allow a provider to use types such as `conn_t` without declaring them in the D file.
+    /// They take precedence over the builtin typedefs, such as `pid_t`, but not over those
+    /// declared in the D file, and each name must end in `_t`.
+    #[serde(default)]
+    pub types: BTreeMap<String, String>,
+    /// The names of the generated probe macros of specific probes, mapping each probe name to the
+    /// name of its macro, such as `do_connect` for the probe `connect`.
+    ///
+    /// This overrides the name otherwise generated with `probe_format`, while DTrace and the
+    /// probe records still see the original probe name. Each name must be a valid Rust
+    /// identifier.
+    #[serde(default)]
+    pub probe_names: BTreeMap<String, String>,
 }
 
 impl CompileProvidersConfig {
     /// Return the formatted name of a probe.
+    ///
+    /// Any dashes in the probe name, e.g., `gc-start`, are mapped to underscores, so that the
+    /// result may be used as a Rust identifier.
+    ///
+    /// A name given for the probe in `probe_names` is returned as is.
     pub fn format_probe(&self, probe_name: &str) -> String {
+        if let Some(name) = self.probe_names.get(probe_name) {
+            return name.clone();
+        }
+        let probe_name = probe_name.replace('-', "_");
         if let Some(fmt) = &self.probe_format {
             fmt.replace(
                 "{provider}",
@@ -91,15 +204,87 @@ impl CompileProvidersConfig {
                     .as_ref()
                     .expect("Expected a provider name when formatting a rpobe"),
             )
-            .replace("{probe}", probe_name)
+            .replace("{probe}", &probe_name)
         } else {
-            String::from(probe_name)
+            probe_name
         }
     }
 
     /// Return the formatted name of the probe as an identifier.
     pub fn probe_ident(&self, probe_name: &str) -> proc_macro2::Ident {
-        quote::format_ident!("{}", self.format_probe(probe_name))
+        rust_ident(&self.format_probe(probe_name))
+    }
+
+    /// Return the provider with the configured prefix, if any, added to its name.
+    pub fn prefix_provider(&self, mut provider: Provider) -> Provider {
+        if let Some(prefix) = &self.provider_prefix {
+            provider.name = format!("{}{}", prefix, provider.name);
+        }
+        provider
+    }
+
+    /// Check that the configured module and probe macro names, if any, are valid Rust
+    /// identifiers, that the section name and provider prefix, if any, are valid C identifiers,
+    /// and that the version tag, if any, may be emitted into the probe records.
+    pub fn validate(&self) -> Result<(), Error> {
+        for name in self.module.iter().chain(self.probe_names.values()) {
+            if syn::parse_str::<syn::Ident>(name).is_err() {
+                return Err(Error::InvalidRustName(name.clone()));
+            }
+        }
+        if let Some(name) = &self.section {
+            // The linker only defines the `__start_` and `__stop_` symbols bounding a section
+            // whose name is a valid C identifier.
+            if !is_c_identifier(name) {
+                return Err(Error::InvalidSectionName(name.clone()));
+            }
+        }
+        if let Some(prefix) = &self.provider_prefix {
+            // Provider names are emitted into the probe records and the symbols of probe sites.
+            if !is_c_identifier(prefix) {
+                return Err(Error::InvalidProviderPrefix(prefix.clone()));
+            }
+        }
+        if let Some(tag) = &self.version_tag {
+            // The tag is emitted as a string in the assembly template of each probe record, where
+            // quotes, backslashes and braces would need escaping, so only allow a safe subset.
+            if !tag
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '+' | '-'))
+            {
+                return Err(Error::InvalidVersionTag(tag.clone()));
+            }
+        }
+        self.typedefs()?;
+        Ok(())
+    }
+
+    /// Parse the providers in a D source file, with the typedefs of the type mapping.
+    pub fn parse_source(&self, source: &str) -> Result<dtrace_parser::File, Error> {
+        let typedefs = self.typedefs()?;
+        let typedefs = typedefs
+            .iter()
+            .map(|(name, typ)| (name.as_str(), *typ))
+            .collect::<Vec<_>>();
+        Ok(dtrace_parser::File::parse_with_typedefs(source, &typedefs)?)
+    }
+
+    // Resolve the type mapping into the types of each name.
+    fn typedefs(&self) -> Result<Vec<(String, dtrace_parser::DataType)>, Error> {
+        self.types
+            .iter()
+            .map(|(name, typ)| {
+                dtrace_parser::DataType::from_rust_type(typ)
+                    .or_else(|| dtrace_parser::DataType::from_c_type(typ))
+                    .map(|resolved| (name.clone(), resolved))
+                    .ok_or_else(|| Error::InvalidTypeMapping(name.clone(), typ.clone()))
+            })
+            .collect()
+    }
+
+    /// Return the name of the section containing the probe records.
+    pub fn section_name(&self) -> &str {
+        self.section.as_deref().unwrap_or(PROBE_RECORD_SECTION)
     }
 
     /// Return the formatted module name as an identifier.
@@ -109,10 +294,101 @@ impl CompileProvidersConfig {
                 .as_ref()
                 .expect("Expected a provider name when making a module ident")
         });
+        rust_ident(name)
+    }
+}
+
+// Return true if the name is a valid C identifier.
+fn is_c_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(false, |ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// The name of the linker section containing probe records, unless configured otherwise.
+pub const PROBE_RECORD_SECTION: &str = "set_dtrace_probes";
+
+/// True if probes compile to no-ops, because no backend supports the target or enabled features.
+/// Such probes are never enabled, and emit no probe records into the binary.
+pub const PROBES_ARE_NOOP: bool = cfg!(usdt_backend_noop);
+
+/// A hash identifying the code generator, which changes along with its sources, backend, or
+/// features, even if the version of this crate doesn't.
+#[doc(hidden)]
+pub const GENERATOR_HASH: &str = env!("USDT_IMPL_GENERATOR_HASH");
+
+// Keywords which can't be used as raw identifiers.
+const RESERVED_NAMES: &[&str] = &["crate", "self", "Self", "super"];
+
+/// Return an identifier for the name of a provider or probe.
+///
+/// Names which are Rust keywords, such as `match`, are escaped as raw identifiers, e.g.,
+/// `r#match`. The original name is still used for the probe as seen by DTrace.
+pub fn rust_ident(name: &str) -> proc_macro2::Ident {
+    if syn::parse_str::<syn::Ident>(name).is_ok() || RESERVED_NAMES.contains(&name) {
         quote::format_ident!("{}", name)
+    } else {
+        proc_macro2::Ident::new_raw(name, proc_macro2::Span::call_site())
     }
 }
 
+// Check that the names of the module and probe macros generated for each provider can be used as
+// identifiers, that no two probes of a provider generate macros of the same name, and that each
+// probe given a macro name in `probe_names` is defined.
+fn check_reserved_names(
+    providers: &[dtrace_parser::Provider],
+    config: &CompileProvidersConfig,
+) -> Result<(), Error> {
+    for provider in providers {
+        let config = CompileProvidersConfig {
+            provider: Some(provider.name.clone()),
+            probe_format: config.probe_format.clone(),
+            module: config.module.clone(),
+            section: config.section.clone(),
+            always_fire: config.always_fire,
+            version_tag: config.version_tag.clone(),
+            provider_prefix: config.provider_prefix.clone(),
+            types: config.types.clone(),
+            probe_names: config.probe_names.clone(),
+        };
+        let module = config.module.as_ref().unwrap_or(&provider.name);
+        let names = std::iter::once(module.clone()).chain(
+            provider
+                .probes
+                .iter()
+                .map(|probe| config.format_probe(&probe.name)),
+        );
+        for name in names {
+            if RESERVED_NAMES.contains(&name.as_str()) {
+                return Err(Error::ReservedName(name));
+            }
+        }
+        let mut macro_names = BTreeMap::new();
+        for probe in provider.probes.iter() {
+            let name = config.format_probe(&probe.name);
+            if let Some(other) = macro_names.insert(name.clone(), &probe.name) {
+                return Err(Error::DuplicateMacroName(
+                    provider.name.clone(),
+                    other.clone(),
+                    probe.name.clone(),
+                    name,
+                ));
+            }
+        }
+    }
+    if let Some(name) = config.probe_names.keys().find(|name| {
+        !providers
+            .iter()
+            .flat_map(|provider| provider.probes.iter())
+            .any(|probe| &&probe.name == name)
+    }) {
+        return Err(Error::UnknownProbeName(name.clone()));
+    }
+    Ok(())
+}
+
 // Compile DTrace provider source code into Rust.
 //
 // This function parses a provider definition, and, for each probe, a corresponding Rust macro is
@@ -124,15 +400,187 @@ pub fn compile_provider_source(
     source: &str,
     config: &CompileProvidersConfig,
 ) -> Result<proc_macro2::TokenStream, Error> {
+    config.validate()?;
+    check_reserved_names(config.parse_source(source)?.providers(), config)?;
     crate::internal::compile_provider_source(source, config)
 }
 
+/// The definition of a provider, built in Rust rather than parsed from D source.
+///
+/// This allows generating probes from another description of a provider, such as an IDL file
+/// processed in a build script. The generated code is the same as for the equivalent D source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderSpec {
+    pub name: String,
+    pub probes: Vec<ProbeSpec>,
+}
+
+/// The definition of a single probe in a [`ProviderSpec`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeSpec {
+    pub name: String,
+    pub types: Vec<NativeType>,
+}
+
+impl ProviderSpec {
+    /// Construct a provider with the given name, and no probes.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            probes: Vec::new(),
+        }
+    }
+
+    /// Add a probe with the given name and argument types.
+    pub fn probe(mut self, name: &str, types: &[NativeType]) -> Self {
+        self.probes.push(ProbeSpec {
+            name: name.to_string(),
+            types: types.to_vec(),
+        });
+        self
+    }
+
+    // Convert the provider into the parser's representation, and check it against the same rules
+    // applied to those parsed from D source.
+    fn to_definition(&self) -> Result<dtrace_parser::Provider, dtrace_parser::DTraceError> {
+        let definition = dtrace_parser::Provider {
+            name: self.name.clone(),
+            probes: self
+                .probes
+                .iter()
+                .map(|probe| dtrace_parser::Probe {
+                    name: probe.name.clone(),
+                    types: probe.types.clone(),
+                    annotations: Vec::new(),
+                })
+                .collect(),
+            annotations: Vec::new(),
+        };
+        definition.validate()?;
+        Ok(definition)
+    }
+}
+
+/// Compile a provider defined in Rust into the same code generated from its D source.
+pub fn compile_provider_spec(
+    spec: &ProviderSpec,
+    config: &CompileProvidersConfig,
+) -> Result<proc_macro2::TokenStream, Error> {
+    config.validate()?;
+    let definition = spec.to_definition()?;
+    check_reserved_names(std::slice::from_ref(&definition), config)?;
+    let config = CompileProvidersConfig {
+        provider: Some(definition.name.clone()),
+        probe_format: config.probe_format.clone(),
+        module: Some(
+            config
+                .module
+                .clone()
+                .unwrap_or_else(|| definition.name.clone()),
+        ),
+        section: config.section.clone(),
+        always_fire: config.always_fire,
+        version_tag: config.version_tag.clone(),
+        provider_prefix: config.provider_prefix.clone(),
+        types: config.types.clone(),
+        probe_names: config.probe_names.clone(),
+    };
+    Ok(compile_provider(&Provider::from(&definition), &config))
+}
+
+/// Generate a C header declaring the probes of a provider defined in Rust.
+///
+/// This is the same header as generated by [`compile_provider_header`] from the equivalent D
+/// source.
+pub fn compile_provider_spec_header(spec: &ProviderSpec) -> Result<String, Error> {
+    Ok(Provider::from(&spec.to_definition()?).to_c_header())
+}
+
+/// Generate the D source of a provider defined in Rust.
+///
+/// The source parses back into the same provider, so this may be used to emit a `.d` file for a
+/// provider built at runtime. Unlike [`Provider::to_d_source`], all arguments are included, and
+/// dashes in probe names are written as they are.
+pub fn compile_provider_spec_source(spec: &ProviderSpec) -> Result<String, Error> {
+    let definition = spec.to_definition()?;
+    let probes = definition
+        .probes
+        .iter()
+        .map(|probe| {
+            let types = probe
+                .types
+                .iter()
+                .map(|typ| match typ {
+                    NativeType::Array(int, len) => format!("{}[{}]", int.to_c_type(), len),
+                    NativeType::Opaque => String::from("void*"),
+                    _ => typ.to_c_type(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("\tprobe {}({});", probe.name, types)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    Ok(format!(
+        "provider {} {{\n{}\n}};\n",
+        definition.name, probes
+    ))
+}
+
+/// Generate a section of probe records for a provider defined in Rust.
+///
+/// The section contains one record for each probe, in the given format, with the arguments
+/// passed to DTrace. As the probes have no code, the address of each is zero. See
+/// [`record::RecordBuilder`] to build records at other addresses.
+#[cfg(any(usdt_backend_standard, feature = "des"))]
+pub fn compile_provider_spec_records(
+    spec: &ProviderSpec,
+    format: record::RecordFormat,
+) -> Result<Vec<u8>, Error> {
+    let provider = Provider::from(&spec.to_definition()?);
+    let builder =
+        provider
+            .probes
+            .iter()
+            .fold(record::RecordBuilder::new(format), |builder, probe| {
+                let types = probe
+                    .dtrace_types()
+                    .iter()
+                    .map(DataType::to_c_type)
+                    .collect::<Vec<_>>();
+                builder.probe(&provider.name, &probe.name, 0, &types)
+            });
+    Ok(builder.build())
+}
+
 // Compile a DTrace provider from its representation in the USDT crate.
 pub fn compile_provider(
     provider: &Provider,
     config: &CompileProvidersConfig,
 ) -> proc_macro2::TokenStream {
-    crate::internal::compile_provider_from_definition(provider, config)
+    let provider = config.prefix_provider(provider.clone());
+    crate::internal::compile_provider_from_definition(&provider, config)
+}
+
+/// Generate a C header declaring the probes of each provider in the D source.
+///
+/// This allows C code to fire the same probes as the Rust code generated from the same provider
+/// definition. See [`Provider::to_c_header`] for details.
+pub fn compile_provider_header(source: &str) -> Result<String, Error> {
+    let dfile = dtrace_parser::File::try_from(source)?;
+    Ok(dfile
+        .providers()
+        .iter()
+        .map(|provider| Provider::from(provider).to_c_header())
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Format the D source of a provider definition in a canonical style.
+///
+/// See [`dtrace_parser::format_source`] for details of the style.
+pub fn format_provider_source(source: &str) -> Result<String, Error> {
+    Ok(dtrace_parser::format_source(source)?)
 }
 
 /// A data type supported by the `usdt` crate.
@@ -141,6 +589,8 @@ pub enum DataType {
     Native(dtrace_parser::DataType),
     UniqueId,
     Serializable(syn::Type),
+    /// A pointer to a `#[repr(C)]` struct implementing [`ProbeStruct`], holding the struct type.
+    StructPointer(syn::Type),
 }
 
 impl DataType {
@@ -150,6 +600,7 @@ impl DataType {
             DataType::Native(ty) => ty.to_c_type(),
             DataType::UniqueId => String::from("uint64_t"),
             DataType::Serializable(_) => String::from("char*"),
+            DataType::StructPointer(_) => String::from("void*"),
         }
     }
 
@@ -159,6 +610,7 @@ impl DataType {
             DataType::Native(ty) => syn::parse_str(&ty.to_rust_ffi_type()).unwrap(),
             DataType::UniqueId => syn::parse_str("::std::os::raw::c_ulonglong").unwrap(),
             DataType::Serializable(_) => syn::parse_str("*const ::std::os::raw::c_char").unwrap(),
+            DataType::StructPointer(_) => syn::parse_str("*const ::std::os::raw::c_void").unwrap(),
         }
     }
 
@@ -168,6 +620,7 @@ impl DataType {
             DataType::Native(ty) => syn::parse_str(&ty.to_rust_type()).unwrap(),
             DataType::UniqueId => syn::parse_str("::usdt::UniqueId").unwrap(),
             DataType::Serializable(ref inner) => inner.clone(),
+            DataType::StructPointer(ref inner) => syn::parse_quote! { *const #inner },
         }
     }
 }
@@ -184,6 +637,44 @@ impl From<&syn::Type> for DataType {
     }
 }
 
+/// Return the Rust type of a probe argument with the given D type, e.g., `u8` for `uint8_t`.
+///
+/// This accepts any type which may appear in the argument list of a probe in a D provider
+/// definition, and returns `None` for any other type. The mapping is the same as that used for
+/// the arguments of the generated probe macros, so this may be used to generate bindings for
+/// probes from their argument types, such as those stored in the probe records of an object file.
+pub fn dtrace_type_to_rust(d_type: &str) -> Option<syn::Type> {
+    dtrace_parser::DataType::from_c_type(d_type).map(|typ| DataType::Native(typ).to_rust_type())
+}
+
+/// A `#[repr(C)]` struct which may be passed to a probe by pointer.
+///
+/// DTrace only sees the address of the struct, so this provides a definition of the struct in D,
+/// which scripts can use to copy in and read its fields. The definitions for the structs used by
+/// a provider are listed in its `STRUCT_DEFINITIONS` constant.
+///
+/// This is usually implemented with `#[derive(ProbeStruct)]`, which generates the definition from
+/// the fields of the struct.
+pub trait ProbeStruct {
+    /// The definition of the struct in D, e.g., `"struct ctx { uint64_t id; };"`.
+    const D_DEFINITION: &'static str;
+}
+
+/// The maximum number of arguments of a probe which are passed to DTrace.
+///
+/// Arguments are passed in registers, and only the first six are supported. Probes may be defined
+/// with more arguments, but a warning is emitted, and only the first six are visible to DTrace.
+pub const MAX_PROBE_ARGUMENTS: usize = 6;
+
+// The maximum lengths of provider and probe names supported by DTrace, including a null
+// terminator. See dtrace.h.
+//
+// DTrace appends the PID to the provider name. The exact size is platform dependent, but use the
+// largest known value of 999,999 on illumos. MacOS and the BSDs are 32-99K. We take the log to get
+// the number of digits.
+pub(crate) const MAX_PROVIDER_NAME_LEN: usize = 64 - 6;
+pub(crate) const MAX_PROBE_NAME_LEN: usize = 64;
+
 /// A single DTrace probe function
 #[derive(Debug, Clone)]
 pub struct Probe {
@@ -201,15 +692,28 @@ impl From<dtrace_parser::Probe> for Probe {
 }
 
 impl Probe {
+    /// Return the types of the arguments which are passed to DTrace, i.e., at most the first
+    /// [`MAX_PROBE_ARGUMENTS`].
+    pub fn dtrace_types(&self) -> &[DataType] {
+        &self.types[..self.types.len().min(MAX_PROBE_ARGUMENTS)]
+    }
+
     /// Return the representation of this probe in D source code.
+    ///
+    /// Dashes in the probe name are written as double-underscores, which DTrace translates back
+    /// into dashes.
     pub fn to_d_source(&self) -> String {
         let types = self
-            .types
+            .dtrace_types()
             .iter()
             .map(|typ| typ.to_c_type())
             .collect::<Vec<_>>()
             .join(", ");
-        format!("probe {name}({types});", name = self.name, types = types)
+        format!(
+            "probe {name}({types});",
+            name = self.name.replace('-', "__"),
+            types = types
+        )
     }
 }
 
@@ -236,6 +740,17 @@ impl Provider {
             probes = probes
         )
     }
+
+    /// Return a C header declaring the probes in this provider.
+    ///
+    /// The header mirrors that generated by `dtrace -h`. Each probe `bar` in the provider `foo`
+    /// is declared as the macro `FOO_BAR(...)`, with a corresponding is-enabled macro
+    /// `FOO_BAR_ENABLED()`. The functions these macros call are only declared, and are defined by
+    /// the object which `dtrace -G` generates from the D file and the C objects using the header,
+    /// so the two must be used together, e.g., on illumos or FreeBSD.
+    pub fn to_c_header(&self) -> String {
+        crate::header::emit_c_header(self)
+    }
 }
 
 impl From<dtrace_parser::Provider> for Provider {
@@ -265,9 +780,186 @@ where
     ::serde_json::to_string(x).map_err(Error::from)
 }
 
+/// The largest array or slice of integers, in bytes, which is copied into a probe's JSON.
+///
+/// Larger arrays and slices of integers are referred to where they are instead, as
+/// `{"ref": {"ptr": <address>, "len": <size in bytes>}}`. DTrace's default `strsize` is 256 bytes,
+/// so the JSON of larger values would often be truncated anyway. Other serializable arguments are
+/// always copied, whatever their size.
+pub const MAX_INLINE_ARGUMENT_SIZE: usize = 256;
+
+/// Serialize a probe argument to the JSON passed to DTrace, as `{"ok": <value>}`, or
+/// `{"err": "<message>"}` if that fails.
+///
+/// NOTE: This is called from the generated probe macros, and is not intended to be used directly.
+pub fn serialize_probe_argument<T>(x: &T) -> String
+where
+    T: ?Sized + ::serde::Serialize,
+{
+    match to_json(x) {
+        Ok(json) => format!("{{\"ok\":{}}}", json),
+        Err(e) => format!("{{\"err\":\"{}\"}}", e),
+    }
+}
+
+/// Serialize an array or slice of integers passed as a probe argument to the JSON passed to
+/// DTrace.
+///
+/// Values of at most [`MAX_INLINE_ARGUMENT_SIZE`] bytes are serialized as with
+/// [`serialize_probe_argument`]. Larger values are passed as their address and size in bytes, as
+/// `{"ref": {"ptr": <address>, "len": <size>}}`, without being copied.
+///
+/// NOTE: This is called from the generated probe macros, and is not intended to be used directly.
+pub fn serialize_probe_integers<T>(x: &[T]) -> String
+where
+    T: ::serde::Serialize,
+{
+    let len = std::mem::size_of_val(x);
+    if len > MAX_INLINE_ARGUMENT_SIZE {
+        format!(
+            "{{\"ref\":{{\"ptr\":{},\"len\":{}}}}}",
+            x.as_ptr() as usize,
+            len
+        )
+    } else {
+        serialize_probe_argument(x)
+    }
+}
+
+// Number of times each probe has fired, keyed by provider and probe name.
+#[cfg(feature = "test-counters")]
+static FIRE_COUNTS: std::sync::Mutex<Vec<(&'static str, &'static str, u64)>> =
+    std::sync::Mutex::new(Vec::new());
+
+/// Record that a probe has fired, for the `test-counters` feature.
+///
+/// NOTE: This is called from the generated probe macros, and is not intended to be used directly.
+#[cfg(feature = "test-counters")]
+pub fn count_probe_firing(provider: &'static str, probe: &'static str) {
+    let mut counts = FIRE_COUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    match counts
+        .iter_mut()
+        .find(|(prov, prob, _)| *prov == provider && *prob == probe)
+    {
+        Some((_, _, count)) => *count += 1,
+        None => counts.push((provider, probe, 1)),
+    }
+}
+
+/// Return the number of times a probe has fired, for the `test-counters` feature.
+#[cfg(feature = "test-counters")]
+pub fn probe_fire_count(provider: &str, probe: &str) -> u64 {
+    FIRE_COUNTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|(prov, prob, _)| *prov == provider && *prob == probe)
+        .map(|(_, _, count)| *count)
+        .unwrap_or(0)
+}
+
 thread_local! {
     static CURRENT_ID: RefCell<u32> = RefCell::new(0);
     static THREAD_ID: RefCell<usize> = RefCell::new(thread_id::get());
+    // Whether a probe is firing on this thread, including the evaluation of its arguments.
+    static FIRING: Cell<bool> = Cell::new(false);
+}
+
+/// A guard held while a probe fires on the current thread, returned by [`enter_probe_firing`].
+///
+/// NOTE: This is used by the generated probe macros, and is not intended to be used directly.
+#[derive(Debug)]
+pub struct ProbeFiringGuard {
+    // The guard must be dropped on the thread which created it.
+    _not_send: std::marker::PhantomData<*const ()>,
+}
+
+impl Drop for ProbeFiringGuard {
+    fn drop(&mut self) {
+        let _ = FIRING.try_with(|firing| firing.set(false));
+    }
+}
+
+/// Mark that a probe is firing on the current thread, until the returned guard is dropped.
+///
+/// This returns `None` if another probe is already firing on this thread, in which case the
+/// caller drops its own firing. A probe fires by evaluating the closure producing its arguments,
+/// so a probe fired from within that closure, say by a `Serialize` implementation or a function
+/// it calls, would otherwise fire while the first is in progress, and could recurse without bound.
+/// Such nested fires are dropped instead: their argument closures aren't called, and they're
+/// invisible to tracers.
+///
+/// NOTE: This is called from the generated probe macros, and is not intended to be used directly.
+pub fn enter_probe_firing() -> Option<ProbeFiringGuard> {
+    // The guard must only be created when this call sets the flag, since dropping it clears it.
+    // The flag is inaccessible while thread-local storage is destroyed, in which case the probe
+    // is treated as already firing and dropped, rather than panicking in a destructor.
+    FIRING
+        .try_with(|firing| {
+            if firing.replace(true) {
+                None
+            } else {
+                Some(ProbeFiringGuard {
+                    _not_send: std::marker::PhantomData,
+                })
+            }
+        })
+        .unwrap_or(None)
+}
+
+/// Return true if a probe specification matches a DTrace-style probe description.
+///
+/// The specification is one of those generated in a provider's `PROBE_SPECS` constant, e.g.,
+/// `"provider:::probe"`. The description has the form `provider:module:function:name`, where
+/// omitted leading fields match anything, as with `dtrace -n`, so that `"bar*"` and `"foo:::bar*"`
+/// are both valid. Each field may contain the wildcards `*`, matching any sequence of characters,
+/// and `?`, matching any single character, and an empty field matches anything.
+///
+/// The module and function of a probe are only known once the program is linked and the probe is
+/// fired, so those fields of the description are ignored.
+pub fn probe_spec_matches(spec: &str, description: &str) -> bool {
+    let (provider, name) = spec.split_once(":::").unwrap_or((spec, ""));
+    let fields = description.split(':').collect::<Vec<_>>();
+    let (provider_pattern, name_pattern) = match fields.as_slice() {
+        [name] | [_, name] | [_, _, name] => ("", *name),
+        [provider, _, _, name] => (*provider, *name),
+        _ => return false,
+    };
+    glob_matches(provider_pattern, provider) && glob_matches(name_pattern, name)
+}
+
+// Return true if the text matches a glob pattern, supporting `*` and `?`. An empty pattern
+// matches anything.
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    if pattern.is_empty() {
+        return true;
+    }
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // The positions in the pattern and text following the last `*`, to backtrack to on a mismatch.
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+            }
+            Some(&ch) if ch == '?' || ch == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_t)) => {
+                    p = star_p;
+                    t = star_t + 1;
+                    backtrack = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&ch| ch == '*')
 }
 
 /// A unique identifier that can be used to correlate multiple USDT probes together.
@@ -431,6 +1123,41 @@ mod test {
     use dtrace_parser::Integer;
     use dtrace_parser::Sign;
 
+    #[test]
+    fn test_dtrace_type_to_rust() {
+        let cases = [
+            ("int8_t", "i8"),
+            ("int16_t", "i16"),
+            ("int32_t", "i32"),
+            ("int64_t", "i64"),
+            ("intptr_t", "isize"),
+            ("uint8_t", "u8"),
+            ("uint16_t", "u16"),
+            ("uint32_t", "u32"),
+            ("uint64_t", "u64"),
+            ("uintptr_t", "usize"),
+            ("int32_t*", "*const i32"),
+            ("char *", "&str"),
+            ("char*", "&str"),
+            ("float", "f32"),
+            ("double", "f64"),
+            ("uint8_t[16]", "[u8; 16]"),
+            ("hrtime_t", "i64"),
+            ("const char*", "&str"),
+        ];
+        for (d_type, rust_type) in cases.iter() {
+            let expected: syn::Type = syn::parse_str(rust_type).unwrap();
+            assert_eq!(
+                dtrace_type_to_rust(d_type),
+                Some(expected),
+                "Unexpected Rust type for \"{}\"",
+                d_type
+            );
+        }
+        assert_eq!(dtrace_type_to_rust("void*"), None);
+        assert_eq!(dtrace_type_to_rust("struct foo"), None);
+    }
+
     #[test]
     fn test_probe_to_d_source() {
         let probe = Probe {
@@ -441,6 +1168,12 @@ mod test {
             }))],
         };
         assert_eq!(probe.to_d_source(), "probe my_probe(uint8_t*);");
+
+        let probe = Probe {
+            name: String::from("gc-start"),
+            types: vec![],
+        };
+        assert_eq!(probe.to_d_source(), "probe gc__start();");
     }
 
     #[test]
@@ -463,6 +1196,94 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_provider_to_c_header() {
+        let source = include_str!("../test-data/provider.d");
+        let expected = include_str!("../test-data/provider.h");
+        assert_eq!(compile_provider_header(source).unwrap(), expected);
+    }
+
+    // Check that C code using the header compiles, and links against definitions of the probe
+    // functions, as generated by `dtrace -G`. This is skipped if there's no C compiler.
+    #[test]
+    fn test_provider_c_header_compiles() {
+        let dir = std::env::temp_dir().join(format!("usdt-c-header-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("provider.h"),
+            include_str!("../test-data/provider.h"),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("main.c"),
+            r#"
+#include "provider.h"
+
+int main(void) {
+    uint64_t count = 0;
+    if (FOO_START_WORK_ENABLED()) {
+        FOO_START_WORK(1);
+    }
+    FOO_STOP_WORK("done", &count);
+    FOO_GC_START();
+    FOO_TICK((hrtime_t)1000);
+    (void)count;
+    return FOO_GC_START_ENABLED() + FOO_STOP_WORK_ENABLED();
+}
+"#,
+        )
+        .unwrap();
+        // Stand-ins for the definitions in the object generated by `dtrace -G`.
+        std::fs::write(
+            dir.join("probes.c"),
+            r#"
+#include <stdint.h>
+
+void __dtrace_foo___start_work(uint8_t arg0) { (void)arg0; }
+int __dtraceenabled_foo___start_work(void) { return 0; }
+void __dtrace_foo___stop_work(char *arg0, uint64_t *arg1) { (void)arg0; (void)arg1; }
+int __dtraceenabled_foo___stop_work(void) { return 0; }
+void __dtrace_foo___gc__start(void) {}
+int __dtraceenabled_foo___gc__start(void) { return 0; }
+void __dtrace_foo___tick(long long arg0) { (void)arg0; }
+int __dtraceenabled_foo___tick(void) { return 0; }
+"#,
+        )
+        .unwrap();
+        let cc = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+        let compile = |args: &[&str]| {
+            std::process::Command::new(&cc)
+                .current_dir(&dir)
+                .args(["-Wall", "-Werror"])
+                .args(args)
+                .status()
+        };
+        let status = match compile(&["-o", "main", "main.c", "probes.c"]) {
+            Ok(status) => status,
+            Err(_) => {
+                eprintln!("Skipping test, no C compiler found");
+                std::fs::remove_dir_all(&dir).unwrap();
+                return;
+            }
+        };
+        assert!(
+            status.success(),
+            "Failed to compile and link with the header"
+        );
+        assert!(std::process::Command::new(dir.join("main"))
+            .status()
+            .unwrap()
+            .success());
+
+        // Disabling the probes needs no definitions.
+        let status = compile(&["-DDTRACE_PROBES_DISABLED=1", "-o", "disabled", "main.c"]).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(
+            status.success(),
+            "Failed to compile with the probes disabled"
+        );
+    }
+
     #[test]
     fn test_data_type() {
         let ty = DataType::Native(DType::Pointer(Integer {
@@ -512,12 +1333,427 @@ mod test {
             provider: Some(String::from("prov")),
             probe_format: Some(String::from("probe_{probe}")),
             module: Some(String::from("not_prov")),
+            section: None,
+            always_fire: false,
+            version_tag: None,
+            provider_prefix: None,
+            types: BTreeMap::new(),
+            probe_names: BTreeMap::new(),
         };
         assert_eq!(config.format_probe("prob"), "probe_prob");
+        assert_eq!(config.format_probe("gc-start"), "probe_gc_start");
         let module = config.module_ident();
         assert_eq!(
             quote::quote! { #module }.to_string(),
             quote::quote! { not_prov }.to_string(),
         );
     }
+
+    #[test]
+    fn test_compile_providers_config_rust_name() {
+        let config: CompileProvidersConfig =
+            serde_json::from_str(r#"{"rust_name": "FooProbes"}"#).unwrap();
+        assert_eq!(config.module.as_deref(), Some("FooProbes"));
+
+        let tokens = compile_provider_source("provider foo { probe bar(); };", &config).unwrap();
+        let module = syn::parse2::<syn::ItemMod>(tokens).unwrap();
+        assert_eq!(module.ident, "FooProbes");
+    }
+
+    #[test]
+    fn test_rust_ident() {
+        assert_eq!(rust_ident("foo").to_string(), "foo");
+        assert_eq!(rust_ident("match").to_string(), "r#match");
+        assert_eq!(rust_ident("type").to_string(), "r#type");
+    }
+
+    #[test]
+    fn test_compile_provider_source_keyword_names() {
+        let tokens = compile_provider_source(
+            "provider type { probe match(uint8_t); };",
+            &CompileProvidersConfig::default(),
+        )
+        .unwrap();
+        let module = syn::parse2::<syn::ItemMod>(tokens).unwrap();
+        assert_eq!(module.ident.to_string(), "r#type");
+        let (_, items) = module.content.unwrap();
+        assert!(items.iter().any(|item| matches!(
+            item,
+            syn::Item::Macro(mac) if mac.ident.as_ref().map(|i| i.to_string()).as_deref() == Some("r#match")
+        )));
+        let specs = quote::quote! { #(#items)* }.to_string();
+        assert!(specs.contains("\"type:::match\""));
+    }
+
+    #[test]
+    fn test_compile_provider_source_reserved_names() {
+        for source in [
+            "provider self { probe bar(); };",
+            "provider foo { probe super(); };",
+        ] {
+            assert!(matches!(
+                compile_provider_source(source, &CompileProvidersConfig::default()),
+                Err(Error::ReservedName(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compile_provider_spec() {
+        let uint8 = Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        };
+        let spec = ProviderSpec::new("foo")
+            .probe("bar", &[NativeType::Integer(uint8), NativeType::String])
+            .probe("gc-start", &[NativeType::Array(uint8, 4)]);
+        let source = "provider foo { probe bar(uint8_t, char*); probe gc-start(uint8_t[4]); };";
+        let config = CompileProvidersConfig {
+            probe_format: Some(String::from("probe_{probe}")),
+            ..Default::default()
+        };
+        assert_eq!(
+            compile_provider_spec(&spec, &config).unwrap().to_string(),
+            compile_provider_source(source, &config)
+                .unwrap()
+                .to_string(),
+        );
+        assert_eq!(
+            compile_provider_spec_header(&spec).unwrap(),
+            compile_provider_header(source).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_compile_provider_spec_source() {
+        let uint8 = Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        };
+        let spec = ProviderSpec::new("foo")
+            .probe("bar", &[NativeType::Integer(uint8), NativeType::String])
+            .probe(
+                "gc-start",
+                &[NativeType::Array(uint8, 4), NativeType::HrTime],
+            )
+            .probe("idle", &[]);
+        let source = compile_provider_spec_source(&spec).unwrap();
+        assert_eq!(
+            source,
+            "provider foo {\n\
+             \tprobe bar(uint8_t, char*);\n\
+             \tprobe gc-start(uint8_t[4], hrtime_t);\n\
+             \tprobe idle();\n\
+             };\n"
+        );
+
+        // The source parses back into the same provider.
+        let file = dtrace_parser::File::try_from(source.as_str()).unwrap();
+        let provider = &file.providers()[0];
+        assert_eq!(provider.name, spec.name);
+        assert_eq!(provider.probes.len(), spec.probes.len());
+        for (parsed, probe) in provider.probes.iter().zip(spec.probes.iter()) {
+            assert_eq!(parsed.name, probe.name);
+            assert_eq!(parsed.types, probe.types);
+        }
+        assert!(compile_provider_spec_source(&ProviderSpec::new("provider")).is_err());
+    }
+
+    #[cfg(feature = "des")]
+    #[test]
+    fn test_compile_provider_spec_records() {
+        let spec = ProviderSpec::new("foo")
+            .probe("bar", &[NativeType::String])
+            .probe("gc-start", &[]);
+        let format = record::RecordFormat::native();
+        let mut data = compile_provider_spec_records(&spec, format).unwrap();
+        let section = record::process_section_with(&mut data, false, format).unwrap();
+        let probes = &section.providers["foo"].probes;
+        assert_eq!(probes["bar"].arguments, vec!["char*"]);
+        assert!(probes["gc-start"].arguments.is_empty());
+    }
+
+    #[test]
+    fn test_compile_provider_spec_invalid() {
+        let config = CompileProvidersConfig::default();
+        for spec in [
+            ProviderSpec::new("provider").probe("bar", &[]),
+            ProviderSpec::new("foo-bar").probe("bar", &[]),
+            ProviderSpec::new("foo").probe("1bar", &[]),
+            ProviderSpec::new("foo").probe("bar", &[]).probe("bar", &[]),
+            ProviderSpec::new("foo")
+                .probe("gc-start", &[])
+                .probe("gc_start", &[]),
+        ] {
+            assert!(matches!(
+                compile_provider_spec(&spec, &config),
+                Err(Error::ParseError(_))
+            ));
+        }
+        assert!(matches!(
+            compile_provider_spec(&ProviderSpec::new("self").probe("bar", &[]), &config),
+            Err(Error::ReservedName(_))
+        ));
+    }
+
+    #[test]
+    fn test_compile_providers_config_invalid_rust_name() {
+        for name in ["my-app", "1foo", "mod", ""] {
+            let config = CompileProvidersConfig {
+                module: Some(String::from(name)),
+                ..Default::default()
+            };
+            assert!(matches!(
+                compile_provider_source("provider foo { probe bar(); };", &config),
+                Err(Error::InvalidRustName(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_enter_probe_firing() {
+        let guard = enter_probe_firing();
+        assert!(guard.is_some());
+        assert!(enter_probe_firing().is_none());
+
+        // Other threads may fire probes at the same time.
+        assert!(std::thread::spawn(|| enter_probe_firing().is_some())
+            .join()
+            .unwrap());
+
+        drop(guard);
+        assert!(enter_probe_firing().is_some());
+    }
+
+    #[test]
+    fn test_enter_probe_firing_from_thread_local_destructor() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        static DROPPED: AtomicBool = AtomicBool::new(false);
+
+        // Fire a probe while the thread's locals are destroyed, as a tracing type's destructor
+        // might. Whether or not it fires, it must not panic.
+        struct FireOnDrop;
+        impl Drop for FireOnDrop {
+            fn drop(&mut self) {
+                drop(enter_probe_firing());
+                DROPPED.store(true, Ordering::SeqCst);
+            }
+        }
+        thread_local! {
+            static FIRE_ON_DROP: FireOnDrop = FireOnDrop;
+        }
+
+        std::thread::spawn(|| {
+            FIRE_ON_DROP.with(|_| {});
+            drop(enter_probe_firing());
+        })
+        .join()
+        .unwrap();
+        assert!(DROPPED.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_probe_spec_matches() {
+        let spec = "foo:::gc-begin";
+        for description in [
+            "foo:::gc-begin",
+            "gc-begin",
+            "gc-*",
+            "*",
+            "",
+            "foo:::",
+            "f*:::*begin",
+            "foo:a.out:main:gc-begin",
+            "foo::main:gc-?egin",
+            "main:gc-begin",
+            ":::gc*",
+        ] {
+            assert!(probe_spec_matches(spec, description), "{}", description);
+        }
+        for description in [
+            "bar:::gc-begin",
+            "foo:::gc-end",
+            "gc",
+            "gc-begin?",
+            "foo*:::*end",
+            "foo:a.out:main:gc-begin:extra",
+        ] {
+            assert!(!probe_spec_matches(spec, description), "{}", description);
+        }
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("a*b*c", "aXbYc"));
+        assert!(glob_matches("a*b*c", "abbbc"));
+        assert!(glob_matches("a**", "a"));
+        assert!(glob_matches("*c", "abcabc"));
+        assert!(!glob_matches("a*b*c", "aXbY"));
+        assert!(!glob_matches("a?c", "ac"));
+        assert!(!glob_matches("abc", "abcd"));
+    }
+
+    #[test]
+    fn test_compile_providers_config_always_fire() {
+        let config: CompileProvidersConfig =
+            serde_json::from_str(r#"{"always_fire": true}"#).unwrap();
+        assert!(config.always_fire);
+        let config: CompileProvidersConfig = serde_json::from_str("{}").unwrap();
+        assert!(!config.always_fire);
+    }
+
+    #[test]
+    fn test_compile_providers_config_version_tag() {
+        let config: CompileProvidersConfig =
+            serde_json::from_str(r#"{"version_tag": "1.2.3-abcdef"}"#).unwrap();
+        assert!(config.validate().is_ok());
+        for tag in ["quo\"te", "back\\slash", "new\nline", "{0}", "with space"] {
+            let config = CompileProvidersConfig {
+                version_tag: Some(String::from(tag)),
+                ..Default::default()
+            };
+            assert!(matches!(
+                compile_provider_source("provider foo { probe bar(); };", &config),
+                Err(Error::InvalidVersionTag(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compile_providers_config_section() {
+        let config: CompileProvidersConfig =
+            serde_json::from_str(r#"{"section": "my_probes"}"#).unwrap();
+        assert_eq!(config.section_name(), "my_probes");
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            CompileProvidersConfig::default().section_name(),
+            "set_dtrace_probes"
+        );
+
+        for name in ["my-probes", "1probes", ".probes", ""] {
+            let config = CompileProvidersConfig {
+                section: Some(String::from(name)),
+                ..Default::default()
+            };
+            assert!(matches!(
+                compile_provider_source("provider foo { probe bar(); };", &config),
+                Err(Error::InvalidSectionName(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compile_providers_config_types() {
+        let config: CompileProvidersConfig =
+            serde_json::from_str(r#"{"types": {"conn_t": "u64", "flags_t": "uint32_t"}}"#).unwrap();
+        assert!(config.validate().is_ok());
+        let file = config
+            .parse_source("provider foo { probe bar(conn_t, flags_t); };")
+            .unwrap();
+        let types = file.providers()[0].probes[0]
+            .types
+            .iter()
+            .map(dtrace_parser::DataType::to_c_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["uint64_t", "uint32_t"]);
+
+        let config: CompileProvidersConfig =
+            serde_json::from_str(r#"{"types": {"conn_t": "u128"}}"#).unwrap();
+        assert!(matches!(
+            compile_provider_source("provider foo { probe bar(conn_t); };", &config),
+            Err(Error::InvalidTypeMapping(name, typ)) if name == "conn_t" && typ == "u128"
+        ));
+    }
+
+    #[test]
+    fn test_compile_providers_config_probe_names() {
+        let config: CompileProvidersConfig = serde_json::from_str(
+            r#"{"provider": "foo", "probe_format": "probe_{probe}", "probe_names": {"connect": "do_connect"}}"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.format_probe("connect"), "do_connect");
+        assert_eq!(config.format_probe("accept"), "probe_accept");
+
+        for name in ["", "do-connect", "fn"] {
+            let config = CompileProvidersConfig {
+                probe_names: [(String::from("connect"), String::from(name))].into(),
+                ..Default::default()
+            };
+            assert!(matches!(
+                compile_provider_source("provider foo { probe connect(); };", &config),
+                Err(Error::InvalidRustName(_))
+            ));
+        }
+
+        // Names which collide with the macro of another probe, and names for undefined probes,
+        // are an error.
+        let config = CompileProvidersConfig {
+            probe_names: [(String::from("connect"), String::from("accept"))].into(),
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile_provider_source("provider foo { probe connect(); probe accept(); };", &config),
+            Err(Error::DuplicateMacroName(provider, first, second, name))
+                if provider == "foo" && first == "connect" && second == "accept" && name == "accept"
+        ));
+        assert!(matches!(
+            compile_provider_source("provider foo { probe accept(); };", &config),
+            Err(Error::UnknownProbeName(name)) if name == "connect"
+        ));
+    }
+
+    #[test]
+    fn test_compile_providers_config_provider_prefix() {
+        let config: CompileProvidersConfig =
+            serde_json::from_str(r#"{"provider_prefix": "mylib_"}"#).unwrap();
+        assert!(config.validate().is_ok());
+        let provider = config.prefix_provider(Provider {
+            name: String::from("foo"),
+            probes: vec![],
+            use_statements: vec![],
+        });
+        assert_eq!(provider.name, "mylib_foo");
+
+        // The module keeps the unprefixed name, while tracers see the prefixed one.
+        let tokens = compile_provider_source("provider foo { probe bar(); };", &config)
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains("mod foo "));
+        assert!(tokens.contains("\"mylib_foo\""));
+
+        for prefix in ["my-lib", "1lib", ""] {
+            let config = CompileProvidersConfig {
+                provider_prefix: Some(String::from(prefix)),
+                ..Default::default()
+            };
+            assert!(matches!(
+                compile_provider_source("provider foo { probe bar(); };", &config),
+                Err(Error::InvalidProviderPrefix(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_serialize_probe_argument() {
+        assert_eq!(serialize_probe_argument(&[1u8, 2]), r#"{"ok":[1,2]}"#);
+        assert_eq!(serialize_probe_integers(&[1u8, 2]), r#"{"ok":[1,2]}"#);
+        let small = [0u8; MAX_INLINE_ARGUMENT_SIZE];
+        assert!(serialize_probe_integers(&small[..]).starts_with(r#"{"ok":[0,"#));
+
+        // Other values are copied whatever their size.
+        let large = vec![(0u32, 0u32); MAX_INLINE_ARGUMENT_SIZE];
+        assert!(serialize_probe_argument(&large).starts_with(r#"{"ok":[[0,0],"#));
+
+        // A large array of integers is passed by reference, as its address and length in bytes.
+        let large = [0u32; MAX_INLINE_ARGUMENT_SIZE];
+        assert_eq!(
+            serialize_probe_integers(&large[..]),
+            format!(
+                r#"{{"ref":{{"ptr":{},"len":{}}}}}"#,
+                large.as_ptr() as usize,
+                MAX_INLINE_ARGUMENT_SIZE * 4
+            )
+        );
+    }
 }
@@ -71,7 +71,6 @@ use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use std::{
     collections::BTreeMap,
-    convert::TryFrom,
     io::Write,
     process::{Command, Stdio},
 };
@@ -81,8 +80,20 @@ pub fn compile_provider_source(
     source: &str,
     config: &crate::CompileProvidersConfig,
 ) -> Result<TokenStream, crate::Error> {
-    let dfile = dtrace_parser::File::try_from(source)?;
-    let header = build_header_from_provider(&source)?;
+    let dfile = config.parse_source(source)?;
+    // Generate the header from the parsed definition, rather than the source itself, which may
+    // use syntax accepted by this crate but not by the system's `dtrace`, e.g., trailing commas.
+    let normalized = dfile
+        .providers()
+        .iter()
+        .map(|provider| {
+            config
+                .prefix_provider(Provider::from(provider))
+                .to_d_source()
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let header = build_header_from_provider(&normalized)?;
     let provider_info = extract_providers(&header);
     let providers = dfile
         .providers()
@@ -98,7 +109,14 @@ pub fn compile_provider_source(
                     None => Some(provider.name.clone()),
                     other => other.clone(),
                 },
+                section: config.section.clone(),
+                always_fire: config.always_fire,
+                version_tag: config.version_tag.clone(),
+                provider_prefix: config.provider_prefix.clone(),
+                types: config.types.clone(),
+                probe_names: config.probe_names.clone(),
             };
+            let provider = config.prefix_provider(provider);
             compile_provider(&provider, &provider_info[&provider.name], &config)
         })
         .collect::<Vec<_>>();
@@ -136,8 +154,25 @@ fn compile_provider(
         ));
     }
     let module = config.module_ident();
+    let record_size = common::build_record_section_size(provider, config);
+    let probe_specs = common::build_probe_specs(provider);
+    let probe_arg_types = common::build_probe_arg_types(provider);
+    let enable_functions = common::build_enable_functions();
+    let provider_info = common::build_provider_info(provider);
+    let struct_definitions = common::build_struct_definitions(provider);
+    let warnings = common::build_argument_warnings(provider);
+    let marker = common::build_provider_marker(provider);
     quote! {
+        #[allow(non_snake_case)]
         pub(crate) mod #module {
+            #marker
+            #warnings
+            #record_size
+            #probe_specs
+            #probe_arg_types
+            #enable_functions
+            #provider_info
+            #struct_definitions
             #(#probe_impls)*
         }
     }
@@ -158,15 +193,21 @@ fn compile_probe(
     let stability_fn = format_ident!("stability");
     let typedefs = &provider_info.typedefs;
     let typedef_fn = format_ident!("typedefs");
-    let is_enabled = &provider_info.is_enabled[probe_name];
-    let is_enabled_fn = format_ident!("{}_{}_enabled", &provider.name, probe_name);
+    // Dashes in probe names appear as double-underscores in the generated header.
+    let header_name = probe_name.replace('-', "__");
+    let is_enabled = &provider_info.is_enabled[&header_name];
+    let is_enabled_fn = format_ident!(
+        "{}_{}_enabled",
+        &provider.name,
+        probe_name.replace('-', "_")
+    );
 
     // The probe function is a little different. We prefix it with `__` because otherwise it has
     // the same name as the macro itself, which leads to conflicts.
-    let probe = &provider_info.probes[probe_name];
+    let probe = &provider_info.probes[&header_name];
     let extern_probe_fn = format_ident!("__{}", config.probe_ident(probe_name));
 
-    let ffi_param_list = types.iter().map(|typ| {
+    let ffi_param_list = types.iter().take(crate::MAX_PROBE_ARGUMENTS).map(|typ| {
         let ty = typ.to_rust_ffi_type();
         syn::parse2::<syn::FnArg>(quote! { _: #ty }).unwrap()
     });
@@ -184,6 +225,33 @@ fn compile_probe(
     #[cfg(not(usdt_stable_asm))]
     let asm_macro = quote! { asm };
 
+    let asm_options = common::probe_asm_options();
+
+    let fire = quote! {
+        #unpacked_args
+        #asm_macro!(
+            ".reference {typedefs}",
+            #call_instruction,
+            ".reference {stability}",
+            typedefs = sym #typedef_fn,
+            extern_probe_fn = sym #extern_probe_fn,
+            stability = sym #stability_fn,
+            #in_regs
+            #asm_options
+        );
+    };
+    let fire = common::guard_probe_firing(fire);
+    // Probes which always fire don't check their is-enabled function.
+    let fire = if config.always_fire {
+        fire
+    } else {
+        quote! {
+            if #is_enabled_fn() != 0 {
+                #fire
+            }
+        }
+    };
+
     let impl_block = quote! {
         extern "C" {
             #[allow(unused)]
@@ -203,23 +271,26 @@ fn compile_probe(
             fn #extern_probe_fn(#(#ffi_param_list,)*);
         }
         unsafe {
-            if #is_enabled_fn() != 0 {
-                #unpacked_args
-                #asm_macro!(
-                    ".reference {typedefs}",
-                    #call_instruction,
-                    ".reference {stability}",
-                    typedefs = sym #typedef_fn,
-                    extern_probe_fn = sym #extern_probe_fn,
-                    stability = sym #stability_fn,
-                    #in_regs
-                    options(nomem, nostack, preserves_flags)
-                );
-            }
+            #fire
         }
     };
 
-    common::build_probe_macro(config, provider, probe_name, types, impl_block)
+    let probe_macro = common::build_probe_macro(config, provider, probe_name, types, impl_block);
+    let is_enabled_raw = common::build_is_enabled_raw(
+        config,
+        probe_name,
+        quote! {
+            extern "C" {
+                #[link_name = #is_enabled]
+                fn #is_enabled_fn() -> i32;
+            }
+            unsafe { #is_enabled_fn() as u16 }
+        },
+    );
+    quote! {
+        #probe_macro
+        #is_enabled_raw
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -14,7 +14,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::convert::TryFrom;
 use std::fs::OpenOptions;
 use std::os::unix::io::AsRawFd;
 
@@ -29,7 +28,7 @@ pub fn compile_provider_source(
     source: &str,
     config: &crate::CompileProvidersConfig,
 ) -> Result<TokenStream, crate::Error> {
-    let dfile = dtrace_parser::File::try_from(source)?;
+    let dfile = config.parse_source(source)?;
     let providers = dfile
         .providers()
         .iter()
@@ -44,7 +43,14 @@ pub fn compile_provider_source(
                     None => Some(provider.name.clone()),
                     other => other.clone(),
                 },
+                section: config.section.clone(),
+                always_fire: config.always_fire,
+                version_tag: config.version_tag.clone(),
+                provider_prefix: config.provider_prefix.clone(),
+                types: config.types.clone(),
+                probe_names: config.probe_names.clone(),
             };
+            let provider = config.prefix_provider(provider);
             compile_provider(&provider, &config)
         })
         .collect::<Vec<_>>();
@@ -67,8 +73,31 @@ fn compile_provider(provider: &Provider, config: &crate::CompileProvidersConfig)
         .map(|probe| compile_probe(provider, probe, config))
         .collect::<Vec<_>>();
     let module = config.module_ident();
+    let record_size = common::build_record_section_size(provider, config);
+    let probe_specs = common::build_probe_specs(provider);
+    let probe_arg_types = common::build_probe_arg_types(provider);
+    let enable_functions = common::build_enable_functions();
+    let probe_section = if config.section_name() == crate::PROBE_RECORD_SECTION {
+        quote! {}
+    } else {
+        build_probe_section(config.section_name())
+    };
+    let provider_info = common::build_provider_info(provider);
+    let struct_definitions = common::build_struct_definitions(provider);
+    let warnings = common::build_argument_warnings(provider);
+    let marker = common::build_provider_marker(provider);
     quote! {
+        #[allow(non_snake_case)]
         pub(crate) mod #module {
+            #marker
+            #warnings
+            #record_size
+            #probe_specs
+            #probe_arg_types
+            #enable_functions
+            #probe_section
+            #provider_info
+            #struct_definitions
             #(#probe_impls)*
         }
     }
@@ -80,15 +109,70 @@ fn compile_probe(
     config: &crate::CompileProvidersConfig,
 ) -> TokenStream {
     let (unpacked_args, in_regs) = common::construct_probe_args(&probe.types);
-    let is_enabled_rec = emit_probe_record(&provider.name, &probe.name, None);
-    let probe_rec = emit_probe_record(&provider.name, &probe.name, Some(&probe.types));
+    let section = config.section_name();
+    let version_tag = config.version_tag.as_deref();
+    let is_enabled_rec = emit_probe_record(section, &provider.name, &probe.name, None, version_tag);
+    let probe_rec = emit_probe_record(
+        section,
+        &provider.name,
+        &probe.name,
+        Some(probe.dtrace_types()),
+        version_tag,
+    );
     #[cfg(usdt_stable_asm)]
     let asm_macro = quote! { std::arch::asm };
     #[cfg(not(usdt_stable_asm))]
     let asm_macro = quote! { asm };
 
-    let impl_block = quote! {
-        {
+    let asm_options = common::probe_asm_options();
+
+    let fire = quote! {
+        #unpacked_args
+        unsafe {
+            #asm_macro!(
+                "990:   nop",
+                #probe_rec,
+                #in_regs
+                #asm_options
+            );
+        }
+    };
+    let fire = common::guard_probe_firing(fire);
+    // Probes which always fire have no is-enabled site.
+    let impl_block = if config.always_fire {
+        quote! {
+            {
+                #fire
+            }
+        }
+    } else {
+        quote! {
+            {
+                let mut is_enabled: u64;
+                unsafe {
+                    #asm_macro!(
+                        "990:   clr rax",
+                        #is_enabled_rec,
+                        out("rax") is_enabled,
+                        options(nomem, nostack, preserves_flags)
+                    );
+                }
+
+                if is_enabled != 0 {
+                    #fire
+                }
+            }
+        }
+    };
+    let probe_macro =
+        common::build_probe_macro(config, provider, &probe.name, &probe.types, impl_block);
+
+    // Reading the flag emits another is-enabled site for the probe, which DTrace enables along
+    // with those of the probe's other call sites.
+    let is_enabled_raw = common::build_is_enabled_raw(
+        config,
+        &probe.name,
+        quote! {
             let mut is_enabled: u64;
             unsafe {
                 #asm_macro!(
@@ -98,21 +182,48 @@ fn compile_probe(
                     options(nomem, nostack, preserves_flags)
                 );
             }
+            is_enabled as u16
+        },
+    );
+    quote! {
+        #probe_macro
+        #is_enabled_raw
+    }
+}
+
+// Build the static recording the bounds of a provider's own section of probe records, which is
+// collected into the `set_usdt_probe_sections` section by the linker, so that `register_probes`
+// registers the records of every such section.
+fn build_probe_section(section: &str) -> TokenStream {
+    let start = format!("__start_{}", section);
+    let stop = format!("__stop_{}", section);
+    quote! {
+        #[used]
+        #[link_section = "set_usdt_probe_sections"]
+        static __USDT_RECORD_SECTION: ::usdt::RecordSection = ::usdt::RecordSection {
+            bounds: || {
+                extern "C" {
+                    #[link_name = #start]
+                    static PROBES_START: usize;
+                    #[link_name = #stop]
+                    static PROBES_STOP: usize;
+                }
+
+                // See `extract_probe_records_from_section` for why this is needed.
+                #[cfg(target_os = "illumos")]
+                #[link_section = #section]
+                #[used]
+                static mut FORCE_LOAD: [u64; 0] = [];
 
-            if is_enabled != 0 {
-                #unpacked_args
                 unsafe {
-                    #asm_macro!(
-                        "990:   nop",
-                        #probe_rec,
-                        #in_regs
-                        options(nomem, nostack, preserves_flags)
-                    );
+                    (
+                        ::std::ptr::addr_of!(PROBES_START) as *mut u8,
+                        ::std::ptr::addr_of!(PROBES_STOP) as *mut u8,
+                    )
                 }
-            }
-        }
-    };
-    common::build_probe_macro(config, provider, &probe.name, &probe.types, impl_block)
+            },
+        };
+    }
 }
 
 fn extract_probe_records_from_section() -> Result<Section, crate::Error> {
@@ -143,8 +254,50 @@ fn extract_probe_records_from_section() -> Result<Section, crate::Error> {
     process_section(data, /* register = */ true)
 }
 
+// Return the bounds of the sections of providers placed in their own section, in the order the
+// linker collected them. A section shared by several providers is listed once for each.
+fn probe_sections() -> &'static [crate::RecordSection] {
+    // The symbols are only used for their addresses, so their type needn't be FFI-safe.
+    #[allow(improper_ctypes)]
+    extern "C" {
+        #[link_name = "__start_set_usdt_probe_sections"]
+        static sections_start: crate::RecordSection;
+        #[link_name = "__stop_set_usdt_probe_sections"]
+        static sections_stop: crate::RecordSection;
+    }
+
+    // Ensure the section exists, so that the linker defines the symbols above, even if no provider
+    // is placed in its own section.
+    #[link_section = "set_usdt_probe_sections"]
+    #[used]
+    static EMPTY: [crate::RecordSection; 0] = [];
+
+    unsafe {
+        let start = &sections_start as *const crate::RecordSection;
+        let len = (&sections_stop as *const crate::RecordSection as usize - start as usize)
+            / std::mem::size_of::<crate::RecordSection>();
+        std::slice::from_raw_parts(start, len)
+    }
+}
+
 pub fn register_probes() -> Result<(), crate::Error> {
-    let section = extract_probe_records_from_section()?;
+    register_section(extract_probe_records_from_section()?)?;
+    for probe_section in probe_sections() {
+        let (start, stop) = (probe_section.bounds)();
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(start, (stop as usize).saturating_sub(start as usize))
+        };
+        // The records of a section are only registered once, so those of a section shared with
+        // an earlier provider, or registered by an earlier call, are skipped.
+        let section = process_section(data, /* register = */ true)?;
+        if !section.is_empty() {
+            register_section(section)?;
+        }
+    }
+    Ok(())
+}
+
+fn register_section(section: Section) -> Result<(), crate::Error> {
     let module_name = section
         .providers
         .values()
@@ -183,3 +336,108 @@ fn ioctl_section(buf: &[u8], modname: [std::os::raw::c_char; 64]) -> Result<(),
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_provider_section() {
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: vec![],
+            use_statements: vec![],
+        };
+
+        // Providers in their own section record its bounds, to be registered with the others.
+        let config = crate::CompileProvidersConfig {
+            module: Some(String::from("foo")),
+            section: Some(String::from("my_probes")),
+            ..Default::default()
+        };
+        let tokens = compile_provider(&provider, &config).to_string();
+        assert!(tokens.contains("set_usdt_probe_sections"));
+        assert!(tokens.contains("__start_my_probes"));
+        assert!(tokens.contains("__stop_my_probes"));
+
+        // Those in the default section are registered without it.
+        let config = crate::CompileProvidersConfig {
+            module: Some(String::from("foo")),
+            ..Default::default()
+        };
+        let tokens = compile_provider(&provider, &config).to_string();
+        assert!(!tokens.contains("set_usdt_probe_sections"));
+    }
+
+    // Return the total size of the probe records in the assembly of the given tokens.
+    fn records_len(tokens: TokenStream) -> usize {
+        tokens
+            .into_iter()
+            .map(|tree| match tree {
+                proc_macro2::TokenTree::Group(group) => records_len(group.stream()),
+                proc_macro2::TokenTree::Literal(lit) => {
+                    match syn::parse_str::<syn::LitStr>(&lit.to_string()) {
+                        Ok(lit) if lit.value().contains("991:") => {
+                            crate::record::test::assembled_record_len(&lit.value())
+                        }
+                        _ => 0,
+                    }
+                }
+                _ => 0,
+            })
+            .sum()
+    }
+
+    // Return the total size of the probe records emitted by a generated provider when each probe
+    // macro is invoked, and each raw is-enabled function is called, once. An invocation expands
+    // only one of the arms of a macro, so only the largest is counted.
+    fn emitted_records_len(tokens: TokenStream) -> usize {
+        let module = match syn::parse2::<syn::File>(tokens).unwrap().items.remove(0) {
+            syn::Item::Mod(module) => module,
+            _ => panic!("Expected the provider module"),
+        };
+        module
+            .content
+            .unwrap()
+            .1
+            .into_iter()
+            .map(|item| match item {
+                syn::Item::Macro(mac) => mac
+                    .mac
+                    .tokens
+                    .into_iter()
+                    .map(|arm| records_len(arm.into()))
+                    .max()
+                    .unwrap_or(0),
+                other => records_len(quote! { #other }),
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_record_section_size_matches_emitted_records() {
+        let source = r#"
+            provider foo {
+                probe work();
+                probe gc__begin(uint8_t, char*);
+                probe gc-end(uint64_t, int32_t, char *);
+            };
+        "#;
+        let dfile = dtrace_parser::File::try_from(source).unwrap();
+        let provider = Provider::from(&dfile.providers()[0]);
+        for always_fire in [false, true] {
+            let config = crate::CompileProvidersConfig {
+                module: Some(String::from("foo")),
+                version_tag: Some(String::from("1.2.3")),
+                always_fire,
+                ..Default::default()
+            };
+            assert_eq!(
+                emitted_records_len(compile_provider(&provider, &config)),
+                common::probe_record_section_size(&provider, &config),
+                "always_fire = {}",
+                always_fire
+            );
+        }
+    }
+}
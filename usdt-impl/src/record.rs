@@ -16,7 +16,8 @@
 // limitations under the License.
 
 use crate::DataType;
-use byteorder::{NativeEndian, ReadBytesExt};
+use crate::{MAX_PROBE_NAME_LEN, MAX_PROVIDER_NAME_LEN};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use dof::{Probe, Provider, Section};
 use std::collections::BTreeMap;
 use std::mem::size_of;
@@ -25,30 +26,497 @@ use std::sync::atomic::Ordering;
 
 // Version number for probe records containing data about all probes.
 //
-// NOTE: This must have a maximum of `u8::MAX - 1`. See `read_record_version` for
+// NOTE: This must be less than `REGISTERED_VERSION_FLAG`. See `read_record_version` for
 // details.
 pub(crate) const PROBE_REC_VERSION: u8 = 1;
 
+// Version number for probe records whose strings are prefixed with their length, rather than
+// terminated by a null byte. These records may be read, but aren't yet emitted by this crate.
+const PROBE_REC_VERSION_LENGTH_PREFIXED: u8 = 2;
+
+// Version number for probe records which end with the version tag of their provider. These are
+// the same as those of `PROBE_REC_VERSION`, with the tag following the argument strings, and are
+// emitted for providers configured with a version tag.
+const PROBE_REC_VERSION_TAGGED: u8 = 3;
+
+// Version number for probe records which end with a CRC-32 checksum, detecting records corrupted
+// in storage or by tools manipulating the binary. These are the same as those of
+// `PROBE_REC_VERSION`, with the checksum following the argument strings, or following the version
+// tag if `RECORD_FLAG_VERSION_TAG` is set. It covers the record from the argument count to the
+// checksum, except the address of the probe, so that it's unchanged when the record is relocated.
+// These records may be read and built with `RecordBuilder`, but aren't emitted by the generated
+// probes.
+const PROBE_REC_VERSION_CHECKSUMMED: u8 = 4;
+
+// The most recent version of probe records which may be read. Any later version is skipped.
+//
+// NOTE: This must be less than `REGISTERED_VERSION_FLAG`, as for `PROBE_REC_VERSION`.
+const MAX_PROBE_REC_VERSION: u8 = PROBE_REC_VERSION_CHECKSUMMED;
+
+// Bit set in the version of a probe record once it has been registered, so that any later pass
+// over the section skips it. The other bits keep the original version, so that it may be restored
+// by `reset_registered_records`.
+//
+// NOTE: Earlier releases of this crate instead rewrote the version of registered records to
+// `u8::MAX`, which also has this bit set. Those releases only read records of
+// `PROBE_REC_VERSION`, so that is the original version of any record marked this way.
+const REGISTERED_VERSION_FLAG: u8 = 0x80;
+
+// Flag set in the records of is-enabled probes.
+const RECORD_FLAG_IS_ENABLED: u16 = 1;
+
+// Flag set in checksummed records which include the version tag of their provider.
+const RECORD_FLAG_VERSION_TAG: u16 = 2;
+
+// Size of the leading length and version fields of every probe record.
+const RECORD_HEADER_LEN: usize = size_of::<u32>() + size_of::<u8>();
+
+// Size of all the fixed-size fields at the start of every probe record.
+const RECORD_FIXED_LEN: usize =
+    RECORD_HEADER_LEN + size_of::<u8>() + size_of::<u16>() + size_of::<u64>();
+
+/// The fixed-size fields at the start of a probe record, preceding the provider, probe, and
+/// argument strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordHeader {
+    /// The version of the record format.
+    pub version: u8,
+    /// The number of argument type strings in the record.
+    pub n_args: u8,
+    /// Record flags, whose lowest bit is set for is-enabled probes.
+    pub flags: u16,
+    /// The address of the probe site.
+    pub address: u64,
+}
+
+/// The byte order of the fields of probe records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    /// Return the byte order of the host.
+    pub const fn native() -> Self {
+        if cfg!(target_endian = "big") {
+            Endian::Big
+        } else {
+            Endian::Little
+        }
+    }
+
+    fn read_u16(self, data: &mut &[u8]) -> std::io::Result<u16> {
+        match self {
+            Endian::Little => data.read_u16::<LittleEndian>(),
+            Endian::Big => data.read_u16::<BigEndian>(),
+        }
+    }
+
+    fn read_u32(self, data: &mut &[u8]) -> std::io::Result<u32> {
+        match self {
+            Endian::Little => data.read_u32::<LittleEndian>(),
+            Endian::Big => data.read_u32::<BigEndian>(),
+        }
+    }
+
+    fn read_u64(self, data: &mut &[u8]) -> std::io::Result<u64> {
+        match self {
+            Endian::Little => data.read_u64::<LittleEndian>(),
+            Endian::Big => data.read_u64::<BigEndian>(),
+        }
+    }
+
+    fn u16_bytes(self, value: u16) -> [u8; 2] {
+        match self {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        }
+    }
+
+    fn u32_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        }
+    }
+
+    fn u64_bytes(self, value: u64) -> [u8; 8] {
+        match self {
+            Endian::Little => value.to_le_bytes(),
+            Endian::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+/// The width of pointers on the target which emitted probe records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointerWidth {
+    Bits32,
+    Bits64,
+}
+
+impl PointerWidth {
+    /// Return the pointer width of the host.
+    pub const fn native() -> Self {
+        if cfg!(target_pointer_width = "32") {
+            PointerWidth::Bits32
+        } else {
+            PointerWidth::Bits64
+        }
+    }
+
+    // The probe address is always stored in 8 bytes. Only the low bits are meaningful on targets
+    // with narrower pointers.
+    fn truncate(self, address: u64) -> u64 {
+        match self {
+            PointerWidth::Bits32 => address & u64::from(u32::MAX),
+            PointerWidth::Bits64 => address,
+        }
+    }
+}
+
+/// The data model of the target which emitted a section of probe records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordFormat {
+    pub endian: Endian,
+    pub pointer_width: PointerWidth,
+}
+
+impl RecordFormat {
+    /// Return the data model of the host, which emits records for its own probes.
+    pub const fn native() -> Self {
+        Self {
+            endian: Endian::native(),
+            pointer_width: PointerWidth::native(),
+        }
+    }
+}
+
+impl Default for RecordFormat {
+    fn default() -> Self {
+        Self::native()
+    }
+}
+
+/// The maximum lengths of the strings in parsed probe records, including a null terminator.
+///
+/// Longer strings are truncated, matching the limits which DTrace imposes on the names of
+/// providers, probes, functions and argument types. The defaults are those of illumos, where the
+/// name of a provider is further limited to leave room for the process ID which DTrace appends to
+/// it. Other implementations or versions of DTrace may differ, which tools can match by setting
+/// these explicitly.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StringLimits {
+    pub provider: usize,
+    pub probe: usize,
+    pub function: usize,
+    pub arg_type: usize,
+}
+
+impl Default for StringLimits {
+    fn default() -> Self {
+        Self {
+            provider: MAX_PROVIDER_NAME_LEN,
+            probe: MAX_PROBE_NAME_LEN,
+            function: MAX_FUNC_NAME_LEN,
+            arg_type: MAX_ARG_TYPE_LEN,
+        }
+    }
+}
+
+/// Parse the header fields of a single probe record, which starts with its length.
+///
+/// This only decodes the fixed-size fields, which allows dumping them even if the remainder of
+/// the record is malformed.
+pub fn parse_header(data: &[u8]) -> Result<RecordHeader, crate::Error> {
+    parse_header_with(data, RecordFormat::native())
+}
+
+/// Parse the header fields of a single probe record, emitted by a target with the given format.
+pub fn parse_header_with(data: &[u8], format: RecordFormat) -> Result<RecordHeader, crate::Error> {
+    if data.len() < RECORD_FIXED_LEN {
+        return Err(crate::Error::MalformedRecord(format!(
+            "record header requires {} bytes, found {}",
+            RECORD_FIXED_LEN,
+            data.len()
+        )));
+    }
+    let mut data = &data[size_of::<u32>()..];
+    Ok(RecordHeader {
+        version: data.read_u8()?,
+        n_args: data.read_u8()?,
+        flags: format.endian.read_u16(&mut data)?,
+        address: format
+            .pointer_width
+            .truncate(format.endian.read_u64(&mut data)?),
+    })
+}
+
 /// Extract records for all defined probes from our custom linker sections.
-pub fn process_section(mut data: &mut [u8], register: bool) -> Result<Section, crate::Error> {
-    let mut providers = BTreeMap::new();
+///
+/// The section data may come from an untrusted object file. Malformed records result in an
+/// error, and this never panics or reads outside of `data`, whatever its contents.
+pub fn process_section(data: &mut [u8], register: bool) -> Result<Section, crate::Error> {
+    process_section_with(data, register, RecordFormat::native())
+}
 
-    while !data.is_empty() {
-        assert!(
-            data.len() >= size_of::<u32>(),
-            "Not enough bytes for length header"
-        );
-        // Read the length without consuming it
-        let len = (&data[..size_of::<u32>()]).read_u32::<NativeEndian>()? as usize;
+/// Extract records for all defined probes from a custom linker section, emitted by a target with
+/// the given format.
+///
+/// This is used to analyze object files built for a target other than the host.
+pub fn process_section_with(
+    data: &mut [u8],
+    register: bool,
+    format: RecordFormat,
+) -> Result<Section, crate::Error> {
+    process_section_with_limits(data, register, format, StringLimits::default())
+}
+
+/// Extract records for all defined probes from a custom linker section, emitted by a target with
+/// the given format, truncating their strings to the given limits.
+pub fn process_section_with_limits(
+    data: &mut [u8],
+    register: bool,
+    format: RecordFormat,
+    limits: StringLimits,
+) -> Result<Section, crate::Error> {
+    process_section_filtered(data, register, format, limits, &|_| true)
+}
+
+/// Extract records for the probes of the providers selected by a filter from a custom linker
+/// section, emitted by a target with the given format, truncating their strings to the given
+/// limits.
+///
+/// The filter is called with the name of the provider of each record. Records of other providers
+/// are skipped before the rest of the record is processed, such as resolving the function
+/// containing the probe, and don't appear in the returned section.
+pub fn process_section_filtered(
+    data: &mut [u8],
+    register: bool,
+    format: RecordFormat,
+    limits: StringLimits,
+    filter: &dyn Fn(&str) -> bool,
+) -> Result<Section, crate::Error> {
+    SectionParser::with_format(data, register, format)
+        .limits(limits)
+        .provider_filter(filter)
+        .into_section()
+}
+
+/// Restore the records of a section emitted by a target with the given format to their
+/// unregistered state.
+///
+/// Registering a section marks each of its records as processed, so that they're skipped by any
+/// later pass over the section. A section read from the memory of a process, such as from a core
+/// dump, has usually been registered already. This restores the original version of each such
+/// record, which is kept when the record is registered, so that the records may be processed
+/// again. Records registered by earlier releases of this crate, which didn't keep the version, are
+/// all of the first version, and are restored to that. Records whose original version isn't known
+/// to this crate are left as they are.
+///
+/// This stops at the first record with an invalid length, leaving the remainder untouched.
+pub fn reset_registered_records(data: &mut [u8], format: RecordFormat) {
+    let mut data = data;
+    while data.len() >= RECORD_HEADER_LEN {
+        let len = match format.endian.read_u32(&mut &data[..size_of::<u32>()]) {
+            Ok(len) => len as usize,
+            Err(_) => return,
+        };
+        if len < RECORD_HEADER_LEN || len > data.len() {
+            return;
+        }
         let (rec, rest) = data.split_at_mut(len);
-        process_probe_record(&mut providers, rec, register)?;
+        if rec[4] == u8::MAX {
+            rec[4] = PROBE_REC_VERSION;
+        } else if rec[4] & REGISTERED_VERSION_FLAG != 0 {
+            let version = rec[4] & !REGISTERED_VERSION_FLAG;
+            if version <= MAX_PROBE_REC_VERSION {
+                rec[4] = version;
+            }
+        }
         data = rest;
     }
+}
 
-    Ok(Section {
-        providers,
-        ..Default::default()
-    })
+/// A single probe record, parsed from a section of probe records.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProbeRecord {
+    /// The name of the provider, limited to the length supported by DTrace.
+    pub provider: String,
+    /// The name of the probe, limited to the length supported by DTrace.
+    pub probe: String,
+    /// The name of the function containing the probe site, or its address if not known.
+    pub function: String,
+    /// The name of the object file containing the probe site, if known.
+    pub module: String,
+    /// The address of the probe site.
+    pub address: u64,
+    /// Whether this record is for the is-enabled check of the probe.
+    pub is_enabled: bool,
+    /// The C types of the probe's arguments.
+    pub arguments: Vec<String>,
+    /// The version string or build hash of the provider, if the record includes one.
+    pub version_tag: Option<String>,
+}
+
+/// An incremental parser over a section of probe records.
+///
+/// This yields one record at a time, so callers may control the iteration, for example to parse
+/// a large section lazily or stop early. [`process_section`] is implemented over this parser.
+///
+/// Records from formats newer than this crate supports, and those already registered, are
+/// skipped. Parsing stops after the first error, as the position of any following record can't
+/// be trusted.
+pub struct SectionParser<'a> {
+    data: &'a mut [u8],
+    register: bool,
+    format: RecordFormat,
+    limits: StringLimits,
+    filter: Option<&'a dyn Fn(&str) -> bool>,
+    symbolizer: Option<&'a Symbolizer>,
+}
+
+/// A function returning the names of the function and object file containing an address.
+///
+/// See [`SectionParser::symbolizer`].
+pub type Symbolizer = dyn Fn(u64) -> (Option<String>, Option<String>);
+
+impl std::fmt::Debug for SectionParser<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SectionParser")
+            .field("data", &self.data)
+            .field("register", &self.register)
+            .field("format", &self.format)
+            .field("limits", &self.limits)
+            .field("filter", &self.filter.is_some())
+            .field("symbolizer", &self.symbolizer.is_some())
+            .finish()
+    }
+}
+
+impl<'a> SectionParser<'a> {
+    /// Create a parser over a section of probe records, emitted by the host.
+    ///
+    /// If `register` is true, each record is marked as processed, so parsing the same section
+    /// again skips it. See [`process_section`].
+    pub fn new(data: &'a mut [u8], register: bool) -> Self {
+        Self::with_format(data, register, RecordFormat::native())
+    }
+
+    /// Create a parser over a section of probe records, emitted by a target with the given format.
+    pub fn with_format(data: &'a mut [u8], register: bool, format: RecordFormat) -> Self {
+        // A section which has been zeroed, e.g., to strip the probes from a binary, has no records.
+        let data = if data.iter().all(|byte| *byte == 0) {
+            &mut []
+        } else {
+            data
+        };
+        Self {
+            data,
+            register,
+            format,
+            limits: StringLimits::default(),
+            filter: None,
+            symbolizer: None,
+        }
+    }
+
+    /// Set the maximum lengths of the strings in parsed records.
+    pub fn limits(mut self, limits: StringLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Skip the records of providers for which the filter returns false.
+    ///
+    /// The filter is called with the name of the provider of each record, truncated to the string
+    /// limits, before the rest of the record is processed.
+    pub fn provider_filter(mut self, filter: &'a dyn Fn(&str) -> bool) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Look up the function and object file containing each probe with the given function.
+    ///
+    /// By default, these are looked up with `dladdr`, which only finds the names of addresses in
+    /// the current process. A symbolizer may instead return names from another source, such as
+    /// the DWARF debugging information of a binary, or fixed names in tests. The function name is
+    /// truncated to the string limits, and only the file name of the object is kept. A probe
+    /// whose function isn't found is named after its address, e.g., `?0x1000`.
+    pub fn symbolizer(mut self, symbolizer: &'a Symbolizer) -> Self {
+        self.symbolizer = Some(symbolizer);
+        self
+    }
+
+    /// Return the number of bytes of the section which have not yet been parsed.
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Parse all of the remaining probe records, and collect them into a section.
+    ///
+    /// This is the same as [`process_section_filtered`], but with any other options of the
+    /// parser, such as its symbolizer.
+    pub fn into_section(mut self) -> Result<Section, crate::Error> {
+        let mut providers = BTreeMap::new();
+        while let Some(record) = self.next_probe() {
+            add_probe_record(&mut providers, record?)?;
+        }
+        Ok(Section {
+            providers,
+            ..Default::default()
+        })
+    }
+
+    /// Parse the next probe record in the section, if any.
+    pub fn next_probe(&mut self) -> Option<Result<ProbeRecord, crate::Error>> {
+        while !self.data.is_empty() {
+            match self.next_record() {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => continue,
+                Err(e) => {
+                    self.data = &mut [];
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+
+    // Consume the next record from the section, returning `None` if it is skipped.
+    fn next_record(&mut self) -> Result<Option<ProbeRecord>, crate::Error> {
+        if self.data.len() < size_of::<u32>() {
+            return Err(crate::Error::MalformedRecord(String::from(
+                "not enough bytes for length header",
+            )));
+        }
+        // Read the length without consuming it
+        let len = self
+            .format
+            .endian
+            .read_u32(&mut &self.data[..size_of::<u32>()])? as usize;
+
+        // Every record must at least contain its own length and version, which also guarantees
+        // that we make progress through the section on each iteration.
+        if len < RECORD_HEADER_LEN || len > self.data.len() {
+            return Err(crate::Error::MalformedRecord(format!(
+                "record length {} is invalid, {} bytes remain in the section",
+                len,
+                self.data.len()
+            )));
+        }
+        let (rec, rest) = std::mem::take(&mut self.data).split_at_mut(len);
+        self.data = rest;
+        parse_probe_record(
+            rec,
+            self.register,
+            self.format,
+            &self.limits,
+            self.filter,
+            self.symbolizer,
+        )
+    }
 }
 
 #[cfg(unix)]
@@ -64,18 +532,16 @@ pub(crate) fn addr_to_info(addr: u64) -> (Option<String>, Option<String>) {
         if libc::dladdr(addr as *const libc::c_void, &mut info as *mut _) == 0 {
             (None, None)
         } else {
-            (
-                Some(
-                    std::ffi::CStr::from_ptr(info.dli_sname)
-                        .to_string_lossy()
-                        .to_string(),
-                ),
-                Some(
-                    std::ffi::CStr::from_ptr(info.dli_fname)
-                        .to_string_lossy()
-                        .to_string(),
-                ),
-            )
+            // Either name may be NULL, even if the address is found, for example if there is no
+            // symbol covering it.
+            let to_string = |ptr: *const libc::c_char| {
+                if ptr.is_null() {
+                    None
+                } else {
+                    Some(std::ffi::CStr::from_ptr(ptr).to_string_lossy().to_string())
+                }
+            };
+            (to_string(info.dli_sname), to_string(info.dli_fname))
         }
     }
 }
@@ -86,20 +552,19 @@ pub(crate) fn addr_to_info(_addr: u64) -> (Option<String>, Option<String>) {
     (None, None)
 }
 
-// Limit a string to the DTrace-imposed maxima. Note that this ensures a null-terminated C string
-// result, i.e., the actual string is of length `limit - 1`.
-// See dtrace.h,
-//
-// DTrace appends the PID to the provider name. The exact size is platform dependent, but use the
-// largest known value of 999,999 on illumos. MacOS and the BSDs are 32-99K. We take the log to get
-// the number of digits.
-const MAX_PROVIDER_NAME_LEN: usize = 64 - 6;
-const MAX_PROBE_NAME_LEN: usize = 64;
+// Limit a string to the DTrace-imposed maxima, which are the defaults of `StringLimits`. Note that
+// this ensures a null-terminated C string result, i.e., the actual string is of length
+// `limit - 1`.
+// See dtrace.h, and `crate::MAX_PROVIDER_NAME_LEN` for the provider name.
 const MAX_FUNC_NAME_LEN: usize = 128;
 const MAX_ARG_TYPE_LEN: usize = 128;
 fn limit_string_length<S: AsRef<str>>(s: S, limit: usize) -> String {
     let s = s.as_ref();
-    let limit = s.len().min(limit - 1);
+    let mut limit = s.len().min(limit.saturating_sub(1));
+    // Don't split a multi-byte character.
+    while !s.is_char_boundary(limit) {
+        limit -= 1;
+    }
     s[..limit].to_string()
 }
 
@@ -109,39 +574,70 @@ fn read_record_version(version: &mut u8, register: bool) -> u8 {
     // (2) if this is a version number this compiled crate could feasibly
     // handle.
     let ver = *version;
-    if !register || ver > PROBE_REC_VERSION {
+    if !register || ver > MAX_PROBE_REC_VERSION {
         return ver;
     }
 
     // At this point we know we need to potentially update the version, and that
-    // we also have code that can handle it. We'll exchange it with the sentinel
-    // unconditionally.
+    // we also have code that can handle it. We'll set the registered flag
+    // unconditionally, keeping the version in the remaining bits.
     //
-    // If we get back the sentinel, another thread beat us to the punch. If we
-    // get back anything else, it is a version we are capable of handling.
+    // If we get back a version with the flag set, another thread beat us to the
+    // punch, and the record is skipped like one of a future version. If we get
+    // back anything else, it is a version we are capable of handling.
     //
     // TODO-safety: We'd love to use `AtomicU8::from_mut`, but that remains a
     // nightly-only feature. In the meantime, this is safe because we have a
     // mutable reference to the data in this method, and atomic types are
     // guaranteed to have the same layout as their inner type.
     let ver = unsafe { std::mem::transmute::<&mut u8, &AtomicU8>(version) };
-    ver.swap(u8::MAX, Ordering::SeqCst)
+    ver.fetch_or(REGISTERED_VERSION_FLAG, Ordering::SeqCst)
 }
 
-// Process a single record from the custom linker section.
+// Process a single record from the custom linker section, adding it to its provider.
+#[cfg(test)]
 fn process_probe_record(
     providers: &mut BTreeMap<String, Provider>,
     rec: &mut [u8],
     register: bool,
+    format: RecordFormat,
 ) -> Result<(), crate::Error> {
+    match parse_probe_record(rec, register, format, &StringLimits::default(), None, None)? {
+        Some(record) => add_probe_record(providers, record),
+        None => Ok(()),
+    }
+}
+
+// Parse a single record from the custom linker section, returning `None` if it should be skipped,
+// including if the filter rejects its provider. The names of the function and object containing
+// the probe are found with the symbolizer, or `addr_to_info` if there is none.
+fn parse_probe_record(
+    rec: &mut [u8],
+    register: bool,
+    format: RecordFormat,
+    limits: &StringLimits,
+    filter: Option<&dyn Fn(&str) -> bool>,
+    symbolizer: Option<&Symbolizer>,
+) -> Result<Option<ProbeRecord>, crate::Error> {
+    // Parse the header before the version may be rewritten below, but only check the result once
+    // we know this is a version we can handle.
+    let header = parse_header_with(rec, format);
+
+    // Records of providers rejected by the filter are skipped before they may be marked as
+    // registered below, so that a later pass without the filter still registers them.
+    if let (Some(filter), Ok(header)) = (filter, &header) {
+        if rec[4] <= MAX_PROBE_REC_VERSION {
+            let provname = limit_string_length(
+                (&rec[RECORD_FIXED_LEN..]).read_record_str(header.version, format.endian)?,
+                limits.provider,
+            );
+            if !filter(&provname) {
+                return Ok(None);
+            }
+        }
+    }
+
     // First four bytes are the length, next byte is the version number.
-    let (rec, mut data) = {
-        // We need `rec` to be mutable and have type `&mut [u8]`, and `data` to
-        // be mutable, but have type `&[u8]`. Use `split_at_mut` to get two
-        // `&mut [u8]` and then convert the latter to a shared reference.
-        let (rec, data) = rec.split_at_mut(5);
-        (rec, &*data)
-    };
     let version = read_record_version(&mut rec[4], register);
 
     // If this record comes from a future version of the data format, we skip it
@@ -149,80 +645,331 @@ fn process_probe_record(
     // recent version. Note that future versions should handle previous formats.
     //
     // NOTE: This version check is also used to implement one-time registration of probes. On the
-    // first pass through the probe section, the version is rewritten to `u8::MAX`, so that any
-    // future read of the section skips all previously-read records.
-    if version > PROBE_REC_VERSION {
-        return Ok(());
+    // first pass through the probe section, the version is marked with `REGISTERED_VERSION_FLAG`,
+    // so that any future read of the section skips all previously-read records.
+    if version > MAX_PROBE_REC_VERSION {
+        return Ok(None);
     }
 
-    let n_args = data.read_u8()? as usize;
-    let flags = data.read_u16::<NativeEndian>()?;
-    let address = data.read_u64::<NativeEndian>()?;
-    let provname = data.read_cstr();
-    let probename = data.read_cstr();
+    // The version may have been rewritten if the record was registered, so use that of the
+    // header to decide how the strings are encoded.
+    let RecordHeader {
+        version,
+        n_args,
+        flags,
+        address,
+    } = header?;
+    let n_args = n_args as usize;
+    let mut data = &rec[RECORD_FIXED_LEN..];
+    let provname = limit_string_length(
+        data.read_record_str(version, format.endian)?,
+        limits.provider,
+    );
+    let probename = data.read_record_str(version, format.endian)?;
     let args = {
+        // The record is padded with zeros, which would otherwise be read as empty argument
+        // strings. No valid argument type is empty, so treat those as missing too.
         let mut args = Vec::with_capacity(n_args);
-        for _ in 0..n_args {
-            args.push(limit_string_length(data.read_cstr(), MAX_ARG_TYPE_LEN));
+        for i in 0..n_args {
+            match data.read_record_str(version, format.endian) {
+                Ok(arg) if !arg.is_empty() => args.push(limit_string_length(arg, limits.arg_type)),
+                _ => {
+                    return Err(crate::Error::MalformedRecord(format!(
+                        "record for probe \"{}\" declares {} arguments, but contains only {}",
+                        probename, n_args, i
+                    )));
+                }
+            }
         }
         args
     };
+    let has_version_tag = version == PROBE_REC_VERSION_TAGGED
+        || (version == PROBE_REC_VERSION_CHECKSUMMED && flags & RECORD_FLAG_VERSION_TAG != 0);
+    let version_tag = if has_version_tag {
+        Some(data.read_cstr()?.to_string())
+    } else {
+        None
+    };
+    if version == PROBE_REC_VERSION_CHECKSUMMED {
+        let end = rec.len() - data.len();
+        let checksum = format.endian.read_u32(&mut data).map_err(|_| {
+            crate::Error::MalformedRecord(format!(
+                "record for probe \"{}\" is missing its checksum",
+                probename
+            ))
+        })?;
+        let expected = record_checksum(&rec[..end]);
+        if checksum != expected {
+            return Err(crate::Error::MalformedRecord(format!(
+                "record for probe \"{}\" has checksum {:#010x}, expected {:#010x}",
+                probename, checksum, expected
+            )));
+        }
+    }
 
-    let funcname = match addr_to_info(address).0 {
-        Some(s) => limit_string_length(s, MAX_FUNC_NAME_LEN),
+    let (funcname, modname) = match symbolizer {
+        Some(symbolizer) => symbolizer(address),
+        None => addr_to_info(address),
+    };
+    let funcname = match funcname {
+        Some(s) => limit_string_length(s, limits.function),
         None => format!("?{:#x}", address),
     };
+    let modname = modname
+        .map(|path| path.rsplit('/').next().map(String::from).unwrap_or(path))
+        .unwrap_or_default();
 
-    let provname = limit_string_length(provname, MAX_PROVIDER_NAME_LEN);
-    let provider = providers.entry(provname.clone()).or_insert(Provider {
-        name: provname,
-        probes: BTreeMap::new(),
-    });
-
-    let probename = limit_string_length(probename, MAX_PROBE_NAME_LEN);
-    let probe = provider.probes.entry(probename.clone()).or_insert(Probe {
-        name: probename,
+    Ok(Some(ProbeRecord {
+        provider: provname,
+        probe: limit_string_length(probename, limits.probe),
         function: funcname,
+        module: modname,
         address,
-        offsets: vec![],
-        enabled_offsets: vec![],
-        arguments: vec![],
-    });
-    probe.arguments = args;
+        is_enabled: flags & RECORD_FLAG_IS_ENABLED != 0,
+        arguments: args,
+        version_tag,
+    }))
+}
+
+// Compute the checksum of a record, up to the checksum itself, which covers everything following
+// the version except the address.
+fn record_checksum(rec: &[u8]) -> u32 {
+    let address = RECORD_FIXED_LEN - size_of::<u64>();
+    crc32(&[&rec[RECORD_HEADER_LEN..address], &rec[RECORD_FIXED_LEN..]].concat())
+}
+
+// Compute the CRC-32 checksum of some data, as used by zlib and PNG.
+fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+// Add a parsed probe record to its provider and probe.
+fn add_probe_record(
+    providers: &mut BTreeMap<String, Provider>,
+    record: ProbeRecord,
+) -> Result<(), crate::Error> {
+    let provider = providers
+        .entry(record.provider.clone())
+        .or_insert(Provider {
+            name: record.provider,
+            probes: BTreeMap::new(),
+            version_tag: None,
+        });
+    if provider.version_tag.is_none() {
+        provider.version_tag = record.version_tag;
+    }
+
+    let probe = provider
+        .probes
+        .entry(record.probe.clone())
+        .or_insert(Probe {
+            name: record.probe,
+            function: record.function,
+            module: record.module,
+            address: record.address,
+            offsets: vec![],
+            enabled_offsets: vec![],
+            arguments: vec![],
+        });
+    probe.arguments = record.arguments;
 
     // We expect to get records in address order for a given probe; our offsets
-    // would be negative otherwise.
-    assert!(address >= probe.address);
+    // would be negative otherwise. The base address is tracked separately for each probe, so
+    // records for different probes may be interleaved, even within the same function.
+    let address = record.address;
+    if address < probe.address {
+        return Err(crate::Error::MalformedRecord(format!(
+            "record address {:#x} precedes that of probe \"{}\" ({:#x})",
+            address, probe.name, probe.address
+        )));
+    }
 
-    if flags == 0 {
-        probe.offsets.push((address - probe.address) as u32);
-    } else {
+    if record.is_enabled {
         probe.enabled_offsets.push((address - probe.address) as u32);
+    } else {
+        probe.offsets.push((address - probe.address) as u32);
     }
     Ok(())
 }
 
 trait ReadCstrExt<'a> {
-    fn read_cstr(&mut self) -> &'a str;
+    fn read_cstr(&mut self) -> Result<&'a str, crate::Error>;
+    fn read_prefixed_str(&mut self, endian: Endian) -> Result<&'a str, crate::Error>;
+
+    // Read a string encoded as in records of the given version.
+    fn read_record_str(&mut self, version: u8, endian: Endian) -> Result<&'a str, crate::Error> {
+        if version == PROBE_REC_VERSION_LENGTH_PREFIXED {
+            self.read_prefixed_str(endian)
+        } else {
+            self.read_cstr()
+        }
+    }
 }
 
 impl<'a> ReadCstrExt<'a> for &'a [u8] {
-    fn read_cstr(&mut self) -> &'a str {
-        let index = self
-            .iter()
-            .position(|ch| *ch == 0)
-            .expect("ran out of bytes before we found a zero");
-
-        let ret = std::str::from_utf8(&self[..index]).unwrap();
+    fn read_cstr(&mut self) -> Result<&'a str, crate::Error> {
+        let index = self.iter().position(|ch| *ch == 0).ok_or_else(|| {
+            crate::Error::MalformedRecord(String::from(
+                "ran out of bytes before finding a null terminator",
+            ))
+        })?;
+        let ret = std::str::from_utf8(&self[..index]).map_err(|_| {
+            crate::Error::MalformedRecord(String::from("record contains a non-UTF-8 string"))
+        })?;
         *self = &self[index + 1..];
-        ret
+        Ok(ret)
     }
+
+    // Read a string preceded by its length in bytes, as a `u16`.
+    fn read_prefixed_str(&mut self, endian: Endian) -> Result<&'a str, crate::Error> {
+        let len = endian.read_u16(self).map_err(|_| {
+            crate::Error::MalformedRecord(String::from(
+                "ran out of bytes before finding a string length",
+            ))
+        })? as usize;
+        if len > self.len() {
+            return Err(crate::Error::MalformedRecord(format!(
+                "string of {} bytes is longer than the remaining {} bytes of the record",
+                len,
+                self.len()
+            )));
+        }
+        let ret = std::str::from_utf8(&self[..len]).map_err(|_| {
+            crate::Error::MalformedRecord(String::from("record contains a non-UTF-8 string"))
+        })?;
+        *self = &self[len..];
+        Ok(ret)
+    }
+}
+
+/// Builds the contents of a probe record section at runtime.
+///
+/// The records have the same layout as those emitted into the section by the generated probe
+/// macros, and may be read back with [`process_section_with`] or [`SectionParser`], for example to
+/// generate the records of a provider built at runtime, or to test tools which consume them.
+#[derive(Clone, Debug)]
+pub struct RecordBuilder {
+    format: RecordFormat,
+    version: u8,
+    version_tag: Option<String>,
+    data: Vec<u8>,
 }
 
-// Construct the ASM record for a probe. If `types` is `None`, then is is an is-enabled probe.
+impl RecordBuilder {
+    /// Create an empty section of records, for a target with the given format.
+    pub fn new(format: RecordFormat) -> Self {
+        Self {
+            format,
+            version: PROBE_REC_VERSION,
+            version_tag: None,
+            data: Vec::new(),
+        }
+    }
+
+    /// Include the given version string or build hash of the provider in each record appended
+    /// after this.
+    pub fn version_tag(mut self, tag: &str) -> Self {
+        self.version_tag = Some(tag.to_string());
+        self
+    }
+
+    /// End each record appended after this with a checksum, which is verified when it's read.
+    ///
+    /// The checksum covers the record, including any version tag, except the address of the probe.
+    pub fn checksum(mut self) -> Self {
+        self.version = PROBE_REC_VERSION_CHECKSUMMED;
+        self
+    }
+
+    /// Append the record of a probe site at the given address, with the C type of each argument.
+    pub fn probe<S: AsRef<str>>(
+        self,
+        provider: &str,
+        probe: &str,
+        address: u64,
+        arguments: &[S],
+    ) -> Self {
+        self.record(provider, probe, address, 0, arguments)
+    }
+
+    /// Append the record of the is-enabled site of a probe at the given address.
+    pub fn is_enabled(self, provider: &str, probe: &str, address: u64) -> Self {
+        self.record::<&str>(provider, probe, address, RECORD_FLAG_IS_ENABLED, &[])
+    }
+
+    /// Return the contents of the section.
+    pub fn build(self) -> Vec<u8> {
+        self.data
+    }
+
+    // Append a single record, padded to 8 bytes, as the `.balign` directives do in the assembly.
+    fn record<S: AsRef<str>>(
+        mut self,
+        provider: &str,
+        probe: &str,
+        address: u64,
+        flags: u16,
+        arguments: &[S],
+    ) -> Self {
+        let endian = self.format.endian;
+        let start = self.data.len();
+        self.data.extend_from_slice(&[0; 4]); // length, filled in below
+
+        // Only checksummed records mark their version tag with a flag.
+        let (version, flags) = match (self.version, &self.version_tag) {
+            (PROBE_REC_VERSION_CHECKSUMMED, Some(_)) => {
+                (self.version, flags | RECORD_FLAG_VERSION_TAG)
+            }
+            (_, Some(_)) => (PROBE_REC_VERSION_TAGGED, flags),
+            (version, None) => (version, flags),
+        };
+        self.data.push(version);
+        self.data.push(arguments.len() as u8);
+        self.data.extend_from_slice(&endian.u16_bytes(flags));
+        self.data
+            .extend_from_slice(&endian.u64_bytes(self.format.pointer_width.truncate(address)));
+        let strings = [provider, probe]
+            .into_iter()
+            .chain(arguments.iter().map(AsRef::as_ref))
+            .chain(self.version_tag.as_deref());
+        for string in strings {
+            if version == PROBE_REC_VERSION_LENGTH_PREFIXED {
+                self.data
+                    .extend_from_slice(&endian.u16_bytes(string.len() as u16));
+                self.data.extend_from_slice(string.as_bytes());
+            } else {
+                self.data.extend_from_slice(string.as_bytes());
+                self.data.push(0);
+            }
+        }
+        if version == PROBE_REC_VERSION_CHECKSUMMED {
+            let checksum = record_checksum(&self.data[start..]);
+            self.data.extend_from_slice(&endian.u32_bytes(checksum));
+        }
+        while self.data.len() % 8 != 0 {
+            self.data.push(0);
+        }
+        let len = (self.data.len() - start) as u32;
+        self.data[start..start + 4].copy_from_slice(&endian.u32_bytes(len));
+        self
+    }
+}
+
+// Construct the ASM record for a probe, in the named section. If `types` is `None`, then is is an
+// is-enabled probe. The record ends with the provider's version tag, if it has one.
 #[allow(dead_code)]
-pub(crate) fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataType]>) -> String {
-    let section_ident = r#"set_dtrace_probes,"aw","progbits""#;
+pub(crate) fn emit_probe_record(
+    section: &str,
+    prov: &str,
+    probe: &str,
+    types: Option<&[DataType]>,
+    version_tag: Option<&str>,
+) -> String {
+    let section_ident = format!(r#"{},"aw","progbits""#, section);
     let is_enabled = types.is_none();
     let n_args = types.map_or(0, |typ| typ.len());
     let arguments = types.map_or_else(String::new, |types| {
@@ -245,17 +992,27 @@ pub(crate) fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataTyp
                     .asciz "{prov}"
                     .asciz "{probe}"
                     {arguments}         // null-terminated strings for each argument
+                    {version_tag}
                     .balign 8
             992:    .popsection
                     {yeet}
         "#,
         section_ident = section_ident,
-        version = PROBE_REC_VERSION,
+        version = if version_tag.is_some() {
+            PROBE_REC_VERSION_TAGGED
+        } else {
+            PROBE_REC_VERSION
+        },
         n_args = n_args,
-        flags = if is_enabled { 1 } else { 0 },
+        flags = if is_enabled {
+            RECORD_FLAG_IS_ENABLED
+        } else {
+            0
+        },
         prov = prov,
         probe = probe.replace("__", "-"),
         arguments = arguments,
+        version_tag = version_tag.map_or_else(String::new, |tag| format!(".asciz \"{}\"", tag)),
         yeet = if cfg!(target_os = "illumos") {
             // The illumos linker may yeet our probes section into the trash under
             // certain conditions. To counteract this, we yeet references to the
@@ -273,17 +1030,37 @@ pub(crate) fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataTyp
 }
 
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use std::collections::BTreeMap;
 
-    use byteorder::{NativeEndian, WriteBytesExt};
+    use byteorder::{BigEndian, NativeEndian, ReadBytesExt, WriteBytesExt};
 
+    use super::add_probe_record;
+    use super::crc32;
     use super::emit_probe_record;
+    use super::limit_string_length;
+    use super::parse_header;
+    use super::parse_header_with;
     use super::process_probe_record;
     use super::process_section;
+    use super::process_section_filtered;
+    use super::process_section_with;
+    use super::process_section_with_limits;
+    use super::reset_registered_records;
     use super::DataType;
+    use super::ProbeRecord;
+    use super::RecordBuilder;
+    use super::RecordHeader;
+    use super::SectionParser;
+    use super::StringLimits;
     use super::PROBE_REC_VERSION;
+    use super::RECORD_FIXED_LEN;
+    use super::{Endian, PointerWidth, RecordFormat};
     use super::{MAX_PROBE_NAME_LEN, MAX_PROVIDER_NAME_LEN};
+    use super::{
+        MAX_PROBE_REC_VERSION, PROBE_REC_VERSION_CHECKSUMMED, PROBE_REC_VERSION_LENGTH_PREFIXED,
+        PROBE_REC_VERSION_TAGGED, RECORD_FLAG_VERSION_TAG, REGISTERED_VERSION_FLAG,
+    };
     use dtrace_parser::BitWidth;
     use dtrace_parser::DataType as DType;
     use dtrace_parser::Integer;
@@ -308,7 +1085,7 @@ mod test {
             .unwrap();
 
         let mut providers = BTreeMap::new();
-        process_probe_record(&mut providers, &mut rec, true).unwrap();
+        process_probe_record(&mut providers, &mut rec, true, RecordFormat::native()).unwrap();
 
         let probe = providers
             .get("provider")
@@ -341,7 +1118,7 @@ mod test {
             .unwrap();
 
         let mut providers = BTreeMap::new();
-        process_probe_record(&mut providers, &mut rec, true).unwrap();
+        process_probe_record(&mut providers, &mut rec, true, RecordFormat::native()).unwrap();
 
         let expected_provider_name = &long_name[..MAX_PROVIDER_NAME_LEN - 1];
         let expected_probe_name = &long_name[..MAX_PROBE_NAME_LEN - 1];
@@ -392,6 +1169,43 @@ mod test {
         data
     }
 
+    #[test]
+    fn test_process_section_with_limits() {
+        let long_name = "p".repeat(100);
+        let mut rec = Vec::<u8>::new();
+        rec.write_u32::<NativeEndian>(0).unwrap();
+        rec.write_u8(PROBE_REC_VERSION).unwrap();
+        rec.write_u8(1).unwrap();
+        rec.write_u16::<NativeEndian>(0).unwrap();
+        rec.write_u64::<NativeEndian>(0x1234).unwrap();
+        rec.write_cstr(&long_name);
+        rec.write_cstr(&long_name);
+        rec.write_cstr(&long_name);
+        let len = rec.len();
+        (&mut rec[..4])
+            .write_u32::<NativeEndian>(len as u32)
+            .unwrap();
+
+        let limits = StringLimits {
+            provider: 8,
+            probe: 16,
+            function: 32,
+            arg_type: 24,
+        };
+        let section =
+            process_section_with_limits(&mut rec, false, RecordFormat::native(), limits).unwrap();
+        let provider = &section.providers[&long_name[..7]];
+        let probe = &provider.probes[&long_name[..15]];
+        assert_eq!(probe.arguments, vec![long_name[..23].to_string()]);
+
+        // The defaults are the same as when no limits are given.
+        let default = process_section_with(&mut rec, false, RecordFormat::native()).unwrap();
+        let provider = &default.providers[&long_name[..MAX_PROVIDER_NAME_LEN - 1]];
+        assert!(provider
+            .probes
+            .contains_key(&long_name[..MAX_PROBE_NAME_LEN - 1]));
+    }
+
     #[test]
     fn test_process_section() {
         let mut data = make_record(PROBE_REC_VERSION);
@@ -416,20 +1230,760 @@ mod test {
         let mut data = make_record(PROBE_REC_VERSION);
         let section = process_section(&mut data, true).unwrap();
         assert_eq!(section.providers.len(), 1);
-        assert_eq!(data[4], u8::MAX);
+        assert_eq!(data[4], PROBE_REC_VERSION | REGISTERED_VERSION_FLAG);
         let section = process_section(&mut data, true).unwrap();
-        assert_eq!(data[4], u8::MAX);
+        assert_eq!(data[4], PROBE_REC_VERSION | REGISTERED_VERSION_FLAG);
         assert_eq!(section.providers.len(), 0);
     }
 
+    #[test]
+    fn test_reset_registered_records() {
+        let mut data = make_record(PROBE_REC_VERSION);
+        let section = process_section(&mut data, true).unwrap();
+        reset_registered_records(&mut data, RecordFormat::native());
+        assert_eq!(data[4], PROBE_REC_VERSION);
+        let reprocessed = process_section(&mut data, false).unwrap();
+        assert_eq!(
+            reprocessed.providers["provider"].probes["probe"].offsets,
+            section.providers["provider"].probes["probe"].offsets
+        );
+
+        // Records registered by earlier releases of this crate are all of the first version.
+        let mut data = make_record(u8::MAX);
+        reset_registered_records(&mut data, RecordFormat::native());
+        assert_eq!(data[4], PROBE_REC_VERSION);
+
+        // Records from future versions are left as they are, whether or not they're registered.
+        let mut data = make_record(PROBE_REC_VERSION + 1);
+        reset_registered_records(&mut data, RecordFormat::native());
+        assert_eq!(data[4], PROBE_REC_VERSION + 1);
+        let registered = (MAX_PROBE_REC_VERSION + 1) | REGISTERED_VERSION_FLAG;
+        let mut data = make_record(registered);
+        reset_registered_records(&mut data, RecordFormat::native());
+        assert_eq!(data[4], registered);
+    }
+
+    #[test]
+    fn test_reset_registered_records_keeps_version() {
+        // Registered records of later versions are restored to their own version, so that the
+        // version tag and checksum are still read.
+        let format = RecordFormat::native();
+        let mut data = RecordBuilder::new(format)
+            .version_tag("1.2.3-abcdef")
+            .probe("provider", "tagged", 0x1000, &["uint8_t"])
+            .build();
+        data.extend(
+            RecordBuilder::new(format)
+                .checksum()
+                .probe("provider", "checked", 0x2000, &["uint8_t"])
+                .build(),
+        );
+        let len = u32::from_ne_bytes(data[..4].try_into().unwrap()) as usize;
+        process_section_with(&mut data, true, format).unwrap();
+        assert_eq!(data[4], PROBE_REC_VERSION_TAGGED | REGISTERED_VERSION_FLAG);
+        assert_eq!(
+            data[len + 4],
+            PROBE_REC_VERSION_CHECKSUMMED | REGISTERED_VERSION_FLAG
+        );
+
+        reset_registered_records(&mut data, format);
+        assert_eq!(data[4], PROBE_REC_VERSION_TAGGED);
+        assert_eq!(data[len + 4], PROBE_REC_VERSION_CHECKSUMMED);
+        let mut parser = SectionParser::with_format(&mut data, false, format);
+        let tagged = parser.next_probe().unwrap().unwrap();
+        assert_eq!(tagged.version_tag.as_deref(), Some("1.2.3-abcdef"));
+        let checked = parser.next_probe().unwrap().unwrap();
+        assert_eq!(checked.probe, "checked");
+        assert_eq!(checked.arguments, vec!["uint8_t"]);
+        assert!(parser.next_probe().is_none());
+    }
+
+    #[test]
+    fn test_section_parser_stop_early() {
+        let mut data = make_record(PROBE_REC_VERSION);
+        let first_len = (&data[..4]).read_u32::<NativeEndian>().unwrap() as usize;
+        let total_len = data.len();
+
+        let mut parser = SectionParser::new(&mut data, true);
+        assert_eq!(parser.remaining(), total_len);
+        let record = parser.next_probe().unwrap().unwrap();
+        assert_eq!(record.provider, "provider");
+        assert_eq!(record.probe, "probe");
+        assert_eq!(record.address, 0x1234);
+        assert!(!record.is_enabled);
+        assert!(record.arguments.is_empty());
+        assert_eq!(parser.remaining(), total_len - first_len);
+
+        // Stop parsing midway through. Only the first record has been registered, so the second
+        // is still returned by a later pass over the section.
+        assert_eq!(data[4], PROBE_REC_VERSION | REGISTERED_VERSION_FLAG);
+        assert_eq!(data[first_len + 4], PROBE_REC_VERSION);
+        let mut parser = SectionParser::new(&mut data, true);
+        let record = parser.next_probe().unwrap().unwrap();
+        assert_eq!(record.address, 0x12ab);
+        assert!(parser.next_probe().is_none());
+        assert_eq!(parser.remaining(), 0);
+    }
+
+    #[test]
+    fn test_section_parser_stops_after_error() {
+        let mut data = make_record(PROBE_REC_VERSION);
+        (&mut data[0..]).write_u32::<NativeEndian>(2).unwrap();
+        let mut parser = SectionParser::new(&mut data, false);
+        assert!(matches!(
+            parser.next_probe(),
+            Some(Err(crate::Error::MalformedRecord(_)))
+        ));
+        assert!(parser.next_probe().is_none());
+    }
+
     #[test]
     fn test_process_section_future_version() {
         // Ensure that we _don't_ modify a future version number in a probe record, but that the
         // probes are still skipped (since by definition we're ignoring future versions).
-        let mut data = make_record(PROBE_REC_VERSION + 1);
+        let mut data = make_record(MAX_PROBE_REC_VERSION + 1);
         let section = process_section(&mut data, true).unwrap();
         assert_eq!(section.providers.len(), 0);
-        assert_eq!(data[4], PROBE_REC_VERSION + 1);
+        assert_eq!(data[4], MAX_PROBE_REC_VERSION + 1);
+    }
+
+    #[test]
+    fn test_process_section_invalid_lengths() {
+        // Too short for the length header itself
+        let mut data = vec![1u8, 0];
+        assert!(matches!(
+            process_section(&mut data, true),
+            Err(crate::Error::MalformedRecord(_))
+        ));
+
+        // A zero length would never advance through the section
+        let mut data = vec![0u8; 16];
+        data[8] = 1;
+        assert!(matches!(
+            process_section(&mut data, true),
+            Err(crate::Error::MalformedRecord(_))
+        ));
+
+        // A length longer than the remaining data
+        let mut data = make_record(PROBE_REC_VERSION);
+        (&mut data[0..]).write_u32::<NativeEndian>(0xffff).unwrap();
+        assert!(matches!(
+            process_section(&mut data, true),
+            Err(crate::Error::MalformedRecord(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let mut data = vec![0x20, 0, 0, 0];
+        data.push(PROBE_REC_VERSION);
+        data.push(2);
+        data.extend_from_slice(&1u16.to_ne_bytes());
+        data.extend_from_slice(&0xdead_beef_u64.to_ne_bytes());
+        data.extend_from_slice(b"foo\0bar\0");
+        let header = parse_header(&data).unwrap();
+        assert_eq!(
+            header,
+            RecordHeader {
+                version: PROBE_REC_VERSION,
+                n_args: 2,
+                flags: 1,
+                address: 0xdead_beef,
+            }
+        );
+
+        // The header is still parsed if the strings are malformed.
+        assert_eq!(parse_header(&data[..16]).unwrap(), header);
+        assert!(matches!(
+            parse_header(&data[..15]),
+            Err(crate::Error::MalformedRecord(_))
+        ));
+    }
+
+    #[test]
+    fn test_process_section_with_format() {
+        // A record from a big-endian, 32-bit target, whose address field is zero-extended.
+        let mut data = Vec::<u8>::new();
+        data.write_u32::<BigEndian>(0).unwrap();
+        data.write_u8(PROBE_REC_VERSION).unwrap();
+        data.write_u8(1).unwrap();
+        data.write_u16::<BigEndian>(0).unwrap();
+        data.write_u64::<BigEndian>(0xffff_ffff_0000_1234).unwrap();
+        data.write_cstr("provider");
+        data.write_cstr("probe");
+        data.write_cstr("uint32_t");
+        let len = data.len();
+        (&mut data[0..]).write_u32::<BigEndian>(len as u32).unwrap();
+
+        let format = RecordFormat {
+            endian: Endian::Big,
+            pointer_width: PointerWidth::Bits32,
+        };
+        let section = process_section_with(&mut data.clone(), false, format).unwrap();
+        let probe = &section.providers["provider"].probes["probe"];
+        assert_eq!(probe.address, 0x1234);
+        assert_eq!(probe.arguments, vec![String::from("uint32_t")]);
+
+        // The full address is kept for 64-bit targets.
+        let format = RecordFormat {
+            pointer_width: PointerWidth::Bits64,
+            ..format
+        };
+        let section = process_section_with(&mut data.clone(), false, format).unwrap();
+        let probe = &section.providers["provider"].probes["probe"];
+        assert_eq!(probe.address, 0xffff_ffff_0000_1234);
+
+        // With the wrong byte order, the length is nonsense.
+        let format = RecordFormat {
+            endian: Endian::Little,
+            ..format
+        };
+        assert!(matches!(
+            process_section_with(&mut data, false, format),
+            Err(crate::Error::MalformedRecord(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_builder() {
+        let format = RecordFormat {
+            endian: Endian::Big,
+            pointer_width: PointerWidth::Bits32,
+        };
+        // As in the generated code, the is-enabled site precedes the probe site.
+        let mut data = RecordBuilder::new(format)
+            .is_enabled("provider", "probe", 0x1000)
+            .probe("provider", "probe", 0x1008, &["uint8_t", "char*"])
+            .probe::<&str>("provider", "other", 0x2000, &[])
+            .build();
+        assert_eq!(data.len() % 8, 0);
+        assert_eq!(parse_header_with(&data, format).unwrap().flags, 1);
+
+        let section = process_section_with(&mut data, false, format).unwrap();
+        let probes = &section.providers["provider"].probes;
+        assert_eq!(probes.len(), 2);
+        let probe = &probes["probe"];
+        assert_eq!(probe.address, 0x1000);
+        assert_eq!(probe.offsets, vec![8]);
+        assert_eq!(probe.enabled_offsets, vec![0]);
+        assert_eq!(probe.arguments, vec!["uint8_t", "char*"]);
+        assert_eq!(probes["other"].address, 0x2000);
+    }
+
+    #[test]
+    fn test_process_section_filtered() {
+        let format = RecordFormat::native();
+        let mut data = RecordBuilder::new(format)
+            .probe::<&str>("first", "probe", 0x1000, &[])
+            .probe::<&str>("second", "probe", 0x2000, &[])
+            .probe::<&str>("second", "other", 0x2010, &[])
+            .probe::<&str>("third", "probe", 0x3000, &[])
+            .build();
+        let section = process_section_filtered(
+            &mut data,
+            false,
+            format,
+            StringLimits::default(),
+            &|provider| provider == "second",
+        )
+        .unwrap();
+        assert_eq!(section.providers.keys().collect::<Vec<_>>(), vec!["second"]);
+        assert_eq!(section.providers["second"].probes.len(), 2);
+
+        // The parser may also be given the filter directly.
+        let mut parser = SectionParser::with_format(&mut data, false, format)
+            .provider_filter(&|provider| provider == "third");
+        assert_eq!(parser.next_probe().unwrap().unwrap().address, 0x3000);
+        assert!(parser.next_probe().is_none());
+
+        // Registering with a filter only marks the records of the selected providers, and leaves
+        // the others to be registered later.
+        let section = process_section_filtered(
+            &mut data,
+            true,
+            format,
+            StringLimits::default(),
+            &|provider| provider == "second",
+        )
+        .unwrap();
+        assert_eq!(section.providers.keys().collect::<Vec<_>>(), vec!["second"]);
+        let section = process_section(&mut data, true).unwrap();
+        assert_eq!(
+            section.providers.keys().collect::<Vec<_>>(),
+            vec!["first", "third"]
+        );
+        assert!(process_section(&mut data, true)
+            .unwrap()
+            .providers
+            .is_empty());
+    }
+
+    #[test]
+    fn test_section_parser_symbolizer() {
+        let format = RecordFormat::native();
+        let mut data = RecordBuilder::new(format)
+            .probe::<&str>("provider", "known", 0x1000, &[])
+            .probe::<&str>("provider", "unknown", 0x2000, &[])
+            .build();
+        let symbolizer = |address| match address {
+            0x1000 => (
+                Some(String::from("do_work")),
+                Some(String::from("/usr/lib/libwork.so")),
+            ),
+            _ => (None, None),
+        };
+        let limits = StringLimits {
+            function: 4,
+            ..Default::default()
+        };
+        let mut parser = SectionParser::with_format(&mut data, false, format)
+            .limits(limits)
+            .symbolizer(&symbolizer);
+        let known = parser.next_probe().unwrap().unwrap();
+        assert_eq!(known.function, "do_");
+        assert_eq!(known.module, "libwork.so");
+        let unknown = parser.next_probe().unwrap().unwrap();
+        assert_eq!(unknown.function, "?0x2000");
+        assert_eq!(unknown.module, "");
+        assert!(parser.next_probe().is_none());
+    }
+
+    #[test]
+    fn test_record_builder_version_tag() {
+        let format = RecordFormat::native();
+        let mut data = RecordBuilder::new(format)
+            .probe("provider", "untagged", 0x1000, &["uint8_t"])
+            .version_tag("1.2.3-abcdef")
+            .probe("provider", "tagged", 0x2000, &["uint8_t", "char*"])
+            .build();
+
+        let len = u32::from_ne_bytes(data[..4].try_into().unwrap()) as usize;
+        assert_eq!(
+            parse_header_with(&data[len..], format).unwrap().version,
+            PROBE_REC_VERSION_TAGGED
+        );
+
+        let mut parser = SectionParser::with_format(&mut data, false, format);
+        let mut records = Vec::new();
+        while let Some(record) = parser.next_probe() {
+            records.push(record.unwrap());
+        }
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].version_tag, None);
+        assert_eq!(records[1].version_tag.as_deref(), Some("1.2.3-abcdef"));
+        assert_eq!(records[1].arguments, vec!["uint8_t", "char*"]);
+
+        // The tag is kept on the provider when the records are collected into a section.
+        let section = process_section_with(&mut data, false, format).unwrap();
+        assert_eq!(
+            section.providers["provider"].version_tag.as_deref(),
+            Some("1.2.3-abcdef")
+        );
+    }
+
+    #[test]
+    fn test_record_builder_checksum() {
+        let format = RecordFormat {
+            endian: Endian::Big,
+            pointer_width: PointerWidth::Bits64,
+        };
+        let data = RecordBuilder::new(format)
+            .checksum()
+            .is_enabled("provider", "probe", 0x1000)
+            .probe("provider", "probe", 0x1010, &["uint8_t", "char*"])
+            .build();
+        assert_eq!(data[4], PROBE_REC_VERSION_CHECKSUMMED);
+
+        let section = process_section_with(&mut data.clone(), true, format).unwrap();
+        let probe = &section.providers["provider"].probes["probe"];
+        assert_eq!(probe.address, 0x1000);
+        assert_eq!(probe.arguments, vec!["uint8_t", "char*"]);
+        assert_eq!(probe.offsets, vec![0x10]);
+
+        // Corrupting any covered byte of the second record, such as the flags or a string, is
+        // detected, as is corrupting the checksum itself.
+        let start = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let checksum_offset = RECORD_FIXED_LEN + "provider\0probe\0uint8_t\0char*\0".len();
+        for offset in [6, RECORD_FIXED_LEN + 2, checksum_offset] {
+            let index = start + offset;
+            let mut corrupt = data.clone();
+            corrupt[index] ^= 0x01;
+            match process_section_with(&mut corrupt, false, format) {
+                Err(crate::Error::MalformedRecord(msg)) => assert!(msg.contains("checksum")),
+                other => panic!("Expected a checksum error, found {:?}", other),
+            }
+        }
+
+        // The address isn't covered, so that relocated records are still valid.
+        let mut relocated = data.clone();
+        relocated[start + 8..start + 16].copy_from_slice(&0x2010u64.to_be_bytes());
+        let section = process_section_with(&mut relocated, false, format).unwrap();
+        assert_eq!(
+            section.providers["provider"].probes["probe"].offsets,
+            vec![0x1010]
+        );
+    }
+
+    #[test]
+    fn test_record_builder_checksum_version_tag() {
+        let format = RecordFormat::native();
+        let data = RecordBuilder::new(format)
+            .version_tag("1.2.3")
+            .checksum()
+            .probe("provider", "probe", 0x1000, &["uint8_t"])
+            .build();
+        let header = parse_header_with(&data, format).unwrap();
+        assert_eq!(header.version, PROBE_REC_VERSION_CHECKSUMMED);
+        assert_eq!(header.flags, RECORD_FLAG_VERSION_TAG);
+
+        // Both the tag and the checksum are kept, and the tag is covered by the checksum.
+        let mut copy = data.clone();
+        let mut parser = SectionParser::with_format(&mut copy, false, format);
+        let record = parser.next_probe().unwrap().unwrap();
+        assert_eq!(record.version_tag.as_deref(), Some("1.2.3"));
+        assert!(!record.is_enabled);
+        let mut corrupt = data.clone();
+        corrupt[RECORD_FIXED_LEN + "provider\0probe\0uint8_t\0".len()] ^= 0x01;
+        assert!(matches!(
+            process_section_with(&mut corrupt, false, format),
+            Err(crate::Error::MalformedRecord(_))
+        ));
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_record_builder_length_prefixed() {
+        let format = RecordFormat {
+            endian: Endian::Big,
+            pointer_width: PointerWidth::Bits64,
+        };
+        let builder = RecordBuilder {
+            version: PROBE_REC_VERSION_LENGTH_PREFIXED,
+            ..RecordBuilder::new(format)
+        };
+        let mut data = builder
+            .probe("provider", "probe", 0x1000, &["uint8_t", "char*"])
+            .build();
+        assert_eq!(
+            &data[RECORD_FIXED_LEN..RECORD_FIXED_LEN + 10],
+            b"\0\x08provider"
+        );
+
+        let section = process_section_with(&mut data, true, format).unwrap();
+        let probe = &section.providers["provider"].probes["probe"];
+        assert_eq!(probe.address, 0x1000);
+        assert_eq!(probe.arguments, vec!["uint8_t", "char*"]);
+
+        // A truncated string is an error, rather than being read past the end of the record.
+        let mut data = RecordBuilder {
+            version: PROBE_REC_VERSION_LENGTH_PREFIXED,
+            ..RecordBuilder::new(format)
+        }
+        .probe::<&str>("provider", "probe", 0x1000, &[])
+        .build();
+        data[RECORD_FIXED_LEN + 1] = 0xff;
+        assert!(matches!(
+            process_section_with(&mut data, false, format),
+            Err(crate::Error::MalformedRecord(_))
+        ));
+    }
+
+    #[test]
+    fn test_process_section_zeroed() {
+        let mut data = vec![0u8; make_record(PROBE_REC_VERSION).len()];
+        let section = process_section(&mut data, true).unwrap();
+        assert!(section.providers.is_empty());
+    }
+
+    #[test]
+    fn test_process_section_address_order() {
+        // Records for the same probe must be in increasing address order.
+        let mut data = make_record(PROBE_REC_VERSION);
+        let len = (&data[..4]).read_u32::<NativeEndian>().unwrap() as usize;
+        (&mut data[len + 8..])
+            .write_u64::<NativeEndian>(0x1000)
+            .unwrap();
+        assert!(matches!(
+            process_section(&mut data, true),
+            Err(crate::Error::MalformedRecord(_))
+        ));
+    }
+
+    #[test]
+    fn test_add_probe_record_interleaved_probes() {
+        // Two probes in the same function, whose records interleave. The second probe's first
+        // record precedes the first probe's base address, which is fine as each probe's offsets
+        // are relative to its own base address.
+        let mut providers = BTreeMap::new();
+        for (probe, address, is_enabled) in [
+            ("first", 0x1010, false),
+            ("second", 0x1008, false),
+            ("first", 0x1020, true),
+            ("second", 0x1018, true),
+            ("first", 0x1030, false),
+        ] {
+            let record = ProbeRecord {
+                provider: String::from("provider"),
+                probe: String::from(probe),
+                function: String::from("func"),
+                module: String::from("module"),
+                address,
+                is_enabled,
+                arguments: vec![],
+                version_tag: None,
+            };
+            add_probe_record(&mut providers, record).unwrap();
+        }
+        let probes = &providers["provider"].probes;
+        assert_eq!(probes.len(), 2);
+
+        let first = &probes["first"];
+        assert_eq!(first.function, "func");
+        assert_eq!(first.address, 0x1010);
+        assert_eq!(first.offsets, vec![0, 0x20]);
+        assert_eq!(first.enabled_offsets, vec![0x10]);
+
+        let second = &probes["second"];
+        assert_eq!(second.function, "func");
+        assert_eq!(second.address, 0x1008);
+        assert_eq!(second.offsets, vec![0]);
+        assert_eq!(second.enabled_offsets, vec![0x10]);
+    }
+
+    #[test]
+    fn test_process_probe_record_missing_arguments() {
+        let mut rec = Vec::<u8>::new();
+        rec.write_u32::<NativeEndian>(0).unwrap();
+        rec.write_u8(PROBE_REC_VERSION).unwrap();
+        rec.write_u8(3).unwrap();
+        rec.write_u16::<NativeEndian>(0).unwrap();
+        rec.write_u64::<NativeEndian>(0x1234).unwrap();
+        rec.write_cstr("provider");
+        rec.write_cstr("probe");
+        rec.write_cstr("uint8_t");
+        let unpadded_len = rec.len();
+
+        // Check both without and with the trailing padding emitted in real records.
+        for padded in [false, true] {
+            let mut rec = rec[..unpadded_len].to_vec();
+            if padded {
+                rec.resize((unpadded_len + 7) / 8 * 8, 0);
+            }
+            let len = rec.len();
+            (&mut rec[0..])
+                .write_u32::<NativeEndian>(len as u32)
+                .unwrap();
+            let mut section = rec.clone();
+            section.extend(make_record(PROBE_REC_VERSION));
+
+            let mut providers = BTreeMap::new();
+            match process_probe_record(&mut providers, &mut rec, true, RecordFormat::native()) {
+                Err(crate::Error::MalformedRecord(msg)) => {
+                    assert!(msg.contains("declares 3 arguments, but contains only 1"))
+                }
+                other => panic!("Expected a malformed record error, found {:?}", other),
+            }
+
+            // The following record must not be consumed as the missing arguments.
+            assert!(process_section(&mut section, true).is_err());
+        }
+    }
+
+    #[test]
+    fn test_process_section_corrupt_data_does_not_panic() {
+        // Truncate the section at every length, and corrupt every byte, ensuring that processing
+        // always returns rather than panicking.
+        let data = make_record(PROBE_REC_VERSION);
+        for len in 0..data.len() {
+            let mut truncated = data[..len].to_vec();
+            let _ = process_section(&mut truncated, false);
+        }
+        for index in 0..data.len() {
+            for byte in [0x00, 0x7f, 0x80, 0xff] {
+                let mut corrupt = data.clone();
+                corrupt[index] = byte;
+                let _ = process_section(&mut corrupt, false);
+                let _ = process_section(&mut corrupt, true);
+            }
+        }
+    }
+
+    #[test]
+    fn test_limit_string_length_char_boundary() {
+        // The limit falls in the middle of the two-byte character.
+        assert_eq!(limit_string_length("aé", 3), "a");
+        assert_eq!(limit_string_length("abc", 0), "");
+    }
+
+    // Compute the size of the record in the assembly emitted by `emit_probe_record`, by summing
+    // the sizes of the data directives between its start and end labels.
+    pub(crate) fn assembled_record_len(asm: &str) -> usize {
+        assemble_record(asm, 0).len()
+    }
+
+    // Assemble the record emitted by `emit_probe_record` into native-endian bytes, as if the
+    // probe site it refers to were at the given address.
+    fn assemble_record(asm: &str, address: u64) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut in_record = false;
+        for line in asm.lines() {
+            let line = line.split("//").next().unwrap().trim();
+            if line == "991:" {
+                in_record = true;
+                continue;
+            } else if line.starts_with("992:") {
+                break;
+            } else if !in_record {
+                continue;
+            }
+            let (directive, arg) = line.split_once(' ').unwrap_or((line, ""));
+            let arg = arg.trim();
+            match directive {
+                ".byte" => data.push(arg.parse().unwrap()),
+                ".2byte" => data
+                    .write_u16::<NativeEndian>(arg.parse().unwrap())
+                    .unwrap(),
+                // The length is filled in once the end of the record is known.
+                ".4byte" => data.write_u32::<NativeEndian>(0).unwrap(),
+                ".8byte" => data.write_u64::<NativeEndian>(address).unwrap(),
+                ".asciz" => data.write_cstr(arg.trim_matches('"')),
+                ".balign" => {
+                    let align: usize = arg.parse().unwrap();
+                    data.resize((data.len() + align - 1) / align * align, 0);
+                }
+                "" => {}
+                other => panic!("Unexpected directive in probe record: {}", other),
+            }
+        }
+        let len = data.len() as u32;
+        (&mut data[..4]).write_u32::<NativeEndian>(len).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_emit_probe_record_zero_arguments() {
+        let source = "provider foo { probe bar(); };";
+        let dfile = dtrace_parser::File::try_from(source).unwrap();
+        let provider = crate::Provider::from(&dfile.providers()[0]);
+        let probe = &provider.probes[0];
+
+        // Emit the records for the probe site and its is-enabled site, as the probe macro does.
+        let mut data = Vec::new();
+        for (types, address) in [(Some(probe.dtrace_types()), 0x1000), (None, 0x1008)] {
+            let record = emit_probe_record(
+                crate::PROBE_RECORD_SECTION,
+                &provider.name,
+                &probe.name,
+                types,
+                None,
+            );
+            assert!(record.contains(".byte 0"));
+            data.extend(assemble_record(&record, address));
+        }
+        // The provider's size also counts the is-enabled record of the raw is-enabled function.
+        assert_eq!(
+            data.len() + crate::common::probe_record_len(&provider.name, &probe.name, None, None),
+            crate::common::probe_record_section_size(
+                &provider,
+                &crate::CompileProvidersConfig::default()
+            )
+        );
+
+        let section = process_section(&mut data, false).unwrap();
+        let probe = &section.providers["foo"].probes["bar"];
+        assert_eq!(probe.name, "bar");
+        assert_eq!(probe.address, 0x1000);
+        assert_eq!(probe.offsets, vec![0]);
+        assert_eq!(probe.enabled_offsets, vec![8]);
+        assert!(probe.arguments.is_empty());
+    }
+
+    #[test]
+    fn test_probe_record_section_size() {
+        let source = r#"
+            provider foo {
+                probe work();
+                probe gc__begin(uint8_t, char*);
+                probe gc-end(uint64_t, int32_t, char *);
+            };
+        "#;
+        let dfile = dtrace_parser::File::try_from(source).unwrap();
+        let provider = crate::Provider::from(&dfile.providers()[0]);
+
+        // Each probe has the is-enabled and probe records of its macro, and the is-enabled record
+        // of its raw is-enabled function.
+        let mut expected = 0;
+        let mut always_fire_expected = 0;
+        for probe in provider.probes.iter() {
+            for types in [None, Some(probe.dtrace_types()), None] {
+                let len = assembled_record_len(&emit_probe_record(
+                    crate::PROBE_RECORD_SECTION,
+                    &provider.name,
+                    &probe.name,
+                    types,
+                    None,
+                ));
+                assert_eq!(
+                    len,
+                    crate::common::probe_record_len(&provider.name, &probe.name, types, None)
+                );
+                expected += len;
+            }
+            // Probes which always fire have no is-enabled site in their macro.
+            always_fire_expected +=
+                crate::common::probe_record_len(
+                    &provider.name,
+                    &probe.name,
+                    Some(probe.dtrace_types()),
+                    None,
+                ) + crate::common::probe_record_len(&provider.name, &probe.name, None, None);
+        }
+        assert_eq!(
+            expected,
+            crate::common::probe_record_section_size(
+                &provider,
+                &crate::CompileProvidersConfig::default()
+            )
+        );
+        let config = crate::CompileProvidersConfig {
+            always_fire: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            always_fire_expected,
+            crate::common::probe_record_section_size(&provider, &config)
+        );
+
+        // Check the constant emitted into the generated provider module.
+        let tokens =
+            crate::compile_provider_source(source, &crate::CompileProvidersConfig::default())
+                .unwrap();
+        let module = match syn::parse2::<syn::File>(tokens).unwrap().items.remove(0) {
+            syn::Item::Mod(module) => module,
+            _ => panic!("Expected the provider module"),
+        };
+        let size = module
+            .content
+            .unwrap()
+            .1
+            .into_iter()
+            .find_map(|item| match item {
+                syn::Item::Const(c) if c.ident == "PROBE_RECORD_SECTION_SIZE" => Some(c.expr),
+                _ => None,
+            })
+            .expect("No record section size in provider module");
+        match *size {
+            syn::Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Int(lit),
+                ..
+            }) => assert_eq!(lit.base10_parse::<usize>().unwrap(), expected),
+            other => panic!("Unexpected record section size: {:?}", other),
+        }
     }
 
     trait WriteCstrExt {
@@ -454,7 +2008,13 @@ mod test {
             })),
             DataType::Native(DType::String),
         ];
-        let record = emit_probe_record(provider, probe, Some(&types));
+        let record = emit_probe_record(
+            crate::PROBE_RECORD_SECTION,
+            provider,
+            probe,
+            Some(&types),
+            None,
+        );
         let mut lines = record.lines();
         println!("{}", record);
         lines.next(); // empty line
@@ -473,6 +2033,19 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_emit_probe_record_hrtime() {
+        let types = [DataType::Native(DType::HrTime)];
+        let record = emit_probe_record(
+            crate::PROBE_RECORD_SECTION,
+            "clock",
+            "tick",
+            Some(&types),
+            None,
+        );
+        assert!(record.contains(".asciz \"hrtime_t\""));
+    }
+
     #[test]
     fn test_emit_probe_record_dunders() {
         let provider = "provider";
@@ -484,10 +2057,57 @@ mod test {
             })),
             DataType::Native(dtrace_parser::DataType::String),
         ];
-        let record = emit_probe_record(provider, probe, Some(&types));
+        let record = emit_probe_record(
+            crate::PROBE_RECORD_SECTION,
+            provider,
+            probe,
+            Some(&types),
+            None,
+        );
         assert!(
             record.contains("my-probe"),
             "Expected double-underscores to be translated to a single dash"
         );
     }
+
+    #[test]
+    fn test_emit_probe_record_dashes() {
+        let record = emit_probe_record(
+            crate::PROBE_RECORD_SECTION,
+            "provider",
+            "gc-start",
+            Some(&[]),
+            None,
+        );
+        assert!(
+            record.contains(".asciz \"gc-start\""),
+            "Expected dashes in the probe name to be preserved"
+        );
+    }
+
+    #[test]
+    fn test_emit_probe_record_section() {
+        let record = emit_probe_record("my_probes", "provider", "probe", Some(&[]), None);
+        assert!(record.contains(r#".pushsection my_probes,"aw","progbits""#));
+        let record =
+            emit_probe_record(crate::PROBE_RECORD_SECTION, "provider", "probe", None, None);
+        assert!(record.contains(r#".pushsection set_dtrace_probes,"aw","progbits""#));
+    }
+
+    #[test]
+    fn test_emit_probe_record_version_tag() {
+        let record = emit_probe_record(
+            crate::PROBE_RECORD_SECTION,
+            "provider",
+            "probe",
+            Some(&[]),
+            Some("1.2.3-abcdef"),
+        );
+        assert!(record.contains(&format!(".byte {}", PROBE_REC_VERSION_TAGGED)));
+        assert!(record.contains(".asciz \"1.2.3-abcdef\""));
+        assert_eq!(
+            assembled_record_len(&record),
+            crate::common::probe_record_len("provider", "probe", Some(&[]), Some("1.2.3-abcdef"))
+        );
+    }
 }
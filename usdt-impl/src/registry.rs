@@ -0,0 +1,118 @@
+//! A process-wide registry of the providers defined in a program.
+//!
+//! Each generated provider module contains a static [`ProviderInfo`], placed in a dedicated linker
+//! section. The linker collects these from every crate in the program into one contiguous array,
+//! whose bounds are given by symbols it defines for the section, in the same way as for the probe
+//! records of the standard backend. The registry therefore requires no code to run at startup,
+//! and includes providers from all crates linked into the program.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Information about a provider defined in the program, as returned by [`all_providers`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(C)]
+pub struct ProviderInfo {
+    /// The name of the provider, as seen by DTrace.
+    pub name: &'static str,
+    /// The path of the Rust module containing the provider's probe macros, e.g., `my_crate::foo`.
+    pub module_path: &'static str,
+    /// The names of the provider's probes, as seen by DTrace.
+    pub probes: &'static [&'static str],
+}
+
+/// Return information about every provider defined in the program, including those defined by
+/// its dependencies.
+///
+/// The order of the providers is unspecified. On platforms other than those using ELF or Mach-O
+/// object files, providers can't be collected, and the list is always empty.
+pub fn all_providers() -> Vec<ProviderInfo> {
+    registered_providers().to_vec()
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "freebsd"
+))]
+fn registered_providers() -> &'static [ProviderInfo] {
+    // The symbols are only used for their addresses, so their type needn't be FFI-safe.
+    #[allow(improper_ctypes)]
+    extern "C" {
+        #[link_name = "__start_set_usdt_providers"]
+        static providers_start: ProviderInfo;
+        #[link_name = "__stop_set_usdt_providers"]
+        static providers_stop: ProviderInfo;
+    }
+
+    // Ensure the section exists, so that the linker defines the symbols above, even if the program
+    // has no providers.
+    #[link_section = "set_usdt_providers"]
+    #[used]
+    static EMPTY: [ProviderInfo; 0] = [];
+
+    unsafe { providers_between(&providers_start, &providers_stop) }
+}
+
+#[cfg(target_os = "macos")]
+fn registered_providers() -> &'static [ProviderInfo] {
+    // The symbols are only used for their addresses, so their type needn't be FFI-safe.
+    #[allow(improper_ctypes)]
+    extern "C" {
+        #[link_name = "\x01section$start$__DATA$__usdt_provs"]
+        static providers_start: ProviderInfo;
+        #[link_name = "\x01section$end$__DATA$__usdt_provs"]
+        static providers_stop: ProviderInfo;
+    }
+
+    // Ensure the section exists, so that the linker defines the symbols above, even if the program
+    // has no providers.
+    #[link_section = "__DATA,__usdt_provs"]
+    #[used]
+    static EMPTY: [ProviderInfo; 0] = [];
+
+    unsafe { providers_between(&providers_start, &providers_stop) }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "freebsd",
+    target_os = "macos"
+)))]
+fn registered_providers() -> &'static [ProviderInfo] {
+    &[]
+}
+
+// Return the providers in the section between the given start and stop symbols.
+//
+// Safety: The symbols must delimit an array of `ProviderInfo`, as collected by the linker.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "illumos",
+    target_os = "solaris",
+    target_os = "freebsd",
+    target_os = "macos"
+))]
+unsafe fn providers_between(
+    start: &'static ProviderInfo,
+    stop: &'static ProviderInfo,
+) -> &'static [ProviderInfo] {
+    let start = start as *const ProviderInfo;
+    let len = (stop as *const ProviderInfo as usize - start as usize)
+        / std::mem::size_of::<ProviderInfo>();
+    std::slice::from_raw_parts(start, len)
+}
@@ -0,0 +1,356 @@
+//! Implementation of USDT probes as SystemTap SDT notes, for Linux.
+//!
+//! Linux tracers such as `perf`, `bpftrace` and SystemTap itself find probes from the
+//! `.note.stapsdt` ELF notes in a binary, rather than from the probe records consumed by DTrace.
+//! Each probe site emits one such note, in the same layout as the `STAP_PROBE` macros of
+//! `<sys/sdt.h>`. Tracers need no registration step, so `register_probes` does nothing.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common;
+use crate::{DataType, Probe, Provider};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// Compile a DTrace provider definition into Rust tokens that implement its probes.
+pub fn compile_provider_source(
+    source: &str,
+    config: &crate::CompileProvidersConfig,
+) -> Result<TokenStream, crate::Error> {
+    let dfile = config.parse_source(source)?;
+    let providers = dfile
+        .providers()
+        .iter()
+        .map(|provider| {
+            let provider = Provider::from(provider);
+            // Ensure that the name of the module in the config is set, either by the caller or
+            // defaulting to the provider name.
+            let config = crate::CompileProvidersConfig {
+                provider: Some(provider.name.clone()),
+                probe_format: config.probe_format.clone(),
+                module: match &config.module {
+                    None => Some(provider.name.clone()),
+                    other => other.clone(),
+                },
+                section: config.section.clone(),
+                always_fire: config.always_fire,
+                version_tag: config.version_tag.clone(),
+                provider_prefix: config.provider_prefix.clone(),
+                types: config.types.clone(),
+                probe_names: config.probe_names.clone(),
+            };
+            let provider = config.prefix_provider(provider);
+            compile_provider(&provider, &config)
+        })
+        .collect::<Vec<_>>();
+    Ok(quote! {
+        #(#providers)*
+    })
+}
+
+pub fn compile_provider_from_definition(
+    provider: &Provider,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    compile_provider(provider, config)
+}
+
+fn compile_provider(provider: &Provider, config: &crate::CompileProvidersConfig) -> TokenStream {
+    let probe_impls = provider
+        .probes
+        .iter()
+        .map(|probe| compile_probe(provider, probe, config))
+        .collect::<Vec<_>>();
+    let module = config.module_ident();
+    let record_size = common::build_record_section_size(provider, config);
+    let probe_specs = common::build_probe_specs(provider);
+    let probe_arg_types = common::build_probe_arg_types(provider);
+    let enable_functions = common::build_enable_functions();
+    let provider_info = common::build_provider_info(provider);
+    let struct_definitions = common::build_struct_definitions(provider);
+    let warnings = common::build_argument_warnings(provider);
+    let marker = common::build_provider_marker(provider);
+    quote! {
+        #[allow(non_snake_case)]
+        pub(crate) mod #module {
+            #marker
+            #warnings
+            #record_size
+            #probe_specs
+            #probe_arg_types
+            #enable_functions
+            #provider_info
+            #struct_definitions
+            #(#probe_impls)*
+        }
+    }
+}
+
+fn compile_probe(
+    provider: &Provider,
+    probe: &Probe,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    let (unpacked_args, in_regs) = common::construct_probe_args(&probe.types);
+    let semaphore = semaphore_symbol(provider, probe);
+    let note = emit_probe_note(provider, probe, &semaphore);
+    #[cfg(usdt_stable_asm)]
+    let asm_macro = quote! { std::arch::asm };
+    #[cfg(not(usdt_stable_asm))]
+    let asm_macro = quote! { asm };
+
+    let asm_options = common::probe_asm_options();
+
+    let fire = quote! {
+        #unpacked_args
+        // The note defines the base and semaphore symbols, guarded against duplicate
+        // definitions should the compiler emit this more than once.
+        #[allow(named_asm_labels)]
+        unsafe {
+            #asm_macro!(
+                "990:   nop",
+                #note,
+                #in_regs
+                #asm_options
+            );
+        }
+    };
+    let fire = common::guard_probe_firing(fire);
+    let impl_block = build_probe_body(&semaphore, fire, config.always_fire);
+    let probe_macro =
+        common::build_probe_macro(config, provider, &probe.name, &probe.types, impl_block);
+
+    // The semaphore is defined here too, since the program may not contain any probe sites.
+    let semaphore_definition = emit_semaphore(&semaphore);
+    let is_enabled_raw = common::build_is_enabled_raw(
+        config,
+        &probe.name,
+        quote! {
+            extern "C" {
+                #[link_name = #semaphore]
+                static SEMAPHORE: u16;
+            }
+            #[allow(named_asm_labels)]
+            unsafe {
+                #asm_macro!(#semaphore_definition, options(nomem, nostack, preserves_flags));
+                ::std::ptr::read_volatile(&SEMAPHORE)
+            }
+        },
+    );
+    quote! {
+        #probe_macro
+        #is_enabled_raw
+    }
+}
+
+pub fn register_probes() -> Result<(), crate::Error> {
+    Ok(())
+}
+
+// The note type of SystemTap SDT notes, version 3 of the format.
+const NT_STAPSDT: u32 = 3;
+
+// Return the name of the symbol of a probe's semaphore.
+//
+// Dashes in the probe name are encoded as double-underscores, as in the symbols generated by
+// `dtrace -h`, so that the name is a valid symbol.
+fn semaphore_symbol(provider: &Provider, probe: &Probe) -> String {
+    format!(
+        "__usdt_sema_{}_{}",
+        provider.name,
+        probe.name.replace('-', "__")
+    )
+}
+
+// Return the SDT argument string for a probe, such as `-8@%rdi 8@%rsi`.
+//
+// Each argument is described by its size in bytes, negated for signed types, and the register
+// holding it. All arguments are passed in full 64-bit registers, in the order assigned by
+// `common::construct_probe_args`.
+fn probe_arguments(types: &[DataType]) -> String {
+    #[cfg(target_arch = "x86_64")]
+    let abi_regs: [&str; crate::MAX_PROBE_ARGUMENTS] =
+        ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+    #[cfg(target_arch = "aarch64")]
+    let abi_regs: [&str; crate::MAX_PROBE_ARGUMENTS] = ["x0", "x1", "x2", "x3", "x4", "x5"];
+    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
+    compile_error!("USDT only supports x86_64 and ARM64 architectures");
+
+    types
+        .iter()
+        .zip(&abi_regs)
+        .map(|(typ, reg)| {
+            let signed = matches!(
+                typ,
+                DataType::Native(dtrace_parser::DataType::Integer(dtrace_parser::Integer {
+                    sign: dtrace_parser::Sign::Signed,
+                    ..
+                })) | DataType::Native(dtrace_parser::DataType::HrTime)
+            );
+            format!("{}8@{}", if signed { "-" } else { "" }, reg)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Emit the assembly for the SDT note of a probe site, which must directly follow the site itself,
+// labeled `990`.
+//
+// The note contains the address of the probe site, the address of the `_.stapsdt.base` symbol,
+// the address of the probe's semaphore, and then the provider name, probe name and argument
+// string. Tracers compare the address of the base symbol with its address in the loaded binary to
+// adjust the other addresses, e.g., after prelinking. The base symbol and the semaphore are each
+// defined once per binary, in a COMDAT group shared by all probe sites referring to them, and
+// guarded so that they're defined once in each object file too.
+fn emit_probe_note(provider: &Provider, probe: &Probe, semaphore: &str) -> String {
+    format!(
+        r#"
+                    .pushsection .note.stapsdt, "", "note"
+                    .balign 4
+                    .4byte 992f-991f, 994f-993f, {note_type}
+            991:    .asciz "stapsdt"
+            992:    .balign 4
+            993:    .8byte 990b
+                    .8byte _.stapsdt.base
+                    .8byte {semaphore}
+                    .asciz "{provider}"
+                    .asciz "{probe}"
+                    .asciz "{arguments}"
+            994:    .balign 4
+                    .popsection
+                    .ifndef _.stapsdt.base
+                    .pushsection .stapsdt.base, "aG", "progbits", .stapsdt.base, comdat
+                    .weak _.stapsdt.base
+                    .hidden _.stapsdt.base
+            _.stapsdt.base:
+                    .space 1
+                    .size _.stapsdt.base, 1
+                    .popsection
+                    .endif
+            {semaphore_definition}
+        "#,
+        note_type = NT_STAPSDT,
+        semaphore = semaphore,
+        provider = provider.name,
+        probe = probe.name.replace("__", "-"),
+        arguments = probe_arguments(probe.dtrace_types()),
+        semaphore_definition = emit_semaphore(semaphore),
+    )
+}
+
+// Emit the assembly defining a probe's semaphore, once per binary.
+//
+// The semaphore is defined in a COMDAT group, so that each probe site, and each reader of the
+// semaphore, may emit it without duplicating the definition.
+fn emit_semaphore(semaphore: &str) -> String {
+    format!(
+        r#"
+                    .ifndef {semaphore}
+                    .pushsection .probes, "awG", "progbits", {semaphore}, comdat
+                    .weak {semaphore}
+                    .hidden {semaphore}
+                    .balign 2
+            {semaphore}:
+                    .2byte 0
+                    .size {semaphore}, 2
+                    .popsection
+                    .endif
+        "#,
+        semaphore = semaphore,
+    )
+}
+
+// Build the body of a probe macro, which fires the probe if its semaphore is non-zero, or always
+// if configured to do so.
+//
+// The semaphore is defined by the note itself, and incremented by tracers while the probe is
+// enabled.
+fn build_probe_body(semaphore: &str, fire: TokenStream, always_fire: bool) -> TokenStream {
+    if always_fire {
+        return quote! {
+            {
+                #fire
+            }
+        };
+    }
+    quote! {
+        {
+            extern "C" {
+                #[link_name = #semaphore]
+                static SEMAPHORE: u16;
+            }
+            let is_enabled = unsafe { ::std::ptr::read_volatile(&SEMAPHORE) };
+
+            if is_enabled != 0 {
+                #fire
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_probe_body, probe_arguments, semaphore_symbol};
+    use crate::{DataType, Probe, Provider};
+    use dtrace_parser::{BitWidth, DataType as DType, Integer, Sign};
+    use quote::quote;
+
+    #[test]
+    fn test_semaphore_symbol() {
+        let provider = Provider {
+            name: String::from("foo"),
+            probes: vec![],
+            use_statements: vec![],
+        };
+        let probe = Probe {
+            name: String::from("gc-begin"),
+            types: vec![],
+        };
+        assert_eq!(
+            semaphore_symbol(&provider, &probe),
+            "__usdt_sema_foo_gc__begin"
+        );
+    }
+
+    #[test]
+    fn test_probe_arguments() {
+        let types = [
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Signed,
+                width: BitWidth::Bit32,
+            })),
+            DataType::Native(DType::String),
+            DataType::UniqueId,
+        ];
+        #[cfg(target_arch = "x86_64")]
+        let expected = "-8@%rdi 8@%rsi 8@%rdx";
+        #[cfg(target_arch = "aarch64")]
+        let expected = "-8@x0 8@x1 8@x2";
+        assert_eq!(probe_arguments(&types), expected);
+        assert_eq!(probe_arguments(&[]), "");
+    }
+
+    #[test]
+    fn test_build_probe_body_always_fire() {
+        let fire = quote! { fire(); };
+        let gated = build_probe_body("__usdt_sema_foo_bar", fire.clone(), false).to_string();
+        assert!(gated.contains("SEMAPHORE"));
+        assert!(gated.contains("if is_enabled != 0"));
+
+        // The probe fires without reading the semaphore at all.
+        let body = build_probe_body("__usdt_sema_foo_bar", fire, true);
+        assert_eq!(body.to_string(), quote! { { fire(); } }.to_string());
+    }
+}
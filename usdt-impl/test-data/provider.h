@@ -0,0 +1,77 @@
+/*
+ * Generated by usdt from the provider "foo". Do not edit.
+ *
+ * The probes are defined by linking the object generated by `dtrace -G`.
+ */
+
+#ifndef	_USDT_FOO_H
+#define	_USDT_FOO_H
+
+#include <stdint.h>
+
+#if defined(__sun)
+#include <sys/time.h>
+#elif !defined(_USDT_HRTIME_T)
+#define	_USDT_HRTIME_T
+typedef long long hrtime_t;
+#endif
+
+#ifdef	__cplusplus
+extern "C" {
+#endif
+
+#if !defined(DTRACE_PROBES_DISABLED) || !DTRACE_PROBES_DISABLED
+
+#define	FOO_START_WORK(arg0) \
+	__dtrace_foo___start_work(arg0)
+#define	FOO_START_WORK_ENABLED() \
+	__dtraceenabled_foo___start_work()
+
+#define	FOO_STOP_WORK(arg0, arg1) \
+	__dtrace_foo___stop_work(arg0, arg1)
+#define	FOO_STOP_WORK_ENABLED() \
+	__dtraceenabled_foo___stop_work()
+
+#define	FOO_GC_START() \
+	__dtrace_foo___gc__start()
+#define	FOO_GC_START_ENABLED() \
+	__dtraceenabled_foo___gc__start()
+
+#define	FOO_TICK(arg0) \
+	__dtrace_foo___tick(arg0)
+#define	FOO_TICK_ENABLED() \
+	__dtraceenabled_foo___tick()
+
+extern void __dtrace_foo___start_work(uint8_t);
+extern int __dtraceenabled_foo___start_work(void);
+
+extern void __dtrace_foo___stop_work(char*, uint64_t*);
+extern int __dtraceenabled_foo___stop_work(void);
+
+extern void __dtrace_foo___gc__start(void);
+extern int __dtraceenabled_foo___gc__start(void);
+
+extern void __dtrace_foo___tick(hrtime_t);
+extern int __dtraceenabled_foo___tick(void);
+
+#else
+
+#define	FOO_START_WORK(arg0)
+#define	FOO_START_WORK_ENABLED() (0)
+
+#define	FOO_STOP_WORK(arg0, arg1)
+#define	FOO_STOP_WORK_ENABLED() (0)
+
+#define	FOO_GC_START()
+#define	FOO_GC_START_ENABLED() (0)
+
+#define	FOO_TICK(arg0)
+#define	FOO_TICK_ENABLED() (0)
+
+#endif
+
+#ifdef	__cplusplus
+}
+#endif
+
+#endif	/* _USDT_FOO_H */
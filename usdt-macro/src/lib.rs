@@ -14,11 +14,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fs;
 use std::iter::FromIterator;
-use std::{fs, path::Path};
+use std::path::{Path, PathBuf};
 
 use quote::quote;
-use syn::{parse_macro_input, Lit};
+use syn::Lit;
 
 use usdt_impl::compile_provider_source;
 
@@ -45,6 +46,17 @@ use usdt_impl::compile_provider_source;
 /// The macro looks for the file relative to the root of the package, so `"test.d"`
 /// in this case would be in the same directory as `"Cargo.toml"`.
 ///
+/// A path with any other extension, such as `"probes.rs"`, is still read as a provider file, but
+/// generates a warning, as it's likely the wrong file was given by mistake. Any string which is a
+/// single word with an extension, such as `"foo.txt"`, is read as the path of a file rather than
+/// as the D source of a provider. The warning is reported through the `deprecated` lint, so it
+/// fails compilation in crates which deny that lint or all warnings.
+///
+/// The file may instead be given relative to the directory of the invoking source file, as with
+/// `include!`, by writing `dtrace_provider!(relative = "test.d")`. Note that this requires Rust
+/// 1.88 or later, as the invoking source file is not available to procedural macros on earlier
+/// compilers. With those, this form is a compile-time error.
+///
 /// By default probe macros are named `{provider}_{probe}!`. Arguments are passed
 /// via a closure that returns a tuple. Note that the provided closure is only
 /// evaluated when the probe is enabled. One can then add points of instrumentation
@@ -66,19 +78,139 @@ use usdt_impl::compile_provider_source;
 /// dtrace_provider!("test.d", format = "dtrace_{provider}_{probe}");
 /// ```
 ///
+/// The generated module is named after the provider by default. A different Rust name may be
+/// given with `rust_name`, for example if the provider name clashes with an existing item. The
+/// probes are still registered with DTrace under the provider name in the D file.
+///
+/// ```ignore
+/// dtrace_provider!("test.d", rust_name = "TestProbes");
+/// TestProbes::start!(|| 0);
+/// ```
+///
+/// Typedefs used by the provider may be given as a map from each name to a Rust or D type with
+/// `types`, rather than declared in the D file:
+///
+/// ```ignore
+/// dtrace_provider!("test.d", types = { "conn_t" = "u64", "flags_t" = "uint32_t" });
+/// ```
+///
+/// The macro of a specific probe may be given a name with `probe_names`, which takes the place of
+/// that generated by `format`. The probe keeps its own name in DTrace.
+///
+/// ```ignore
+/// dtrace_provider!("test.d", probe_names = { "start" = "begin" });
+/// test::begin!(|| 0);
+/// ```
+///
+/// Each provider may only be defined once in a crate, even from different modules, since its
+/// probes are registered under the provider name. A second definition fails to compile, with an
+/// error that the hidden macro `__usdt_private_provider_{provider}_is_defined_by_more_than_one_macro_invocation`
+/// is defined multiple times.
+///
 /// Note
 /// ----
-/// The only supported types are integers of specific bit-width (e.g., `uint16_t`),
-/// pointers to integers, and `char *`.
+/// The following are the only supported types:
+///
+#[doc = dtrace_parser::supported_types!()]
 #[proc_macro]
 pub fn dtrace_provider(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let mut tokens = item.into_iter().collect::<Vec<proc_macro::TokenTree>>();
+    compile_dtrace_provider(item, /* reexport = */ false)
+}
+
+/// Generate DTrace probe macros from a provider definition, and re-export them in place.
+///
+/// This accepts the same arguments as [`dtrace_provider!`], and generates the same provider
+/// modules. The contents of each module, i.e., its probe macros and constants, are then
+/// re-exported into the scope of the invocation, so that at the crate root, the probes may be
+/// fired without naming the provider module:
+///
+/// ```ignore
+/// probes!("test.d");
+/// start!(|| 0);
+/// test::start!(|| 0);
+/// ```
+///
+/// Note that the re-exported items of different providers may conflict, for example the
+/// `PROBE_SPECS` constant of each provider. Such names are ambiguous at the crate root, and must
+/// be used via the provider module.
+#[proc_macro]
+pub fn probes(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    compile_dtrace_provider(item, /* reexport = */ true)
+}
+
+/// Build the arguments of a printf-style probe from a format string and fewer integer arguments.
+///
+/// A probe declared as `probe log(char *, ...);` takes a format string and exactly
+/// `usdt::MAX_PRINTF_ARGS` `usize` arguments. This macro converts each of the given arguments to a
+/// `usize`, and pads the remainder with zeros. Passing more than `MAX_PRINTF_ARGS` arguments is a
+/// compile error.
+///
+/// ```ignore
+/// logging::log!(|| usdt::printf_args!("read %d bytes at offset %d", len, offset));
+/// ```
+#[proc_macro]
+pub fn printf_args(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    generate_printf_args(item.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+// Expand `printf_args!` into a tuple of the format string and `MAX_PRINTF_ARGS` integers.
+fn generate_printf_args(item: proc_macro2::TokenStream) -> syn::Result<proc_macro2::TokenStream> {
+    let args = syn::parse::Parser::parse2(
+        syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated,
+        item.clone(),
+    )?;
+    let mut args = args.into_iter();
+    let format = args.next().ok_or_else(|| {
+        syn::Error::new_spanned(&item, "printf-style probes take a format string")
+    })?;
+    let args = args.collect::<Vec<_>>();
+    if args.len() > dtrace_parser::MAX_PRINTF_ARGS {
+        return Err(syn::Error::new_spanned(
+            &item,
+            "printf-style probes take at most `usdt::MAX_PRINTF_ARGS` arguments",
+        ));
+    }
+    let padding = dtrace_parser::MAX_PRINTF_ARGS - args.len();
+    let zeros = std::iter::repeat(quote! { 0usize }).take(padding);
+    Ok(quote! {
+        (#format, #((#args) as usize,)* #(#zeros,)*)
+    })
+}
+
+// Compile the provider definitions for `dtrace_provider!` or `probes!`, optionally re-exporting
+// the contents of each generated module into the invoking scope.
+fn compile_dtrace_provider(
+    item: proc_macro::TokenStream,
+    reexport: bool,
+) -> proc_macro::TokenStream {
+    generate_dtrace_provider(item.into(), reexport)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn generate_dtrace_provider(
+    item: proc_macro2::TokenStream,
+    reexport: bool,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut tokens = item.into_iter().collect::<Vec<proc_macro2::TokenTree>>();
+
+    // A leading `relative =` gives a path relative to the invoking source file.
+    let relative = matches!(
+        tokens.as_slice(),
+        [proc_macro2::TokenTree::Ident(ident), proc_macro2::TokenTree::Punct(eq), ..]
+            if ident == "relative" && eq.as_char() == '='
+    );
+    if relative {
+        tokens.drain(..2);
+    }
 
     let comma_index = tokens
         .iter()
         .enumerate()
         .find_map(|(i, token)| match token {
-            proc_macro::TokenTree::Punct(p) if p.as_char() == ',' => Some(i),
+            proc_macro2::TokenTree::Punct(p) if p.as_char() == ',' => Some(i),
             _ => None,
         });
 
@@ -92,44 +224,577 @@ pub fn dtrace_provider(item: proc_macro::TokenStream) -> proc_macro::TokenStream
     };
 
     // Parse the config from the remaining tokens.
-    let config: usdt_impl::CompileProvidersConfig = serde_tokenstream::from_tokenstream(
-        &proc_macro2::TokenStream::from(proc_macro::TokenStream::from_iter(rest)),
-    )
-    .unwrap();
-
-    let first_item = proc_macro::TokenStream::from_iter(tokens);
-    let tok = parse_macro_input!(first_item as Lit);
-    let filename = match tok {
+    let config: usdt_impl::CompileProvidersConfig =
+        serde_tokenstream::from_tokenstream(&proc_macro2::TokenStream::from_iter(rest))?;
+
+    let tok = syn::parse2::<Lit>(proc_macro2::TokenStream::from_iter(tokens))?;
+    let filename = match &tok {
         Lit::Str(f) => f.value(),
-        _ => panic!("DTrace provider must be a single literal string filename"),
+        _ => {
+            return Err(syn::Error::new_spanned(
+                tok,
+                "DTrace provider must be a single literal string filename",
+            ))
+        }
     };
-    let source = if filename.ends_with(".d") {
+    let from_file = relative || filename.ends_with(".d") || looks_like_path(&filename);
+    let source = if relative {
+        let path = invoking_source_file()
+            .map(|source_file| resolve_relative(&source_file, &filename))
+            .map_err(|message| syn::Error::new_spanned(&tok, message))?;
+        fs::read_to_string(&path).map_err(|_| {
+            syn::Error::new_spanned(
+                &tok,
+                format!(
+                    "Could not read D source file \"{}\" at {:?}",
+                    &filename, path,
+                ),
+            )
+        })?
+    } else if from_file {
         let dir = std::env::var("CARGO_MANIFEST_DIR").map_or_else(
             |_| std::env::current_dir().unwrap(),
             |s| Path::new(&s).to_path_buf(),
         );
 
         let path = dir.join(&filename);
-        fs::read_to_string(path).unwrap_or_else(|_| {
-            panic!(
-                "Could not read D source file \"{}\" in {:?}",
-                &filename, dir,
+        fs::read_to_string(path).map_err(|_| {
+            let hint = if filename.ends_with(".d") {
+                ""
+            } else {
+                ", and D source files should have a `.d` extension"
+            };
+            syn::Error::new_spanned(
+                &tok,
+                format!(
+                    "Could not read D source file \"{}\" in {:?}{}",
+                    &filename, dir, hint,
+                ),
             )
-        })
+        })?
     } else {
         filename.clone()
     };
-    match compile_provider_source(&source, &config) {
-        Ok(provider) => provider.into(),
-        Err(e) => {
-            let message = format!(
+    let provider = compile_provider_source(&source, &config).map_err(|e| {
+        syn::Error::new(
+            proc_macro2::Span::call_site(),
+            format!(
                 "Error building provider definition in \"{}\"\n\n{}",
                 filename, e
-            );
-            let out = quote! {
-                compile_error!(#message);
-            };
-            out.into()
+            ),
+        )
+    })?;
+    let warning = extension_warning(&filename, from_file);
+    if reexport {
+        let reexports = provider_reexports(&source, &config);
+        Ok(quote! {
+            #warning
+            #provider
+            #reexports
+        })
+    } else {
+        Ok(quote! {
+            #warning
+            #provider
+        })
+    }
+}
+
+// Return true if a string given in place of a path looks like the path of a file, rather than the
+// D source of a provider. That is, it's a single word with an extension, such as `probes.rs`.
+fn looks_like_path(s: &str) -> bool {
+    !s.contains(|c: char| c.is_whitespace() || matches!(c, '{' | '}' | ';'))
+        && Path::new(s).extension().is_some()
+}
+
+// Return a warning that a D source file doesn't have a `.d` extension, which often means the path
+// of another file was given by mistake.
+//
+// The warning is emitted as the use of a deprecated constant, which doesn't fail compilation
+// unless the `deprecated` lint is denied.
+fn extension_warning(filename: &str, is_file: bool) -> proc_macro2::TokenStream {
+    if !is_file || filename.ends_with(".d") {
+        return quote! {};
+    }
+    let note = format!(
+        "The DTrace provider file \"{}\" doesn't have a `.d` extension",
+        filename
+    );
+    quote! {
+        const _: () = {
+            #[deprecated(note = #note)]
+            #[allow(non_upper_case_globals)]
+            const __usdt_private_provider_file_extension: () = ();
+            __usdt_private_provider_file_extension
+        };
+    }
+}
+
+// Return the path of the source file invoking the macro.
+#[cfg(usdt_span_local_file)]
+fn invoking_source_file() -> Result<PathBuf, String> {
+    let path = proc_macro::Span::call_site().local_file().ok_or_else(|| {
+        String::from("The invoking source file of the DTrace provider could not be determined")
+    })?;
+    // The path is relative to the working directory of the compiler, if not absolute.
+    Ok(std::env::current_dir()
+        .map(|dir| dir.join(&path))
+        .unwrap_or(path))
+}
+
+// Return the path of the source file invoking the macro.
+//
+// The invoking source file is only available to procedural macros from Rust 1.88.
+#[cfg(not(usdt_span_local_file))]
+fn invoking_source_file() -> Result<PathBuf, String> {
+    Err(String::from(concat!(
+        "DTrace provider paths relative to the invoking source file require Rust 1.88 or later, ",
+        "use a path relative to the package root instead",
+    )))
+}
+
+// Resolve a path relative to the directory containing a source file, as `include!` does.
+fn resolve_relative(source_file: &Path, relative: &str) -> PathBuf {
+    source_file
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(relative)
+}
+
+// Re-export the contents of the module generated for each provider in the D source, which has
+// already been compiled successfully.
+fn provider_reexports(
+    source: &str,
+    config: &usdt_impl::CompileProvidersConfig,
+) -> proc_macro2::TokenStream {
+    let mut modules = Vec::new();
+    if let Ok(dfile) = dtrace_parser::File::try_from(source) {
+        for provider in dfile.providers() {
+            let module = config
+                .module
+                .clone()
+                .unwrap_or_else(|| provider.name.clone());
+            if !modules.contains(&module) {
+                modules.push(module);
+            }
         }
     }
+    let modules = modules.iter().map(|module| usdt_impl::rust_ident(module));
+    quote! {
+        #(
+            #[allow(unused_imports)]
+            pub(crate) use self::#modules::*;
+        )*
+    }
+}
+
+/// Generate DTrace probe macros from a provider definition in a module's documentation.
+///
+/// This allows keeping the definition of a provider together with its documentation. The D
+/// source is taken from any code blocks fenced as `dtrace` in the doc comments of the annotated
+/// module, which must otherwise be empty. For example:
+///
+/// ````ignore
+/// /// Probes for the work loop.
+/// ///
+/// /// ```dtrace
+/// /// provider test {
+/// ///     probe start_work(uint8_t);
+/// /// };
+/// /// ```
+/// #[usdt::doc_provider]
+/// mod test {}
+/// ````
+///
+/// The probes are generated in place of the module, which keeps its name and documentation, so
+/// the above may be used as `test::start_work!(|| 0)`. The same options as [`dtrace_provider!`] may
+/// be given, e.g., `#[usdt::doc_provider(probe_format = "probe_{probe}")]`, except that the
+/// module may not be renamed.
+#[proc_macro_attribute]
+pub fn doc_provider(
+    attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> proc_macro::TokenStream {
+    generate_doc_provider(attr.into(), item.into())
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn generate_doc_provider(
+    attr: proc_macro2::TokenStream,
+    item: proc_macro2::TokenStream,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let mut config: usdt_impl::CompileProvidersConfig = serde_tokenstream::from_tokenstream(&attr)?;
+    if config.module.is_some() {
+        return Err(syn::Error::new_spanned(
+            attr,
+            "The provider module may not be renamed via the doc-comment macro",
+        ));
+    }
+    let mod_ = syn::parse2::<syn::ItemMod>(item)?;
+    if mod_
+        .content
+        .as_ref()
+        .map_or(false, |(_, items)| !items.is_empty())
+    {
+        return Err(syn::Error::new_spanned(
+            &mod_.ident,
+            "Provider modules defined in doc comments must be empty",
+        ));
+    }
+    let source = doc_provider_source(&mod_.attrs).ok_or_else(|| {
+        syn::Error::new_spanned(
+            &mod_.ident,
+            "Expected a provider definition in a ```dtrace block in the module's doc comments",
+        )
+    })?;
+    config.module = Some(mod_.ident.to_string());
+    let provider = compile_provider_source(&source, &config).map_err(|e| {
+        syn::Error::new_spanned(
+            &mod_.ident,
+            format!("Error building provider definition\n\n{}", e),
+        )
+    })?;
+    let docs = mod_.attrs.iter().filter(|attr| attr.path().is_ident("doc"));
+    Ok(quote! {
+        #(#docs)*
+        #provider
+    })
+}
+
+// Extract the D source from the code blocks fenced as `dtrace` in the given doc attributes.
+fn doc_provider_source(attrs: &[syn::Attribute]) -> Option<String> {
+    let mut docs = String::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("doc")) {
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: Lit::Str(ref doc),
+                    ..
+                }),
+            ..
+        }) = attr.meta
+        {
+            docs.push_str(&doc.value());
+            docs.push('\n');
+        }
+    }
+
+    let mut source = None::<String>;
+    let mut in_block = false;
+    for line in docs.lines() {
+        let line = line.trim();
+        if in_block {
+            if line.starts_with("```") {
+                in_block = false;
+            } else {
+                let source = source.get_or_insert_with(String::new);
+                source.push_str(line);
+                source.push('\n');
+            }
+        } else if line.starts_with("```") && line[3..].trim() == "dtrace" {
+            in_block = true;
+        }
+    }
+    source
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_relative;
+    use super::{
+        doc_provider_source, generate_doc_provider, generate_dtrace_provider, generate_printf_args,
+    };
+    use quote::quote;
+    use std::path::Path;
+
+    #[test]
+    fn test_resolve_relative() {
+        assert_eq!(
+            resolve_relative(Path::new("src/nested/inner/mod.rs"), "probes.d"),
+            Path::new("src/nested/inner/probes.d"),
+        );
+        assert_eq!(
+            resolve_relative(Path::new("src/nested/inner.rs"), "../probes.d"),
+            Path::new("src/nested/../probes.d"),
+        );
+        assert_eq!(
+            resolve_relative(Path::new("lib.rs"), "probes.d"),
+            Path::new("probes.d"),
+        );
+    }
+
+    #[test]
+    fn test_doc_provider_source() {
+        let mod_: syn::ItemMod = syn::parse2(quote! {
+            /// Probes for the work loop.
+            ///
+            /// ```dtrace
+            /// provider test {
+            ///     probe start_work(uint8_t);
+            /// };
+            /// ```
+            ///
+            /// ```
+            /// not_dtrace();
+            /// ```
+            mod test {}
+        })
+        .unwrap();
+        assert_eq!(
+            doc_provider_source(&mod_.attrs).unwrap(),
+            "provider test {\nprobe start_work(uint8_t);\n};\n"
+        );
+
+        let mod_: syn::ItemMod = syn::parse2(quote! {
+            /// ```
+            /// provider test { probe start_work(uint8_t); };
+            /// ```
+            mod test {}
+        })
+        .unwrap();
+        assert!(doc_provider_source(&mod_.attrs).is_none());
+    }
+
+    // Return the module generated for a provider, which contains the marker rejecting a second
+    // definition of it.
+    fn provider_module(tokens: proc_macro2::TokenStream) -> syn::ItemMod {
+        let module = syn::parse2::<syn::ItemMod>(tokens).unwrap();
+        let (_, items) = module.content.as_ref().expect("Expected an inline module");
+        assert!(
+            matches!(items.first(), Some(syn::Item::Macro(_))),
+            "Expected the provider's module to begin with its marker"
+        );
+        module
+    }
+
+    #[test]
+    fn test_generate_doc_provider() {
+        let item = quote! {
+            /// ```dtrace
+            /// provider test {
+            ///     probe start_work(uint8_t);
+            /// };
+            /// ```
+            mod probes {}
+        };
+        let out = generate_doc_provider(quote! {}, item).unwrap();
+        let mod_ = provider_module(out);
+        assert_eq!(mod_.ident, "probes");
+        assert!(mod_.attrs.iter().any(|attr| attr.path().is_ident("doc")));
+        let (_, items) = mod_.content.unwrap();
+        assert!(items
+            .iter()
+            .any(|item| matches!(item, syn::Item::Macro(m) if m.ident.as_ref().map_or(false, |i| i == "start_work"))));
+    }
+
+    #[test]
+    fn test_generate_doc_provider_errors() {
+        let no_block = quote! {
+            /// Just documentation
+            mod probes {}
+        };
+        assert!(generate_doc_provider(quote! {}, no_block).is_err());
+
+        let not_empty = quote! {
+            /// ```dtrace
+            /// provider test { probe start_work(); };
+            /// ```
+            mod probes { fn start_work() {} }
+        };
+        assert!(generate_doc_provider(quote! {}, not_empty).is_err());
+
+        let renamed = quote! {
+            /// ```dtrace
+            /// provider test { probe start_work(); };
+            /// ```
+            mod probes {}
+        };
+        assert!(generate_doc_provider(quote! { module = "other" }, renamed).is_err());
+    }
+
+    #[test]
+    fn test_generate_dtrace_provider() {
+        let out = generate_dtrace_provider(
+            quote! { "provider test { probe work_done(uint8_t); };", probe_format = "probe_{probe}" },
+            /* reexport = */ false,
+        )
+        .unwrap();
+        let mod_ = provider_module(out);
+        assert_eq!(mod_.ident, "test");
+        let (_, items) = mod_.content.unwrap();
+        assert!(items
+            .iter()
+            .any(|item| matches!(item, syn::Item::Macro(m) if m.ident.as_ref().map_or(false, |i| i == "probe_work_done"))));
+
+        let out = generate_dtrace_provider(
+            quote! { "provider test { probe work_done(uint8_t); };" },
+            /* reexport = */ true,
+        )
+        .unwrap();
+        let file = syn::parse2::<syn::File>(out).unwrap();
+        assert!(matches!(
+            file.items.as_slice(),
+            [syn::Item::Mod(_), syn::Item::Use(_)]
+        ));
+    }
+
+    #[test]
+    fn test_generate_dtrace_provider_types() {
+        let out = generate_dtrace_provider(
+            quote! {
+                "provider test { probe open(conn_t, flags_t); };",
+                types = { "conn_t" = "u64", "flags_t" = "uint32_t" }
+            },
+            /* reexport = */ false,
+        )
+        .unwrap();
+        let mod_ = provider_module(out);
+        let (_, items) = mod_.content.unwrap();
+        let arg_types = items
+            .iter()
+            .find_map(|item| match item {
+                syn::Item::Const(c) if c.ident == "PROBE_ARG_TYPES" => Some(quote! { #c }),
+                _ => None,
+            })
+            .expect("No argument types in provider module")
+            .to_string();
+        assert!(
+            arg_types.contains(r#""uint64_t" , "uint32_t""#),
+            "{}",
+            arg_types
+        );
+
+        // Unknown types in the mapping are an error.
+        assert!(generate_dtrace_provider(
+            quote! {
+                "provider test { probe open(conn_t); };",
+                types = { "conn_t" = "connection" }
+            },
+            /* reexport = */ false,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_generate_dtrace_provider_probe_names() {
+        let out = generate_dtrace_provider(
+            quote! {
+                "provider test { probe connect(uint8_t); };",
+                probe_names = { "connect" = "do_connect" }
+            },
+            /* reexport = */ false,
+        )
+        .unwrap();
+        let mod_ = provider_module(out);
+        let (_, items) = mod_.content.unwrap();
+        let has_macro = |name: &str| {
+            items.iter().any(|item| match item {
+                syn::Item::Macro(m) => m.ident.as_ref().map_or(false, |i| i == name),
+                _ => false,
+            })
+        };
+        assert!(has_macro("do_connect"));
+        assert!(!has_macro("connect"));
+
+        // The probe keeps its name, as seen by DTrace.
+        let specs = items
+            .iter()
+            .find_map(|item| match item {
+                syn::Item::Const(c) if c.ident == "PROBE_SPECS" => Some(quote! { #c }),
+                _ => None,
+            })
+            .expect("No probe specs in provider module")
+            .to_string();
+        assert!(specs.contains(r#""test:::connect""#), "{}", specs);
+    }
+
+    #[test]
+    fn test_generate_dtrace_provider_version_tag() {
+        let out = generate_dtrace_provider(
+            quote! {
+                "provider test { probe work_done(uint8_t); };",
+                version_tag = "1.2.3+abcdef"
+            },
+            /* reexport = */ false,
+        )
+        .unwrap();
+        provider_module(out);
+
+        // Tags which can't be emitted verbatim into the assembly of the probe records are an error.
+        for tag in ["1.2.3 {0}", "\"quoted\""] {
+            let error = generate_dtrace_provider(
+                quote! { "provider test { probe work_done(uint8_t); };", version_tag = #tag },
+                /* reexport = */ false,
+            )
+            .unwrap_err()
+            .to_string();
+            assert!(error.contains("Invalid version tag"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_generate_dtrace_provider_errors() {
+        for item in [
+            quote! { 1 },
+            quote! { "provider test { probe work_done(uint8_t) };" },
+            quote! { "missing.d" },
+            quote! { "provider test { probe work_done(); };", not_an_option = "foo" },
+        ] {
+            assert!(generate_dtrace_provider(item, /* reexport = */ false).is_err());
+        }
+    }
+
+    #[test]
+    fn test_generate_dtrace_provider_extension_warning() {
+        let dir = std::env::temp_dir().join(format!("usdt-macro-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("provider.txt");
+        std::fs::write(&path, "provider test { probe work_done(uint8_t); };").unwrap();
+        let filename = path.to_str().unwrap();
+        let result = generate_dtrace_provider(quote! { #filename }, /* reexport = */ false);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The provider is still generated, along with the warning.
+        let file = syn::parse2::<syn::File>(result.unwrap()).unwrap();
+        assert!(matches!(
+            file.items.as_slice(),
+            [syn::Item::Const(_), syn::Item::Mod(_)]
+        ));
+        let warning = quote! { #file }.to_string();
+        assert!(warning.contains("deprecated"));
+        assert!(warning.contains("doesn't have a `.d` extension"));
+
+        // Inline definitions and `.d` files have no warning.
+        let out = generate_dtrace_provider(
+            quote! { "provider test { probe work_done(uint8_t); };" },
+            /* reexport = */ false,
+        )
+        .unwrap();
+        provider_module(out);
+
+        // Missing files with another extension mention it in the error.
+        let error = generate_dtrace_provider(quote! { "probes.rs" }, /* reexport = */ false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("`.d` extension"));
+    }
+
+    #[test]
+    fn test_generate_printf_args() {
+        // The arguments are padded with zeros to `MAX_PRINTF_ARGS`.
+        assert_eq!(dtrace_parser::MAX_PRINTF_ARGS, 5);
+        let out = generate_printf_args(quote! { "%d %d", len, -1i64 }).unwrap();
+        assert_eq!(
+            out.to_string(),
+            quote! { ("%d %d", (len) as usize, (-1i64) as usize, 0usize, 0usize, 0usize,) }
+                .to_string()
+        );
+
+        let error = generate_printf_args(quote! { "%d %d %d %d %d %d", 1, 2, 3, 4, 5, 6 })
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("at most"));
+        assert!(generate_printf_args(quote! {}).is_err());
+    }
 }
@@ -44,13 +44,13 @@
 //! };
 //! ```
 //!
-//! Providers and probes may be named in any way, as long as they form valid Rust identifiers. The
-//! names are intended to help understand the behavior of a program, so they should be semantically
-//! meaningful. Probes accept zero or more arguments, data that is associated with the probe event
-//! itself (timestamps, file descriptors, filesystem paths, etc.). The arguments may be specified
-//! as any of the exact bit-width integer types (e.g., `int16_t`), pointers to
-//! such integers, or strings (`char *`s). See [Data types](#data-types) for a full list of
-//! supported types.
+//! Providers and probes may be named in any way, as long as they form valid Rust identifiers.
+//! Probe names may also contain dashes, see [below](#double-underscores). The names are intended
+//! to help understand the behavior of a program, so they should be semantically meaningful.
+//! Probes accept zero or more arguments, data that is associated with the probe event itself
+//! (timestamps, file descriptors, filesystem paths, etc.). The arguments may be specified as any
+//! of the exact bit-width integer types (e.g., `int16_t`), pointers to such integers, or strings
+//! (`char *`s). See [Data types](#data-types) for a full list of supported types.
 //!
 //! Assuming the above is in a file called `"test.d"`, the probes may be compiled into Rust code
 //! with:
@@ -64,6 +64,16 @@
 //! notes](#features) for a discussion. The invocation of `dtrace_provider` (and any required
 //! feature directives) **should be at the crate root**, i.e., `src/lib.rs` or `src/main.rs`.
 //!
+//! The D definition may also be written in a code block fenced as `dtrace` in the doc comments of
+//! an empty module annotated with [`doc_provider`], which keeps the definition alongside its
+//! documentation. Alternatively, [`probes!`] generates the same provider as `dtrace_provider!`,
+//! and re-exports the probe macros at the crate root, so they may be called without naming the
+//! provider module. In a build script, [`Builder::from_spec`] generates the same code from a
+//! [`ProviderSpec`] built in Rust, for providers derived from some other description such as an
+//! IDL file. To validate a D file without generating any code, for example in CI, use
+//! [`check_provider`], which reports each problem with its line and column, and to rewrite one in
+//! a canonical style, use [`format_provider`].
+//!
 //! One may then call the `start` probe via:
 //!
 //! ```ignore
@@ -134,6 +144,23 @@
 //! like `{"ok": _}` or `{"err": "some error message"}`. In the error case, the message is
 //! created by formatting the `serde_json::error::Error` that describes why serialization failed.
 //!
+//! Arrays and slices of integers larger than [`MAX_INLINE_ARGUMENT_SIZE`] bytes, e.g., an argument
+//! declared as `&[u32]` or `[u64; 32]`, aren't copied into the JSON at all. They're passed by
+//! reference instead, encoded as `{"ref": {"ptr": _, "len": _}}` with the address of the first
+//! element and the size in bytes, and consumers copy in the data themselves, e.g.:
+//!
+//! ```console
+//! $ dtrace -n 'big_work {
+//!     this->json = copyinstr(arg0);
+//!     tracemem(copyin(strtoll(json(this->json, "ref.ptr")), 1024), 1024,
+//!         strtoll(json(this->json, "ref.len")));
+//! }'
+//! ```
+//!
+//! This is decided from the declared type of the argument, and all other serializable arguments,
+//! including structs, are copied into the JSON whatever their size. Integer arrays in D
+//! definitions, such as `uint8_t[16]`, always have their elements copied, whatever their size.
+//!
 //! > **Note**: It's not possible to define probes in D that accept a serializable type, because the
 //! corresponding C type is just `char *`. There's currently no way to disambiguate such a type
 //! from an actual string, when generating the Rust probe macros.
@@ -178,7 +205,20 @@
 //!
 //! This probe `bar` will appear in DTrace as `foo:::bar`, but will now be accessible in Rust via
 //! the macro `probes::bar!`. Note that it's not possible to rename the provider as it appears in
-//! DTrace when using the builder version.
+//! DTrace when using the builder version. The `dtrace_provider!` macro accepts the same rename as
+//! `rust_name`, e.g., `dtrace_provider!("test.d", rust_name = "probes")`.
+//!
+//! The macro of a single probe may also be named differently from the probe, for example if the
+//! probe name doesn't match the naming convention of the crate. The name replaces that generated
+//! by `probe_format`, while the probe keeps its name in DTrace and in the probe records. Each
+//! name must be that of a probe in the file, and two probes of a provider can't share a macro:
+//!
+//! ```ignore
+//! usdt::dtrace_provider!("test.d", probe_names = { "bar" = "do_bar" });
+//! foo::do_bar!(|| ());
+//! ```
+//!
+//! The builder version accepts the same names via `Builder::probe_name`.
 //!
 //! Double-underscores
 //! ------------------
@@ -201,6 +241,152 @@
 //! matches the behavior of existing DTrace implementations, and guarantees that
 //! providers are similarly named regardless of the target platform.
 //!
+//! Probes defined in D may also be named with dashes directly, such as `gc-start`. The probe
+//! appears to DTrace as `gc-start`, and the dashes are mapped to underscores in the Rust probe
+//! macro, i.e., `gc_start!`. Because of this, a provider may not define two probes which map to
+//! the same Rust name, such as `gc-start` and `gc_start`, or to the same DTrace name, such as
+//! `gc-start` and `gc__start`; doing so is a compile-time error.
+//!
+//! Probe specifications
+//! --------------------
+//!
+//! Each generated provider module also contains a constant `PROBE_SPECS`, listing the DTrace
+//! specification of each of its probes, such as `"foo:::bar"`. These can be used to construct
+//! invocations of `dtrace -n`, or to check against the probes actually registered.
+//!
+//! Alongside these, the constant `PROBE_ARG_TYPES` pairs the name of each probe with the D types
+//! of its arguments, such as `("bar", &["uint8_t", "char*"])`. Tools consuming the probes may use
+//! these to check their expectations about a provider's schema at runtime.
+//!
+//! Programs may select probes by a DTrace-style description, say from a `--probe foo:::gc-*`
+//! command-line option, with the function `matches_spec` generated in each provider module. This
+//! returns the names of the provider's probes matching the description, which may contain the
+//! wildcards `*` and `?`, using [`probe_spec_matches`].
+//!
+//! The number of probes in a provider is returned by the `const fn` `probe_count`, also generated
+//! in each provider module, which may be used in constant contexts such as the length of an
+//! array, e.g., `[0u64; foo::probe_count()]`.
+//!
+//! Listing providers
+//! -----------------
+//!
+//! The providers defined in a program, including those of its dependencies, may be listed at
+//! runtime with [`all_providers`]. Each generated provider module contains a static
+//! [`ProviderInfo`], which the linker collects into a dedicated section, so this requires no
+//! registration step. This is supported on platforms using ELF or Mach-O object files.
+//!
+//! Enabling providers
+//! ------------------
+//!
+//! Each generated provider module also contains the functions `enable()` and `disable()`, for
+//! compatibility with other USDT crates which require providers to be enabled explicitly.
+//! `enable()` is the same as calling [`register_probes`], which registers the probes of every
+//! provider in the program. `disable()` does nothing, as probes can't be unregistered, and always
+//! succeeds.
+//!
+//! Record section size
+//! -------------------
+//!
+//! Each generated provider module contains a constant `PROBE_RECORD_SECTION_SIZE`, giving the
+//! number of bytes of probe records emitted when each of its probes is invoked once, and each
+//! `<probe>_is_enabled_raw` function is called once. This can be used by environments which must
+//! reserve space for the probe section ahead of time. Note that each invocation of a probe macro
+//! emits its own records, and that probes which always fire have no is-enabled record at their
+//! call sites.
+//!
+//! Probe record sections
+//! ---------------------
+//!
+//! Where the probe records are emitted by this crate, such as on illumos, the records of every
+//! provider in a program are collected in the `set_dtrace_probes` section. A provider may instead
+//! be placed in its own section, for example so that the probes of one crate may be stripped from
+//! a binary independently of the others, with the `section` argument to any of the
+//! code-generators, or the `Builder::section` method:
+//!
+//! ```ignore
+//! usdt::dtrace_provider!("test.d", section = "my_crate_probes");
+//! ```
+//!
+//! The section name must be a valid C identifier, so that the linker defines symbols for its
+//! bounds. Each such provider records the bounds of its section in another section collected by
+//! the linker, so [`register_probes`] registers the probes of these sections along with those of
+//! the default section. To read the probe records from such sections, list their names in
+//! [`ExtractOptions::extra_section_names`] and call [`probe_records_with`].
+//!
+//! The records may also carry a version string or build hash of the provider, given with the
+//! `version_tag` argument or the `Builder::version_tag` method, e.g., from a build script which
+//! knows the commit being built. The tag may only contain ASCII letters and digits, `.`, `_`, `+`
+//! and `-`. This is read back as the `version_tag` of each [`ProbeRecord`] parsed from the section
+//! with a [`SectionParser`], and of each provider returned by [`probe_records`], so that tools can
+//! tell which build a probe came from.
+//!
+//! Provider name prefixes
+//! ----------------------
+//!
+//! A library shipping probes may namespace its providers, so that they don't collide with
+//! providers of the same name in an application using it. The `provider_prefix` argument to any
+//! of the code-generators, or the `Builder::provider_prefix` method, adds the prefix to the name
+//! of each provider as seen by tracers and in the probe records:
+//!
+//! ```ignore
+//! usdt::dtrace_provider!("test.d", provider_prefix = "my_crate_");
+//! ```
+//!
+//! The generated module keeps the name of the provider in the D file. If `test.d` defines the
+//! provider `test` with a probe `begin`, the probe is still fired as `test::begin!()`, but appears
+//! to DTrace as `my_crate_test:::begin`. The prefix must be a valid C identifier. To choose it
+//! when building, e.g., from an environment variable, read the variable in a build script and
+//! pass it to `Builder::provider_prefix`.
+//!
+//! Unconditional probes
+//! --------------------
+//!
+//! Each probe normally checks whether it's enabled before evaluating its arguments and firing:
+//! the semaphore count with SystemTap SDT notes, or an is-enabled site with DTrace. Some tracers
+//! don't manage semaphores, and never see the probes fire. The `always_fire` argument to any of
+//! the code-generators, or the `Builder::always_fire` method, omits this check:
+//!
+//! ```ignore
+//! usdt::dtrace_provider!("test.d", always_fire = true);
+//! ```
+//!
+//! The probe then fires on every invocation, whether or not a tracer is attached. This means the
+//! closure producing its arguments is always called, so any work done there, such as formatting
+//! strings or serializing to JSON, is done every time, and the probe is no longer nearly free
+//! while disabled. This is best kept to probes which are rarely invoked or have cheap arguments.
+//!
+//! Counting probe firings
+//! ----------------------
+//!
+//! When the `test-counters` feature is enabled, each probe also gets a function named for the
+//! probe macro with a `_fire_count` suffix, e.g., `my_provider::my_probe_fire_count()`. It
+//! returns the number of times the probe macro has been invoked, whether or not the probe was
+//! enabled in DTrace. This is intended to let tests assert that instrumentation is reached,
+//! without requiring DTrace or elevated privileges, and should not be enabled in production
+//! builds.
+//!
+//! Enabled state
+//! -------------
+//!
+//! Each probe also gets a function named for the probe macro with an `_is_enabled_raw` suffix,
+//! e.g., `my_provider::my_probe_is_enabled_raw()`. It returns the raw value of the flag the probe
+//! macro checks before evaluating its arguments: the semaphore count with SystemTap SDT notes, or
+//! the result of an is-enabled site with DTrace. The value is zero while no tracer has enabled the
+//! probe, and is always zero when probes aren't supported on the platform. This is meant for
+//! diagnosing why a probe does or doesn't fire.
+//!
+//! Printf-style probes
+//! -------------------
+//!
+//! A probe in a D file may take a format string followed by an ellipsis, e.g.,
+//! `probe log(char *, ...);`. This declares the format string and [`MAX_PRINTF_ARGS`] `uintptr_t`
+//! arguments, which fill the arguments passed to DTrace. The [`printf_args!`] macro builds the
+//! arguments from fewer integers, padding the rest with zeros:
+//!
+//! ```ignore
+//! logging::log!(|| usdt::printf_args!("read %d bytes", len));
+//! ```
+//!
 //! Examples
 //! --------
 //!
@@ -218,6 +404,32 @@
 //! expensive to construct. However, this cost will only be incurred if the probe is actually
 //! enabled.
 //!
+//! String arguments are copied into a buffer, to be NUL-terminated for DTrace, which is allocated
+//! each time an enabled probe fires. In hot paths, a reusable buffer may be passed before the
+//! closure instead, so that no allocation is needed once the buffer has grown large enough:
+//!
+//! ```ignore
+//! let mut buffer = Vec::new();
+//! for path in paths {
+//!     my_provider::open!(buffer = &mut buffer, || (path, 0));
+//! }
+//! ```
+//!
+//! Only one probe fires at a time on each thread. If a probe is fired while another is firing on
+//! the same thread, for example from within its closure or from a `Serialize` implementation of
+//! one of its arguments, the nested fire is dropped: its closure isn't called and tracers don't
+//! see it. This prevents a probe which is reached again while evaluating its arguments from
+//! recursing without bound.
+//!
+//! Probes and optimization
+//! -----------------------
+//!
+//! A probe site is emitted as an `asm!` block which is not marked `pure`, so the compiler must
+//! treat it as having side effects. It is therefore kept in optimized builds, even when the
+//! arguments passed to the probe are not otherwise used by the program. The block is also
+//! declared to read memory, which ensures that any data referred to by the arguments, such as
+//! string contents, has been written by the time DTrace reads it.
+//!
 //! Data types
 //! ----------
 //!
@@ -230,10 +442,43 @@
 //! - `(u?)int(8|16|32|64)_t`
 //! - Pointers to the above integer types
 //! - `char *`
+//! - `wchar_t *`, which is treated exactly as `char *`: the Rust argument is a UTF-8 string,
+//!   passed to DTrace as a NUL-terminated `char *` and read with `copyinstr()`. C callers must
+//!   likewise pass UTF-8, not UTF-16 or UTF-32.
+//! - `float` and `double` (`f32` and `f64` in Rust)
+//! - `hrtime_t`, a timestamp in nanoseconds (`i64` in Rust), e.g., from a monotonic clock
+//! - Fixed-length arrays of the above integer types, e.g., `uint8_t[16]` (`[u8; 16]` in Rust),
+//!   with at most 256 elements. The elements are copied inline when the probe fires, and passed to
+//!   DTrace as a pointer to that copy, without a length, so they're read with the length from the
+//!   definition, e.g., `copyin(arg0, 16 * sizeof (uint8_t))`. The generated probe macro's docs
+//!   give this length.
+//! - Standard POSIX typedefs, e.g., `pid_t` (`i32` in Rust) or `off_t` (`i64` in Rust), and
+//!   typedefs of the above types declared in the D file, e.g., `typedef uint16_t port_t;`. The
+//!   names of typedefs must end in `_t`. Typedefs may also be given without editing the D file,
+//!   as a map from each name to a Rust or D type, with the `types` argument to the
+//!   code-generators, e.g., `dtrace_provider!("test.d", types = { "conn_t" = "u64" })`, or the
+//!   `Builder::type_mapping` method. Those declared in the D file take precedence.
+//! - Other pointers and typedefs, e.g., `void *` or `callback_t`, which are passed as a `usize`,
+//!   with a compiler warning
 //! - `T: Clone + serde::Serialize` (Only when defining probes in Rust)
+//! - `*const T`, where `T` is a `#[repr(C)]` struct implementing [`ProbeStruct`] (Only when
+//!   defining probes in Rust)
+//!
+//! > **Note**: Only the address of a struct passed by pointer is given to DTrace. The trait
+//! [`ProbeStruct`], usually derived with `#[derive(usdt::ProbeStruct)]`, provides a definition of
+//! the struct in D. The definitions of the structs used by a provider are listed in its
+//! `STRUCT_DEFINITIONS` constant, which may be included in D scripts to read the fields, e.g.,
+//! `((struct Context *)copyin(arg0, sizeof (struct Context)))->id`. Such probes may be passed
+//! either a reference to the struct or a raw pointer.
+//!
+//! > **Note**: DTrace reads all USDT probe arguments from the integer registers, so floating
+//! point values are passed as their bit patterns, i.e., the result of `f64::to_bits`. The
+//! argument types are still recorded as `float` or `double`, and consumers must reinterpret the
+//! integer value. For example, a `float` argument occupies the low 32 bits of its argument.
 //!
-//! Currently, up to six (6) arguments are supported, though this limitation may be lifted in the
-//! future.
+//! Currently, up to six (6) arguments are passed to DTrace, though this limitation may be lifted
+//! in the future. Probes may be defined with more arguments, in which case the compiler emits a
+//! warning, and only the first six are visible to DTrace.
 //!
 //! > **Note**: Serializable types must implement the `Clone` trait. It's important to note that
 //! this may almost always be derived, and, more importantly, that the data in probes will _never
@@ -275,6 +520,20 @@
 //! purpose. It may be passed as any argument to a probe function, and is guaranteed to be unique
 //! between different invocations of the same probe. See the type's documentation for details.
 //!
+//! SystemTap SDT notes on Linux
+//! ----------------------------
+//!
+//! There is no DTrace on Linux, so probes are no-ops there by default. Linux tracers such as
+//! `perf` and `bpftrace` instead find probes from the `.note.stapsdt` ELF notes defined by
+//! SystemTap, which `<sys/sdt.h>` emits for C programs. With the `stapsdt` feature, each probe
+//! site emits such a note on Linux, describing the provider and probe names, the location of the
+//! site, and the registers holding each argument.
+//!
+//! Each probe also has a semaphore, which tracers increment while they're attached to it. Probes
+//! are only enabled while their semaphore is non-zero, so as on other platforms, the arguments of
+//! a probe are only evaluated while it's traced. No registration is needed, and
+//! [`register_probes`] does nothing with this feature.
+//!
 //! Features
 //! --------
 //!
@@ -318,6 +577,13 @@
 //! the internals. This generates the same probe macros, but with empty bodies, meaning the code can
 //! be compiled unchanged.
 //!
+//! The probes then have no runtime cost. The closure passed to each probe macro is type-checked,
+//! but never called, so its arguments are never evaluated, and no probe records are emitted into
+//! the binary. The constants and functions describing each provider, such as `PROBE_SPECS`, are
+//! still generated, so that code using them also compiles unchanged. Since the no-op implementation
+//! is also selected for targets or compilers without a backend, `usdt::PROBES_ARE_NOOP` reports
+//! whichever was chosen.
+//!
 //! Library developers may choose to re-export this feature, with a name such as `probes`, which
 //! implies the `asm` feature of the `usdt` crate. This feature-gating allows users to select a
 //! nightly compiler in exchange for probes, but still allows the code to be compiled with a stable
@@ -353,26 +619,60 @@
 //! [asm-sym-feature-pr]: https://github.com/rust-lang/rust/pull/90348
 
 use dof::{extract_dof_sections, Section};
+use goblin::container::{Container, Ctx};
+use goblin::elf::compression_header::{CompressionHeader, ELFCOMPRESS_ZLIB};
+use goblin::elf::reloc::{R_AARCH64_ABS64, R_X86_64_64};
+use goblin::elf::section_header::SHF_COMPRESSED;
+use goblin::elf::{header, note, program_header, Elf};
 use goblin::Object;
+#[cfg(any(unix, windows))]
+use memmap::MmapMut;
 use memmap::{Mmap, MmapOptions};
+use std::collections::BTreeSet;
 use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::{env, fs};
 
-pub use usdt_attr_macro::provider;
+pub use usdt_attr_macro::{provider, ProbeStruct};
+pub use usdt_impl::record::{
+    Endian, PointerWidth, ProbeRecord, RecordBuilder, RecordFormat, SectionParser, StringLimits,
+    Symbolizer,
+};
 #[doc(hidden)]
-pub use usdt_impl::to_json;
-pub use usdt_impl::{Error, UniqueId};
-pub use usdt_macro::dtrace_provider;
+pub use usdt_impl::RecordSection;
+pub use usdt_impl::{all_providers, probe_spec_matches, ProviderInfo};
+pub use usdt_impl::{compile_provider_spec_records, compile_provider_spec_source};
+#[cfg(feature = "test-counters")]
+#[doc(hidden)]
+pub use usdt_impl::{count_probe_firing, probe_fire_count};
+#[doc(hidden)]
+pub use usdt_impl::{enter_probe_firing, ProbeFiringGuard};
+#[doc(hidden)]
+pub use usdt_impl::{serialize_probe_argument, serialize_probe_integers, to_json};
+pub use usdt_impl::{
+    BitWidth, Integer, NativeType, ProbeSpec, ProviderSpec, Sign, MAX_INLINE_ARGUMENT_SIZE,
+    MAX_PRINTF_ARGS, PROBES_ARE_NOOP,
+};
+pub use usdt_impl::{Diagnostic, Error, ProbeStruct, UniqueId};
+pub use usdt_macro::{doc_provider, dtrace_provider, printf_args, probes};
 
 /// A simple struct used to build DTrace probes into Rust code in a build.rs script.
 #[derive(Debug)]
 pub struct Builder {
-    source_file: PathBuf,
+    source: Source,
     out_file: PathBuf,
+    header_file: Option<PathBuf>,
     config: usdt_impl::CompileProvidersConfig,
 }
 
+// The definition of the providers generated by a `Builder`.
+#[derive(Debug)]
+enum Source {
+    File(PathBuf),
+    Spec(ProviderSpec),
+}
+
 impl Builder {
     /// Construct a new builder from a path to a D provider definition file.
     pub fn new<P: AsRef<Path>>(file: P) -> Self {
@@ -380,8 +680,24 @@ impl Builder {
         let mut out_file = source_file.clone();
         out_file.set_extension("rs");
         Builder {
-            source_file,
+            source: Source::File(source_file),
+            out_file,
+            header_file: None,
+            config: usdt_impl::CompileProvidersConfig::default(),
+        }
+    }
+
+    /// Construct a new builder from a provider defined in Rust, rather than in a D file.
+    ///
+    /// This generates the same code as the equivalent D source, and is useful when the shape of a
+    /// provider is derived from some other description, such as an IDL file. The default output
+    /// filename is the name of the provider, with the `".rs"` extension.
+    pub fn from_spec(spec: ProviderSpec) -> Self {
+        let out_file = PathBuf::from(format!("{}.rs", spec.name));
+        Builder {
+            source: Source::Spec(spec),
             out_file,
+            header_file: None,
             config: usdt_impl::CompileProvidersConfig::default(),
         }
     }
@@ -394,6 +710,20 @@ impl Builder {
         self
     }
 
+    /// Also generate a C header declaring the same probes, with the given filename.
+    ///
+    /// The header is written to `OUT_DIR`, alongside the generated Rust code, with the `".h"`
+    /// extension. This allows C code in the same package to fire probes of the provider. As with
+    /// a header generated by `dtrace -h`, the functions called by its probe macros are defined by
+    /// the object which `dtrace -G` generates from the provider file and the C objects, which
+    /// must also be linked into the program.
+    pub fn header_file<P: AsRef<Path>>(mut self, file: P) -> Self {
+        let mut header_file = file.as_ref().to_path_buf();
+        header_file.set_extension("h");
+        self.header_file = Some(header_file);
+        self
+    }
+
     /// Set the format for the name of generated probe macros.
     ///
     /// The provided format may include the tokens `{provider}` and `{probe}`, which will be
@@ -404,24 +734,151 @@ impl Builder {
     }
 
     /// Set the name of the module containing the generated probe macros.
+    ///
+    /// This is the Rust name only, and must be a valid identifier. Probes are still registered
+    /// with DTrace under the name of the provider in the D file.
     pub fn module(mut self, module: &str) -> Self {
         self.config.module = Some(module.to_string());
         self
     }
 
+    /// Set the name of the linker section containing the provider's probe records.
+    ///
+    /// See [Probe record sections](crate#probe-record-sections).
+    pub fn section(mut self, section: &str) -> Self {
+        self.config.section = Some(section.to_string());
+        self
+    }
+
+    /// Set whether the provider's probes fire unconditionally, rather than only while enabled.
+    ///
+    /// See [Unconditional probes](crate#unconditional-probes).
+    pub fn always_fire(mut self, always_fire: bool) -> Self {
+        self.config.always_fire = always_fire;
+        self
+    }
+
+    /// Set a version string or build hash of the provider, to include in its probe records.
+    ///
+    /// See [Probe record sections](crate#probe-record-sections).
+    pub fn version_tag(mut self, tag: &str) -> Self {
+        self.config.version_tag = Some(tag.to_string());
+        self
+    }
+
+    /// Set a prefix to add to the name of each provider, as seen by tracers.
+    ///
+    /// See [Provider name prefixes](crate#provider-name-prefixes).
+    pub fn provider_prefix(mut self, prefix: &str) -> Self {
+        self.config.provider_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Map a typedef name used by the provider to a Rust or D type, such as `u64` or `uint64_t`.
+    ///
+    /// See [Data types](crate#data-types).
+    pub fn type_mapping(mut self, name: &str, typ: &str) -> Self {
+        self.config.types.insert(name.to_string(), typ.to_string());
+        self
+    }
+
+    /// Name the generated macro of a probe, rather than deriving it from the probe name.
+    ///
+    /// See [Configurable names](crate#configurable-names).
+    pub fn probe_name(mut self, probe: &str, rust_name: &str) -> Self {
+        self.config
+            .probe_names
+            .insert(probe.to_string(), rust_name.to_string());
+        self
+    }
+
     /// Generate the Rust code from the D provider file, writing the result to the output file.
+    ///
+    /// When building from a D provider file, a hash of its contents, of the builder's options and
+    /// of the code generator is stored alongside the output, with the `".hash"` extension
+    /// appended. The output is only regenerated if this hash differs, or any output is missing,
+    /// since modification times are unreliable across checkouts.
     pub fn build(self) -> Result<(), Error> {
-        let source = fs::read_to_string(self.source_file)?;
-        let tokens = usdt_impl::compile_provider_source(&source, &self.config)?;
-        let mut out_file = Path::new(&env::var("OUT_DIR")?).to_path_buf();
-        out_file.push(
+        let out_dir = Path::new(&env::var("OUT_DIR")?).to_path_buf();
+        self.build_in(&out_dir)
+    }
+
+    // Generate the Rust code and header, writing them to the given output directory.
+    fn build_in(self, out_dir: &Path) -> Result<(), Error> {
+        let out_file = out_dir.join(
             self.out_file
                 .file_name()
                 .expect("Could not extract filename"),
         );
-        fs::write(out_file, tokens.to_string().as_bytes())?;
+        let header_file = self
+            .header_file
+            .as_ref()
+            .map(|file| out_dir.join(file.file_name().expect("Could not extract filename")));
+        let (tokens, header, hash) = match &self.source {
+            Source::File(source_file) => {
+                let source = fs::read_to_string(source_file)?;
+                let hash = self.source_hash(&source);
+                let outputs_exist =
+                    out_file.exists() && header_file.as_ref().map_or(true, |file| file.exists());
+                if outputs_exist && read_hash(&out_file).as_deref() == Some(hash.as_str()) {
+                    return Ok(());
+                }
+                let tokens = usdt_impl::compile_provider_source(&source, &self.config)?;
+                let header = match self.header_file {
+                    Some(_) => Some(usdt_impl::compile_provider_header(&source)?),
+                    None => None,
+                };
+                (tokens, header, Some(hash))
+            }
+            Source::Spec(spec) => {
+                let tokens = usdt_impl::compile_provider_spec(spec, &self.config)?;
+                let header = match self.header_file {
+                    Some(_) => Some(usdt_impl::compile_provider_spec_header(spec)?),
+                    None => None,
+                };
+                (tokens, header, None)
+            }
+        };
+        fs::write(&out_file, tokens.to_string().as_bytes())?;
+        if let (Some(header_file), Some(header)) = (header_file, header) {
+            fs::write(header_file, header.as_bytes())?;
+        }
+        if let Some(hash) = hash {
+            fs::write(hash_file(&out_file), hash)?;
+        }
         Ok(())
     }
+
+    // Return the hash identifying the output generated from the given provider source. This covers
+    // the builder's options, the version of this crate and the code generator, as well as the
+    // source itself.
+    fn source_hash(&self, source: &str) -> String {
+        let key = format!(
+            "{}\0{}\0{:?}\0{:?}\0{}",
+            env!("CARGO_PKG_VERSION"),
+            usdt_impl::GENERATOR_HASH,
+            self.config,
+            self.header_file,
+            source
+        );
+        // 64-bit FNV-1a, which unlike the standard library's hasher is stable across toolchains.
+        let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+        format!("{:016x}", hash)
+    }
+}
+
+// Return the path of the file storing the source hash of a generated file.
+fn hash_file(out_file: &Path) -> PathBuf {
+    let mut file = out_file.as_os_str().to_owned();
+    file.push(".hash");
+    PathBuf::from(file)
+}
+
+// Return the source hash stored alongside a generated file, if any.
+fn read_hash(out_file: &Path) -> Option<String> {
+    fs::read_to_string(hash_file(out_file)).ok()
 }
 
 /// Register an application's probes with DTrace.
@@ -442,6 +899,37 @@ pub fn register_probes() -> Result<(), Error> {
     usdt_impl::register_probes().map_err(Error::from)
 }
 
+/// Check a D provider definition file, without generating any code.
+///
+/// This reports the same problems as [`Builder::build`] and the [`dtrace_provider`] macro, each
+/// with its line and column in the file, along with names too long for DTrace and probes with more
+/// arguments than DTrace supports. It's intended for linting provider files, e.g., in CI or an
+/// editor, and failing to read the file is reported as a diagnostic at its start.
+pub fn check_provider<P: AsRef<Path>>(path: P) -> Result<(), Vec<Diagnostic>> {
+    let source = fs::read_to_string(path).map_err(|e| {
+        vec![Diagnostic {
+            line: 1,
+            column: 1,
+            message: e.to_string(),
+        }]
+    })?;
+    usdt_impl::check_provider_source(&source)
+}
+
+/// Format the D source of a provider definition in a canonical style.
+///
+/// Probes are indented by a tab, one per line, with each attribute on its own line, and types are
+/// spelled consistently, e.g., `char *` rather than `char*`, but `uint8_t*`. Stray semicolons,
+/// groups of probes, and any name or attributes following the closing brace of a provider are
+/// removed. Comments between the items of the file, or between probes, are kept, while a comment
+/// within a declaration is an error.
+///
+/// The source must be a valid provider definition, which is unchanged by formatting, and
+/// formatting the result again returns it unchanged.
+pub fn format_provider(source: &str) -> Result<String, Error> {
+    usdt_impl::format_provider_source(source)
+}
+
 /// Extract embedded USDT probe records from a file.
 ///
 /// DTrace in general works by storing metadata about the probes in a special
@@ -449,87 +937,3016 @@ pub fn register_probes() -> Result<(), Error> {
 /// platform compiler and linker on systems with linker support (macOS), or
 /// created manually by this crate on other platforms. In either case, this
 /// method extracts the metadata as a [`Section`] from the object file, if it
-/// can be found.
+/// can be found. This includes the DOF in relocatable objects generated by the
+/// system `dtrace -G`, whose probe addresses are resolved from the object's
+/// relocations.
+///
+/// An empty list is returned if the probe record section exists, but contains
+/// no records, for example after [`strip_probe_records`]. Sections without any
+/// probes, as reported by [`Section::is_empty`], are never returned by this or
+/// the other functions extracting probe records, so each returned section
+/// contains at least one probe.
+///
+/// With the `zlib` feature, ELF sections compressed with zlib, i.e., with the
+/// `SHF_COMPRESSED` flag, are decompressed before their records are read.
+/// Without it, such sections are reported as [`Error::InvalidFile`].
+///
+/// In relocatable ELF objects, i.e., unlinked `.o` files, the records are
+/// located by their section header, since the `__start` and `__stop` symbols
+/// bounding them are only defined by the linker. The relocations against the
+/// section are applied to the records, so that the address of each probe is an
+/// offset into the section of the object containing it, e.g., `.text`.
+///
+/// The module of each probe is set to the name of the file, as DTrace does by
+/// default.
 pub fn probe_records<P: AsRef<Path>>(path: P) -> Result<Vec<Section>, Error> {
-    // Extract DOF section data, which is applicable for an object file built using this crate on
-    // macOS, or generally using the platform's dtrace tool, i.e., `dtrace -G` and compiler.
-    let dof_sections = extract_dof_sections(&path).map_err(|_| Error::InvalidFile)?;
-    if dof_sections.len() > 0 {
-        return Ok(dof_sections);
+    probe_records_with(path, &ExtractOptions::default())
+}
+
+/// Extract embedded USDT probe records from a file, without blocking the async runtime.
+///
+/// This returns the same records as [`probe_records`], but reads and parses the file on tokio's
+/// pool of blocking threads, so that other tasks may continue to run in the meantime. This must be
+/// called from within a tokio runtime, and requires the `tokio` feature.
+#[cfg(feature = "tokio")]
+pub async fn probe_records_async<P: AsRef<Path>>(path: P) -> Result<Vec<Section>, Error> {
+    let path = path.as_ref().to_path_buf();
+    match tokio::task::spawn_blocking(move || probe_records(path)).await {
+        Ok(result) => result,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Extract embedded USDT probe records from many files in parallel.
+///
+/// Each file is read with [`probe_records`] on rayon's thread pool, for example to scan all the
+/// binaries in a `target/` directory. The result for each file is returned along with its path, in
+/// the order the paths were given. This requires the `rayon` feature.
+#[cfg(feature = "rayon")]
+pub fn probe_records_many<I>(paths: I) -> Vec<(PathBuf, Result<Vec<Section>, Error>)>
+where
+    I: IntoIterator<Item = PathBuf>,
+{
+    use rayon::prelude::*;
+    paths
+        .into_iter()
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .map(|path| {
+            let records = probe_records(&path);
+            (path, records)
+        })
+        .collect()
+}
+
+/// Options for extracting probe records from an object file, used with [`probe_records_with`].
+///
+/// By default, the byte order and pointer width of the probe records are detected from the
+/// object file, and the records are read from the section used by this crate on the object's
+/// platform. Each of these may be overridden, for example when analyzing a core dump or a binary
+/// built for another target, whose data model can't be inferred from the file itself.
+///
+/// DOF sections describe their own data model, so these options only apply to the probe records
+/// emitted by this crate.
+#[derive(Clone, Default)]
+pub struct ExtractOptions {
+    /// The byte order of the probe records.
+    pub endian: Option<Endian>,
+    /// The width of pointers on the target which emitted the probe records.
+    pub pointer_width: Option<PointerWidth>,
+    /// The name of the section containing the probe records, rather than `set_dtrace_probes` for
+    /// ELF or `__dtrace_probes` for Mach-O.
+    pub section_name: Option<String>,
+    /// The name of the Mach-O segment containing the probe record section. By default, the
+    /// section is found in any segment, preferring `__DATA` if more than one segment contains a
+    /// section of that name. This is ignored for ELF files, whose sections aren't in segments.
+    pub segment_name: Option<String>,
+    /// The names of any other sections containing probe records, such as those given to
+    /// providers with the `section` option. Records are read from each of these sections which
+    /// exists, as well as from the section above, and an error is only returned if none of them
+    /// exist.
+    pub extra_section_names: Vec<String>,
+    /// The maximum lengths of the names in the probe records, to match those of a specific
+    /// DTrace implementation. Longer names are truncated.
+    pub string_limits: StringLimits,
+    /// A function looking up the name of the function containing the address of each probe, in
+    /// place of `dladdr`, which only finds the names of addresses in the running process. See
+    /// [`SectionParser::symbolizer`], and [`dwarf_symbolizer`] for one reading the DWARF
+    /// debugging information of the binary. The module of each probe is still named after the
+    /// file.
+    pub symbolizer: Option<Arc<SharedSymbolizer>>,
+}
+
+/// A [`Symbolizer`] which may be shared between threads, as used by [`ExtractOptions`].
+pub type SharedSymbolizer = dyn Fn(u64) -> (Option<String>, Option<String>) + Send + Sync;
+
+impl std::fmt::Debug for ExtractOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExtractOptions")
+            .field("endian", &self.endian)
+            .field("pointer_width", &self.pointer_width)
+            .field("section_name", &self.section_name)
+            .field("segment_name", &self.segment_name)
+            .field("extra_section_names", &self.extra_section_names)
+            .field("string_limits", &self.string_limits)
+            .field("symbolizer", &self.symbolizer.is_some())
+            .finish()
     }
+}
+
+/// Extract embedded USDT probe records from a file, with the given options.
+///
+/// This is the same as [`probe_records`], but allows overriding the detected data model of the
+/// object file, the name of the section containing the records, or how the functions containing
+/// the probes are named.
+pub fn probe_records_with<P: AsRef<Path>>(
+    path: P,
+    options: &ExtractOptions,
+) -> Result<Vec<Section>, Error> {
+    let path = path.as_ref();
+    let mut sections = extract_probe_sections(path, options, &|_| true)?;
+    set_probe_modules(path, &mut sections);
+    Ok(sections)
+}
 
-    // File contains no DOF data. Try to parse out the ASM records inserted by the `usdt` crate.
+/// Extract embedded USDT probe records from a file, keeping only the providers selected by a
+/// filter.
+///
+/// This is the same as [`probe_records`], but the filter is called with the name of the provider
+/// of each record, and the records of other providers are skipped as they're read, rather than
+/// being parsed in full. This saves work and memory when a binary contains many providers, but a
+/// tool only needs a few of them.
+pub fn probe_records_filtered<P: AsRef<Path>, F: Fn(&str) -> bool>(
+    path: P,
+    filter: F,
+) -> Result<Vec<Section>, Error> {
+    let path = path.as_ref();
+    let mut sections = extract_probe_sections(path, &ExtractOptions::default(), &filter)?;
+    set_probe_modules(path, &mut sections);
+    Ok(sections)
+}
+
+/// Extract embedded USDT probe records from a file, using a memory-mapping of the file.
+///
+/// This returns the same records as [`probe_records`], but maps the entire file into memory once
+/// and processes the DOF sections or probe records directly over the mapping, rather than reading
+/// or copying them. This is useful for tools which quickly scan many large binaries.
+///
+/// The mapping is private and copy-on-write, so the file is never modified. On platforms which
+/// don't support memory-mapping files, the file contents are read into memory instead.
+pub fn probe_records_mmap<P: AsRef<Path>>(path: P) -> Result<Vec<Section>, Error> {
+    let path = path.as_ref();
     let file = OpenOptions::new().read(true).create(false).open(path)?;
-    let (offset, len) = locate_probe_section(&file).ok_or(Error::InvalidFile)?;
+    if file.metadata()?.len() == 0 {
+        return Err(Error::InvalidFile);
+    }
+    let mut map = map_file(&file)?;
 
-    // Remap only the probe section itself as mutable, using a private
-    // copy-on-write mapping to avoid writing to disk in any circumstance.
-    let mut map = unsafe { MmapOptions::new().offset(offset).len(len).map_copy(&file)? };
-    usdt_impl::record::process_section(&mut map, /* register = */ false).map(|s| vec![s])
+    let dof_sections =
+        dof::extract_dof_sections_from_bytes(&map).map_err(|_| Error::InvalidFile)?;
+    let mut sections = if !dof_sections.is_empty() {
+        non_empty_sections(dof_sections)
+    } else {
+        let location =
+            locate_probe_section(&map, &ExtractOptions::default()).ok_or(Error::InvalidFile)?;
+        if location.len == 0 {
+            return Ok(vec![]);
+        }
+        let start = usize::try_from(location.offset).map_err(|_| Error::InvalidFile)?;
+        let data = start
+            .checked_add(location.len)
+            .and_then(|end| map.get_mut(start..end))
+            .ok_or(Error::InvalidFile)?;
+        location.process(data, StringLimits::default(), &|_| true, None)?
+    };
+    set_probe_modules(path, &mut sections);
+    Ok(sections)
 }
 
-// Return the offset and size of the file's probe record section, if it exists.
-fn locate_probe_section(file: &File) -> Option<(u64, usize)> {
-    let map = unsafe { Mmap::map(file) }.ok()?;
-    match Object::parse(&map).ok()? {
-        Object::Elf(object) => {
-            // Try to find our special `set_dtrace_probes` section from the section headers. These
-            // may not exist, e.g., if the file has been stripped. In that case, we look for the
-            // special __start and __stop symbols themselves.
-            if let Some(section) = object.section_headers.iter().find(|header| {
-                if let Some(name) = object.shdr_strtab.get_at(header.sh_name) {
-                    name == "set_dtrace_probes"
-                } else {
-                    false
-                }
-            }) {
-                Some((section.sh_offset, section.sh_size as usize))
-            } else {
-                // Failed to look up the section directly, iterate over the symbols.
-                let mut bounds = object.syms.iter().filter(|symbol| {
-                    matches!(
-                        object.strtab.get_at(symbol.st_name),
-                        Some("__start_set_dtrace_probes") | Some("__stop_set_dtrace_probes")
-                    )
-                });
+/// Extract embedded USDT probe records from an object file already read into memory.
+///
+/// This parses the buffer as a complete ELF or Mach-O object file, in the same way as
+/// [`probe_records`], for example when a binary is piped in from another tool or embedded in
+/// another file. As there's no file name, the module of each probe is left empty.
+pub fn probe_records_from_bytes(data: &[u8]) -> Result<Vec<Section>, Error> {
+    let dof_sections =
+        dof::extract_dof_sections_from_bytes(data).map_err(|_| Error::InvalidFile)?;
+    if !dof_sections.is_empty() {
+        return Ok(non_empty_sections(dof_sections));
+    }
 
-                if let (Some(start), Some(stop)) = (bounds.next(), bounds.next()) {
-                    Some((start.st_value, (stop.st_value - start.st_value) as usize))
-                } else {
-                    None
-                }
+    let location =
+        locate_probe_section(data, &ExtractOptions::default()).ok_or(Error::InvalidFile)?;
+    if location.len == 0 {
+        return Ok(vec![]);
+    }
+
+    // Processing the records requires a mutable buffer, so copy out only the section itself.
+    let start = usize::try_from(location.offset).map_err(|_| Error::InvalidFile)?;
+    let mut section = start
+        .checked_add(location.len)
+        .and_then(|end| data.get(start..end))
+        .ok_or(Error::InvalidFile)?
+        .to_vec();
+    location.process(&mut section, StringLimits::default(), &|_| true, None)
+}
+
+/// Extract the USDT probe records of an executable from a core dump of a process running it.
+///
+/// Core files have no section headers or symbols, so the probe records can't be found from the
+/// core alone. Instead, the address of the probe record section is looked up in the executable,
+/// and adjusted by the address at which the executable was loaded, as recorded in the `NT_FILE`
+/// note of the core. If the core has no such note, the executable is assumed to be loaded at the
+/// address it was linked at. The records are then read from the loadable segment of the core
+/// containing that address.
+///
+/// The records are read as they were in the memory of the process, where they have usually been
+/// registered already. These are returned all the same. The module of each probe is set to the
+/// name of the executable.
+pub fn probe_records_from_core<P: AsRef<Path>, Q: AsRef<Path>>(
+    core: P,
+    executable: Q,
+) -> Result<Vec<Section>, Error> {
+    let executable = executable.as_ref();
+    let exe_data = fs::read(executable)?;
+    let exe = Elf::parse(&exe_data).map_err(|_| Error::InvalidFile)?;
+    let (address, len) = locate_probe_address(&exe).ok_or(Error::InvalidFile)?;
+    if len == 0 {
+        return Ok(vec![]);
+    }
+    let format = RecordFormat {
+        endian: if exe.little_endian {
+            Endian::Little
+        } else {
+            Endian::Big
+        },
+        pointer_width: if exe.is_64 {
+            PointerWidth::Bits64
+        } else {
+            PointerWidth::Bits32
+        },
+    };
+
+    let core_data = fs::read(core)?;
+    let core = Elf::parse(&core_data).map_err(|_| Error::InvalidFile)?;
+    if core.header.e_type != header::ET_CORE {
+        return Err(Error::InvalidFile);
+    }
+    let address = load_bias(&core, &core_data, &exe, executable)
+        .and_then(|bias| address.checked_add(bias))
+        .ok_or(Error::InvalidFile)?;
+
+    // Copy the records out of the segment containing them, as processing them requires a mutable
+    // buffer.
+    let mut data = core
+        .program_headers
+        .iter()
+        .filter(|phdr| phdr.p_type == program_header::PT_LOAD)
+        .find_map(|phdr| {
+            let start = address.checked_sub(phdr.p_vaddr)?;
+            let end = start.checked_add(len as u64)?;
+            if end > phdr.p_filesz {
+                return None;
             }
+            let offset = usize::try_from(phdr.p_offset.checked_add(start)?).ok()?;
+            core_data.get(offset..offset.checked_add(len)?)
+        })
+        .ok_or(Error::InvalidFile)?
+        .to_vec();
+    usdt_impl::record::reset_registered_records(&mut data, format);
+    let mut sections = non_empty_sections(vec![usdt_impl::record::process_section_with(
+        &mut data, /* register = */ false, format,
+    )?]);
+    set_probe_modules(executable, &mut sections);
+    Ok(sections)
+}
+
+/// A single probe site at its address in a running process, as returned by
+/// [`runtime_probe_sites`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProbeSite {
+    /// The name of the provider.
+    pub provider: String,
+    /// The name of the probe.
+    pub probe: String,
+    /// The runtime address of the probe site.
+    pub address: u64,
+    /// Whether this is the site of the probe's is-enabled check, rather than the probe itself.
+    pub is_enabled: bool,
+}
+
+/// Translate the probe sites of a section extracted from an object file into their addresses in
+/// a process which loaded the object file.
+///
+/// The probe addresses in the section are those the object file was linked at, and each site is
+/// at an offset from the address of its probe. `load_bias` is the difference between the address
+/// the object file was loaded at, e.g., after address space layout randomization, and the address
+/// it was linked at, as returned by [`object_load_bias`]. The sites are returned in the order of
+/// their providers and probes, with each probe's sites before its is-enabled sites.
+pub fn runtime_probe_sites(section: &Section, load_bias: u64) -> Vec<ProbeSite> {
+    let mut sites = Vec::new();
+    for provider in section.providers.values() {
+        for probe in provider.probes.values() {
+            let offsets = probe.offsets.iter().map(|offset| (offset, false));
+            let enabled_offsets = probe.enabled_offsets.iter().map(|offset| (offset, true));
+            sites.extend(offsets.chain(enabled_offsets).map(|(offset, is_enabled)| {
+                ProbeSite {
+                    provider: provider.name.clone(),
+                    probe: probe.name.clone(),
+                    address: probe
+                        .address
+                        .wrapping_add(u64::from(*offset))
+                        .wrapping_add(load_bias),
+                    is_enabled,
+                }
+            }));
         }
-        Object::Mach(goblin::mach::Mach::Binary(object)) => {
-            // Try to find our special `__dtrace_probes` section from the section headers.
-            for (section, _) in object.segments.sections().flatten().flatten() {
-                if section.sectname.starts_with(b"__dtrace_probes") {
-                    return Some((section.offset as u64, section.size as usize));
+    }
+    sites
+}
+
+/// Return the difference between the address at which an ELF object file was loaded, and the
+/// address it was linked at, for use with [`runtime_probe_sites`].
+///
+/// `load_base` is the address at which the start of the file is mapped, such as the start of the
+/// mapping of the file at offset zero in `/proc/<pid>/maps`, or the `dli_fbase` returned by
+/// `dladdr` for a symbol in the file. The result is zero for an executable which isn't
+/// position-independent, as it's always loaded at its linked address.
+pub fn object_load_bias<P: AsRef<Path>>(path: P, load_base: u64) -> Result<u64, Error> {
+    let data = fs::read(path)?;
+    let object = Elf::parse(&data).map_err(|_| Error::InvalidFile)?;
+    load_base
+        .checked_sub(linked_base(&object))
+        .ok_or(Error::InvalidFile)
+}
+
+/// Counts of the probes in a set of extracted sections, as returned by [`probe_records_summary`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RecordsSummary {
+    /// The number of probes in all sections.
+    pub probes: usize,
+    /// The number of probes whose function name couldn't be resolved, and which are named by
+    /// their address instead, e.g., `?0x1234`.
+    pub unresolved_functions: usize,
+}
+
+/// Count the probes in a set of sections, and those whose function names couldn't be resolved.
+///
+/// The function names of probe records are resolved from their addresses in the running process,
+/// so names are usually only found when extracting the records of the running binary, or of a
+/// library it has loaded. Tools may use this to warn when most names are missing, for example
+/// because the records were extracted from a different binary.
+pub fn probe_records_summary(sections: &[Section]) -> RecordsSummary {
+    sections
+        .iter()
+        .flat_map(|section| section.providers.values())
+        .flat_map(|provider| provider.probes.values())
+        .fold(RecordsSummary::default(), |summary, probe| RecordsSummary {
+            probes: summary.probes + 1,
+            unresolved_functions: summary.unresolved_functions
+                + usize::from(probe.function.starts_with("?0x")),
+        })
+}
+
+/// Resolve the function names of probes from the DWARF debugging information of an ELF binary.
+///
+/// The function names of probe records are resolved from the symbol table of the running process,
+/// which may not name the functions containing the probes, e.g., when the records are extracted
+/// from a binary other than the running one, or where the probe is inlined into another function.
+/// This consults the `DW_TAG_subprogram` and `DW_TAG_inlined_subroutine` entries of the binary's
+/// debugging information instead, and renames each probe whose function is still named by its
+/// address, e.g., `?0x1234`, for the innermost function containing that address. The linkage
+/// name of a function is preferred over its plain name, matching the names of the symbol table.
+///
+/// Split DWARF units, whose debugging information is in separate files, are read from the DWARF
+/// package named after the binary with the `.dwp` extension appended, e.g., `app.dwp`, if it
+/// exists. Otherwise each is read from the `.dwo` file named by its skeleton unit, relative to its
+/// compilation directory or the directory of the binary. Units whose files can't be found are
+/// skipped. This returns the number of probes renamed, and is only available with the `dwarf`
+/// feature, since it's considerably heavier than reading the probe records alone.
+#[cfg(feature = "dwarf")]
+pub fn resolve_dwarf_function_names<P: AsRef<Path>>(
+    path: P,
+    sections: &mut [Section],
+) -> Result<usize, Error> {
+    let path = path.as_ref();
+    let data = fs::read(path)?;
+    let object = Elf::parse(&data).map_err(|_| Error::InvalidFile)?;
+    let functions = dwarf_functions(path, &data, &object)?;
+    let mut resolved = 0;
+    for probe in sections
+        .iter_mut()
+        .flat_map(|section| section.providers.values_mut())
+        .flat_map(|provider| provider.probes.values_mut())
+        .filter(|probe| probe.function.starts_with("?0x"))
+    {
+        if let Some(name) = innermost_function(&functions, probe.address) {
+            probe.function = name.to_string();
+            resolved += 1;
+        }
+    }
+    Ok(resolved)
+}
+
+/// Return a symbolizer naming the functions containing probes from the DWARF debugging
+/// information of an ELF binary, for use in [`ExtractOptions::symbolizer`].
+///
+/// The debugging information is read once, in the same way as
+/// [`resolve_dwarf_function_names`], and each address is then named for the innermost function
+/// containing it, so that the probe records have the right function names as they're extracted.
+/// The module of each address is the binary itself. This is only available with the `dwarf`
+/// feature.
+#[cfg(feature = "dwarf")]
+pub fn dwarf_symbolizer<P: AsRef<Path>>(path: P) -> Result<Arc<SharedSymbolizer>, Error> {
+    let path = path.as_ref();
+    let data = fs::read(path)?;
+    let object = Elf::parse(&data).map_err(|_| Error::InvalidFile)?;
+    let functions = dwarf_functions(path, &data, &object)?;
+    let module = path.to_string_lossy().into_owned();
+    Ok(Arc::new(move |address| {
+        match innermost_function(&functions, address) {
+            Some(name) => (Some(name.to_string()), Some(module.clone())),
+            None => (None, None),
+        }
+    }))
+}
+
+// Return the name of the innermost function containing an address, from the function ranges
+// returned by `dwarf_functions`.
+#[cfg(feature = "dwarf")]
+fn innermost_function(functions: &[(u64, u64, String)], address: u64) -> Option<&str> {
+    functions
+        .iter()
+        .filter(|(begin, end, _)| (*begin..*end).contains(&address))
+        .min_by_key(|(begin, end, _)| end - begin)
+        .map(|(_, _, name)| name.as_str())
+}
+
+// Return the address ranges of the functions described by the DWARF sections of an ELF object,
+// as (begin, end, name), with the end exclusive.
+//
+// The split units of the object are read from a DWARF package named after the object with the
+// `.dwp` extension appended, if it exists, or from the `.dwo` file named by each skeleton unit.
+#[cfg(feature = "dwarf")]
+fn dwarf_functions(
+    path: &Path,
+    data: &[u8],
+    object: &Elf<'_>,
+) -> Result<Vec<(u64, u64, String)>, Error> {
+    let sections = gimli::Dwarf::load(|id| dwarf_section(data, object, Some(id.name())))?;
+    let endian = dwarf_endian(object);
+    let dwarf = sections.borrow(|section| gimli::EndianSlice::new(section, endian));
+
+    let mut package_path = path.as_os_str().to_owned();
+    package_path.push(".dwp");
+    let package_data = fs::read(package_path).ok();
+    let package_object = package_data
+        .as_deref()
+        .map(Elf::parse)
+        .transpose()
+        .map_err(|_| Error::InvalidFile)?;
+    let package_sections = match (&package_data, &package_object) {
+        (Some(data), Some(object)) => DWARF_PACKAGE_SECTIONS
+            .iter()
+            .map(|id| Ok((*id, dwarf_section(data, object, id.dwo_name())?)))
+            .collect::<Result<Vec<_>, Error>>()?,
+        _ => Vec::new(),
+    };
+    let package = match &package_object {
+        Some(object) => {
+            let endian = dwarf_endian(object);
+            let package = gimli::DwarfPackage::load(
+                |id| -> Result<_, gimli::Error> {
+                    let section = package_sections
+                        .iter()
+                        .find(|(section_id, _)| *section_id == id)
+                        .map_or(&[][..], |(_, section)| section);
+                    Ok(gimli::EndianSlice::new(section, endian))
+                },
+                gimli::EndianSlice::new(&[], endian),
+            );
+            Some(package.map_err(|_| Error::InvalidFile)?)
+        }
+        None => None,
+    };
+
+    let mut functions = Vec::new();
+    let mut headers = dwarf.units();
+    while let Some(header) = headers.next().map_err(|_| Error::InvalidFile)? {
+        if let gimli::UnitType::SplitCompilation(_) = header.type_() {
+            continue;
+        }
+        let unit = dwarf.unit(header).map_err(|_| Error::InvalidFile)?;
+        // Skeleton units have the identifier of their split unit, either in their header, or as
+        // an attribute with the GNU extension to DWARF 4.
+        match unit.dwo_id {
+            Some(dwo_id) => {
+                let split = match &package {
+                    Some(package) => package
+                        .find_cu(dwo_id, &dwarf)
+                        .map_err(|_| Error::InvalidFile)?
+                        .map(|split| split_unit_functions(&split, &unit)),
+                    None => None,
+                };
+                match split {
+                    Some(split) => functions.extend(split.map_err(|_| Error::InvalidFile)?),
+                    None => functions.extend(dwo_functions(path, &dwarf, &unit)?),
                 }
             }
+            None => functions
+                .extend(dwarf_unit_functions(&dwarf, &unit).map_err(|_| Error::InvalidFile)?),
+        }
+    }
+    Ok(functions)
+}
 
-            // Failed to look up the section directly, iterate over the symbols.
-            if let Some(syms) = object.symbols {
-                let mut bounds = syms.iter().filter_map(|symbol| {
-                    if let Ok((name, nlist)) = symbol {
-                        if name.contains("__dtrace_probes") {
-                            Some(nlist.n_value)
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                });
-                if let (Some(start), Some(stop)) = (bounds.next(), bounds.next()) {
-                    Some((start, (stop - start) as usize))
-                } else {
-                    None
-                }
+// The sections of a DWARF package, which are needed to find the split units it contains.
+#[cfg(feature = "dwarf")]
+const DWARF_PACKAGE_SECTIONS: [gimli::SectionId; 11] = [
+    gimli::SectionId::DebugCuIndex,
+    gimli::SectionId::DebugTuIndex,
+    gimli::SectionId::DebugAbbrev,
+    gimli::SectionId::DebugInfo,
+    gimli::SectionId::DebugLine,
+    gimli::SectionId::DebugStr,
+    gimli::SectionId::DebugStrOffsets,
+    gimli::SectionId::DebugLoc,
+    gimli::SectionId::DebugLocLists,
+    gimli::SectionId::DebugRngLists,
+    gimli::SectionId::DebugTypes,
+];
+
+// Return the byte order of the DWARF information of an ELF object.
+#[cfg(feature = "dwarf")]
+fn dwarf_endian(object: &Elf<'_>) -> gimli::RunTimeEndian {
+    if object.little_endian {
+        gimli::RunTimeEndian::Little
+    } else {
+        gimli::RunTimeEndian::Big
+    }
+}
+
+// Return the contents of the named DWARF section of an ELF object, decompressing it if needed.
+// Missing sections are empty.
+#[cfg(feature = "dwarf")]
+fn dwarf_section<'a>(
+    data: &'a [u8],
+    object: &Elf<'_>,
+    name: Option<&str>,
+) -> Result<std::borrow::Cow<'a, [u8]>, Error> {
+    use std::borrow::Cow;
+
+    let header = object.section_headers.iter().find(|header| {
+        name.is_some()
+            && object.shdr_strtab.get_at(header.sh_name) == name
+            && header.sh_type != goblin::elf::section_header::SHT_NOBITS
+    });
+    let header = match header {
+        Some(header) => header,
+        None => return Ok(Cow::Borrowed(&[])),
+    };
+    let start = usize::try_from(header.sh_offset).map_err(|_| Error::InvalidFile)?;
+    let len = usize::try_from(header.sh_size).map_err(|_| Error::InvalidFile)?;
+    let bytes = start
+        .checked_add(len)
+        .and_then(|end| data.get(start..end))
+        .ok_or(Error::InvalidFile)?;
+    if header.sh_flags & u64::from(SHF_COMPRESSED) != 0 {
+        let ctx = Ctx::new(
+            if object.is_64 {
+                Container::Big
             } else {
-                None
-            }
+                Container::Little
+            },
+            if object.little_endian {
+                goblin::container::Endian::Little
+            } else {
+                goblin::container::Endian::Big
+            },
+        );
+        Ok(Cow::Owned(decompress_section(bytes, ctx)?))
+    } else {
+        Ok(Cow::Borrowed(bytes))
+    }
+}
+
+// Return the functions of the split unit of a skeleton unit, read from the `.dwo` file it names.
+//
+// The file is looked for relative to the unit's compilation directory, and then to the directory
+// of the object. Units whose file can't be found are skipped, as the debugging information of
+// the object is then incomplete rather than invalid.
+#[cfg(feature = "dwarf")]
+fn dwo_functions(
+    path: &Path,
+    dwarf: &gimli::Dwarf<DwarfReader<'_>>,
+    skeleton: &gimli::Unit<DwarfReader<'_>>,
+) -> Result<Vec<(u64, u64, String)>, Error> {
+    let name = match skeleton.dwo_name().map_err(|_| Error::InvalidFile)? {
+        Some(name) => dwarf
+            .attr_string(skeleton, name)
+            .map(|name| name.to_string_lossy().into_owned())
+            .map_err(|_| Error::InvalidFile)?,
+        None => return Ok(Vec::new()),
+    };
+    let comp_dir = skeleton
+        .comp_dir
+        .as_ref()
+        .map(|dir| PathBuf::from(dir.to_string_lossy().as_ref()));
+    let data = match comp_dir
+        .into_iter()
+        .chain(path.parent().map(Path::to_path_buf))
+        .find_map(|dir| fs::read(dir.join(&name)).ok())
+    {
+        Some(data) => data,
+        None => return Ok(Vec::new()),
+    };
+    let object = Elf::parse(&data).map_err(|_| Error::InvalidFile)?;
+    let sections = gimli::Dwarf::load(|id| dwarf_section(&data, &object, id.dwo_name()))?;
+    let endian = dwarf_endian(&object);
+    let mut split = sections.borrow(|section| gimli::EndianSlice::new(section, endian));
+    split.make_dwo(dwarf);
+    split_unit_functions(&split, skeleton).map_err(|_| Error::InvalidFile)
+}
+
+// The reader of DWARF sections, borrowed from the data of an object.
+#[cfg(feature = "dwarf")]
+type DwarfReader<'a> = gimli::EndianSlice<'a, gimli::RunTimeEndian>;
+
+// Collect the address ranges of the functions in the split unit of a skeleton unit, given the
+// DWARF information containing the split unit.
+#[cfg(feature = "dwarf")]
+fn split_unit_functions<R: gimli::Reader>(
+    split: &gimli::Dwarf<R>,
+    skeleton: &gimli::Unit<R>,
+) -> Result<Vec<(u64, u64, String)>, gimli::Error> {
+    let mut functions = Vec::new();
+    let mut headers = split.units();
+    while let Some(header) = headers.next()? {
+        let mut unit = split.unit(header)?;
+        if unit.dwo_id != skeleton.dwo_id {
+            continue;
         }
-        _ => None,
+        unit.copy_relocated_attributes(skeleton);
+        functions.extend(dwarf_unit_functions(split, &unit)?);
+    }
+    Ok(functions)
+}
+
+// Collect the address ranges of the functions in a unit of the DWARF information.
+#[cfg(feature = "dwarf")]
+fn dwarf_unit_functions<R: gimli::Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+) -> Result<Vec<(u64, u64, String)>, gimli::Error> {
+    let mut functions = Vec::new();
+    let mut entries = unit.entries();
+    while let Some((_, entry)) = entries.next_dfs()? {
+        if !matches!(
+            entry.tag(),
+            gimli::DW_TAG_subprogram | gimli::DW_TAG_inlined_subroutine
+        ) {
+            continue;
+        }
+        let name = match dwarf_entry_name(dwarf, unit, entry, 0)? {
+            Some(name) => name,
+            None => continue,
+        };
+        let mut ranges = dwarf.die_ranges(unit, entry)?;
+        while let Some(range) = ranges.next()? {
+            if range.begin < range.end {
+                functions.push((range.begin, range.end, name.clone()));
+            }
+        }
+    }
+    Ok(functions)
+}
+
+// Return the name of a function's entry, following its abstract origin or specification, which
+// name inlined and out-of-line instances of a function.
+#[cfg(feature = "dwarf")]
+fn dwarf_entry_name<R: gimli::Reader>(
+    dwarf: &gimli::Dwarf<R>,
+    unit: &gimli::Unit<R>,
+    entry: &gimli::DebuggingInformationEntry<'_, '_, R>,
+    depth: usize,
+) -> Result<Option<String>, gimli::Error> {
+    // Limit the references followed, in case they form a cycle.
+    const MAX_DEPTH: usize = 8;
+
+    for attr in [
+        gimli::DW_AT_linkage_name,
+        gimli::DW_AT_MIPS_linkage_name,
+        gimli::DW_AT_name,
+    ] {
+        if let Some(value) = entry.attr_value(attr)? {
+            let name = dwarf.attr_string(unit, value)?;
+            return Ok(Some(name.to_string_lossy()?.into_owned()));
+        }
+    }
+    if depth < MAX_DEPTH {
+        for attr in [gimli::DW_AT_abstract_origin, gimli::DW_AT_specification] {
+            if let Some(gimli::AttributeValue::UnitRef(offset)) = entry.attr_value(attr)? {
+                let origin = unit.entry(offset)?;
+                return dwarf_entry_name(dwarf, unit, &origin, depth + 1);
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// A difference between the probes declared by a provider and those recorded in a binary, as
+/// returned by [`compare_probe_specs`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeMismatch {
+    /// A declared probe has no record, given as its specification, e.g., `"provider:::probe"`.
+    Missing(String),
+    /// A recorded probe of a declared provider isn't itself declared, given as its specification.
+    Unexpected(String),
+}
+
+/// Compare the probe specifications declared by providers with the probes in a set of sections.
+///
+/// The specifications are those of the `PROBE_SPECS` constant generated for each provider, e.g.,
+/// `"provider:::probe"`. Only the recorded probes of providers named by one of the specifications
+/// are compared, since a binary usually contains the probes of other providers as well. An empty
+/// list means that each declared probe is recorded, and no other probes of those providers are.
+pub fn compare_probe_specs<S: AsRef<str>>(specs: &[S], sections: &[Section]) -> Vec<ProbeMismatch> {
+    let recorded = sections
+        .iter()
+        .flat_map(|section| section.providers.values())
+        .flat_map(|provider| {
+            provider
+                .probes
+                .keys()
+                .map(move |probe| (provider.name.as_str(), probe.as_str()))
+        })
+        .collect::<BTreeSet<_>>();
+    let declared = specs
+        .iter()
+        .map(|spec| {
+            let spec = spec.as_ref();
+            spec.split_once(":::").unwrap_or((spec, ""))
+        })
+        .collect::<BTreeSet<_>>();
+    let providers = declared
+        .iter()
+        .map(|(provider, _)| *provider)
+        .collect::<BTreeSet<_>>();
+
+    let missing = declared
+        .difference(&recorded)
+        .map(|(provider, probe)| ProbeMismatch::Missing(format!("{}:::{}", provider, probe)));
+    let unexpected = recorded
+        .difference(&declared)
+        .filter(|(provider, _)| providers.contains(provider))
+        .map(|(provider, probe)| ProbeMismatch::Unexpected(format!("{}:::{}", provider, probe)));
+    missing.chain(unexpected).collect()
+}
+
+/// Compare the probe specifications declared by providers with the probes recorded in the running
+/// binary.
+///
+/// This extracts the probe records of the running binary, as with [`probe_records`], and compares
+/// them with the given specifications using [`compare_probe_specs`]. Declared probes missing from
+/// the binary usually mean that the record section was stripped or discarded by the linker, in
+/// which case DTrace can't see them either.
+///
+/// As with [`probe_records`], an error is returned if the binary has no probe record section,
+/// including when probes are emitted as SystemTap SDT notes.
+pub fn verify_probe_specs<S: AsRef<str>>(specs: &[S]) -> Result<Vec<ProbeMismatch>, Error> {
+    let sections = probe_records(std::env::current_exe()?)?;
+    Ok(compare_probe_specs(specs, &sections))
+}
+
+/// Strip the USDT probe records from an object file, writing the result to a new file.
+///
+/// This zeroes the contents of the probe record section, `set_dtrace_probes` for ELF or
+/// `__dtrace_probes` for Mach-O, leaving the layout of the file otherwise unchanged. A zeroed
+/// section contains no records, so no probes are registered by the resulting binary, and
+/// [`probe_records`] returns an empty list for it.
+///
+/// The permissions of the input file are copied to the output file. An error is returned if the
+/// input file has no probe record section.
+pub fn strip_probe_records<P: AsRef<Path>, Q: AsRef<Path>>(
+    in_path: P,
+    out_path: Q,
+) -> Result<(), Error> {
+    let in_path = in_path.as_ref();
+    let mut data = fs::read(in_path)?;
+    let location =
+        locate_probe_section(&data, &ExtractOptions::default()).ok_or(Error::InvalidFile)?;
+    let start = usize::try_from(location.offset).map_err(|_| Error::InvalidFile)?;
+    start
+        .checked_add(location.len)
+        .and_then(|end| data.get_mut(start..end))
+        .ok_or(Error::InvalidFile)?
+        .fill(0);
+    let out_path = out_path.as_ref();
+    fs::write(out_path, &data)?;
+    fs::set_permissions(out_path, fs::metadata(in_path)?.permissions())?;
+    Ok(())
+}
+
+// Set the module of each probe to the name of the file it was extracted from.
+fn set_probe_modules(path: &Path, sections: &mut [Section]) {
+    let module = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    for probe in sections
+        .iter_mut()
+        .flat_map(|section| section.providers.values_mut())
+        .flat_map(|provider| provider.probes.values_mut())
+    {
+        probe.module = module.clone();
+    }
+}
+
+// Map an entire file into memory, as a private copy-on-write mapping.
+#[cfg(any(unix, windows))]
+fn map_file(file: &File) -> Result<MmapMut, Error> {
+    Ok(unsafe { MmapOptions::new().map_copy(file)? })
+}
+
+// Platforms without support for memory-mapping fall back to reading the file.
+#[cfg(not(any(unix, windows)))]
+fn map_file(mut file: &File) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    std::io::Read::read_to_end(&mut file, &mut data)?;
+    Ok(data)
+}
+
+// Extract the probe records of the providers selected by a filter from a file.
+fn extract_probe_sections(
+    path: &Path,
+    options: &ExtractOptions,
+    filter: &dyn Fn(&str) -> bool,
+) -> Result<Vec<Section>, Error> {
+    // Extract DOF section data, which is applicable for an object file built using this crate on
+    // macOS, or generally using the platform's dtrace tool, i.e., `dtrace -G` and compiler.
+    let mut dof_sections = extract_dof_sections(path).map_err(|_| Error::InvalidFile)?;
+    if !dof_sections.is_empty() {
+        for section in dof_sections.iter_mut() {
+            section.providers.retain(|name, _| filter(name));
+        }
+        return Ok(non_empty_sections(dof_sections));
+    }
+
+    // File contains no DOF data. Try to parse out the ASM records inserted by the `usdt` crate,
+    // from each of the candidate sections.
+    let file = OpenOptions::new().read(true).create(false).open(path)?;
+    let locations = {
+        let map = unsafe { Mmap::map(&file) }.map_err(|_| Error::InvalidFile)?;
+        std::iter::once(options.section_name.as_deref())
+            .chain(
+                options
+                    .extra_section_names
+                    .iter()
+                    .map(|name| Some(name.as_str())),
+            )
+            .filter_map(|name| locate_named_probe_section(&map, options, name))
+            .collect::<Vec<_>>()
+    };
+    if locations.is_empty() {
+        return Err(Error::InvalidFile);
+    }
+
+    let mut sections = Vec::new();
+    for location in locations {
+        // The section may exist but be empty, for example if the `__start` and `__stop` symbols
+        // have the same value, or the section header has a size of zero. There are no records to
+        // extract in that case.
+        if location.len == 0 {
+            continue;
+        }
+
+        // Remap only the probe section itself as mutable, using a private
+        // copy-on-write mapping to avoid writing to disk in any circumstance.
+        let mut map = unsafe {
+            MmapOptions::new()
+                .offset(location.offset)
+                .len(location.len)
+                .map_copy(&file)?
+        };
+        sections.extend(
+            location.process(
+                &mut map,
+                options.string_limits,
+                filter,
+                options
+                    .symbolizer
+                    .as_deref()
+                    .map(|symbolizer| symbolizer as &Symbolizer),
+            )?,
+        );
+    }
+    Ok(sections)
+}
+
+// Return the virtual address and size of the probe record section of an ELF executable.
+fn locate_probe_address(object: &Elf<'_>) -> Option<(u64, usize)> {
+    let name = "set_dtrace_probes";
+    if let Some(section) = object
+        .section_headers
+        .iter()
+        .find(|header| object.shdr_strtab.get_at(header.sh_name) == Some(name))
+    {
+        return Some((section.sh_addr, usize::try_from(section.sh_size).ok()?));
+    }
+    let symbol = |symbol_name: String| {
+        object.syms.iter().find_map(|symbol| {
+            (object.strtab.get_at(symbol.st_name) == Some(symbol_name.as_str()))
+                .then_some(symbol.st_value)
+        })
+    };
+    let start = symbol(format!("__start_{}", name))?;
+    let stop = symbol(format!("__stop_{}", name))?;
+    Some((start, usize::try_from(stop.checked_sub(start)?).ok()?))
+}
+
+// Return the difference between the address at which an executable was loaded into the process
+// of a core dump, and the address it was linked at.
+//
+// This is found from the mapping of the start of the executable in the `NT_FILE` note of the
+// core, matched by file name. The executable is assumed to be loaded at its linked address if the
+// core has no such note.
+fn load_bias(core: &Elf<'_>, core_data: &[u8], exe: &Elf<'_>, path: &Path) -> Option<u64> {
+    let name = path.file_name()?;
+    let start = core
+        .iter_note_headers(core_data)
+        .into_iter()
+        .flatten()
+        .filter_map(|note| note.ok())
+        .filter(|note| note.n_type == note::NT_FILE && note.name == "CORE")
+        .find_map(|note| {
+            mapped_files(note.desc, core.is_64, core.little_endian)?
+                .into_iter()
+                .find(|(file, _, offset)| *offset == 0 && Path::new(file).file_name() == Some(name))
+                .map(|(_, start, _)| start)
+        });
+    let start = match start {
+        Some(start) => start,
+        None => return Some(0),
+    };
+    start.checked_sub(linked_base(exe))
+}
+
+// Return the address at which the start of an ELF object file is linked.
+//
+// The first page of the file is mapped at the start of its lowest loadable segment.
+fn linked_base(object: &Elf<'_>) -> u64 {
+    object
+        .program_headers
+        .iter()
+        .filter(|phdr| phdr.p_type == program_header::PT_LOAD)
+        .map(|phdr| phdr.p_vaddr & !(phdr.p_align.max(1) - 1))
+        .min()
+        .unwrap_or(0)
+}
+
+// Parse the description of an `NT_FILE` note, returning the path, start address and file offset
+// of each mapped file.
+fn mapped_files(desc: &[u8], is_64: bool, little_endian: bool) -> Option<Vec<(&str, u64, u64)>> {
+    let width = if is_64 { 8 } else { 4 };
+    let word = |index: usize| -> Option<u64> {
+        let bytes = desc.get(index * width..(index + 1) * width)?;
+        let mut value = [0u8; 8];
+        if little_endian {
+            value[..width].copy_from_slice(bytes);
+            Some(u64::from_le_bytes(value))
+        } else {
+            value[8 - width..].copy_from_slice(bytes);
+            Some(u64::from_be_bytes(value))
+        }
+    };
+
+    // The note contains the number of mappings and the page size, then the start, end and offset
+    // in pages of each mapping, followed by the paths of each.
+    let count = usize::try_from(word(0)?).ok()?;
+    let page_size = word(1)?;
+    let names_start = count.checked_mul(3)?.checked_add(2)?.checked_mul(width)?;
+    let mut names = desc.get(names_start..)?.split(|byte| *byte == 0);
+    (0..count)
+        .map(|i| {
+            let start = word(2 + 3 * i)?;
+            let offset = word(4 + 3 * i)?.checked_mul(page_size)?;
+            let name = std::str::from_utf8(names.next()?).ok()?;
+            Some((name, start, offset))
+        })
+        .collect()
+}
+
+// Remove any sections without probes, e.g., after the records have been stripped or their
+// providers filtered out, so that extracting the records of a file without probes always returns
+// an empty list.
+fn non_empty_sections(mut sections: Vec<Section>) -> Vec<Section> {
+    sections.retain(|section| !section.is_empty());
+    sections
+}
+
+// The location of the probe record section in an object file, and the format of its records.
+struct ProbeSection {
+    offset: u64,
+    len: usize,
+    format: RecordFormat,
+    // The layout of the object file's headers, if the section is compressed. This is needed to
+    // parse the compression header at the start of the section.
+    compression: Option<Ctx>,
+    // The relocations of a relocatable object's section, as the offset in the section of each
+    // relocated address and its value.
+    relocations: Vec<(usize, u64)>,
+}
+
+impl ProbeSection {
+    // Return the records of the providers selected by the filter in the section, given its
+    // contents in the object file.
+    //
+    // Processing the records requires a mutable buffer. Compressed sections are decompressed into
+    // a new buffer, and others are processed in place.
+    fn process(
+        &self,
+        data: &mut [u8],
+        limits: StringLimits,
+        filter: &dyn Fn(&str) -> bool,
+        symbolizer: Option<&Symbolizer>,
+    ) -> Result<Vec<Section>, Error> {
+        for (offset, value) in self.relocations.iter() {
+            let field = data
+                .get_mut(*offset..*offset + std::mem::size_of::<u64>())
+                .ok_or(Error::InvalidFile)?;
+            field.copy_from_slice(&match self.format.endian {
+                Endian::Little => value.to_le_bytes(),
+                Endian::Big => value.to_be_bytes(),
+            });
+        }
+        let mut decompressed;
+        let data = match self.compression {
+            Some(ctx) => {
+                decompressed = decompress_section(data, ctx)?;
+                &mut decompressed[..]
+            }
+            None => data,
+        };
+        let parser = SectionParser::with_format(data, /* register = */ false, self.format)
+            .limits(limits)
+            .provider_filter(filter);
+        let section = match symbolizer {
+            Some(symbolizer) => parser.symbolizer(symbolizer).into_section()?,
+            None => parser.into_section()?,
+        };
+        Ok(non_empty_sections(vec![section]))
+    }
+}
+
+// Decompress a section with the `SHF_COMPRESSED` flag, which starts with a compression header.
+//
+// Only zlib compression is supported, which is the format emitted by linkers with the
+// `--compress-debug-sections=zlib` option.
+fn decompress_section(data: &[u8], ctx: Ctx) -> Result<Vec<u8>, Error> {
+    let header = CompressionHeader::parse(data, 0, ctx).map_err(|_| Error::InvalidFile)?;
+    if header.ch_type != ELFCOMPRESS_ZLIB {
+        return Err(Error::InvalidFile);
+    }
+    let size = usize::try_from(header.ch_size).map_err(|_| Error::InvalidFile)?;
+    let compressed = data
+        .get(CompressionHeader::size(ctx)..)
+        .ok_or(Error::InvalidFile)?;
+    let decompressed = inflate(compressed, size)?;
+    if decompressed.len() != size {
+        return Err(Error::InvalidFile);
+    }
+    Ok(decompressed)
+}
+
+// Inflate zlib-compressed data, which decompresses to at most the given size.
+#[cfg(feature = "zlib")]
+fn inflate(compressed: &[u8], size: usize) -> Result<Vec<u8>, Error> {
+    miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(compressed, size)
+        .map_err(|_| Error::InvalidFile)
+}
+
+// Compressed sections are only supported with the `zlib` feature.
+#[cfg(not(feature = "zlib"))]
+fn inflate(_compressed: &[u8], _size: usize) -> Result<Vec<u8>, Error> {
+    Err(Error::InvalidFile)
+}
+
+// Return the location of the probe record section in the object file data, if it exists.
+//
+// The format of the records is detected from the object file, unless overridden in `options`.
+fn locate_probe_section(data: &[u8], options: &ExtractOptions) -> Option<ProbeSection> {
+    locate_named_probe_section(data, options, options.section_name.as_deref())
+}
+
+// Return the location of the named section of probe records in the object file data, or of the
+// section used by this crate on the object's platform if no name is given.
+fn locate_named_probe_section(
+    data: &[u8],
+    options: &ExtractOptions,
+    name: Option<&str>,
+) -> Option<ProbeSection> {
+    let mut relocations = Vec::new();
+    let (offset, len, little_endian, is_64, compression) = match Object::parse(data).ok()? {
+        Object::Elf(object) => {
+            let name = name.unwrap_or("set_dtrace_probes");
+
+            // Try to find our special `set_dtrace_probes` section from the section headers. These
+            // may not exist, e.g., if the file has been stripped. In that case, we look for the
+            // special __start and __stop symbols themselves.
+            let is_relocatable = object.header.e_type == header::ET_REL;
+            let (offset, len, compressed) = if let Some((index, section)) = object
+                .section_headers
+                .iter()
+                .enumerate()
+                .find(|(_, header)| object.shdr_strtab.get_at(header.sh_name) == Some(name))
+            {
+                let compressed = section.sh_flags & u64::from(SHF_COMPRESSED) != 0;
+                if is_relocatable && !compressed {
+                    relocations = section_relocations(data, &object, index, section.sh_offset);
+                }
+                (section.sh_offset, section.sh_size as usize, compressed)
+            } else if is_relocatable {
+                // The `__start` and `__stop` symbols are left undefined until the object is
+                // linked, so the records of a relocatable object can only be found by its
+                // section headers.
+                return None;
+            } else {
+                // Failed to look up the section directly, iterate over the symbols.
+                let start_symbol = format!("__start_{}", name);
+                let stop_symbol = format!("__stop_{}", name);
+                let mut bounds = object.syms.iter().filter(|symbol| {
+                    object
+                        .strtab
+                        .get_at(symbol.st_name)
+                        .map_or(false, |symbol| {
+                            symbol == start_symbol || symbol == stop_symbol
+                        })
+                });
+
+                if let (Some(start), Some(stop)) = (bounds.next(), bounds.next()) {
+                    (
+                        start.st_value,
+                        stop.st_value.checked_sub(start.st_value)? as usize,
+                        false,
+                    )
+                } else {
+                    return None;
+                }
+            };
+            let compression = compressed.then(|| {
+                let container = if object.is_64 {
+                    Container::Big
+                } else {
+                    Container::Little
+                };
+                let endian = if object.little_endian {
+                    goblin::container::Endian::Little
+                } else {
+                    goblin::container::Endian::Big
+                };
+                Ctx::new(container, endian)
+            });
+            (offset, len, object.little_endian, object.is_64, compression)
+        }
+        Object::Mach(goblin::mach::Mach::Binary(object)) => {
+            let name = name.unwrap_or("__dtrace_probes");
+
+            // Try to find our special `__dtrace_probes` section from the section headers. The same
+            // section name may appear in more than one segment, so only those in the requested
+            // segment are considered, or those in `__DATA` are preferred.
+            let sections = object
+                .segments
+                .sections()
+                .flatten()
+                .flatten()
+                .map(|(section, _)| section)
+                .filter(|section| section.name().ok() == Some(name))
+                .filter(|section| {
+                    options
+                        .segment_name
+                        .as_deref()
+                        .map_or(true, |segment| section.segname().ok() == Some(segment))
+                })
+                .collect::<Vec<_>>();
+            let section = sections
+                .iter()
+                .find(|section| section.segname().ok() == Some("__DATA"))
+                .or_else(|| sections.first());
+            let (offset, len) = if let Some(section) = section {
+                (section.offset as u64, section.size as usize)
+            } else if let Some(syms) = object.symbols {
+                // Failed to look up the section directly, iterate over the symbols.
+                let mut bounds = syms.iter().filter_map(|symbol| {
+                    if let Ok((symbol, nlist)) = symbol {
+                        if symbol.contains(name) {
+                            Some(nlist.n_value)
+                        } else {
+                            None
+                        }
+                    } else {
+                        None
+                    }
+                });
+                if let (Some(start), Some(stop)) = (bounds.next(), bounds.next()) {
+                    (start, (stop - start) as usize)
+                } else {
+                    return None;
+                }
+            } else {
+                return None;
+            };
+            (offset, len, object.little_endian, object.is_64, None)
+        }
+        _ => return None,
+    };
+    let format = RecordFormat {
+        endian: options.endian.unwrap_or(if little_endian {
+            Endian::Little
+        } else {
+            Endian::Big
+        }),
+        pointer_width: options.pointer_width.unwrap_or(if is_64 {
+            PointerWidth::Bits64
+        } else {
+            PointerWidth::Bits32
+        }),
+    };
+    Some(ProbeSection {
+        offset,
+        len,
+        format,
+        compression,
+        relocations,
+    })
+}
+
+// Return the relocations of the section of a relocatable ELF object with the given index and
+// offset in the file, as the offset in the section of each relocated address and its value.
+//
+// Only relocations to absolute 64-bit addresses are applied, which are those of the addresses in
+// probe records. The value is that of the symbol, which for symbols defined in a section of the
+// object is an offset into that section, plus the addend.
+fn section_relocations(
+    data: &[u8],
+    object: &Elf<'_>,
+    index: usize,
+    section_offset: u64,
+) -> Vec<(usize, u64)> {
+    object
+        .shdr_relocs
+        .iter()
+        .filter(|(reloc_index, _)| {
+            object
+                .section_headers
+                .get(*reloc_index)
+                .map_or(false, |header| header.sh_info as usize == index)
+        })
+        .flat_map(|(_, relocs)| relocs.iter())
+        .filter(|reloc| matches!(reloc.r_type, R_X86_64_64 | R_AARCH64_ABS64))
+        .filter_map(|reloc| {
+            let symbol = object.syms.get(reloc.r_sym)?;
+            let offset = usize::try_from(reloc.r_offset).ok()?;
+            // REL relocations store the addend in place.
+            let addend = match reloc.r_addend {
+                Some(addend) => addend,
+                None => {
+                    let start = usize::try_from(section_offset).ok()?.checked_add(offset)?;
+                    let field = data.get(start..start + std::mem::size_of::<u64>())?;
+                    let field = field.try_into().ok()?;
+                    if object.little_endian {
+                        i64::from_le_bytes(field)
+                    } else {
+                        i64::from_be_bytes(field)
+                    }
+                }
+            };
+            Some((offset, symbol.st_value.wrapping_add(addend as u64)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_provider;
+    use super::format_provider;
+    use super::probe_records_filtered;
+    use super::probe_records_from_core;
+    use super::strip_probe_records;
+    use super::{compare_probe_specs, ProbeMismatch};
+    #[cfg(feature = "dwarf")]
+    use super::{dwarf_symbolizer, resolve_dwarf_function_names};
+    use super::{hash_file, Builder};
+    use super::{object_load_bias, runtime_probe_sites, ProbeSite};
+    use super::{probe_records, probe_records_from_bytes, probe_records_mmap, probe_records_with};
+    use super::{probe_records_summary, RecordsSummary};
+    use super::{Endian, ExtractOptions, PointerWidth, StringLimits};
+    use super::{RecordBuilder, RecordFormat};
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    const ET_REL: u16 = 1;
+    const ET_EXEC: u16 = 2;
+
+    // A section included in a test object file.
+    struct TestSection<'a> {
+        name: &'a str,
+        data: &'a [u8],
+    }
+
+    // A relocation against a section of a test object file.
+    struct TestRelocation<'a> {
+        // The name of the section the relocation applies to.
+        section: &'a str,
+        offset: u64,
+        // The index of the symbol, where the first of the given symbols has index 1.
+        symbol: u32,
+        r_type: u32,
+        addend: i64,
+    }
+
+    // Build a minimal 64-bit little-endian ELF executable, containing the given sections and a
+    // symbol table with the given symbols.
+    fn build_elf(sections: &[TestSection<'_>], symbols: &[(&str, u64)]) -> Vec<u8> {
+        build_object(ET_EXEC, sections, symbols, &[])
+    }
+
+    // Build a minimal 64-bit little-endian ELF object file of the given type, containing the given
+    // sections, a symbol table with the given symbols, and a section of the given relocations.
+    fn build_object(
+        e_type: u16,
+        sections: &[TestSection<'_>],
+        symbols: &[(&str, u64)],
+        relocations: &[TestRelocation<'_>],
+    ) -> Vec<u8> {
+        const EHDR_SIZE: usize = 64;
+        const SHDR_SIZE: usize = 64;
+        const SYM_SIZE: usize = 24;
+        const RELA_SIZE: usize = 24;
+        const SHT_PROGBITS: u32 = 1;
+        const SHT_SYMTAB: u32 = 2;
+        const SHT_STRTAB: u32 = 3;
+        const SHT_RELA: u32 = 4;
+        const SYMTAB_INDEX: u32 = 3;
+
+        // Section header string table, with the fixed sections first.
+        let mut shstrtab = vec![0u8];
+        let mut add_name = |name: &str| {
+            let index = shstrtab.len() as u32;
+            shstrtab.extend_from_slice(name.as_bytes());
+            shstrtab.push(0);
+            index
+        };
+        let shstrtab_name = add_name(".shstrtab");
+        let strtab_name = add_name(".strtab");
+        let symtab_name = add_name(".symtab");
+        let section_names: Vec<_> = sections.iter().map(|s| add_name(s.name)).collect();
+        let rela_name = add_name(".rela");
+
+        // Symbol string table and symbol table, starting with the null symbol. In relocatable
+        // objects, the symbols are defined relative to the first of the given sections.
+        let st_shndx = if e_type == ET_EXEC {
+            0u16
+        } else {
+            SYMTAB_INDEX as u16 + 1
+        };
+        let mut strtab = vec![0u8];
+        let mut symtab = vec![0u8; SYM_SIZE];
+        for (name, value) in symbols.iter() {
+            let st_name = strtab.len() as u32;
+            strtab.extend_from_slice(name.as_bytes());
+            strtab.push(0);
+            symtab.extend_from_slice(&st_name.to_le_bytes());
+            symtab.push(0); // st_info
+            symtab.push(0); // st_other
+            symtab.extend_from_slice(&st_shndx.to_le_bytes());
+            symtab.extend_from_slice(&value.to_le_bytes());
+            symtab.extend_from_slice(&0u64.to_le_bytes()); // st_size
+        }
+
+        // Relocations, each against the section they are applied to.
+        let mut relas = Vec::new();
+        let mut rela_target = 0;
+        for reloc in relocations.iter() {
+            let index = sections
+                .iter()
+                .position(|s| s.name == reloc.section)
+                .expect("Relocation against an unknown section");
+            rela_target = index as u32 + SYMTAB_INDEX + 1;
+            relas.extend_from_slice(&reloc.offset.to_le_bytes());
+            let info = (u64::from(reloc.symbol) << 32) | u64::from(reloc.r_type);
+            relas.extend_from_slice(&info.to_le_bytes());
+            relas.extend_from_slice(&reloc.addend.to_le_bytes());
+        }
+
+        // Lay out the section data directly after the ELF header, each 8-byte aligned.
+        // Each entry is (name, type, data, link, info, entsize).
+        type Layout<'a> = (u32, u32, &'a [u8], u32, u32, u64);
+        let mut contents: Vec<Layout<'_>> = vec![
+            (shstrtab_name, SHT_STRTAB, &shstrtab, 0, 0, 0),
+            (strtab_name, SHT_STRTAB, &strtab, 0, 0, 0),
+            (symtab_name, SHT_SYMTAB, &symtab, 2, 0, SYM_SIZE as u64),
+        ];
+        for (section, name) in sections.iter().zip(section_names) {
+            contents.push((name, SHT_PROGBITS, section.data, 0, 0, 0));
+        }
+        if !relas.is_empty() {
+            contents.push((
+                rela_name,
+                SHT_RELA,
+                &relas,
+                SYMTAB_INDEX,
+                rela_target,
+                RELA_SIZE as u64,
+            ));
+        }
+        let mut data = vec![0u8; EHDR_SIZE];
+        let mut headers = vec![0u8; SHDR_SIZE]; // null section header
+        for (name, typ, bytes, link, info, entsize) in contents.iter() {
+            while data.len() % 8 != 0 {
+                data.push(0);
+            }
+            let offset = data.len() as u64;
+            data.extend_from_slice(bytes);
+            headers.extend_from_slice(&name.to_le_bytes());
+            headers.extend_from_slice(&typ.to_le_bytes());
+            headers.extend_from_slice(&0u64.to_le_bytes()); // sh_flags
+
+            // Executables are laid out as if loaded at address zero.
+            let addr = if e_type == ET_EXEC { offset } else { 0 };
+            headers.extend_from_slice(&addr.to_le_bytes()); // sh_addr
+            headers.extend_from_slice(&offset.to_le_bytes());
+            headers.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            headers.extend_from_slice(&link.to_le_bytes());
+            headers.extend_from_slice(&info.to_le_bytes());
+            headers.extend_from_slice(&8u64.to_le_bytes()); // sh_addralign
+            headers.extend_from_slice(&entsize.to_le_bytes());
+        }
+        while data.len() % 8 != 0 {
+            data.push(0);
+        }
+        let shoff = data.len() as u64;
+        data.extend_from_slice(&headers);
+
+        // Fill in the ELF header itself.
+        let mut ehdr = Vec::with_capacity(EHDR_SIZE);
+        ehdr.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+        ehdr.extend_from_slice(&[0; 8]);
+        ehdr.extend_from_slice(&e_type.to_le_bytes());
+        ehdr.extend_from_slice(&62u16.to_le_bytes()); // e_machine, EM_X86_64
+        ehdr.extend_from_slice(&1u32.to_le_bytes()); // e_version
+        ehdr.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+        ehdr.extend_from_slice(&0u64.to_le_bytes()); // e_phoff
+        ehdr.extend_from_slice(&shoff.to_le_bytes());
+        ehdr.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+        ehdr.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+        ehdr.extend_from_slice(&56u16.to_le_bytes()); // e_phentsize
+        ehdr.extend_from_slice(&0u16.to_le_bytes()); // e_phnum
+        ehdr.extend_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+        ehdr.extend_from_slice(&((contents.len() + 1) as u16).to_le_bytes());
+        ehdr.extend_from_slice(&1u16.to_le_bytes()); // e_shstrndx
+        data[..EHDR_SIZE].copy_from_slice(&ehdr);
+        data
+    }
+
+    // Build a minimal 64-bit little-endian Mach-O executable, with each of the given sections in
+    // its own segment, given as the segment name, section name and section data.
+    fn build_macho(sections: &[(&str, &str, &[u8])]) -> Vec<u8> {
+        const HEADER_SIZE: usize = 32;
+        const SEGMENT_SIZE: usize = 72;
+        const SECTION_SIZE: usize = 80;
+        const LC_SEGMENT_64: u32 = 0x19;
+
+        let name = |name: &str| {
+            let mut bytes = [0u8; 16];
+            bytes[..name.len()].copy_from_slice(name.as_bytes());
+            bytes
+        };
+        let cmdsize = SEGMENT_SIZE + SECTION_SIZE;
+        let mut offset = HEADER_SIZE + cmdsize * sections.len();
+        let mut commands = Vec::new();
+        let mut contents = Vec::new();
+        for (segname, sectname, data) in sections {
+            commands.extend_from_slice(&LC_SEGMENT_64.to_le_bytes());
+            commands.extend_from_slice(&(cmdsize as u32).to_le_bytes());
+            commands.extend_from_slice(&name(segname));
+            commands.extend_from_slice(&(offset as u64).to_le_bytes()); // vmaddr
+            commands.extend_from_slice(&(data.len() as u64).to_le_bytes()); // vmsize
+            commands.extend_from_slice(&(offset as u64).to_le_bytes()); // fileoff
+            commands.extend_from_slice(&(data.len() as u64).to_le_bytes()); // filesize
+            commands.extend_from_slice(&3u32.to_le_bytes()); // maxprot
+            commands.extend_from_slice(&3u32.to_le_bytes()); // initprot
+            commands.extend_from_slice(&1u32.to_le_bytes()); // nsects
+            commands.extend_from_slice(&0u32.to_le_bytes()); // flags
+
+            commands.extend_from_slice(&name(sectname));
+            commands.extend_from_slice(&name(segname));
+            commands.extend_from_slice(&(offset as u64).to_le_bytes()); // addr
+            commands.extend_from_slice(&(data.len() as u64).to_le_bytes()); // size
+            commands.extend_from_slice(&(offset as u32).to_le_bytes());
+            commands.extend_from_slice(&[0; 28]); // align through reserved3
+            contents.extend_from_slice(data);
+            offset += data.len();
+        }
+
+        let mut macho = Vec::with_capacity(offset);
+        macho.extend_from_slice(&0xfeed_facf_u32.to_le_bytes()); // MH_MAGIC_64
+        macho.extend_from_slice(&0x0100_0007_u32.to_le_bytes()); // CPU_TYPE_X86_64
+        macho.extend_from_slice(&3u32.to_le_bytes()); // cpusubtype
+        macho.extend_from_slice(&2u32.to_le_bytes()); // MH_EXECUTE
+        macho.extend_from_slice(&(sections.len() as u32).to_le_bytes()); // ncmds
+        macho.extend_from_slice(&(commands.len() as u32).to_le_bytes()); // sizeofcmds
+        macho.extend_from_slice(&0u32.to_le_bytes()); // flags
+        macho.extend_from_slice(&0u32.to_le_bytes()); // reserved
+        macho.extend_from_slice(&commands);
+        macho.extend_from_slice(&contents);
+        macho
+    }
+
+    // Build a minimal 64-bit little-endian ELF core file, with a single loadable segment at the
+    // given address containing the given data. If `mapped` is given, the core includes an
+    // `NT_FILE` note mapping the start of that file at the same address.
+    fn build_core(address: u64, data: &[u8], mapped: Option<&str>) -> Vec<u8> {
+        const EHDR_SIZE: usize = 64;
+        const PHDR_SIZE: usize = 56;
+        const ET_CORE: u16 = 4;
+        const PT_LOAD: u32 = 1;
+        const PT_NOTE: u32 = 4;
+        const NT_FILE: u32 = 0x4649_4c45;
+        const PAGE_SIZE: u64 = 0x1000;
+
+        let mut notes = Vec::new();
+        if let Some(path) = mapped {
+            let mut desc = Vec::new();
+            for word in [1, PAGE_SIZE, address, address + data.len() as u64, 0] {
+                desc.extend_from_slice(&word.to_le_bytes());
+            }
+            desc.extend_from_slice(path.as_bytes());
+            desc.push(0);
+            while desc.len() % 4 != 0 {
+                desc.push(0);
+            }
+            notes.extend_from_slice(&5u32.to_le_bytes()); // n_namesz
+            notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+            notes.extend_from_slice(&NT_FILE.to_le_bytes());
+            notes.extend_from_slice(b"CORE\0\0\0\0");
+            notes.extend_from_slice(&desc);
+        }
+
+        // Each entry is (type, offset, address, data), with the notes directly following the
+        // program headers, then the loadable segment.
+        let notes_offset = (EHDR_SIZE + 2 * PHDR_SIZE) as u64;
+        let load_offset = notes_offset + notes.len() as u64;
+        let segments = [
+            (PT_NOTE, notes_offset, 0, &notes[..]),
+            (PT_LOAD, load_offset, address, data),
+        ];
+
+        let mut core = Vec::with_capacity(load_offset as usize + data.len());
+        core.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+        core.extend_from_slice(&[0; 8]);
+        core.extend_from_slice(&ET_CORE.to_le_bytes());
+        core.extend_from_slice(&62u16.to_le_bytes()); // e_machine, EM_X86_64
+        core.extend_from_slice(&1u32.to_le_bytes()); // e_version
+        core.extend_from_slice(&0u64.to_le_bytes()); // e_entry
+        core.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // e_phoff
+        core.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+        core.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+        core.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+        core.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        core.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+        core.extend_from_slice(&64u16.to_le_bytes()); // e_shentsize
+        core.extend_from_slice(&0u16.to_le_bytes()); // e_shnum
+        core.extend_from_slice(&0u16.to_le_bytes()); // e_shstrndx
+        for (typ, offset, vaddr, bytes) in segments.iter() {
+            core.extend_from_slice(&typ.to_le_bytes());
+            core.extend_from_slice(&0u32.to_le_bytes()); // p_flags
+            core.extend_from_slice(&offset.to_le_bytes());
+            core.extend_from_slice(&vaddr.to_le_bytes());
+            core.extend_from_slice(&0u64.to_le_bytes()); // p_paddr
+            core.extend_from_slice(&(bytes.len() as u64).to_le_bytes()); // p_filesz
+            core.extend_from_slice(&(bytes.len() as u64).to_le_bytes()); // p_memsz
+            core.extend_from_slice(&4u64.to_le_bytes()); // p_align
+        }
+        for (_, _, _, bytes) in segments.iter() {
+            core.extend_from_slice(bytes);
+        }
+        core
+    }
+
+    // Write the object file to a unique path in the temporary directory.
+    fn write_object(name: &str, data: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("usdt-test-{}-{}.o", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    // Build a single probe record, in the format emitted by `usdt-impl`.
+    fn probe_record(provider: &str, probe: &str, address: u64, args: &[&str]) -> Vec<u8> {
+        let mut rec = vec![0u8; 4]; // length, filled in below
+        rec.push(1); // version
+        rec.push(args.len() as u8);
+        rec.extend_from_slice(&0u16.to_ne_bytes()); // flags
+        rec.extend_from_slice(&address.to_ne_bytes());
+        for s in [provider, probe].iter().chain(args.iter()) {
+            rec.extend_from_slice(s.as_bytes());
+            rec.push(0);
+        }
+        while rec.len() % 8 != 0 {
+            rec.push(0);
+        }
+        let len = rec.len() as u32;
+        rec[..4].copy_from_slice(&len.to_ne_bytes());
+        rec
+    }
+
+    #[test]
+    fn test_probe_records_module() {
+        let record = probe_record("foo", "bar", 0x1234, &["uint8_t"]);
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("module", &data);
+        let sections = probe_records(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let provider = &sections[0].providers["foo"];
+        let probe = &provider.probes["bar"];
+        let basename = path.file_name().unwrap().to_str().unwrap();
+        assert_eq!(probe.module, basename);
+        assert_eq!(
+            probe.full_name(provider),
+            format!("foo:{}:{}:bar", basename, probe.function)
+        );
+    }
+
+    #[test]
+    fn test_probe_records_filtered() {
+        let mut records = probe_record("foo", "bar", 0x1000, &["uint8_t"]);
+        records.extend(probe_record("qux", "baz", 0x2000, &[]));
+        records.extend(probe_record("quux", "corge", 0x3000, &[]));
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &records,
+            }],
+            &[],
+        );
+        let path = write_object("filtered", &data);
+        let sections = probe_records_filtered(&path, |provider| provider == "qux").unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(sections.len(), 1);
+        assert_eq!(
+            sections[0].providers.keys().collect::<Vec<_>>(),
+            vec!["qux"]
+        );
+        let probe = &sections[0].providers["qux"].probes["baz"];
+        assert_eq!(probe.address, 0x2000);
+        assert_eq!(probe.module, path.file_name().unwrap().to_str().unwrap());
+    }
+
+    #[test]
+    fn test_probe_records_with_symbolizer() {
+        let mut records = probe_record("foo", "known", 0x1000, &[]);
+        records.extend(probe_record("foo", "unknown", 0x2000, &[]));
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &records,
+            }],
+            &[],
+        );
+        let path = write_object("with-symbolizer", &data);
+        let options = ExtractOptions {
+            symbolizer: Some(Arc::new(|address| match address {
+                0x1000 => (
+                    Some(String::from("do_work")),
+                    Some(String::from("libwork.so")),
+                ),
+                _ => (None, None),
+            })),
+            ..Default::default()
+        };
+        let sections = probe_records_with(&path, &options);
+        std::fs::remove_file(&path).unwrap();
+
+        let probes = &sections.unwrap()[0].providers["foo"].probes;
+        assert_eq!(probes["known"].function, "do_work");
+        assert_eq!(probes["unknown"].function, "?0x2000");
+        assert_eq!(
+            probes["known"].module,
+            path.file_name().unwrap().to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_probe_records_with_endian() {
+        // Swap the multi-byte fields of the record, as if emitted by a target with the opposite
+        // byte order from the host and from the object file itself.
+        let mut record = probe_record("foo", "bar", 0x1234, &["uint8_t"]);
+        record[..4].reverse();
+        record[6..8].reverse();
+        record[8..16].reverse();
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("with-endian", &data);
+        let detected = probe_records(&path);
+        let options = ExtractOptions {
+            endian: Some(match Endian::native() {
+                Endian::Little => Endian::Big,
+                Endian::Big => Endian::Little,
+            }),
+            ..Default::default()
+        };
+        let overridden = probe_records_with(&path, &options);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(detected.is_err());
+        let sections = overridden.unwrap();
+        let probe = &sections[0].providers["foo"].probes["bar"];
+        assert_eq!(probe.address, 0x1234);
+        assert_eq!(probe.arguments, vec![String::from("uint8_t")]);
+    }
+
+    #[test]
+    fn test_probe_records_with_pointer_width() {
+        let record = probe_record("foo", "bar", 0xffff_ffff_0000_1234, &[]);
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("with-pointer-width", &data);
+        let detected = probe_records(&path).unwrap();
+        let options = ExtractOptions {
+            pointer_width: Some(PointerWidth::Bits32),
+            ..Default::default()
+        };
+        let overridden = probe_records_with(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            detected[0].providers["foo"].probes["bar"].address,
+            0xffff_ffff_0000_1234
+        );
+        assert_eq!(overridden[0].providers["foo"].probes["bar"].address, 0x1234);
+    }
+
+    #[test]
+    fn test_probe_records_with_section_name() {
+        let record = probe_record("foo", "bar", 0x1234, &[]);
+        let data = build_elf(
+            &[TestSection {
+                name: "my_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("with-section-name", &data);
+        let detected = probe_records(&path);
+        let options = ExtractOptions {
+            section_name: Some(String::from("my_probes")),
+            ..Default::default()
+        };
+        let overridden = probe_records_with(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(detected, Err(crate::Error::InvalidFile)));
+        assert!(overridden[0].providers["foo"].probes.contains_key("bar"));
+    }
+
+    #[test]
+    fn test_probe_records_macho_segment() {
+        // The section is found in a segment other than `__DATA`.
+        let record = probe_record("foo", "bar", 0x1234, &[]);
+        let data = build_macho(&[("__USDT", "__dtrace_probes", &record)]);
+        let sections = probe_records_from_bytes(&data).unwrap();
+        assert!(sections[0].providers["foo"].probes.contains_key("bar"));
+
+        // If more than one segment contains the section, `__DATA` is preferred, unless another
+        // segment is requested.
+        let other = probe_record("foo", "baz", 0x5678, &[]);
+        let data = build_macho(&[
+            ("__OTHER", "__dtrace_probes", &other),
+            ("__DATA", "__dtrace_probes", &record),
+        ]);
+        let sections = probe_records_from_bytes(&data).unwrap();
+        assert!(sections[0].providers["foo"].probes.contains_key("bar"));
+
+        let path = write_object("macho-segment", &data);
+        let options = ExtractOptions {
+            segment_name: Some(String::from("__OTHER")),
+            ..Default::default()
+        };
+        let overridden = probe_records_with(&path, &options);
+        let missing = probe_records_with(
+            &path,
+            &ExtractOptions {
+                segment_name: Some(String::from("__MISSING")),
+                ..Default::default()
+            },
+        );
+        std::fs::remove_file(&path).unwrap();
+        let overridden = overridden.unwrap();
+        assert!(overridden[0].providers["foo"].probes.contains_key("baz"));
+        assert!(!overridden[0].providers["foo"].probes.contains_key("bar"));
+        assert!(matches!(missing, Err(crate::Error::InvalidFile)));
+    }
+
+    #[test]
+    fn test_probe_records_compressed_section() {
+        let record = probe_record("foo", "bar", 0x1234, &["uint8_t"]);
+
+        // An `Elf64_Chdr`, followed by the zlib-compressed records.
+        let mut compressed = Vec::new();
+        compressed.extend_from_slice(&1u32.to_le_bytes()); // ch_type, ELFCOMPRESS_ZLIB
+        compressed.extend_from_slice(&0u32.to_le_bytes()); // ch_reserved
+        compressed.extend_from_slice(&(record.len() as u64).to_le_bytes()); // ch_size
+        compressed.extend_from_slice(&1u64.to_le_bytes()); // ch_addralign
+        compressed.extend_from_slice(&miniz_oxide::deflate::compress_to_vec_zlib(&record, 6));
+        let mut data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &compressed,
+            }],
+            &[],
+        );
+
+        // Set the `SHF_COMPRESSED` flag on the probe section, the last section in the file.
+        let (flags, ch_type) = {
+            let elf = goblin::elf::Elf::parse(&data).unwrap();
+            let index = elf
+                .section_headers
+                .iter()
+                .position(|header| {
+                    elf.shdr_strtab.get_at(header.sh_name) == Some("set_dtrace_probes")
+                })
+                .unwrap();
+            let flags = elf.header.e_shoff as usize + index * 64 + 8;
+            (flags, elf.section_headers[index].sh_offset as usize)
+        };
+        data[flags..flags + 8].copy_from_slice(&0x800u64.to_le_bytes());
+
+        let path = write_object("compressed-section", &data);
+        let read = probe_records(&path);
+        let mapped = probe_records_mmap(&path);
+        std::fs::remove_file(&path).unwrap();
+        let from_bytes = probe_records_from_bytes(&data);
+
+        for sections in [read, mapped, from_bytes] {
+            // Compressed sections are only read with the `zlib` feature.
+            if cfg!(not(feature = "zlib")) {
+                assert!(matches!(sections, Err(crate::Error::InvalidFile)));
+                continue;
+            }
+            let sections = sections.unwrap();
+            let probe = &sections[0].providers["foo"].probes["bar"];
+            assert_eq!(probe.address, 0x1234);
+            assert_eq!(probe.arguments, &["uint8_t"]);
+        }
+
+        // Other compression formats are rejected.
+        data[ch_type..ch_type + 4].copy_from_slice(&2u32.to_le_bytes());
+        assert!(matches!(
+            probe_records_from_bytes(&data),
+            Err(crate::Error::InvalidFile)
+        ));
+    }
+
+    #[test]
+    fn test_probe_records_with_extra_section_names() {
+        let record = probe_record("foo", "bar", 0x1234, &[]);
+        let other_record = probe_record("baz", "qux", 0x5678, &["uint8_t"]);
+        let data = build_elf(
+            &[
+                TestSection {
+                    name: "set_dtrace_probes",
+                    data: &record,
+                },
+                TestSection {
+                    name: "other_probes",
+                    data: &other_record,
+                },
+            ],
+            &[],
+        );
+        let path = write_object("with-extra-section-names", &data);
+        let default = probe_records(&path).unwrap();
+        let options = ExtractOptions {
+            extra_section_names: vec![String::from("other_probes"), String::from("missing")],
+            ..Default::default()
+        };
+        let both = probe_records_with(&path, &options).unwrap();
+        let options = ExtractOptions {
+            section_name: Some(String::from("missing")),
+            extra_section_names: vec![String::from("other_probes")],
+            ..Default::default()
+        };
+        let other = probe_records_with(&path, &options).unwrap();
+        let options = ExtractOptions {
+            section_name: Some(String::from("missing")),
+            extra_section_names: vec![String::from("also_missing")],
+            ..Default::default()
+        };
+        let neither = probe_records_with(&path, &options);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(default.len(), 1);
+        assert_eq!(both.len(), 2);
+        assert!(both[0].providers["foo"].probes.contains_key("bar"));
+        let probe = &both[1].providers["baz"].probes["qux"];
+        assert_eq!(probe.address, 0x5678);
+        assert_eq!(probe.arguments, vec![String::from("uint8_t")]);
+        assert_eq!(other.len(), 1);
+        assert!(other[0].providers.contains_key("baz"));
+        assert!(matches!(neither, Err(crate::Error::InvalidFile)));
+    }
+
+    #[test]
+    fn test_probe_records_with_string_limits() {
+        let record = probe_record("provider", "probe-name", 0x1234, &["uint8_t"]);
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("with-string-limits", &data);
+        let options = ExtractOptions {
+            string_limits: StringLimits {
+                provider: 5,
+                probe: 6,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let sections = probe_records_with(&path, &options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(sections[0].providers["prov"].probes.contains_key("probe"));
+    }
+
+    #[test]
+    fn test_probe_records_empty_symbol_bounds() {
+        let data = build_elf(
+            &[],
+            &[
+                ("__start_set_dtrace_probes", 0x1000),
+                ("__stop_set_dtrace_probes", 0x1000),
+            ],
+        );
+        let path = write_object("empty-bounds", &data);
+        let sections = probe_records(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(sections.is_empty());
+    }
+
+    #[test]
+    fn test_probe_records_empty_named_section() {
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &[],
+            }],
+            &[],
+        );
+        let path = write_object("empty-section", &data);
+        let sections = probe_records(&path).unwrap();
+        let mmap_sections = probe_records_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(sections.is_empty());
+        assert!(mmap_sections.is_empty());
+    }
+
+    #[test]
+    fn test_probe_records_mmap_matches_read() {
+        let mut records = probe_record("foo", "bar", 0x1000, &["uint8_t", "char *"]);
+        records.extend(probe_record("foo", "baz", 0x2000, &[]));
+        records.extend(probe_record("qux", "bar", 0x3000, &["int64_t"]));
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &records,
+            }],
+            &[],
+        );
+        let path = write_object("mmap-records", &data);
+        let expected = probe_records(&path).unwrap();
+        let actual = probe_records_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(expected.len(), 1);
+        assert_eq!(format!("{:?}", expected), format!("{:?}", actual));
+
+        // Check the same records, serialized as DOF.
+        let dof = dof::serialize_section(&expected[0]);
+        let data = build_elf(
+            &[TestSection {
+                name: ".SUNW_dof",
+                data: &dof,
+            }],
+            &[],
+        );
+        let path = write_object("mmap-dof", &data);
+        let expected = probe_records(&path).unwrap();
+        let actual = probe_records_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(expected.len(), 1);
+        assert_eq!(format!("{:?}", expected), format!("{:?}", actual));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_probe_records_async() {
+        let record = probe_record("foo", "bar", 0x1234, &["uint8_t"]);
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("async-records", &data);
+        let expected = probe_records(&path).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let actual = runtime.block_on(super::probe_records_async(&path)).unwrap();
+        let missing = runtime.block_on(super::probe_records_async(path.with_extension("missing")));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(format!("{:?}", expected), format!("{:?}", actual));
+        assert!(missing.is_err());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_probe_records_many() {
+        let mut paths = Vec::new();
+        for i in 0..8 {
+            let record = probe_record("foo", &format!("bar{}", i), 0x1234, &["uint8_t"]);
+            let data = build_elf(
+                &[TestSection {
+                    name: "set_dtrace_probes",
+                    data: &record,
+                }],
+                &[],
+            );
+            paths.push(write_object(&format!("many-records-{}", i), &data));
+        }
+        let missing = paths[0].with_extension("missing");
+        paths.push(missing.clone());
+
+        let results = super::probe_records_many(paths.clone());
+        for path in &paths[..8] {
+            std::fs::remove_file(path).unwrap();
+        }
+        assert_eq!(results.len(), paths.len());
+        for (i, (path, result)) in results.iter().take(8).enumerate() {
+            assert_eq!(path, &paths[i]);
+            let sections = result.as_ref().unwrap();
+            let probe = &sections[0].providers["foo"].probes[&format!("bar{}", i)];
+            assert_eq!(probe.address, 0x1234);
+        }
+        assert_eq!(results[8].0, missing);
+        assert!(results[8].1.is_err());
+    }
+
+    #[test]
+    fn test_all_providers_empty() {
+        // This test program defines no providers, but the registry must still link.
+        assert!(super::all_providers().is_empty());
+    }
+
+    #[test]
+    fn test_probe_records_from_bytes() {
+        let mut records = probe_record("foo", "bar", 0x1000, &["uint8_t", "char *"]);
+        records.extend(probe_record("qux", "baz", 0x2000, &[]));
+        let data: Vec<u8> = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &records,
+            }],
+            &[],
+        );
+        let path = write_object("bytes-records", &data);
+        let mut expected = probe_records(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let actual = probe_records_from_bytes(&data).unwrap();
+        assert_eq!(actual.len(), 1);
+        let probe = &actual[0].providers["foo"].probes["bar"];
+        assert_eq!(probe.address, 0x1000);
+        assert!(probe.module.is_empty());
+
+        // Apart from the module, the records match those read from the file.
+        for probe in expected
+            .iter_mut()
+            .flat_map(|section| section.providers.values_mut())
+            .flat_map(|provider| provider.probes.values_mut())
+        {
+            probe.module.clear();
+        }
+        assert_eq!(format!("{:?}", expected), format!("{:?}", actual));
+
+        assert!(matches!(
+            probe_records_from_bytes(&[]),
+            Err(crate::Error::InvalidFile)
+        ));
+    }
+
+    #[test]
+    fn test_probe_records_summary() {
+        // The addresses of these records aren't in the running process, so their function names
+        // can't be resolved.
+        let mut records = probe_record("foo", "bar", 0x1234, &[]);
+        records.extend(probe_record("foo", "baz", 0x5678, &[]));
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &records,
+            }],
+            &[],
+        );
+        let mut sections = probe_records_from_bytes(&data).unwrap();
+        assert_eq!(
+            sections[0].providers["foo"].probes["bar"].function,
+            "?0x1234"
+        );
+        assert_eq!(
+            probe_records_summary(&sections),
+            RecordsSummary {
+                probes: 2,
+                unresolved_functions: 2,
+            }
+        );
+
+        sections[0]
+            .providers
+            .get_mut("foo")
+            .unwrap()
+            .probes
+            .get_mut("baz")
+            .unwrap()
+            .function = String::from("func");
+        assert_eq!(
+            probe_records_summary(&sections),
+            RecordsSummary {
+                probes: 2,
+                unresolved_functions: 1,
+            }
+        );
+        assert_eq!(probe_records_summary(&[]), RecordsSummary::default());
+    }
+
+    #[cfg(feature = "dwarf")]
+    #[test]
+    fn test_resolve_dwarf_function_names() {
+        use gimli::write::{Address, AttributeValue, DwarfUnit, EndianVec, Sections};
+
+        // Describe a function at 0x1000, with another inlined into it at 0x1040, which are only
+        // named by the DWARF information and not the symbol table.
+        let encoding = gimli::Encoding {
+            format: gimli::Format::Dwarf32,
+            version: 4,
+            address_size: 8,
+        };
+        let mut dwarf = DwarfUnit::new(encoding);
+        let root = dwarf.unit.root();
+        let helper = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+        dwarf.unit.get_mut(helper).set(
+            gimli::DW_AT_name,
+            AttributeValue::String(b"helper".to_vec()),
+        );
+        let work = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+        let entry = dwarf.unit.get_mut(work);
+        entry.set(
+            gimli::DW_AT_name,
+            AttributeValue::String(b"do_work".to_vec()),
+        );
+        entry.set(
+            gimli::DW_AT_low_pc,
+            AttributeValue::Address(Address::Constant(0x1000)),
+        );
+        entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x100));
+        let inlined = dwarf.unit.add(work, gimli::DW_TAG_inlined_subroutine);
+        let entry = dwarf.unit.get_mut(inlined);
+        entry.set(
+            gimli::DW_AT_abstract_origin,
+            AttributeValue::UnitRef(helper),
+        );
+        entry.set(
+            gimli::DW_AT_low_pc,
+            AttributeValue::Address(Address::Constant(0x1040)),
+        );
+        entry.set(gimli::DW_AT_high_pc, AttributeValue::Udata(0x20));
+        let mut debug = Sections::new(EndianVec::new(gimli::LittleEndian));
+        dwarf.write(&mut debug).unwrap();
+        let mut debug_sections = Vec::new();
+        debug
+            .for_each(|id, data| {
+                if !data.slice().is_empty() {
+                    debug_sections.push((id.name(), data.slice().to_vec()));
+                }
+                Ok::<_, gimli::write::Error>(())
+            })
+            .unwrap();
+
+        let mut records = probe_record("foo", "outer", 0x1010, &[]);
+        records.extend(probe_record("foo", "inner", 0x1050, &[]));
+        records.extend(probe_record("foo", "elsewhere", 0x5000, &[]));
+        let mut test_sections = vec![TestSection {
+            name: "set_dtrace_probes",
+            data: &records,
+        }];
+        test_sections.extend(
+            debug_sections
+                .iter()
+                .map(|(name, data)| TestSection { name, data }),
+        );
+        let path = write_object("dwarf", &build_elf(&test_sections, &[]));
+        let mut sections = probe_records(&path).unwrap();
+        let resolved = resolve_dwarf_function_names(&path, &mut sections);
+        let symbolized = dwarf_symbolizer(&path).and_then(|symbolizer| {
+            let options = ExtractOptions {
+                symbolizer: Some(symbolizer),
+                ..Default::default()
+            };
+            probe_records_with(&path, &options)
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(resolved.unwrap(), 2);
+        for sections in [sections, symbolized.unwrap()] {
+            let probes = &sections[0].providers["foo"].probes;
+            assert_eq!(probes["outer"].function, "do_work");
+            assert_eq!(probes["inner"].function, "helper");
+            assert_eq!(probes["elsewhere"].function, "?0x5000");
+        }
+    }
+
+    // Check that the functions of split DWARF units are read from their `.dwo` file, or from a
+    // DWARF package, as built by the system C compiler and `llvm-dwp` or `dwp`, for both DWARF 5
+    // and the GNU extension to DWARF 4. This is skipped if there's no C compiler, and packages are
+    // only checked if a packaging tool succeeds.
+    #[cfg(all(feature = "dwarf", target_os = "linux"))]
+    #[test]
+    fn test_resolve_dwarf_function_names_split() {
+        for version in ["-gdwarf-4", "-gdwarf-5"] {
+            let dir = std::env::temp_dir().join(format!(
+                "usdt-test-split-dwarf{}-{}",
+                version,
+                std::process::id()
+            ));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(
+                dir.join("work.c"),
+                "static int calls;\n\
+                 __attribute__((noinline)) void do_work(void) { calls++; }\n\
+                 int main(void) { do_work(); return calls - 1; }\n",
+            )
+            .unwrap();
+            let run = |program: &str, args: &[&str]| {
+                std::process::Command::new(program)
+                    .current_dir(&dir)
+                    .args(args)
+                    .stderr(std::process::Stdio::null())
+                    .status()
+                    .map(|status| status.success())
+            };
+            let cc = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+            let args = [
+                "-g",
+                version,
+                "-gsplit-dwarf",
+                "-O0",
+                "-c",
+                "-o",
+                "work.o",
+                "work.c",
+            ];
+            match run(&cc, &args) {
+                Ok(compiled) => assert!(compiled, "Failed to compile with split DWARF"),
+                Err(_) => {
+                    eprintln!("Skipping test, no C compiler found");
+                    std::fs::remove_dir_all(&dir).unwrap();
+                    return;
+                }
+            }
+            assert!(run(&cc, &["-o", "work", "work.o"]).unwrap());
+            let path = dir.join("work");
+            assert!(dir.join("work.dwo").exists());
+
+            // A probe within `do_work`, which is only named by the split unit.
+            let data = std::fs::read(&path).unwrap();
+            let object = goblin::elf::Elf::parse(&data).unwrap();
+            let address = object
+                .syms
+                .iter()
+                .find(|sym| object.strtab.get_at(sym.st_name) == Some("do_work"))
+                .unwrap()
+                .st_value
+                + 4;
+            let probe = dof::Probe {
+                name: String::from("bar"),
+                function: format!("?{:#x}", address),
+                module: String::from("work"),
+                address,
+                offsets: vec![0],
+                enabled_offsets: vec![],
+                arguments: vec![],
+            };
+            let provider = dof::Provider {
+                name: String::from("foo"),
+                probes: [(probe.name.clone(), probe)].into_iter().collect(),
+                version_tag: None,
+            };
+            let section = dof::Section {
+                providers: [(provider.name.clone(), provider)].into_iter().collect(),
+                ..Default::default()
+            };
+            let resolve = || {
+                let mut sections = vec![section.clone()];
+                let resolved = resolve_dwarf_function_names(&path, &mut sections).unwrap();
+                let function = sections[0].providers["foo"].probes["bar"].function.clone();
+                (resolved, function)
+            };
+            let resolved = (1, String::from("do_work"));
+            assert_eq!(resolve(), resolved, "{}", version);
+
+            // A DWARF package takes the place of the `.dwo` files.
+            let package = ["-e", "work", "-o", "work.dwp"];
+            let packaged = ["llvm-dwp", "dwp"]
+                .iter()
+                .any(|tool| run(tool, &package).unwrap_or(false));
+            std::fs::remove_file(dir.join("work.dwo")).unwrap();
+            if packaged {
+                assert_eq!(resolve(), resolved, "{}", version);
+                std::fs::remove_file(dir.join("work.dwp")).unwrap();
+            }
+
+            // Without either, the split unit is skipped.
+            assert_eq!(resolve(), (0, format!("?{:#x}", address)), "{}", version);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_compare_probe_specs() {
+        let mut records = probe_record("foo", "bar", 0x1234, &[]);
+        records.extend(probe_record("foo", "baz", 0x5678, &[]));
+        records.extend(probe_record("other", "probe", 0x9abc, &[]));
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &records,
+            }],
+            &[],
+        );
+        let sections = probe_records_from_bytes(&data).unwrap();
+        assert!(compare_probe_specs(&["foo:::bar", "foo:::baz"], &sections).is_empty());
+
+        // Probes of providers which aren't declared at all are ignored.
+        assert_eq!(
+            compare_probe_specs(&["foo:::bar", "foo:::qux", "missing:::probe"], &sections),
+            vec![
+                ProbeMismatch::Missing(String::from("foo:::qux")),
+                ProbeMismatch::Missing(String::from("missing:::probe")),
+                ProbeMismatch::Unexpected(String::from("foo:::baz")),
+            ]
+        );
+        assert_eq!(
+            compare_probe_specs(&["foo:::bar"], &[]),
+            vec![ProbeMismatch::Missing(String::from("foo:::bar"))]
+        );
+    }
+
+    #[test]
+    fn test_runtime_probe_sites() {
+        let mut section = dof::Section::default();
+        let probe = dof::Probe {
+            name: String::from("bar"),
+            function: String::from("func"),
+            module: String::new(),
+            address: 0x1000,
+            offsets: vec![0, 0x10],
+            enabled_offsets: vec![0x4],
+            arguments: vec![],
+        };
+        section.providers.insert(
+            String::from("foo"),
+            dof::Provider {
+                name: String::from("foo"),
+                probes: [(probe.name.clone(), probe)].into_iter().collect(),
+                version_tag: None,
+            },
+        );
+
+        // The object file contains no program headers, so is linked at address zero, and the
+        // bias is the load base itself.
+        let path = write_object("load-bias", &build_elf(&[], &[]));
+        let load_base = 0x5555_0000_0000;
+        let bias = object_load_bias(&path, load_base);
+        std::fs::remove_file(&path).unwrap();
+        let bias = bias.unwrap();
+        assert_eq!(bias, load_base);
+
+        let site = |address, is_enabled| ProbeSite {
+            provider: String::from("foo"),
+            probe: String::from("bar"),
+            address,
+            is_enabled,
+        };
+        assert_eq!(
+            runtime_probe_sites(&section, bias),
+            vec![
+                site(0x5555_0000_1000, false),
+                site(0x5555_0000_1010, false),
+                site(0x5555_0000_1004, true),
+            ]
+        );
+        assert_eq!(runtime_probe_sites(&section, 0)[1], site(0x1010, false));
+    }
+
+    #[test]
+    fn test_probe_records_from_core() {
+        let record = probe_record("foo", "bar", 0x1234, &["uint8_t"]);
+        let exe = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let exe_path = write_object("core-exe", &exe);
+        let exe_name = exe_path.to_str().unwrap();
+
+        // The memory of the process contains the executable, loaded at some other address, whose
+        // records have been registered.
+        let mut memory = exe.clone();
+        let offset = memory
+            .windows(record.len())
+            .position(|window| window == record)
+            .unwrap();
+        memory[offset + 4] = u8::MAX;
+        let core_path = write_object("core", &build_core(0x5555_0000, &memory, Some(exe_name)));
+        let sections = probe_records_from_core(&core_path, &exe_path);
+
+        // Without the note mapping the executable, it's expected at its linked address.
+        let unmapped_path = write_object("core-unmapped", &build_core(0, &memory, None));
+        let unmapped = probe_records_from_core(&unmapped_path, &exe_path);
+        let moved_path = write_object("core-moved", &build_core(0x5555_0000, &memory, None));
+        let moved = probe_records_from_core(&moved_path, &exe_path);
+        let not_core = probe_records_from_core(&exe_path, &exe_path);
+        for path in [&exe_path, &core_path, &unmapped_path, &moved_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let sections = sections.unwrap();
+        let probe = &sections[0].providers["foo"].probes["bar"];
+        assert_eq!(probe.address, 0x1234);
+        assert_eq!(probe.arguments, vec![String::from("uint8_t")]);
+        assert_eq!(
+            probe.module,
+            exe_path.file_name().unwrap().to_str().unwrap()
+        );
+        assert_eq!(
+            unmapped.unwrap()[0].providers["foo"].probes["bar"].address,
+            0x1234
+        );
+        assert!(moved.is_err());
+        assert!(not_core.is_err());
+    }
+
+    #[test]
+    fn test_probe_records_from_core_version_tag() {
+        let format = RecordFormat::native();
+        let record = RecordBuilder::new(format)
+            .version_tag("1.2.3-abcdef")
+            .probe("foo", "bar", 0x1234, &["uint8_t"])
+            .build();
+        let exe = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let exe_path = write_object("core-tagged-exe", &exe);
+        let exe_name = exe_path.to_str().unwrap();
+
+        // Register the records in the memory of the process, as the running program would have.
+        let mut memory = exe.clone();
+        let offset = memory
+            .windows(record.len())
+            .position(|window| window == record)
+            .unwrap();
+        usdt_impl::record::process_section_with(
+            &mut memory[offset..offset + record.len()],
+            /* register = */ true,
+            format,
+        )
+        .unwrap();
+        assert_ne!(memory[offset + 4], record[4]);
+        let core_path = write_object(
+            "core-tagged",
+            &build_core(0x5555_0000, &memory, Some(exe_name)),
+        );
+        let sections = probe_records_from_core(&core_path, &exe_path);
+        for path in [&exe_path, &core_path] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        let sections = sections.unwrap();
+        let provider = &sections[0].providers["foo"];
+        assert_eq!(provider.version_tag.as_deref(), Some("1.2.3-abcdef"));
+        assert_eq!(
+            provider.probes["bar"].arguments,
+            vec![String::from("uint8_t")]
+        );
+    }
+
+    #[test]
+    fn test_probe_records_mmap_invalid_file() {
+        let path = write_object("mmap-empty", &[]);
+        let result = probe_records_mmap(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(crate::Error::InvalidFile)));
+    }
+
+    #[test]
+    fn test_probe_records_without_probes() {
+        // Each way of extracting records returns an empty list for a file without probes, rather
+        // than a section without any.
+        let dof = dof::serialize_section(&dof::Section::default());
+        let data = build_elf(
+            &[TestSection {
+                name: ".SUNW_dof",
+                data: &dof,
+            }],
+            &[],
+        );
+        let path = write_object("empty-dof", &data);
+        let sections = probe_records(&path).unwrap();
+        let mmap_sections = probe_records_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(sections.is_empty());
+        assert!(mmap_sections.is_empty());
+        assert!(probe_records_from_bytes(&data).unwrap().is_empty());
+
+        // Filtering out all providers leaves no probes, from either DOF or the probe records.
+        let record = probe_record("foo", "bar", 0x1234, &["uint8_t"]);
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("filtered-records", &data);
+        let section = probe_records(&path).unwrap().remove(0);
+        let filtered = probe_records_filtered(&path, |_| false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!section.is_empty());
+        assert!(filtered.is_empty());
+
+        let dof = dof::serialize_section(&section);
+        let data = build_elf(
+            &[TestSection {
+                name: ".SUNW_dof",
+                data: &dof,
+            }],
+            &[],
+        );
+        let path = write_object("filtered-dof", &data);
+        let sections = probe_records(&path).unwrap();
+        let filtered = probe_records_filtered(&path, |_| false).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(sections.len(), 1);
+        assert!(filtered.is_empty());
+    }
+
+    #[test]
+    fn test_strip_probe_records() {
+        let record = probe_record("foo", "bar", 0x1234, &["uint8_t"]);
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("strip-in", &data);
+        let out_path = path.with_extension("stripped.o");
+        strip_probe_records(&path, &out_path).unwrap();
+        let original = probe_records(&path).unwrap();
+        let stripped = probe_records(&out_path).unwrap();
+        let stripped_mmap = probe_records_mmap(&out_path).unwrap();
+        let stripped_data = std::fs::read(&out_path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&out_path).unwrap();
+
+        assert_eq!(original.len(), 1);
+        assert!(stripped.is_empty());
+        assert!(stripped_mmap.is_empty());
+        assert_eq!(stripped_data.len(), data.len());
+    }
+
+    #[test]
+    fn test_strip_probe_records_no_section() {
+        let data = build_elf(&[], &[]);
+        let path = write_object("strip-none", &data);
+        let out_path = path.with_extension("stripped.o");
+        let result = strip_probe_records(&path, &out_path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(crate::Error::InvalidFile)));
+        assert!(!out_path.exists());
+    }
+
+    // Return the DOF for a probe `foo:::bar` with no address, in the function `do_work`, along
+    // with the offset of the probe's address in the DOF.
+    fn relocatable_dof() -> (Vec<u8>, u64) {
+        let record = probe_record("foo", "bar", 0, &["uint8_t"]);
+        let data = build_elf(
+            &[TestSection {
+                name: "set_dtrace_probes",
+                data: &record,
+            }],
+            &[],
+        );
+        let path = write_object("relocatable-records", &data);
+        let mut section = probe_records(&path).unwrap().remove(0);
+        std::fs::remove_file(&path).unwrap();
+        let probe = section
+            .providers
+            .get_mut("foo")
+            .unwrap()
+            .probes
+            .get_mut("bar")
+            .unwrap();
+        probe.function = String::from("do_work");
+        let dof = dof::serialize_section(&section);
+
+        // The address is the first field of the probe's entry.
+        let probes_offset = dof::des::deserialize_raw_sections(&dof)
+            .unwrap()
+            .sections
+            .iter()
+            .find(|(header, _)| header.dofs_type == dof::dof_bindings::DOF_SECT_PROBES)
+            .unwrap()
+            .0
+            .dofs_offset;
+        (dof, probes_offset)
+    }
+
+    #[test]
+    fn test_probe_records_relocatable_dof() {
+        // This mirrors the objects generated by `dtrace -G`, where the address of each probe is
+        // filled in by a relocation against its function's symbol.
+        let (dof, probes_offset) = relocatable_dof();
+        const R_X86_64_64: u32 = 1;
+        let text = [0x90u8; 0x80];
+        let data = build_object(
+            ET_REL,
+            &[
+                TestSection {
+                    name: ".text",
+                    data: &text,
+                },
+                TestSection {
+                    name: ".SUNW_dof",
+                    data: &dof,
+                },
+            ],
+            &[("do_work", 0x40)],
+            &[TestRelocation {
+                section: ".SUNW_dof",
+                offset: probes_offset,
+                symbol: 1,
+                r_type: R_X86_64_64,
+                addend: 0x8,
+            }],
+        );
+        let path = write_object("relocatable-dof", &data);
+        let sections = probe_records(&path).unwrap();
+        let mmap_sections = probe_records_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let probe = &sections[0].providers["foo"].probes["bar"];
+        assert_eq!(probe.function, "do_work");
+        assert_eq!(probe.address, 0x48);
+        assert_eq!(probe.arguments, ["uint8_t"]);
+        assert_eq!(format!("{:?}", sections), format!("{:?}", mmap_sections));
+    }
+
+    // Check the DOF of a relocatable object built by the system C compiler and assembler, whose
+    // sections, symbols and relocations are laid out as in those generated by `dtrace -G`. This is
+    // skipped if there's no C compiler.
+    #[cfg(any(target_os = "linux", target_os = "illumos"))]
+    #[test]
+    fn test_probe_records_compiled_relocatable_dof() {
+        let (dof, probes_offset) = relocatable_dof();
+        let (head, rest) = dof.split_at(probes_offset as usize);
+        let tail = &rest[std::mem::size_of::<u64>()..];
+        let bytes = |data: &[u8]| {
+            data.iter()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let source = format!(
+            r#"
+static int calls;
+
+void start(void) {{ calls = 0; }}
+void do_work(void) {{ calls++; }}
+
+struct dof {{
+    unsigned char head[{head_len}];
+    void *address;
+    unsigned char tail[{tail_len}];
+}} __attribute__((packed));
+
+__attribute__((section(".SUNW_dof"), used, aligned(8)))
+static const struct dof dof = {{ {{ {head} }}, (char *)do_work + 8, {{ {tail} }} }};
+"#,
+            head_len = head.len(),
+            tail_len = tail.len(),
+            head = bytes(head),
+            tail = bytes(tail),
+        );
+        let dir = std::env::temp_dir().join(format!("usdt-test-dtrace-g-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("dof.c"), source).unwrap();
+        let cc = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+        let status = std::process::Command::new(cc)
+            .current_dir(&dir)
+            .args(["-c", "-O0", "-o", "dof.o", "dof.c"])
+            .status();
+        let path = dir.join("dof.o");
+        let sections = match status {
+            Ok(status) if status.success() => probe_records(&path),
+            Ok(_) => panic!("Failed to compile the object"),
+            Err(_) => {
+                eprintln!("Skipping test, no C compiler found");
+                std::fs::remove_dir_all(&dir).unwrap();
+                return;
+            }
+        };
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The object is relocatable, and `do_work` isn't at the start of its section.
+        let object = goblin::elf::Elf::parse(&data).unwrap();
+        assert_eq!(object.header.e_type, ET_REL);
+        let function = object
+            .syms
+            .iter()
+            .find(|sym| object.strtab.get_at(sym.st_name) == Some("do_work"))
+            .expect("Expected a symbol for the function");
+        assert_ne!(function.st_value, 0);
+
+        let sections = sections.unwrap();
+        let probe = &sections[0].providers["foo"].probes["bar"];
+        assert_eq!(probe.function, "do_work");
+        assert_eq!(probe.address, function.st_value + 8);
+        assert_eq!(probe.arguments, ["uint8_t"]);
+    }
+
+    #[test]
+    fn test_probe_records_relocatable() {
+        // In an unlinked object, the records are found by their section header, and the address
+        // of each probe is filled in by a relocation against the section containing it.
+        const R_X86_64_64: u32 = 1;
+        let text = [0x90u8; 0x80];
+        let mut records = probe_record("foo", "bar", 0, &["uint8_t"]);
+        let len = records.len() as u64;
+        records.extend(probe_record("foo", "baz", 0, &[]));
+        let data = build_object(
+            ET_REL,
+            &[
+                TestSection {
+                    name: ".text",
+                    data: &text,
+                },
+                TestSection {
+                    name: "set_dtrace_probes",
+                    data: &records,
+                },
+            ],
+            &[(".text", 0)],
+            &[
+                TestRelocation {
+                    section: "set_dtrace_probes",
+                    offset: 8,
+                    symbol: 1,
+                    r_type: R_X86_64_64,
+                    addend: 0x10,
+                },
+                TestRelocation {
+                    section: "set_dtrace_probes",
+                    offset: len + 8,
+                    symbol: 1,
+                    r_type: R_X86_64_64,
+                    addend: 0x20,
+                },
+            ],
+        );
+        let path = write_object("relocatable", &data);
+        let sections = probe_records(&path).unwrap();
+        let mmap_sections = probe_records_mmap(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let probes = &sections[0].providers["foo"].probes;
+        assert_eq!(probes["bar"].address, 0x10);
+        assert_eq!(probes["bar"].arguments, ["uint8_t"]);
+        assert_eq!(probes["baz"].address, 0x20);
+        assert_eq!(format!("{:?}", sections), format!("{:?}", mmap_sections));
+    }
+
+    // Check the records of a relocatable object built by the system C compiler and assembler, laid
+    // out as those emitted by `usdt-impl`, including a reference to the `__start` symbol which is
+    // left undefined until the object is linked. This is skipped if there's no C compiler.
+    #[cfg(any(target_os = "linux", target_os = "illumos"))]
+    #[test]
+    fn test_probe_records_compiled_relocatable() {
+        let record = probe_record("foo", "bar", 0, &["uint8_t"]);
+        let (head, rest) = record.split_at(8);
+        let tail = &rest[std::mem::size_of::<u64>()..];
+        let bytes = |data: &[u8]| {
+            data.iter()
+                .map(u8::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let source = format!(
+            r#"
+static int calls;
+
+void start(void) {{ calls = 0; }}
+void do_work(void) {{ calls++; }}
+
+struct record {{
+    unsigned char head[{head_len}];
+    void *address;
+    unsigned char tail[{tail_len}];
+}} __attribute__((packed));
+
+__attribute__((section("set_dtrace_probes"), used, aligned(8)))
+static struct record record = {{ {{ {head} }}, (char *)do_work + 2, {{ {tail} }} }};
+
+extern char __start_set_dtrace_probes[];
+char *records(void) {{ return __start_set_dtrace_probes; }}
+"#,
+            head_len = head.len(),
+            tail_len = tail.len(),
+            head = bytes(head),
+            tail = bytes(tail),
+        );
+        let dir = std::env::temp_dir().join(format!("usdt-test-records-o-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("records.c"), source).unwrap();
+        let cc = std::env::var("CC").unwrap_or_else(|_| String::from("cc"));
+        let status = std::process::Command::new(cc)
+            .current_dir(&dir)
+            .args(["-c", "-O0", "-o", "records.o", "records.c"])
+            .status();
+        let path = dir.join("records.o");
+        let sections = match status {
+            Ok(status) if status.success() => probe_records(&path),
+            Ok(_) => panic!("Failed to compile the object"),
+            Err(_) => {
+                eprintln!("Skipping test, no C compiler found");
+                std::fs::remove_dir_all(&dir).unwrap();
+                return;
+            }
+        };
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The object is relocatable, the `__start` symbol is undefined, and `do_work` isn't at the
+        // start of its section.
+        let object = goblin::elf::Elf::parse(&data).unwrap();
+        assert_eq!(object.header.e_type, ET_REL);
+        let symbol = |name| {
+            object
+                .syms
+                .iter()
+                .find(|sym| object.strtab.get_at(sym.st_name) == Some(name))
+                .unwrap_or_else(|| panic!("Expected a symbol named {}", name))
+        };
+        assert_eq!(
+            symbol("__start_set_dtrace_probes").st_shndx,
+            goblin::elf::section_header::SHN_UNDEF as usize
+        );
+        let function = symbol("do_work");
+        assert_ne!(function.st_value, 0);
+
+        let sections = sections.unwrap();
+        let probe = &sections[0].providers["foo"].probes["bar"];
+        assert_eq!(probe.address, function.st_value + 2);
+        assert_eq!(probe.arguments, ["uint8_t"]);
+    }
+
+    #[test]
+    fn test_builder_skips_unchanged_provider() {
+        let dir = std::env::temp_dir().join(format!("usdt-test-hash-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source_file = dir.join("cached.d");
+        let out_file = dir.join("cached.rs");
+        std::fs::write(&source_file, "provider cached { probe first(); };").unwrap();
+        Builder::new(&source_file).build_in(&dir).unwrap();
+        assert!(std::fs::read_to_string(&out_file)
+            .unwrap()
+            .contains("first"));
+        assert!(hash_file(&out_file).exists());
+
+        // An unchanged provider leaves the output alone, which is detected by overwriting it.
+        std::fs::write(&out_file, "// cached").unwrap();
+        Builder::new(&source_file).build_in(&dir).unwrap();
+        assert_eq!(std::fs::read_to_string(&out_file).unwrap(), "// cached");
+
+        // Changing the builder's options regenerates it.
+        Builder::new(&source_file)
+            .always_fire(true)
+            .build_in(&dir)
+            .unwrap();
+        assert!(std::fs::read_to_string(&out_file)
+            .unwrap()
+            .contains("first"));
+
+        // As does changing the provider.
+        std::fs::write(&source_file, "provider cached { probe second(); };").unwrap();
+        Builder::new(&source_file).build_in(&dir).unwrap();
+        assert!(std::fs::read_to_string(&out_file)
+            .unwrap()
+            .contains("second"));
+
+        // As does a missing header.
+        let header_file = dir.join("cached.h");
+        Builder::new(&source_file)
+            .header_file("cached")
+            .build_in(&dir)
+            .unwrap();
+        std::fs::remove_file(&header_file).unwrap();
+        Builder::new(&source_file)
+            .header_file("cached")
+            .build_in(&dir)
+            .unwrap();
+        assert!(header_file.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_printf_args() {
+        assert_eq!(crate::printf_args!("none"), ("none", 0, 0, 0, 0, 0));
+        assert_eq!(
+            crate::printf_args!("%d %d", 1u8, -1i64),
+            ("%d %d", 1, usize::MAX, 0, 0, 0)
+        );
+        assert_eq!(
+            crate::printf_args!("%d %d %d %d %d", 1, 2, 3, 4, 5,),
+            ("%d %d %d %d %d", 1, 2, 3, 4, 5)
+        );
+        assert_eq!(crate::MAX_PRINTF_ARGS, 5);
+    }
+
+    #[test]
+    fn test_check_provider() {
+        let path = write_object(
+            "check-provider",
+            b"provider foo {\n    probe bar(uint8_t);\n};\n",
+        );
+        assert_eq!(check_provider(&path), Ok(()));
+
+        std::fs::write(&path, "provider foo {\n    probe bar(u8);\n};\n").unwrap();
+        let diagnostics = check_provider(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (2, 11));
+
+        let diagnostics = check_provider(&path).unwrap_err();
+        assert_eq!((diagnostics[0].line, diagnostics[0].column), (1, 1));
+    }
+
+    #[test]
+    fn test_format_provider() {
+        let source = "provider foo{probe bar(uint8_t,char*);;probe baz();}foo;";
+        let formatted = format_provider(source).unwrap();
+        assert_eq!(
+            formatted,
+            "provider foo {\n\tprobe bar(uint8_t, char *);\n\tprobe baz();\n};\n"
+        );
+        assert_eq!(format_provider(&formatted).unwrap(), formatted);
+        assert!(format_provider("provider foo {};").is_err());
     }
 }